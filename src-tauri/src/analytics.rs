@@ -0,0 +1,463 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap};
+use crate::fx::{Currency, FxRates};
+use crate::models::{ReturnReport, ReturnSummary, Trade};
+use crate::money::Money;
+
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-9;
+
+/// 一笔带日期的现金流，投入为负、收回为正
+#[derive(Debug, Clone, Copy)]
+pub struct CashFlow {
+    pub date: NaiveDate,
+    pub amount: f64,
+}
+
+/// 某日收盘后的组合估值，flow 为当日外部资金净流入
+#[derive(Debug, Clone, Copy)]
+pub struct Valuation {
+    pub date: NaiveDate,
+    pub value: f64,
+    pub flow: f64,
+}
+
+/// 资金加权年化收益率，与 Excel 的 XIRR 一致（按实际天数 / 365 计息）
+///
+/// 先用牛顿法求解，不收敛时退回二分法；现金流没有正负两个方向时无解。
+pub fn xirr(flows: &[CashFlow]) -> Option<f64> {
+    let has_outflow = flows.iter().any(|f| f.amount < 0.0);
+    let has_inflow = flows.iter().any(|f| f.amount > 0.0);
+    if !has_outflow || !has_inflow {
+        return None;
+    }
+
+    let start = flows.iter().map(|f| f.date).min()?;
+    let terms: Vec<(f64, f64)> = flows
+        .iter()
+        .map(|f| ((f.date - start).num_days() as f64 / 365.0, f.amount))
+        .collect();
+
+    let npv = |rate: f64| -> f64 { terms.iter().map(|(t, a)| a / (1.0 + rate).powf(*t)).sum() };
+    let slope = |rate: f64| -> f64 { terms.iter().map(|(t, a)| -t * a / (1.0 + rate).powf(t + 1.0)).sum() };
+
+    let mut rate = 0.1;
+    for _ in 0..MAX_ITERATIONS {
+        let value = npv(rate);
+        if value.abs() < TOLERANCE {
+            return Some(rate);
+        }
+
+        let derivative = slope(rate);
+        if derivative == 0.0 || !derivative.is_finite() {
+            break;
+        }
+
+        let next = rate - value / derivative;
+        if !next.is_finite() || next <= -1.0 {
+            break;
+        }
+        if (next - rate).abs() < TOLERANCE {
+            return Some(next);
+        }
+        rate = next;
+    }
+
+    // 牛顿法失败时在 (-1, high] 内二分，high 逐步放大直到区间包含根
+    let mut low = -1.0 + 1e-6;
+    let mut high = 1.0;
+    while npv(low).signum() == npv(high).signum() {
+        high *= 2.0;
+        if high > 1e6 {
+            return None;
+        }
+    }
+
+    for _ in 0..1000 {
+        let mid = (low + high) / 2.0;
+        let value = npv(mid);
+        if value.abs() < TOLERANCE || high - low < TOLERANCE {
+            return Some(mid);
+        }
+        if value.signum() == npv(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Some((low + high) / 2.0)
+}
+
+/// 时间加权收益率（区间累计），按日拆分子区间连乘以剔除资金进出的影响
+pub fn time_weighted_return(valuations: &[Valuation]) -> Option<f64> {
+    if valuations.len() < 2 {
+        return None;
+    }
+
+    let growth: f64 = daily_returns(valuations).iter().map(|r| 1.0 + r).product();
+    Some(growth - 1.0)
+}
+
+/// 按日期排序后逐日计算收益率，结果比估值少一项
+///
+/// 当日的外部资金视为开盘前到账：子区间收益 = 当日估值 / (前一日估值 + 当日资金流入) - 1，
+/// 分母不为正时该日收益记为 0。
+pub fn daily_returns(valuations: &[Valuation]) -> Vec<f64> {
+    let mut sorted = valuations.to_vec();
+    sorted.sort_by_key(|v| v.date);
+
+    sorted
+        .windows(2)
+        .map(|pair| {
+            let base = pair[0].value + pair[1].flow;
+            if base > 0.0 {
+                pair[1].value / base - 1.0
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+/// 净值序列的最大回撤（正数小数）及其峰值、谷底位置
+pub fn max_drawdown(nav: &[f64]) -> (f64, Option<(usize, usize)>) {
+    let mut peak_index = 0;
+    let mut worst = 0.0;
+    let mut range = None;
+
+    for (i, value) in nav.iter().enumerate() {
+        if *value > nav[peak_index] {
+            peak_index = i;
+        }
+        if nav[peak_index] > 0.0 {
+            let drawdown = (nav[peak_index] - value) / nav[peak_index];
+            if drawdown > worst {
+                worst = drawdown;
+                range = Some((peak_index, i));
+            }
+        }
+    }
+
+    (worst, range)
+}
+
+/// 日收益率的样本标准差按 252 个交易日年化
+pub fn annualized_volatility(returns: &[f64]) -> Option<f64> {
+    if returns.len() < 2 {
+        return None;
+    }
+
+    let n = returns.len() as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    Some(variance.sqrt() * 252f64.sqrt())
+}
+
+/// 组合相对基准的贝塔：两组日收益率的协方差 / 基准方差
+pub fn beta(portfolio: &[f64], benchmark: &[f64]) -> Option<f64> {
+    if portfolio.len() != benchmark.len() || portfolio.len() < 2 {
+        return None;
+    }
+
+    let n = portfolio.len() as f64;
+    let mean_p = portfolio.iter().sum::<f64>() / n;
+    let mean_b = benchmark.iter().sum::<f64>() / n;
+    let covariance: f64 = portfolio
+        .iter()
+        .zip(benchmark)
+        .map(|(p, b)| (p - mean_p) * (b - mean_b))
+        .sum::<f64>()
+        / (n - 1.0);
+    let variance: f64 = benchmark.iter().map(|b| (b - mean_b).powi(2)).sum::<f64>() / (n - 1.0);
+
+    if variance > 0.0 {
+        Some(covariance / variance)
+    } else {
+        None
+    }
+}
+
+/// 年化跟踪误差：日超额收益的样本标准差按 252 个交易日年化
+pub fn tracking_error(portfolio: &[f64], benchmark: &[f64]) -> Option<f64> {
+    if portfolio.len() != benchmark.len() {
+        return None;
+    }
+
+    let excess: Vec<f64> = portfolio.iter().zip(benchmark).map(|(p, b)| p - b).collect();
+    annualized_volatility(&excess)
+}
+
+/// 将区间累计收益率换算为年化收益率
+pub fn annualize(total_return: f64, days: i64) -> Option<f64> {
+    if days <= 0 || total_return <= -1.0 {
+        return None;
+    }
+    Some((1.0 + total_return).powf(365.0 / days as f64) - 1.0)
+}
+
+/// 计算每个持仓、每个账户和整体的 XIRR
+///
+/// 每笔买入记为当日的投入，截至 as_of 的市值记为最后一笔收回；
+/// 没有行情的股票按买入价估值。金额按 `base` 折算，投入用买入日汇率，市值用 as_of 当日汇率，
+/// 缺少汇率的交易不计入。
+pub fn build_return_report(
+    trades: &[Trade],
+    prices: &HashMap<String, Money>,
+    fx: &FxRates,
+    base: Currency,
+    as_of: DateTime<Utc>,
+) -> ReturnReport {
+    let mut missing_fx_rates: Vec<Currency> = trades
+        .iter()
+        .map(|t| t.currency())
+        .filter(|currency| fx.rate(*currency, base, local_date(as_of)).is_none())
+        .collect();
+    missing_fx_rates.sort();
+    missing_fx_rates.dedup();
+
+    let mut positions: BTreeMap<(String, String), Vec<&Trade>> = BTreeMap::new();
+    let mut accounts: BTreeMap<String, Vec<&Trade>> = BTreeMap::new();
+
+    let converted: Vec<&Trade> = trades
+        .iter()
+        .filter(|t| !missing_fx_rates.contains(&t.currency()))
+        .collect();
+    for trade in &converted {
+        positions
+            .entry((trade.account.clone(), trade.stock_code.clone()))
+            .or_default()
+            .push(trade);
+        accounts.entry(trade.account.clone()).or_default().push(trade);
+    }
+
+    let summary = |group: &[&Trade], account, stock_code| summarize(group, prices, fx, base, as_of, account, stock_code);
+
+    ReturnReport {
+        base_currency: base,
+        overall: summary(&converted, None, None),
+        accounts: accounts
+            .into_iter()
+            .map(|(account, group)| summary(&group, Some(account), None))
+            .collect(),
+        positions: positions
+            .into_iter()
+            .map(|((account, code), group)| summary(&group, Some(account), Some(code)))
+            .collect(),
+        missing_fx_rates,
+        as_of,
+    }
+}
+
+fn summarize(
+    trades: &[&Trade],
+    prices: &HashMap<String, Money>,
+    fx: &FxRates,
+    base: Currency,
+    as_of: DateTime<Utc>,
+    account: Option<String>,
+    stock_code: Option<String>,
+) -> ReturnSummary {
+    let mut flows = Vec::with_capacity(trades.len() + 1);
+    let mut invested = Money::ZERO;
+    let mut market_value = Money::ZERO;
+
+    for trade in trades {
+        let (Some(buy_rate), Some(rate)) = (
+            fx.rate(trade.currency(), base, local_date(trade.buy_time)),
+            fx.rate(trade.currency(), base, local_date(as_of)),
+        ) else {
+            continue;
+        };
+        let cost = (trade.buy_price * trade.quantity as i64).mul_f64(buy_rate);
+        let price = prices.get(&trade.stock_code).copied().unwrap_or(trade.buy_price);
+
+        invested += cost;
+        market_value += (price * trade.quantity as i64).mul_f64(rate);
+        flows.push(CashFlow {
+            date: local_date(trade.buy_time),
+            amount: -cost.to_f64(),
+        });
+    }
+
+    flows.push(CashFlow {
+        date: local_date(as_of),
+        amount: market_value.to_f64(),
+    });
+
+    ReturnSummary {
+        stock_name: stock_code
+            .as_ref()
+            .and_then(|_| trades.first().map(|t| t.stock_name.clone())),
+        account,
+        stock_code,
+        invested,
+        market_value,
+        xirr: xirr(&flows),
+    }
+}
+
+fn local_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn flow(y: i32, m: u32, d: u32, amount: f64) -> CashFlow {
+        CashFlow { date: date(y, m, d), amount }
+    }
+
+    #[test]
+    fn xirr_matches_excel_reference() {
+        // Excel XIRR 帮助文档中的示例，结果为 0.373362535
+        let flows = [
+            flow(2008, 1, 1, -10000.0),
+            flow(2008, 3, 1, 2750.0),
+            flow(2008, 10, 30, 4250.0),
+            flow(2009, 2, 15, 3250.0),
+            flow(2009, 4, 1, 2750.0),
+        ];
+        let rate = xirr(&flows).unwrap();
+        assert!((rate - 0.373362535).abs() < 1e-6, "rate = {}", rate);
+    }
+
+    #[test]
+    fn xirr_of_single_year_holding_is_simple_return() {
+        let flows = [flow(2023, 1, 1, -1000.0), flow(2024, 1, 1, 1100.0)];
+        assert!((xirr(&flows).unwrap() - 0.10).abs() < 1e-9);
+    }
+
+    #[test]
+    fn xirr_handles_losses_and_additional_purchases() {
+        // 两次投入，最终亏损，结果应为负且 NPV 为零
+        let flows = [
+            flow(2023, 1, 1, -1000.0),
+            flow(2023, 7, 1, -1000.0),
+            flow(2024, 1, 1, 1500.0),
+        ];
+        let rate = xirr(&flows).unwrap();
+        assert!(rate < 0.0);
+
+        let npv: f64 = flows
+            .iter()
+            .map(|f| f.amount / (1.0 + rate).powf((f.date - date(2023, 1, 1)).num_days() as f64 / 365.0))
+            .sum();
+        assert!(npv.abs() < 1e-6);
+    }
+
+    #[test]
+    fn xirr_requires_inflow_and_outflow() {
+        assert!(xirr(&[flow(2023, 1, 1, -1000.0), flow(2023, 6, 1, -500.0)]).is_none());
+        assert!(xirr(&[]).is_none());
+    }
+
+    #[test]
+    fn twr_ignores_external_flows() {
+        // 第一天 +10%，第二天追加 50 后再涨 6.25%，累计 1.1 × 1.0625 - 1
+        let valuations = [
+            Valuation { date: date(2024, 1, 1), value: 100.0, flow: 100.0 },
+            Valuation { date: date(2024, 1, 2), value: 110.0, flow: 0.0 },
+            Valuation { date: date(2024, 1, 3), value: 170.0, flow: 50.0 },
+        ];
+        let twr = time_weighted_return(&valuations).unwrap();
+        assert!((twr - 0.16875).abs() < 1e-12);
+    }
+
+    #[test]
+    fn twr_without_flows_equals_simple_return() {
+        let valuations = [
+            Valuation { date: date(2024, 1, 3), value: 90.0, flow: 0.0 },
+            Valuation { date: date(2024, 1, 1), value: 100.0, flow: 0.0 },
+            Valuation { date: date(2024, 1, 2), value: 120.0, flow: 0.0 },
+        ];
+        assert!((time_weighted_return(&valuations).unwrap() + 0.10).abs() < 1e-12);
+    }
+
+    #[test]
+    fn max_drawdown_finds_peak_and_trough() {
+        let nav = [1.0, 1.2, 0.9, 1.1, 1.3, 1.04];
+        let (drawdown, range) = max_drawdown(&nav);
+        assert!((drawdown - 0.25).abs() < 1e-12);
+        assert_eq!(range, Some((1, 2)));
+        assert_eq!(max_drawdown(&[1.0, 1.1, 1.2]), (0.0, None));
+    }
+
+    #[test]
+    fn volatility_of_alternating_returns() {
+        // 样本标准差为 0.01 × sqrt(4/3)
+        let returns = [0.01, -0.01, 0.01, -0.01];
+        let expected = 0.01 * (4.0f64 / 3.0).sqrt() * 252f64.sqrt();
+        assert!((annualized_volatility(&returns).unwrap() - expected).abs() < 1e-12);
+        assert!(annualized_volatility(&[0.01]).is_none());
+    }
+
+    #[test]
+    fn beta_of_leveraged_returns() {
+        let benchmark = [0.01, -0.02, 0.015, 0.005];
+        let portfolio: Vec<f64> = benchmark.iter().map(|r| r * 1.5 + 0.001).collect();
+        assert!((beta(&portfolio, &benchmark).unwrap() - 1.5).abs() < 1e-12);
+        assert!(beta(&[0.01, 0.02], &[0.01, 0.01]).is_none());
+    }
+
+    #[test]
+    fn tracking_error_is_zero_when_returns_match() {
+        let returns = [0.01, -0.02, 0.015];
+        assert!(tracking_error(&returns, &returns).unwrap().abs() < 1e-12);
+
+        // 超额收益为常数时跟踪误差也为 0
+        let shifted: Vec<f64> = returns.iter().map(|r| r + 0.002).collect();
+        assert!(tracking_error(&shifted, &returns).unwrap().abs() < 1e-12);
+    }
+
+    #[test]
+    fn annualize_half_year_return() {
+        let annual = annualize(0.05, 365 / 2).unwrap();
+        assert!((annual - (1.05f64.powf(365.0 / 182.0) - 1.0)).abs() < 1e-12);
+        assert!(annualize(0.05, 0).is_none());
+    }
+
+    #[test]
+    fn return_report_converts_to_base_currency() {
+        use crate::models::FxRate;
+
+        let trade = |code: &str, price: &str| Trade {
+            id: None,
+            stock_code: code.to_string(),
+            stock_name: code.to_string(),
+            buy_price: price.parse().unwrap(),
+            buy_time: "2023-01-01T02:00:00Z".parse().unwrap(),
+            quantity: 100,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        };
+        let fx = FxRates::new(&[
+            FxRate { currency: Currency::Hkd, rate_date: date(2023, 1, 1), rate: 0.9 },
+            FxRate { currency: Currency::Hkd, rate_date: date(2024, 1, 1), rate: 0.99 },
+        ]);
+        let prices = HashMap::from([("hk00700".to_string(), "300".parse().unwrap())]);
+
+        let report = build_return_report(
+            &[trade("hk00700", "300"), trade("gb_aapl", "100")],
+            &prices,
+            &fx,
+            Currency::Cny,
+            "2024-01-01T07:00:00Z".parse().unwrap(),
+        );
+
+        // 港币价格不变，人民币收益全部来自汇率；没有美元汇率的交易不计入
+        assert_eq!(report.missing_fx_rates, vec![Currency::Usd]);
+        assert_eq!(report.overall.invested, "27000".parse().unwrap());
+        assert_eq!(report.overall.market_value, "29700".parse().unwrap());
+        assert!((report.overall.xirr.unwrap() - 0.1).abs() < 1e-9);
+        assert_eq!(report.positions.len(), 1);
+    }
+}
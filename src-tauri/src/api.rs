@@ -1,6 +1,9 @@
 use anyhow::Result;
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use crate::models::StockPriceResponse;
+use crate::money::Money;
 
 pub struct StockApi;
 
@@ -26,15 +29,11 @@ impl StockApi {
                 
                 if parts.len() >= 4 {
                     let name = parts[0].to_string();
-                    let current_price: f64 = parts[3].parse().unwrap_or(0.0);
-                    let prev_close: f64 = parts[2].parse().unwrap_or(0.0);
+                    let current_price: Money = parts[3].parse().unwrap_or_default();
+                    let prev_close: Money = parts[2].parse().unwrap_or_default();
                     
                     let change = current_price - prev_close;
-                    let change_percent = if prev_close > 0.0 {
-                        (change / prev_close) * 100.0
-                    } else {
-                        0.0
-                    };
+                    let change_percent = change.ratio(prev_close) * 100.0;
                     
                     return Ok(StockPriceResponse {
                         code: stock_code.to_string(),
@@ -54,48 +53,419 @@ impl StockApi {
     /// 验证股票代码是否有效
     pub async fn validate_stock_code(stock_code: &str) -> Result<bool> {
         match Self::get_stock_price(stock_code).await {
-            Ok(response) => Ok(response.price > 0.0),
+            Ok(response) => Ok(response.price.is_positive()),
             Err(_) => Ok(false),
         }
     }
 }
 
+/// 卖出目标价按至少持有的天数计算收益
+pub const MIN_HOLDING_DAYS: i64 = 30;
+
+/// 卖出目标价的增长模型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum GrowthModel {
+    /// 单利：1 + r × t
+    #[default]
+    Simple,
+    /// 按日复利：(1 + r ÷ 基准天数) ^ 天数
+    DailyCompound,
+    /// 按年复利：(1 + r) ^ t
+    AnnualCompound,
+    /// 连续复利：e ^ (r × t)
+    Continuous,
+}
+
+/// 计息天数基准，年数 t = 天数 ÷ 基准天数
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum DayCountBasis {
+    #[default]
+    Days360,
+    Days365,
+}
+
+impl DayCountBasis {
+    pub fn days_per_year(&self) -> f64 {
+        match self {
+            DayCountBasis::Days360 => 360.0,
+            DayCountBasis::Days365 => 365.0,
+        }
+    }
+}
+
+impl FromStr for GrowthModel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "simple" => Ok(GrowthModel::Simple),
+            "daily_compound" => Ok(GrowthModel::DailyCompound),
+            "annual_compound" => Ok(GrowthModel::AnnualCompound),
+            "continuous" => Ok(GrowthModel::Continuous),
+            _ => Err(anyhow::anyhow!("未知的增长模型: {}", s)),
+        }
+    }
+}
+
+impl FromStr for DayCountBasis {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "days360" | "360" => Ok(DayCountBasis::Days360),
+            "days365" | "365" => Ok(DayCountBasis::Days365),
+            _ => Err(anyhow::anyhow!("未知的计息天数基准: {}", s)),
+        }
+    }
+}
+
+/// 卖出目标价的计算方式，默认与最初的公式一致（单利、360天）
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(default)]
+pub struct GrowthSettings {
+    pub growth_model: GrowthModel,
+    pub day_count_basis: DayCountBasis,
+}
+
+impl GrowthSettings {
+    /// 从配置项读取，缺失或无法识别的值使用默认值
+    pub fn from_settings(growth_model: Option<&str>, day_count_basis: Option<&str>) -> Self {
+        GrowthSettings {
+            growth_model: growth_model.and_then(|s| s.parse().ok()).unwrap_or_default(),
+            day_count_basis: day_count_basis.and_then(|s| s.parse().ok()).unwrap_or_default(),
+        }
+    }
+
+    /// 按年化收益率持有 days 天后的增长倍数，第 0 天为 1
+    pub fn growth_factor(&self, annual_return_rate: f64, days: f64) -> f64 {
+        let basis = self.day_count_basis.days_per_year();
+        let years = days / basis;
+
+        match self.growth_model {
+            GrowthModel::Simple => 1.0 + annual_return_rate * years,
+            GrowthModel::DailyCompound => (1.0 + annual_return_rate / basis).powf(days),
+            GrowthModel::AnnualCompound => (1.0 + annual_return_rate).powf(years),
+            GrowthModel::Continuous => (annual_return_rate * years).exp(),
+        }
+    }
+}
+
+/// 止损触发的原因
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopKind {
+    /// 跌破固定止损价
+    StopLoss,
+    /// 从买入后的最高价回落超过比例
+    TrailingStop,
+    /// 持有满一定天数仍未盈利
+    TimeStop,
+}
+
+impl StopKind {
+    /// 提醒消息中使用的名称
+    pub fn label(&self) -> &'static str {
+        match self {
+            StopKind::StopLoss => "止损价",
+            StopKind::TrailingStop => "跟踪止损价",
+            StopKind::TimeStop => "时间止损",
+        }
+    }
+}
+
+/// 现价触发的价格信号，JSON 中以 kind 区分，其余字段与 [`SignalTarget`] 相同
+///
+/// 卖出目标优先；其次是止损，止损优先于加仓；再次是买入目标。都未触发时为 none，目标为卖出目标。
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PriceSignal {
+    /// 现价达到卖出目标
+    Sell(SignalTarget),
+    /// 触发止损，目标为止损价（时间止损为买入价）
+    Stop {
+        reason: StopKind,
+        #[serde(flatten)]
+        target: SignalTarget,
+    },
+    /// 现价跌到买入目标
+    Buy(SignalTarget),
+    /// 未触发任何目标
+    #[serde(rename = "none")]
+    Hold(SignalTarget),
+    /// 没有行情，无法判断
+    NoQuote,
+}
+
+impl PriceSignal {
+    /// 提醒类型，与提醒历史中的 alert_type 一致
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PriceSignal::Sell(_) => "sell",
+            PriceSignal::Stop { .. } => "stop",
+            PriceSignal::Buy(_) => "buy",
+            PriceSignal::Hold(_) => "none",
+            PriceSignal::NoQuote => "no_quote",
+        }
+    }
+
+    pub fn target(&self) -> Option<&SignalTarget> {
+        match self {
+            PriceSignal::Sell(target) | PriceSignal::Buy(target) | PriceSignal::Hold(target) => Some(target),
+            PriceSignal::Stop { target, .. } => Some(target),
+            PriceSignal::NoQuote => None,
+        }
+    }
+}
+
+/// 信号对应的目标价、现价与目标价的差距和按目标价成交的盈亏
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignalTarget {
+    pub target_price: Money,
+    /// 目标价 - 现价，正数表示还需上涨
+    pub distance: Money,
+    /// 差距占现价的百分比
+    pub distance_percent: f64,
+    /// 全部数量按目标价成交相对买入价的盈亏，不含费用
+    pub projected_pnl: Money,
+    /// 按设定的收益率，卖出目标价涨过现价还需的天数；只用于卖出信号，目标价不再增长时为空
+    pub days_until_target_passes: Option<i64>,
+}
+
+impl SignalTarget {
+    pub fn new(target_price: Money, current_price: Money, buy_price: Money, quantity: i64) -> Self {
+        let distance = target_price - current_price;
+        SignalTarget {
+            target_price,
+            distance,
+            distance_percent: distance.ratio(current_price) * 100.0,
+            projected_pnl: (target_price - buy_price) * quantity,
+            days_until_target_passes: None,
+        }
+    }
+}
+
+/// 止损规则，为空的项不启用
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StopRules {
+    /// 相对买入价的止损比例，如 0.08 表示跌 8% 止损
+    pub stop_loss_percent: Option<f64>,
+    /// 固定止损价，与比例同时设置时取较高的止损价
+    pub stop_loss_price: Option<Money>,
+    pub time_stop_days: Option<i64>,
+    /// 相对买入后最高价的回落比例
+    pub trailing_stop_percent: Option<f64>,
+}
+
 /// 价格计算工具
 pub struct PriceCalculator;
 
 impl PriceCalculator {
-    /// 计算卖出目标价格
-    /// 公式: 买入价格 × (1 + 年化收益率 ÷ 360) × MAX(持有天数, 30)
+    /// 按指定的增长模型计算卖出目标价格，最短持有天数可调
+    /// 公式: 买入价格 × 增长倍数(年化收益率, MAX(持有天数, 最短持有天数))
+    /// 结果四舍五入到 0.0001 元，按报价单位取整由调用方决定方向
     pub fn calculate_sell_target_price(
-        buy_price: f64,
+        buy_price: Money,
         annual_return_rate: f64,
         days_held: i64,
-    ) -> f64 {
-        let effective_days = days_held.max(30) as f64;
-        buy_price * (1.0 + (annual_return_rate / 360.0) * effective_days)
+        min_holding_days: i64,
+        growth: GrowthSettings,
+    ) -> Money {
+        let effective_days = days_held.max(min_holding_days) as f64;
+        buy_price.mul_f64(growth.growth_factor(annual_return_rate, effective_days))
     }
     
     /// 计算买入目标价格
     /// 公式: 卖出目标价格 × (1 - 买入台阶)
     pub fn calculate_buy_target_price(
-        sell_target_price: f64,
+        sell_target_price: Money,
         buy_step_percentage: f64,
-    ) -> f64 {
-        sell_target_price * (1.0 - buy_step_percentage)
+    ) -> Money {
+        sell_target_price.mul_f64(1.0 - buy_step_percentage)
     }
     
-    /// 判断当前价格是否达到目标
-    pub fn check_price_target(
-        current_price: f64,
-        sell_target: f64,
-        buy_target: f64,
-    ) -> String {
-        if current_price >= sell_target {
-            "sell".to_string()
-        } else if current_price <= buy_target {
-            "buy".to_string()
-        } else {
-            "none".to_string()
+    /// 计算固定止损价
+    /// 公式: MAX(买入价格 × (1 - 止损比例), 固定止损价)
+    pub fn calculate_stop_loss_price(buy_price: Money, rules: &StopRules) -> Option<Money> {
+        let by_percent = rules.stop_loss_percent.map(|p| buy_price.mul_f64(1.0 - p));
+        match (by_percent, rules.stop_loss_price) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// 计算跟踪止损价
+    /// 公式: 买入后最高价 × (1 - 回落比例)
+    pub fn calculate_trailing_stop_price(high_water_price: Money, rules: &StopRules) -> Option<Money> {
+        rules.trailing_stop_percent.map(|p| high_water_price.mul_f64(1.0 - p))
+    }
+
+    /// 判断是否触发止损，返回触发原因和对应的价格（时间止损为买入价）
+    /// 依次检查固定止损、跟踪止损和时间止损
+    pub fn check_stop(
+        buy_price: Money,
+        current_price: Money,
+        days_held: i64,
+        high_water_price: Money,
+        rules: &StopRules,
+    ) -> Option<(StopKind, Money)> {
+        if let Some(stop) = Self::calculate_stop_loss_price(buy_price, rules).filter(|stop| current_price <= *stop) {
+            return Some((StopKind::StopLoss, stop));
+        }
+        if let Some(stop) = Self::calculate_trailing_stop_price(high_water_price, rules).filter(|stop| current_price <= *stop) {
+            return Some((StopKind::TrailingStop, stop));
+        }
+        match rules.time_stop_days {
+            Some(days) if days_held >= days && current_price <= buy_price => Some((StopKind::TimeStop, buy_price)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODELS: [GrowthModel; 4] = [
+        GrowthModel::Simple,
+        GrowthModel::DailyCompound,
+        GrowthModel::AnnualCompound,
+        GrowthModel::Continuous,
+    ];
+    const RATES: [f64; 5] = [0.01, 0.05, 0.2, 0.5, 1.0];
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    fn settings(growth_model: GrowthModel, day_count_basis: DayCountBasis) -> GrowthSettings {
+        GrowthSettings {
+            growth_model,
+            day_count_basis,
+        }
+    }
+
+    #[test]
+    fn default_matches_original_simple_interest_formula() {
+        let target = PriceCalculator::calculate_sell_target_price(money("10"), 0.2, 90, 30, GrowthSettings::default());
+        assert_eq!(target, money("10.5"));
+
+        // 10 × (1 + 0.2 ÷ 360 × 30) = 10.1666…，四舍五入到 0.0001 元
+        let floor = PriceCalculator::calculate_sell_target_price(money("10"), 0.2, 5, 30, GrowthSettings::default());
+        assert_eq!(floor, money("10.1667"));
+    }
+
+    #[test]
+    fn all_models_agree_at_day_zero() {
+        for model in MODELS {
+            for basis in [DayCountBasis::Days360, DayCountBasis::Days365] {
+                for rate in RATES {
+                    let target = PriceCalculator::calculate_sell_target_price(
+                        money("12.34"),
+                        rate,
+                        0,
+                        0,
+                        settings(model, basis),
+                    );
+                    assert_eq!(target, money("12.34"), "{:?} {:?} {}", model, basis, rate);
+                }
+            }
         }
     }
+
+    #[test]
+    fn targets_increase_with_holding_days() {
+        for model in MODELS {
+            for rate in RATES {
+                let growth = settings(model, DayCountBasis::Days365);
+                let mut previous = 1.0;
+                for days in 1..=1000 {
+                    let factor = growth.growth_factor(rate, days as f64);
+                    assert!(factor > previous, "{:?} {} {}", model, rate, days);
+                    previous = factor;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compounding_models_diverge_as_expected() {
+        for basis in [DayCountBasis::Days360, DayCountBasis::Days365] {
+            let year = basis.days_per_year();
+            for rate in RATES {
+                for days in [1.0, 30.0, 90.0, year, 2.0 * year, 5.0 * year] {
+                    let factor = |model| settings(model, basis).growth_factor(rate, days);
+                    let simple = factor(GrowthModel::Simple);
+                    let daily = factor(GrowthModel::DailyCompound);
+                    let annual = factor(GrowthModel::AnnualCompound);
+                    let continuous = factor(GrowthModel::Continuous);
+
+                    // 连续复利最高，按日复利次之，且不低于单利
+                    assert!(continuous >= daily - 1e-12);
+                    assert!(daily >= simple - 1e-12);
+
+                    // 按年复利不足一年时低于单利，满一年时相等，超过一年后高于单利
+                    if days < year {
+                        assert!(annual < simple);
+                    } else if days == year {
+                        assert!((annual - simple).abs() < 1e-12);
+                    } else {
+                        assert!(annual > simple);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn longer_day_count_basis_gives_lower_target() {
+        for model in MODELS {
+            let short = settings(model, DayCountBasis::Days360).growth_factor(0.2, 180.0);
+            let long = settings(model, DayCountBasis::Days365).growth_factor(0.2, 180.0);
+            assert!(short > long, "{:?}", model);
+        }
+    }
+
+    #[test]
+    fn stop_loss_uses_higher_of_percent_and_price() {
+        let rules = StopRules {
+            stop_loss_percent: Some(0.1),
+            stop_loss_price: Some(money("9.5")),
+            ..StopRules::default()
+        };
+        assert_eq!(PriceCalculator::calculate_stop_loss_price(money("10"), &rules), Some(money("9.5")));
+        assert_eq!(PriceCalculator::calculate_stop_loss_price(money("10"), &StopRules::default()), None);
+    }
+
+    #[test]
+    fn stops_trigger_in_order() {
+        let rules = StopRules {
+            stop_loss_percent: Some(0.1),
+            trailing_stop_percent: Some(0.08),
+            time_stop_days: Some(60),
+            ..StopRules::default()
+        };
+
+        let check = |current: &str, days: i64, high: &str| {
+            PriceCalculator::check_stop(money("10"), money(current), days, money(high), &rules)
+        };
+
+        assert_eq!(check("8.9", 5, "10"), Some((StopKind::StopLoss, money("9"))));
+        assert_eq!(check("10.9", 5, "12"), Some((StopKind::TrailingStop, money("11.04"))));
+        assert_eq!(check("9.8", 60, "10"), Some((StopKind::TimeStop, money("10"))));
+        assert_eq!(check("9.8", 59, "10"), None);
+        assert_eq!(check("10.2", 90, "10.5"), None);
+    }
+
+    #[test]
+    fn settings_fall_back_to_defaults() {
+        let growth = GrowthSettings::from_settings(Some("continuous"), Some("365"));
+        assert_eq!(growth, settings(GrowthModel::Continuous, DayCountBasis::Days365));
+        assert_eq!(GrowthSettings::from_settings(Some("bogus"), None), GrowthSettings::default());
+    }
 }
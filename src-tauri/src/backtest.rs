@@ -0,0 +1,537 @@
+use anyhow::{anyhow, Result};
+use chrono::{FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+use crate::analytics;
+use crate::api::{GrowthSettings, PriceCalculator, MIN_HOLDING_DAYS};
+use crate::csv_import::{parse_datetime_auto, parse_number, NumberFormat, LOT_SIZE};
+use crate::error::{AppError, FieldError};
+use crate::fx::{Currency, FxRates};
+use crate::models::{DailyBar, TradeSide};
+use crate::money::{Money, Rounding};
+use crate::security::{self, SecurityId};
+
+/// 日线 CSV 各列自动匹配的表头，不区分大小写
+const DATE_HEADERS: &[&str] = &["date", "day", "日期", "交易日期"];
+const OPEN_HEADERS: &[&str] = &["open", "开盘", "开盘价"];
+const HIGH_HEADERS: &[&str] = &["high", "最高", "最高价"];
+const LOW_HEADERS: &[&str] = &["low", "最低", "最低价"];
+const CLOSE_HEADERS: &[&str] = &["close", "收盘", "收盘价"];
+const VOLUME_HEADERS: &[&str] = &["volume", "vol", "成交量"];
+
+/// A股交易费用，默认按万 2.5 佣金（最低 5 元）、卖出千 0.5 印花税、十万分之一过户费计算
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct FeeModel {
+    pub commission_rate: f64,
+    pub min_commission: Money,
+    pub stamp_tax_rate: f64,
+    pub transfer_fee_rate: f64,
+}
+
+impl Default for FeeModel {
+    fn default() -> Self {
+        FeeModel {
+            commission_rate: 0.00025,
+            min_commission: Money::from_yuan(5),
+            stamp_tax_rate: 0.0005,
+            transfer_fee_rate: 0.00001,
+        }
+    }
+}
+
+impl FeeModel {
+    /// 计算一笔成交的费用合计，四舍五入到分
+    pub fn fees(&self, amount: Money, is_sell: bool) -> Money {
+        let commission = amount.mul_f64(self.commission_rate).max(self.min_commission);
+        let stamp_tax = if is_sell { amount.mul_f64(self.stamp_tax_rate) } else { Money::ZERO };
+        let transfer_fee = amount.mul_f64(self.transfer_fee_rate);
+        (commission + stamp_tax + transfer_fee).round_to(Money::FEN, Rounding::HalfUp)
+    }
+
+    /// 资金允许的最大买入数量，返回 (数量, 成交金额, 费用)，一手也买不起时返回空
+    ///
+    /// 从 wanted 开始按买入数量规则向下调整；amount 把数量换算为人民币成交金额。
+    pub fn affordable_quantity(
+        &self,
+        wanted: i64,
+        rule: (i64, i64),
+        cash: Money,
+        amount: impl Fn(i64) -> Money,
+    ) -> Option<(i64, Money, Money)> {
+        // 先按每股金额估算上限，避免逐个递增单位尝试
+        let upper = cash.units_of(amount(1)).saturating_add(1);
+        let mut quantity = security::round_buy_quantity(wanted.min(upper), rule);
+        while quantity > 0 {
+            let value = amount(quantity);
+            let fees = self.fees(value, false);
+            if value + fees <= cash {
+                return Some((quantity, value, fees));
+            }
+            quantity = security::round_buy_quantity(quantity - rule.1, rule);
+        }
+        None
+    }
+}
+
+/// 回测标的的交易规则：计价货币、最小报价单位和买入数量规则
+#[derive(Debug, Clone, Copy)]
+pub struct Instrument {
+    pub currency: Currency,
+    pub tick: Money,
+    pub quantity_rule: (i64, i64),
+}
+
+impl Default for Instrument {
+    /// 无法识别代码时按人民币计价的 A 股主板规则
+    fn default() -> Self {
+        Instrument {
+            currency: Currency::Cny,
+            tick: Money::FEN,
+            quantity_rule: (LOT_SIZE as i64, LOT_SIZE as i64),
+        }
+    }
+}
+
+impl Instrument {
+    pub fn for_code(stock_code: &str) -> Self {
+        SecurityId::parse(stock_code)
+            .map(|id| Instrument {
+                currency: id.currency(),
+                tick: id.tick_size(),
+                quantity_rule: id.buy_quantity_rule(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BacktestConfig {
+    pub annual_return_rate: f64,
+    pub buy_step_percentage: f64,
+    /// 卖出目标价按至少持有的天数计算收益
+    pub min_holding_days: i64,
+    /// 卖出目标价的增长模型和计息天数基准
+    pub growth: GrowthSettings,
+    pub initial_cash: Money,
+    /// 每次买入的手数，资金不足时按可买的最大手数成交
+    pub lots_per_trade: i64,
+    pub fees: FeeModel,
+}
+
+impl BacktestConfig {
+    /// 检查资金、手数和买入台阶，字段名与序列化后的一致
+    pub fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if !self.initial_cash.is_positive() {
+            errors.push(FieldError::new("initial_cash", "初始资金必须大于0"));
+        }
+        if self.lots_per_trade <= 0 {
+            errors.push(FieldError::new("lots_per_trade", "每次买入手数必须大于0"));
+        }
+        if !(0.0..1.0).contains(&self.buy_step_percentage) {
+            errors.push(FieldError::new("buy_step_percentage", "买入台阶必须在0到1之间"));
+        }
+        errors
+    }
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig {
+            annual_return_rate: 0.20,
+            buy_step_percentage: 0.05,
+            min_holding_days: MIN_HOLDING_DAYS,
+            growth: GrowthSettings::default(),
+            initial_cash: Money::from_yuan(100_000),
+            lots_per_trade: 10,
+            fees: FeeModel::default(),
+        }
+    }
+}
+
+/// 回测中的一笔成交，卖出时记录该笔持仓扣除买卖费用后的盈亏
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BacktestTrade {
+    pub date: NaiveDate,
+    pub side: TradeSide,
+    pub price: Money,
+    pub quantity: i64,
+    pub fees: Money,
+    pub pnl: Option<Money>,
+    pub holding_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BacktestEquityPoint {
+    pub date: NaiveDate,
+    pub equity: f64,
+}
+
+/// 回测结果，收益率和回撤均为小数
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BacktestReport {
+    /// 日线价格和成交价的计价货币，资金、费用、盈亏和权益均为人民币
+    pub currency: Currency,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub initial_cash: Money,
+    pub final_equity: Money,
+    pub total_return: f64,
+    pub annualized_return: Option<f64>,
+    /// 买入和卖出的成交笔数合计
+    pub trade_count: usize,
+    /// 已平仓的持仓笔数
+    pub round_trips: usize,
+    pub win_rate: Option<f64>,
+    pub total_fees: Money,
+    pub max_drawdown: f64,
+    /// 回测结束时仍持有的股数，按最后一天收盘价计入权益
+    pub open_quantity: i64,
+    pub trades: Vec<BacktestTrade>,
+    pub equity_curve: Vec<BacktestEquityPoint>,
+}
+
+/// 一笔未卖出的买入，与交易记录一样独立计算卖出目标价
+struct Lot {
+    buy_date: NaiveDate,
+    buy_price: Money,
+    quantity: i64,
+    /// 按买入日汇率折算的人民币成本
+    cost: Money,
+    buy_fees: Money,
+}
+
+/// 逐日回放日线，模拟卖出目标价 / 买入台阶策略
+///
+/// 每笔买入单独计算卖出目标价，当日最高价达到目标即按目标价卖出（跳空高开按开盘价），买入当日不能卖出；
+/// 最近一笔买入的买入目标价被当日最低价触及时加仓（跳空低开按开盘价）；
+/// 空仓时按当日收盘价建仓，每天最多加仓一次。日线价格换算为 [`Money`] 后参与计算，权益曲线仍为浮点数。
+/// 卖出目标价向上、买入目标价向下取整到报价单位，买入数量按标的的数量规则确定。
+/// 资金为人民币，外币计价的成交金额和持仓市值按当天汇率折算，费用按折算后的金额计算。
+pub fn run_backtest(
+    bars: &[DailyBar],
+    instrument: &Instrument,
+    fx: &FxRates,
+    config: &BacktestConfig,
+) -> Result<BacktestReport> {
+    if bars.is_empty() {
+        return Err(AppError::Validation("没有可用的日线数据".to_string()).into());
+    }
+    let errors = config.validate();
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors).into());
+    }
+
+    let mut bars = bars.to_vec();
+    bars.sort_by_key(|bar| bar.date);
+
+    let mut cash = config.initial_cash;
+    let mut lots: Vec<Lot> = Vec::new();
+    let mut trades = Vec::new();
+    let mut equity_curve = Vec::with_capacity(bars.len());
+    let mut total_fees = Money::ZERO;
+    let mut final_equity = config.initial_cash;
+
+    let currency = instrument.currency;
+    for bar in &bars {
+        let rate = fx
+            .cny_rate(currency, bar.date)
+            .ok_or_else(|| AppError::Validation(format!("缺少 {} 汇率，无法回测", currency.code())))?;
+        let (open, high, low, close) = (bar.open, bar.high, bar.low, bar.close);
+        let mut remaining = Vec::with_capacity(lots.len());
+        for lot in lots.drain(..) {
+            let days_held = (bar.date - lot.buy_date).num_days();
+            let sell_target = PriceCalculator::calculate_sell_target_price(
+                lot.buy_price,
+                config.annual_return_rate,
+                days_held,
+                config.min_holding_days,
+                config.growth,
+            )
+            .round_to(instrument.tick, Rounding::Up);
+
+            if days_held > 0 && high >= sell_target {
+                let price = sell_target.max(open);
+                let amount = (price * lot.quantity).mul_f64(rate);
+                let fees = config.fees.fees(amount, true);
+
+                cash += amount - fees;
+                total_fees += fees;
+                trades.push(BacktestTrade {
+                    date: bar.date,
+                    side: TradeSide::Sell,
+                    price,
+                    quantity: lot.quantity,
+                    fees,
+                    pnl: Some(amount - fees - lot.cost - lot.buy_fees),
+                    holding_days: Some(days_held),
+                });
+            } else {
+                remaining.push(lot);
+            }
+        }
+        lots = remaining;
+
+        let entry_price = match lots.last() {
+            None => Some(close),
+            Some(reference) => {
+                let days_held = (bar.date - reference.buy_date).num_days();
+                let sell_target = PriceCalculator::calculate_sell_target_price(
+                    reference.buy_price,
+                    config.annual_return_rate,
+                    days_held,
+                    config.min_holding_days,
+                    config.growth,
+                );
+                // 与实时提醒一致，买入目标价由未取整的卖出目标价计算
+                let buy_target = PriceCalculator::calculate_buy_target_price(sell_target, config.buy_step_percentage)
+                    .round_to(instrument.tick, Rounding::Down);
+
+                (days_held > 0 && low <= buy_target).then(|| buy_target.min(open))
+            }
+        };
+
+        if let Some(price) = entry_price {
+            let wanted = (config.lots_per_trade * LOT_SIZE as i64).max(instrument.quantity_rule.0);
+            let bought = config
+                .fees
+                .affordable_quantity(wanted, instrument.quantity_rule, cash, |quantity| (price * quantity).mul_f64(rate));
+            if let Some((quantity, amount, fees)) = bought {
+                cash -= amount + fees;
+                total_fees += fees;
+                lots.push(Lot {
+                    buy_date: bar.date,
+                    buy_price: price,
+                    quantity,
+                    cost: amount,
+                    buy_fees: fees,
+                });
+                trades.push(BacktestTrade {
+                    date: bar.date,
+                    side: TradeSide::Buy,
+                    price,
+                    quantity,
+                    fees,
+                    pnl: None,
+                    holding_days: None,
+                });
+            }
+        }
+
+        let shares: i64 = lots.iter().map(|lot| lot.quantity).sum();
+        final_equity = cash + (close * shares).mul_f64(rate);
+        equity_curve.push(BacktestEquityPoint {
+            date: bar.date,
+            equity: final_equity.to_f64(),
+        });
+    }
+
+    let start_date = bars[0].date;
+    let end_date = bars[bars.len() - 1].date;
+    let total_return = final_equity.ratio(config.initial_cash) - 1.0;
+
+    let closed: Vec<Money> = trades.iter().filter_map(|t| t.pnl).collect();
+    let wins = closed.iter().filter(|pnl| pnl.is_positive()).count();
+    let equity: Vec<f64> = equity_curve.iter().map(|p| p.equity).collect();
+
+    Ok(BacktestReport {
+        currency,
+        start_date,
+        end_date,
+        initial_cash: config.initial_cash,
+        final_equity,
+        total_return,
+        annualized_return: analytics::annualize(total_return, (end_date - start_date).num_days()),
+        trade_count: trades.len(),
+        round_trips: closed.len(),
+        win_rate: (!closed.is_empty()).then(|| wins as f64 / closed.len() as f64),
+        total_fees,
+        max_drawdown: analytics::max_drawdown(&equity).0,
+        open_quantity: lots.iter().map(|lot| lot.quantity).sum(),
+        trades,
+        equity_curve,
+    })
+}
+
+/// 从 CSV 读取日线，表头按常见的中英文名称自动识别，成交量列可省略
+pub fn load_bars_csv(content: &str) -> Result<Vec<DailyBar>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+
+    let headers = reader.headers()?.clone();
+    let find = |aliases: &[&str]| {
+        headers
+            .iter()
+            .position(|h| aliases.iter().any(|a| a.eq_ignore_ascii_case(h)))
+    };
+
+    let date_col = find(DATE_HEADERS).ok_or_else(|| anyhow!("缺少日期列"))?;
+    let open_col = find(OPEN_HEADERS).ok_or_else(|| anyhow!("缺少开盘价列"))?;
+    let high_col = find(HIGH_HEADERS).ok_or_else(|| anyhow!("缺少最高价列"))?;
+    let low_col = find(LOW_HEADERS).ok_or_else(|| anyhow!("缺少最低价列"))?;
+    let close_col = find(CLOSE_HEADERS).ok_or_else(|| anyhow!("缺少收盘价列"))?;
+    let volume_col = find(VOLUME_HEADERS);
+
+    let beijing = FixedOffset::east_opt(8 * 3600).unwrap();
+    let mut bars = Vec::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = index + 2;
+        let field = |col: usize| record.get(col).unwrap_or("");
+        let price = |col: usize, name: &str| {
+            parse_number(field(col), NumberFormat::Standard)
+                .and_then(|value| Money::checked_from_f64(value).ok())
+                .ok_or_else(|| anyhow!("第{}行{}无效: {}", line, name, field(col)))
+        };
+
+        let date = parse_datetime_auto(field(date_col))
+            .map(|t| t.with_timezone(&beijing).date_naive())
+            .ok_or_else(|| anyhow!("第{}行日期无效: {}", line, field(date_col)))?;
+
+        bars.push(DailyBar {
+            date,
+            open: price(open_col, "开盘价")?,
+            high: price(high_col, "最高价")?,
+            low: price(low_col, "最低价")?,
+            close: price(close_col, "收盘价")?,
+            volume: volume_col
+                .and_then(|col| parse_number(field(col), NumberFormat::Standard))
+                .unwrap_or(0.0) as i64,
+        });
+    }
+
+    bars.sort_by_key(|bar| bar.date);
+    bars.dedup_by_key(|bar| bar.date);
+    Ok(bars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FxRate;
+
+    fn bar(day: u32, open: f64, high: f64, low: f64, close: f64) -> DailyBar {
+        DailyBar {
+            date: NaiveDate::from_ymd_opt(2024, 3, day).unwrap(),
+            open: Money::from_f64(open),
+            high: Money::from_f64(high),
+            low: Money::from_f64(low),
+            close: Money::from_f64(close),
+            volume: 0,
+        }
+    }
+
+    fn config() -> BacktestConfig {
+        BacktestConfig {
+            lots_per_trade: 10,
+            ..BacktestConfig::default()
+        }
+    }
+
+    #[test]
+    fn fee_model_applies_minimum_commission_and_stamp_tax() {
+        let fees = FeeModel::default();
+        let money = |value: &str| value.parse::<Money>().unwrap();
+        assert_eq!(fees.fees(Money::from_yuan(10_000), false), money("5.10"));
+        assert_eq!(fees.fees(Money::from_yuan(10_000), true), money("10.10"));
+        assert_eq!(fees.fees(Money::from_yuan(100_000), true), money("76"));
+    }
+
+    #[test]
+    fn sells_at_target_and_reenters_at_close() {
+        // 目标价 = 10 × (1 + 0.2 / 360 × 30) ≈ 10.1667，向上取整到 10.17
+        let bars = [
+            bar(1, 10.0, 10.0, 10.0, 10.0),
+            bar(4, 10.0, 10.1, 9.9, 10.0),
+            bar(5, 10.0, 10.3, 10.0, 10.2),
+        ];
+        let report = run_backtest(&bars, &Instrument::default(), &FxRates::default(), &config()).unwrap();
+
+        let sells: Vec<_> = report.trades.iter().filter(|t| t.side == TradeSide::Sell).collect();
+        assert_eq!(sells.len(), 1);
+        assert_eq!(sells[0].price, "10.17".parse().unwrap());
+        assert!(sells[0].pnl.unwrap().is_positive());
+        assert_eq!(report.round_trips, 1);
+        assert_eq!(report.win_rate, Some(1.0));
+        // 卖出当天按收盘价重新建仓
+        assert_eq!(report.open_quantity, 1000);
+        assert_eq!(report.trades.last().unwrap().price, "10.2".parse().unwrap());
+    }
+
+    #[test]
+    fn adds_lot_when_price_falls_to_buy_target() {
+        let bars = [
+            bar(1, 10.0, 10.0, 10.0, 10.0),
+            bar(4, 9.8, 9.8, 9.5, 9.6),
+        ];
+        let report = run_backtest(&bars, &Instrument::default(), &FxRates::default(), &config()).unwrap();
+
+        // 买入目标 = 10.1667 × 0.95 ≈ 9.6584，向下取整到 9.65
+        assert_eq!(report.trades.len(), 2);
+        assert_eq!(report.trades[1].price, "9.65".parse().unwrap());
+        assert_eq!(report.open_quantity, 2000);
+        assert!(report.max_drawdown > 0.0);
+    }
+
+    #[test]
+    fn sizes_buys_with_the_quantity_rule() {
+        let bars = [bar(1, 10.0, 10.0, 10.0, 10.0)];
+        let config = BacktestConfig {
+            initial_cash: Money::from_yuan(2_800),
+            lots_per_trade: 3,
+            ..BacktestConfig::default()
+        };
+
+        // 科创板 200 股起按 1 股递增，资金不足 300 股时买入可负担的最大股数
+        let report = run_backtest(&bars, &Instrument::for_code("688981"), &FxRates::default(), &config).unwrap();
+        assert_eq!(report.trades[0].quantity, 279);
+
+        let report = run_backtest(&bars, &Instrument::for_code("600000"), &FxRates::default(), &config).unwrap();
+        assert_eq!(report.trades[0].quantity, 200);
+    }
+
+    #[test]
+    fn converts_foreign_bars_at_daily_rates() {
+        let bars = [
+            bar(1, 100.0, 100.0, 100.0, 100.0),
+            bar(4, 100.0, 101.0, 100.0, 101.0),
+        ];
+        let config = BacktestConfig {
+            lots_per_trade: 1,
+            fees: FeeModel { min_commission: Money::ZERO, ..FeeModel::default() },
+            ..BacktestConfig::default()
+        };
+        let rate = |day: u32, rate: f64| FxRate {
+            currency: Currency::Hkd,
+            rate_date: NaiveDate::from_ymd_opt(2024, 3, day).unwrap(),
+            rate,
+        };
+        let fx = FxRates::new(&[rate(1, 0.9), rate(4, 0.92)]);
+        let hkd = Instrument { currency: Currency::Hkd, ..Instrument::default() };
+        let report = run_backtest(&bars, &hkd, &fx, &config).unwrap();
+
+        // 成交价仍为港币，买入 10000 港币按 0.9 折合 9000 元，持仓市值按当天 0.92 折算
+        assert_eq!(report.currency, Currency::Hkd);
+        assert_eq!(report.trades[0].price, Money::from_yuan(100));
+        let buy_fees = report.trades[0].fees;
+        assert_eq!(buy_fees, config.fees.fees(Money::from_yuan(9_000), false));
+        assert_eq!(report.final_equity, config.initial_cash - Money::from_yuan(9_000) - buy_fees + Money::from_yuan(9_292));
+
+        let usd = Instrument { currency: Currency::Usd, ..Instrument::default() };
+        assert!(run_backtest(&bars, &usd, &fx, &config).is_err());
+    }
+
+    #[test]
+    fn loads_bars_from_chinese_csv() {
+        let content = "\u{feff}日期,开盘,最高,最低,收盘,成交量\n2024-03-05,10.1,10.5,10.0,10.4,12000\n2024/03/04,10,10.2,9.9,10.1,1000\n";
+        let bars = load_bars_csv(content).unwrap();
+        assert_eq!(bars.len(), 2);
+        assert_eq!(bars[0].date, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap());
+        assert_eq!(bars[1].close, "10.4".parse().unwrap());
+        assert!(load_bars_csv("date,open,high,low\n2024-01-01,1,1,1\n").is_err());
+    }
+}
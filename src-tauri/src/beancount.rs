@@ -0,0 +1,319 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use crate::broker_import::is_same_fill;
+use crate::fx::Currency;
+use crate::models::{BrokerTransaction, FxRate, Trade, TransactionKind};
+use crate::money::Money;
+use crate::stock_api::StockApi;
+
+/// 交割单来自 A 股券商，资金流水都以人民币结算
+const STATEMENT_CURRENCY: Currency = Currency::Cny;
+const FEES_ACCOUNT: &str = "Expenses:Fees:Trading";
+const OTHER_FEES_ACCOUNT: &str = "Expenses:Fees:Other";
+const DIVIDEND_TAX_ACCOUNT: &str = "Expenses:Taxes:Dividend";
+
+/// price 指令使用的价格，来自实时行情或保存的日线收盘价，不使用可能是模拟数据的行情缓存
+#[derive(Debug, Clone)]
+pub struct PricePoint {
+    pub stock_code: String,
+    pub date: NaiveDate,
+    pub price: Money,
+}
+
+/// 生成 Beancount 账本
+///
+/// 交易记录生成带成本的买入分录，按交易的计价货币记账，手续费取自匹配的交割单买入流水，以人民币记账；
+/// 交割单中的红利和费用单独生成分录，资金记入对应券商的现金账户；
+/// prices 生成 price 指令，同一股票同一天只保留最后一个价格，fx_rates 为账本中的外币生成兑人民币的 price 指令。
+/// 交割单中的买卖流水以交易记录为准，不重复生成。
+pub fn render_journal(
+    trades: &[Trade],
+    broker_transactions: &[BrokerTransaction],
+    prices: &[PricePoint],
+    fx_rates: &[FxRate],
+    exported_at: DateTime<Utc>,
+) -> String {
+    // 商品名对应首次买入日期、名称和计价货币
+    let mut commodities: BTreeMap<String, (NaiveDate, String, Currency)> = BTreeMap::new();
+    // 账户对应允许持有的商品或货币，同一现金账户可能有多种货币
+    let mut accounts: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let mut entries: Vec<(NaiveDate, String)> = Vec::new();
+
+    let mut sorted_trades: Vec<&Trade> = trades.iter().collect();
+    sorted_trades.sort_by_key(|t| t.buy_time);
+
+    for trade in sorted_trades {
+        let date = local_date(trade.buy_time);
+        let currency = trade.currency().code();
+        let commodity = commodity_name(&trade.stock_code);
+        commodities
+            .entry(commodity.clone())
+            .or_insert_with(|| (date, trade.stock_name.clone(), trade.currency()));
+
+        let base = format!("Assets:Broker:{}", account_component(&trade.account));
+        let holding_account = format!("{}:{}", base, commodity);
+        let cash_account = format!("{}:Cash", base);
+        accounts.entry(holding_account.clone()).or_default().insert(commodity.clone());
+        accounts.entry(cash_account.clone()).or_default().insert(currency.to_string());
+
+        let fees = broker_transactions
+            .iter()
+            .find(|t| is_same_fill(trade, t))
+            .map(|t| t.fees)
+            .unwrap_or(Money::ZERO);
+        let cost = trade.buy_price * trade.quantity as i64;
+
+        let mut entry = format!(
+            "{} * \"{}\" \"买入 {} {}股\"\n",
+            date,
+            escape(&trade.stock_name),
+            trade.stock_code,
+            trade.quantity
+        );
+        if let Some(id) = trade.id {
+            entry.push_str(&format!("  trade_id: {}\n", id));
+        }
+        entry.push_str(&format!(
+            "  {}  {} {} {{{} {}}}\n",
+            holding_account,
+            trade.quantity,
+            commodity,
+            trade.buy_price,
+            currency
+        ));
+        // 手续费来自交割单，按人民币记账；外币交易的现金分两笔扣减，每种货币各自平衡
+        let fee_currency = STATEMENT_CURRENCY.code();
+        if fees.is_positive() {
+            accounts.entry(FEES_ACCOUNT.to_string()).or_default().insert(fee_currency.to_string());
+            entry.push_str(&format!("  {}  {} {}\n", FEES_ACCOUNT, fees, fee_currency));
+        }
+        if trade.currency() == STATEMENT_CURRENCY {
+            entry.push_str(&format!("  {}  {} {}\n", cash_account, -(cost + fees), currency));
+        } else {
+            entry.push_str(&format!("  {}  {} {}\n", cash_account, -cost, currency));
+            if fees.is_positive() {
+                accounts.entry(cash_account.clone()).or_default().insert(fee_currency.to_string());
+                entry.push_str(&format!("  {}  {} {}\n", cash_account, -fees, fee_currency));
+            }
+        }
+
+        entries.push((date, entry));
+    }
+
+    for transaction in broker_transactions {
+        if transaction.net_amount.is_zero() {
+            continue;
+        }
+
+        let other_account = match transaction.kind {
+            TransactionKind::Dividend => {
+                let suffix = transaction
+                    .stock_code
+                    .as_deref()
+                    .map(commodity_name)
+                    .unwrap_or_else(|| "Cash".to_string());
+                format!("Income:Dividends:{}", suffix)
+            }
+            TransactionKind::Fee if transaction.operation.contains('税') => DIVIDEND_TAX_ACCOUNT.to_string(),
+            TransactionKind::Fee => OTHER_FEES_ACCOUNT.to_string(),
+            _ => continue,
+        };
+
+        let date = local_date(transaction.trade_time);
+        let cash_account = format!("Assets:Broker:{:?}:Cash", transaction.broker);
+        let currency = STATEMENT_CURRENCY.code();
+        accounts.entry(cash_account.clone()).or_default().insert(currency.to_string());
+        accounts.entry(other_account.clone()).or_default().insert(currency.to_string());
+
+        let payee = transaction
+            .stock_name
+            .clone()
+            .unwrap_or_else(|| format!("{:?}", transaction.broker));
+        let entry = format!(
+            "{} * \"{}\" \"{}\"\n  {}  {} {}\n  {}  {} {}\n",
+            date,
+            escape(&payee),
+            escape(&transaction.operation),
+            cash_account,
+            transaction.net_amount,
+            currency,
+            other_account,
+            -transaction.net_amount,
+            currency
+        );
+        entries.push((date, entry));
+    }
+
+    entries.sort_by_key(|(date, _)| *date);
+
+    let mut output = String::new();
+    let mut currencies: BTreeSet<Currency> = commodities.values().map(|(_, _, currency)| *currency).collect();
+    currencies.insert(STATEMENT_CURRENCY);
+
+    output.push_str(&format!("; 由 stock-trader 导出于 {}\n", exported_at.to_rfc3339()));
+    output.push_str("option \"title\" \"股票交易记录\"\n");
+    for currency in &currencies {
+        output.push_str(&format!("option \"operating_currency\" \"{}\"\n", currency.code()));
+    }
+    output.push('\n');
+
+    for (commodity, (date, name, _)) in &commodities {
+        output.push_str(&format!("{} commodity {}\n  name: \"{}\"\n", date, commodity, escape(name)));
+    }
+    if !commodities.is_empty() {
+        output.push('\n');
+    }
+
+    // 所有账户在最早的分录日期开立
+    if let Some(open_date) = entries.first().map(|(date, _)| *date) {
+        for (account, currencies) in &accounts {
+            let currencies: Vec<&str> = currencies.iter().map(String::as_str).collect();
+            output.push_str(&format!("{} open {} {}\n", open_date, account, currencies.join(",")));
+        }
+        output.push('\n');
+    }
+
+    for (_, entry) in &entries {
+        output.push_str(entry);
+        output.push('\n');
+    }
+
+    // 只为账本中出现的商品生成价格，按日期和商品排序
+    let mut price_lines: BTreeMap<(NaiveDate, String), Money> = BTreeMap::new();
+    for point in prices.iter().filter(|p| p.price.is_positive()) {
+        let commodity = commodity_name(&point.stock_code);
+        if commodities.contains_key(&commodity) {
+            price_lines.insert((point.date, commodity), point.price);
+        }
+    }
+    for ((date, commodity), price) in &price_lines {
+        let currency = commodities[commodity].2;
+        output.push_str(&format!("{} price {} {} {}\n", date, commodity, price, currency.code()));
+    }
+
+    // 账本中出现的外币按日期生成兑人民币汇率，同一货币同一天只保留最后一个
+    let mut fx_lines: BTreeMap<(NaiveDate, Currency), f64> = BTreeMap::new();
+    for rate in fx_rates.iter().filter(|r| r.rate > 0.0) {
+        if rate.currency != Currency::Cny && currencies.contains(&rate.currency) {
+            fx_lines.insert((rate.rate_date, rate.currency), rate.rate);
+        }
+    }
+    for ((date, currency), rate) in &fx_lines {
+        output.push_str(&format!("{} price {} {} {}\n", date, currency.code(), rate, Currency::Cny.code()));
+    }
+
+    output
+}
+
+/// Beancount 商品名必须以字母开头，使用带交易所前缀的代码，如 SZ000001，无法识别的代码加 X 前缀
+fn commodity_name(stock_code: &str) -> String {
+    StockApi::sina_symbol(stock_code)
+        .unwrap_or_else(|_| format!("x{}", stock_code))
+        .to_uppercase()
+}
+
+/// 账户名只能包含字母、数字和连字符，且以大写字母开头
+fn account_component(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    let mut chars = cleaned.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() => first.to_ascii_uppercase().to_string() + chars.as_str(),
+        Some(_) => format!("A{}", cleaned),
+        None => "Default".to_string(),
+    }
+}
+
+fn local_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Broker;
+
+    fn trade(id: i64, account: &str, code: &str, name: &str, price: &str, time: &str, quantity: i32) -> Trade {
+        Trade {
+            id: Some(id),
+            stock_code: code.to_string(),
+            stock_name: name.to_string(),
+            buy_price: price.parse().unwrap(),
+            buy_time: time.parse().unwrap(),
+            quantity,
+            notes: None,
+            account: account.to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        }
+    }
+
+    fn transaction(kind: TransactionKind, time: &str, code: &str, operation: &str, quantity: i64, net: &str, fees: &str) -> BrokerTransaction {
+        BrokerTransaction {
+            id: None,
+            broker: Broker::Huatai,
+            kind,
+            trade_time: time.parse().unwrap(),
+            stock_code: Some(code.to_string()),
+            stock_name: Some("浦发银行".to_string()),
+            operation: operation.to_string(),
+            price: Money::ZERO,
+            quantity,
+            amount: Money::ZERO,
+            net_amount: net.parse().unwrap(),
+            fees: fees.parse().unwrap(),
+            contract_no: None,
+        }
+    }
+
+    fn price(code: &str, date: (i32, u32, u32), price: &str) -> PricePoint {
+        PricePoint {
+            stock_code: code.to_string(),
+            date: NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap(),
+            price: price.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn renders_golden_journal() {
+        let trades = [
+            trade(2, "hk", "hk00700", "腾讯控股", "320.2", "2024-01-03T02:00:00Z", 100),
+            trade(1, "main", "600000", "浦发\"银行\"", "10.5", "2024-01-02T02:00:00Z", 1000),
+        ];
+        let transactions = [
+            // 与 1 号交易是同一笔成交，只提供手续费
+            transaction(TransactionKind::Buy, "2024-01-02T03:00:00Z", "600000", "证券买入", 1000, "-10505", "5"),
+            // 港股通买入的手续费以人民币结算
+            transaction(TransactionKind::Buy, "2024-01-03T03:00:00Z", "hk00700", "证券买入", 100, "-29441.8", "12.3"),
+            transaction(TransactionKind::Dividend, "2024-06-20T01:00:00Z", "600000", "红利入账", 0, "410", "0"),
+            transaction(TransactionKind::Fee, "2024-07-01T01:00:00Z", "600000", "股息红利税补缴", 0, "-41", "0"),
+            transaction(TransactionKind::Subscription, "2024-07-02T01:00:00Z", "600000", "新股申购", 1000, "0", "0"),
+        ];
+        let prices = [
+            price("600000", (2024, 1, 2), "10.6"),
+            price("hk00700", (2024, 1, 3), "321.4"),
+            // 同一天的实时行情覆盖日线收盘价
+            price("600000", (2024, 1, 2), "10.62"),
+            // 账本中没有的股票不生成价格
+            price("000001", (2024, 1, 2), "9.1"),
+        ];
+
+        let fx_rates = [
+            FxRate { currency: Currency::Hkd, rate_date: NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(), rate: 0.9091 },
+            FxRate { currency: Currency::Hkd, rate_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(), rate: 0.9088 },
+            // 账本中没有美元，不生成汇率
+            FxRate { currency: Currency::Usd, rate_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(), rate: 7.1 },
+        ];
+
+        let journal = render_journal(&trades, &transactions, &prices, &fx_rates, "2024-08-01T00:00:00Z".parse().unwrap());
+        assert_eq!(journal, include_str!("../tests/fixtures/beancount/journal.beancount"));
+    }
+}
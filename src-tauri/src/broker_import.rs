@@ -0,0 +1,616 @@
+use anyhow::{anyhow, Result};
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::csv_import::{normalize_stock_code, parse_datetime_auto, parse_number, NumberFormat};
+use crate::error::FieldError;
+use crate::market::TimeCheck;
+use crate::models::{default_account, Broker, BrokerTransaction, Trade, TransactionKind};
+use crate::money::Money;
+use crate::security::SecurityId;
+
+/// 在文件开头多少行内查找表头（部分交割单在表头前有标题行）
+const HEADER_SEARCH_ROWS: usize = 20;
+
+/// 各券商交割单的列名，同一字段可能有多种写法
+struct BrokerProfile {
+    broker: Broker,
+    /// 用于自动识别券商的特征表头，全部出现才算匹配
+    signature: &'static [&'static str],
+    date: &'static [&'static str],
+    time: &'static [&'static str],
+    code: &'static [&'static str],
+    name: &'static [&'static str],
+    operation: &'static [&'static str],
+    quantity: &'static [&'static str],
+    price: &'static [&'static str],
+    amount: &'static [&'static str],
+    net_amount: &'static [&'static str],
+    fees: &'static [&'static str],
+    contract_no: &'static [&'static str],
+}
+
+const PROFILES: &[BrokerProfile] = &[
+    BrokerProfile {
+        broker: Broker::Huatai,
+        signature: &["发生日期", "业务名称"],
+        date: &["发生日期", "成交日期"],
+        time: &["成交时间"],
+        code: &["证券代码"],
+        name: &["证券名称"],
+        operation: &["业务名称"],
+        quantity: &["成交数量"],
+        price: &["成交价格", "成交均价"],
+        amount: &["成交金额"],
+        net_amount: &["发生金额", "清算金额"],
+        fees: &["佣金", "手续费", "印花税", "过户费", "交易所清算费", "其他费用"],
+        contract_no: &["合同编号", "委托编号"],
+    },
+    BrokerProfile {
+        broker: Broker::Eastmoney,
+        signature: &["成交日期", "委托方向"],
+        date: &["成交日期", "发生日期"],
+        time: &["成交时间"],
+        code: &["证券代码"],
+        name: &["证券名称"],
+        operation: &["委托方向", "业务名称"],
+        quantity: &["成交数量"],
+        price: &["成交价格", "成交均价"],
+        amount: &["成交金额"],
+        net_amount: &["发生金额", "资金发生数"],
+        fees: &["佣金", "手续费", "印花税", "过户费", "其他费用", "其他费"],
+        contract_no: &["合同编号", "委托编号"],
+    },
+    BrokerProfile {
+        broker: Broker::Tonghuashun,
+        signature: &["成交日期", "操作"],
+        date: &["成交日期"],
+        time: &["成交时间"],
+        code: &["证券代码"],
+        name: &["证券名称"],
+        operation: &["操作", "摘要"],
+        quantity: &["成交数量", "成交股数"],
+        price: &["成交均价", "成交价格"],
+        amount: &["成交金额"],
+        net_amount: &["发生金额"],
+        fees: &["手续费", "佣金", "印花税", "过户费", "其他费", "其他杂费"],
+        contract_no: &["合同编号"],
+    },
+];
+
+/// 解析后的交割单
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BrokerStatement {
+    pub broker: Broker,
+    pub transactions: Vec<BrokerTransaction>,
+    /// 无法解析的行及原因
+    pub errors: Vec<String>,
+}
+
+/// 交割单中的买入与交易记录的对账结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ReconciliationReport {
+    pub period_start: Option<DateTime<Utc>>,
+    pub period_end: Option<DateTime<Utc>>,
+    pub matched_count: usize,
+    /// 券商有成交但交易记录中没有
+    pub missing_in_journal: Vec<BrokerTransaction>,
+    /// 交易记录中有但对账期间内券商没有成交
+    pub missing_at_broker: Vec<Trade>,
+    /// 代码、日期、数量一致但价格不一致
+    pub price_mismatches: Vec<PriceMismatch>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PriceMismatch {
+    pub trade: Trade,
+    pub transaction: BrokerTransaction,
+}
+
+struct Columns {
+    date: usize,
+    time: Option<usize>,
+    code: Option<usize>,
+    name: Option<usize>,
+    operation: usize,
+    quantity: Option<usize>,
+    price: Option<usize>,
+    amount: Option<usize>,
+    net_amount: Option<usize>,
+    fees: Vec<usize>,
+    contract_no: Option<usize>,
+}
+
+/// 券商交割单解析
+pub struct BrokerStatementParser;
+
+impl BrokerStatementParser {
+    /// 解析交割单文件，支持 GBK/UTF-8 编码的 CSV、制表符文本以及 XLS/XLSX
+    pub fn parse_file(path: &Path, broker: Option<Broker>) -> Result<BrokerStatement> {
+        let bytes = std::fs::read(path)?;
+        let rows = Self::read_rows(&bytes)?;
+        Self::parse_rows(&rows, broker)
+    }
+
+    pub fn parse_rows(rows: &[Vec<String>], broker: Option<Broker>) -> Result<BrokerStatement> {
+        let header_index = rows
+            .iter()
+            .take(HEADER_SEARCH_ROWS)
+            .position(|row| {
+                PROFILES.iter().any(|p| {
+                    find_column(row, p.date).is_some() && find_column(row, p.operation).is_some()
+                })
+            })
+            .ok_or_else(|| anyhow!("未找到交割单表头"))?;
+        let headers = &rows[header_index];
+
+        let profile = match broker {
+            Some(broker) => PROFILES.iter().find(|p| p.broker == broker).unwrap(),
+            None => PROFILES
+                .iter()
+                .find(|p| p.signature.iter().all(|s| find_column(headers, &[s]).is_some()))
+                .ok_or_else(|| anyhow!("无法识别交割单所属券商，请手动选择"))?,
+        };
+
+        let columns = Columns {
+            date: find_column(headers, profile.date).ok_or_else(|| anyhow!("交割单缺少日期列"))?,
+            time: find_column(headers, profile.time),
+            code: find_column(headers, profile.code),
+            name: find_column(headers, profile.name),
+            operation: find_column(headers, profile.operation)
+                .ok_or_else(|| anyhow!("交割单缺少业务名称列"))?,
+            quantity: find_column(headers, profile.quantity),
+            price: find_column(headers, profile.price),
+            amount: find_column(headers, profile.amount),
+            net_amount: find_column(headers, profile.net_amount),
+            fees: profile
+                .fees
+                .iter()
+                .filter_map(|h| find_column(headers, &[h]))
+                .collect(),
+            contract_no: find_column(headers, profile.contract_no),
+        };
+
+        let mut transactions = Vec::new();
+        let mut errors = Vec::new();
+
+        for (offset, row) in rows[header_index + 1..].iter().enumerate() {
+            if row.iter().all(|c| c.is_empty()) {
+                continue;
+            }
+
+            let line = header_index + offset + 2;
+            match Self::parse_row(profile.broker, &columns, row) {
+                Ok(Some(transaction)) => transactions.push(transaction),
+                Ok(None) => {}
+                Err(e) => errors.push(format!("第{}行: {}", line, e)),
+            }
+        }
+
+        Ok(BrokerStatement {
+            broker: profile.broker,
+            transactions,
+            errors,
+        })
+    }
+
+    fn parse_row(broker: Broker, columns: &Columns, row: &[String]) -> Result<Option<BrokerTransaction>> {
+        let cell = |index: Option<usize>| -> &str {
+            index.and_then(|i| row.get(i)).map(|s| s.as_str()).unwrap_or("")
+        };
+        let number = |index: Option<usize>| parse_number(cell(index), NumberFormat::Standard).unwrap_or(0.0);
+        let money = |index: Option<usize>| Money::from_f64(number(index));
+
+        let operation = cell(Some(columns.operation)).to_string();
+        // 合计行等没有业务名称的行直接跳过
+        if operation.is_empty() {
+            return Ok(None);
+        }
+
+        let date = cell(Some(columns.date));
+        let time = cell(columns.time);
+        let trade_time = parse_datetime_auto(format!("{} {}", date, time).trim())
+            .or_else(|| parse_datetime_auto(date))
+            .ok_or_else(|| anyhow!("无法解析日期: {}", date))?;
+
+        let stock_code = Some(normalize_stock_code(cell(columns.code))).filter(|c| !c.is_empty());
+        let stock_name = Some(cell(columns.name).to_string()).filter(|n| !n.is_empty());
+        let fees: Money = columns.fees.iter().map(|i| money(Some(*i)).abs()).sum();
+
+        Ok(Some(BrokerTransaction {
+            id: None,
+            broker,
+            kind: classify_operation(&operation),
+            trade_time,
+            stock_code,
+            stock_name,
+            price: money(columns.price).abs(),
+            quantity: number(columns.quantity).abs().round() as i64,
+            amount: money(columns.amount).abs(),
+            net_amount: money(columns.net_amount),
+            fees,
+            contract_no: Some(cell(columns.contract_no).to_string()).filter(|c| !c.is_empty()),
+            operation,
+        }))
+    }
+
+    /// 读取文件为二维字符串表格
+    fn read_rows(bytes: &[u8]) -> Result<Vec<Vec<String>>> {
+        // 真正的 Excel 文件（OLE 或 ZIP 格式），同花顺导出的 .xls 实际上多为制表符文本
+        if bytes.starts_with(&[0xD0, 0xCF, 0x11, 0xE0]) || bytes.starts_with(b"PK") {
+            return Self::read_workbook(bytes);
+        }
+
+        let text = decode_text(bytes);
+        let delimiter = if text.lines().take(HEADER_SEARCH_ROWS).any(|l| l.contains('\t')) {
+            b'\t'
+        } else {
+            b','
+        };
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(text.as_bytes());
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            rows.push(record?.iter().map(clean_cell).collect());
+        }
+
+        Ok(rows)
+    }
+
+    fn read_workbook(bytes: &[u8]) -> Result<Vec<Vec<String>>> {
+        let mut workbook = open_workbook_auto_from_rs(std::io::Cursor::new(bytes.to_vec()))?;
+        let range = workbook
+            .worksheet_range_at(0)
+            .ok_or_else(|| anyhow!("Excel 文件中没有工作表"))??;
+
+        Ok(range
+            .rows()
+            .map(|row| row.iter().map(cell_to_string).collect())
+            .collect())
+    }
+}
+
+/// 根据业务名称判断流水类型
+fn classify_operation(operation: &str) -> TransactionKind {
+    let has = |keywords: &[&str]| keywords.iter().any(|k| operation.contains(k));
+
+    if has(&["税", "费"]) {
+        TransactionKind::Fee
+    } else if has(&["红利", "股息", "派息", "利息"]) {
+        TransactionKind::Dividend
+    } else if has(&["银行转", "转银行", "银证", "证转银", "资金转", "存入", "取出"]) {
+        TransactionKind::Transfer
+    } else if has(&["中签", "配号"]) {
+        // 中签、配号后按发行价成交
+        TransactionKind::Buy
+    } else if has(&["申购"]) {
+        TransactionKind::Subscription
+    } else if has(&["买"]) {
+        TransactionKind::Buy
+    } else if has(&["卖"]) {
+        TransactionKind::Sell
+    } else {
+        TransactionKind::Other
+    }
+}
+
+/// 按交易规则检查交割单中的证券买入流水，不符合的流水移入 errors，不再导入
+///
+/// 无法识别代码的流水（如逆回购）不检查；没有成交时间的流水和新股、转债的中签配号只检查是否为交易日。
+pub fn check_trade_rules(statement: &mut BrokerStatement, mut validate: impl FnMut(&Trade, TimeCheck) -> Vec<FieldError>) {
+    let mut errors = Vec::new();
+    statement.transactions.retain(|transaction| {
+        let Some(trade) = buy_trade(transaction) else { return true };
+
+        let date_only = transaction.trade_time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).time() == NaiveTime::MIN;
+        let allotment = ["中签", "配号"].iter().any(|k| transaction.operation.contains(k));
+        let time_check = if date_only || allotment { TimeCheck::TradingDay } else { TimeCheck::Session };
+
+        let failures = validate(&trade, time_check);
+        if failures.is_empty() {
+            return true;
+        }
+        let messages: Vec<String> = failures.into_iter().map(|e| e.message).collect();
+        errors.push(format!(
+            "{} {} {}: {}",
+            trading_date(transaction.trade_time),
+            trade.stock_code,
+            transaction.operation,
+            messages.join("；")
+        ));
+        false
+    });
+    statement.errors.extend(errors);
+}
+
+/// 把可识别代码的买入流水转换为交易记录，用于按交易规则检查
+pub fn buy_trade(transaction: &BrokerTransaction) -> Option<Trade> {
+    if transaction.kind != TransactionKind::Buy {
+        return None;
+    }
+    let stock_code = transaction.stock_code.as_ref()?;
+    let security = SecurityId::parse(stock_code)?;
+
+    Some(Trade {
+        id: None,
+        stock_code: stock_code.clone(),
+        stock_name: transaction.stock_name.clone().unwrap_or_default(),
+        buy_price: transaction.price,
+        buy_time: transaction.trade_time,
+        // 超出范围的数量按 0 处理，由数量规则报错
+        quantity: i32::try_from(transaction.quantity).unwrap_or(0),
+        notes: None,
+        account: default_account(),
+        currency: Some(security.currency()),
+        security: Some(security),
+        created_at: None,
+    })
+}
+
+/// 对账：交割单中的买入与交易记录按代码、成交日期（北京时间）、数量匹配
+pub fn reconcile(transactions: &[BrokerTransaction], trades: &[Trade]) -> ReconciliationReport {
+    let period_start = transactions.iter().map(|t| t.trade_time).min();
+    let period_end = transactions.iter().map(|t| t.trade_time).max();
+
+    let mut unmatched_trades: Vec<&Trade> = trades
+        .iter()
+        .filter(|t| match (period_start, period_end) {
+            (Some(start), Some(end)) => trading_date(t.buy_time) >= trading_date(start)
+                && trading_date(t.buy_time) <= trading_date(end),
+            _ => false,
+        })
+        .collect();
+
+    let mut matched_count = 0;
+    let mut missing_in_journal = Vec::new();
+    let mut price_mismatches = Vec::new();
+    // 价格相差不超过半分视为同一笔成交
+    let price_tolerance = Money::FEN / 2;
+
+    for transaction in transactions.iter().filter(|t| t.kind == TransactionKind::Buy) {
+        let same_fill = |trade: &&Trade| is_same_fill(trade, transaction);
+
+        if let Some(index) = unmatched_trades
+            .iter()
+            .position(|t| same_fill(t) && (t.buy_price - transaction.price).abs() <= price_tolerance)
+        {
+            unmatched_trades.remove(index);
+            matched_count += 1;
+        } else if let Some(index) = unmatched_trades.iter().position(same_fill) {
+            let trade = unmatched_trades.remove(index);
+            price_mismatches.push(PriceMismatch {
+                trade: trade.clone(),
+                transaction: transaction.clone(),
+            });
+        } else {
+            missing_in_journal.push(transaction.clone());
+        }
+    }
+
+    ReconciliationReport {
+        period_start,
+        period_end,
+        matched_count,
+        missing_in_journal,
+        missing_at_broker: unmatched_trades.into_iter().cloned().collect(),
+        price_mismatches,
+    }
+}
+
+/// 交易记录与交割单买入流水的代码、成交日期（北京时间）、数量是否一致
+pub(crate) fn is_same_fill(trade: &Trade, transaction: &BrokerTransaction) -> bool {
+    transaction.kind == TransactionKind::Buy
+        && Some(&trade.stock_code) == transaction.stock_code.as_ref()
+        && trading_date(trade.buy_time) == trading_date(transaction.trade_time)
+        && trade.quantity as i64 == transaction.quantity
+}
+
+fn trading_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+fn find_column(headers: &[String], names: &[&str]) -> Option<usize> {
+    names
+        .iter()
+        .find_map(|name| headers.iter().position(|h| h == name))
+}
+
+/// 国内券商导出文件多为 GBK 编码，UTF-8 解码失败时按 GB18030 处理
+fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => encoding_rs::GB18030.decode(bytes).0.into_owned(),
+    }
+}
+
+/// 去掉 ="600000" 这类防止 Excel 截断前导零的写法
+fn clean_cell(value: &str) -> String {
+    value
+        .trim()
+        .trim_start_matches('=')
+        .trim_matches('"')
+        .trim()
+        .to_string()
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        // 只有日期的单元格不输出时间，便于与单独的成交时间列拼接
+        Data::DateTime(value) => match value.as_datetime() {
+            Some(dt) if dt.time() == NaiveTime::MIN => dt.format("%Y-%m-%d").to_string(),
+            Some(dt) => dt.format("%Y-%m-%d %H:%M:%S").to_string(),
+            None => String::new(),
+        },
+        Data::Float(v) if v.fract() == 0.0 => format!("{}", *v as i64),
+        Data::Empty => String::new(),
+        other => clean_cell(&other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> BrokerStatement {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/broker").join(name);
+        BrokerStatementParser::parse_file(&path, None).unwrap()
+    }
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    /// 每条流水的类型、代码、价格、数量、发生金额和费用
+    type Summary<'a> = (TransactionKind, Option<&'a str>, Money, i64, Money, Money);
+
+    fn summary(statement: &BrokerStatement) -> Vec<Summary<'_>> {
+        statement
+            .transactions
+            .iter()
+            .map(|t| (t.kind, t.stock_code.as_deref(), t.price, t.quantity, t.net_amount, t.fees))
+            .collect()
+    }
+
+    #[test]
+    fn parses_tonghuashun_tab_separated_gbk() {
+        let statement = fixture("tonghuashun.xls");
+        assert_eq!(statement.broker, Broker::Tonghuashun);
+        assert!(statement.errors.is_empty());
+        assert_eq!(
+            summary(&statement),
+            [
+                (TransactionKind::Buy, Some("600000"), money("7.05"), 1000, money("-7055"), money("5")),
+                (TransactionKind::Sell, Some("000001"), money("9.5"), 500, money("4740.25"), money("9.75")),
+                (TransactionKind::Subscription, Some("787041"), money("36"), 1000, money("0"), money("0")),
+                (TransactionKind::Buy, Some("688041"), money("36"), 500, money("-18000"), money("0")),
+                (TransactionKind::Transfer, None, money("0"), 0, money("50000"), money("0")),
+            ]
+        );
+
+        let first = &statement.transactions[0];
+        assert_eq!(first.stock_name.as_deref(), Some("浦发银行"));
+        assert_eq!(first.contract_no.as_deref(), Some("1001"));
+        assert_eq!(first.trade_time.to_rfc3339(), "2024-01-15T02:30:15+00:00");
+    }
+
+    #[test]
+    fn parses_eastmoney_csv_with_excel_text_cells() {
+        let statement = fixture("eastmoney.csv");
+        assert_eq!(statement.broker, Broker::Eastmoney);
+        assert!(statement.errors.is_empty());
+        assert_eq!(
+            summary(&statement),
+            [
+                (TransactionKind::Buy, Some("600519"), money("1680"), 100, money("-168005"), money("5")),
+                (TransactionKind::Sell, Some("300750"), money("150.25"), 200, money("30019.97"), money("30.03")),
+                (TransactionKind::Dividend, Some("600519"), money("0"), 0, money("2595"), money("0")),
+                (TransactionKind::Fee, Some("600519"), money("0"), 0, money("-259.5"), money("0")),
+            ]
+        );
+        assert_eq!(statement.transactions[0].amount, money("168000"));
+        assert_eq!(statement.transactions[1].contract_no.as_deref(), Some("20002"));
+        // 只有日期的流水记为北京时间收盘
+        assert_eq!(statement.transactions[2].trade_time.to_rfc3339(), "2024-02-05T07:00:00+00:00");
+    }
+
+    #[test]
+    fn parses_huatai_csv_after_title_rows() {
+        let statement = fixture("huatai.csv");
+        assert_eq!(statement.broker, Broker::Huatai);
+        assert!(statement.errors.is_empty());
+        assert_eq!(
+            summary(&statement),
+            [
+                (TransactionKind::Buy, Some("601318"), money("42.1"), 300, money("-12635"), money("5")),
+                (TransactionKind::Buy, Some("510300"), money("3.512"), 1000, money("-3517"), money("5")),
+                (TransactionKind::Subscription, Some("732999"), money("10"), 1000, money("0"), money("0")),
+                (TransactionKind::Buy, Some("113999"), money("100"), 10, money("-1000"), money("0")),
+                (TransactionKind::Dividend, None, money("0"), 0, money("1.23"), money("0")),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_subscriptions_separately_from_fills() {
+        assert_eq!(classify_operation("新股申购"), TransactionKind::Subscription);
+        assert_eq!(classify_operation("申购还款"), TransactionKind::Subscription);
+        assert_eq!(classify_operation("新股中签"), TransactionKind::Buy);
+        assert_eq!(classify_operation("申购中签"), TransactionKind::Buy);
+        assert_eq!(classify_operation("配号"), TransactionKind::Buy);
+        assert_eq!(classify_operation("证券买入"), TransactionKind::Buy);
+        assert_eq!(classify_operation("股息红利税补缴"), TransactionKind::Fee);
+        assert_eq!(classify_operation("撤单"), TransactionKind::Other);
+    }
+
+    #[test]
+    fn reconciles_fixture_buys_against_trades() {
+        let statement = fixture("tonghuashun.xls");
+        let trade = |code: &str, price: &str, quantity: i32, time: &str| Trade {
+            id: None,
+            stock_code: code.to_string(),
+            stock_name: String::new(),
+            buy_price: money(price),
+            buy_time: DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc),
+            quantity,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        };
+
+        let report = reconcile(
+            &statement.transactions,
+            &[
+                trade("600000", "7.05", 1000, "2024-01-15T10:30:00+08:00"),
+                trade("688041", "36.5", 500, "2024-01-19T15:00:00+08:00"),
+                trade("600036", "35", 100, "2024-01-18T10:00:00+08:00"),
+            ],
+        );
+
+        // 申购委托不参与对账
+        assert_eq!(report.matched_count, 1);
+        assert_eq!(report.price_mismatches.len(), 1);
+        assert_eq!(report.price_mismatches[0].transaction.stock_code.as_deref(), Some("688041"));
+        assert_eq!(report.missing_at_broker.len(), 1);
+        assert!(report.missing_in_journal.is_empty());
+    }
+
+    #[test]
+    fn moves_buys_breaking_trade_rules_to_errors() {
+        let mut statement = fixture("huatai.csv");
+        // 零股买入沪市主板股票，且成交在午间休市
+        statement.transactions[0].quantity = 150;
+        statement.transactions[0].trade_time = DateTime::parse_from_rfc3339("2024-03-01T12:00:00+08:00")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let mut checks = Vec::new();
+        check_trade_rules(&mut statement, |trade, time_check| {
+            checks.push((trade.stock_code.clone(), time_check));
+            crate::market::validate_trade(trade, None, time_check, Utc::now())
+        });
+
+        // 申购委托和利息不检查，转债配号只检查是否为交易日
+        assert_eq!(
+            checks,
+            [
+                ("601318".to_string(), TimeCheck::Session),
+                ("510300".to_string(), TimeCheck::Session),
+                ("113999".to_string(), TimeCheck::TradingDay),
+            ]
+        );
+        assert_eq!(statement.transactions.len(), 4);
+        assert_eq!(statement.transactions[0].stock_code.as_deref(), Some("510300"));
+        assert_eq!(statement.errors.len(), 1);
+        assert!(statement.errors[0].starts_with("2024-03-01 601318 证券买入"));
+        assert!(statement.errors[0].contains("交易时段"));
+    }
+}
@@ -1,158 +1,1153 @@
 use tauri::{command, api::notification::Notification};
-use crate::database::get_database;
-use crate::models::{Trade, PriceCalculation};
-use crate::api::PriceCalculator;
-use crate::stock_api::StockApi;
-use chrono::Utc;
-use anyhow::Result;
+use crate::database::{self, get_database, Database, DATABASE_PASSPHRASE_KEY};
+use crate::error::{AppError, FieldError, Result};
+use crate::models::{Trade, PriceCalculation, DatabaseStatus, AlertRecord, FxRate, PortfolioSummary, ReturnReport, SnapshotReport, StockInfo, EquityCurve, BenchmarkComparison, BenchmarkPeriod, DailyBar, PaperComparison, PaperFill, PaperPortfolio, OverrideScope, StrategyOverride, StrategyParams, LadderPlan, LadderRung, TradeSide};
+use crate::backtest::{self, BacktestConfig, BacktestReport, Instrument};
+use crate::optimizer::{self, SweepConfig, SweepDataset, SweepReport};
+use crate::paper;
+use crate::ladder::{self, LadderRequest};
+use crate::portfolio;
+use crate::fx::{self, Currency, FxRates};
+use crate::market::{self, PriceLimits, TimeCheck};
+use crate::security::SecurityId;
+use crate::money::Money;
+use crate::analytics;
+use crate::valuation;
+use crate::keychain;
+use crate::strategy::{self, StrategyResolver};
+use crate::api::PriceSignal;
+use crate::stock_api::{StockApi, BENCHMARK_INDICES, MAX_DAILY_BARS};
+use crate::beancount;
+use crate::export::{ExportDocument, ExportEntity, ExportFilter};
+use crate::csv_import::{trade_key, CsvImporter, CsvImportOptions, CsvImportPreview, CsvImportResult};
+use crate::broker_import::{self, BrokerStatement, BrokerStatementParser, ReconciliationReport};
+use crate::models::Broker;
+use crate::onedrive::{self, DeviceCodeInfo, OneDriveClient, OneDriveConfig, OneDriveItem};
+use chrono::{FixedOffset, NaiveDate, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
 
 #[command]
-pub async fn create_trade(trade: Trade) -> Result<i64, String> {
-    let db = get_database();
+pub async fn create_trade(trade: Trade) -> Result<i64> {
+    let errors = trade_errors(&trade, TimeCheck::Session).await?;
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+
+    let db = get_database()?;
     let db_lock = db.lock().await;
     db_lock
         .create_trade(&trade)
         .await
-        .map_err(|e| e.to_string())
 }
 
 #[command]
-pub async fn get_all_trades() -> Result<Vec<Trade>, String> {
-    let db = get_database();
+pub async fn get_all_trades() -> Result<Vec<Trade>> {
+    let db = get_database()?;
     let db_lock = db.lock().await;
     db_lock
         .get_all_trades()
         .await
-        .map_err(|e| e.to_string())
 }
 
 #[command]
-pub async fn update_trade(trade: Trade) -> Result<(), String> {
-    let db = get_database();
+pub async fn update_trade(trade: Trade) -> Result<()> {
+    let db = get_database()?;
+    // 买入时间没有改动时不再按交易时段检查，以免无法修改按旧规则录入的交易
+    let time_check = match trade.id {
+        Some(id) if db.lock().await.get_trade(id).await?.buy_time == trade.buy_time => TimeCheck::Unchanged,
+        _ => TimeCheck::Session,
+    };
+
+    let mut errors = trade_errors(&trade, time_check).await?;
+    if trade.id.is_none() {
+        errors.insert(0, FieldError::new("id", "缺少交易 ID"));
+    }
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+
     let db_lock = db.lock().await;
     db_lock
         .update_trade(&trade)
         .await
-        .map_err(|e| e.to_string())
 }
 
 #[command]
-pub async fn delete_trade(id: i64) -> Result<(), String> {
-    let db = get_database();
+pub async fn delete_trade(id: i64) -> Result<()> {
+    let db = get_database()?;
     let db_lock = db.lock().await;
     db_lock
         .delete_trade(id)
         .await
-        .map_err(|e| e.to_string())
+}
+
+/// 按交易规则检查交易记录，同时检查价格是否在买入当日的日线区间内
+async fn trade_errors(trade: &Trade, time_check: TimeCheck) -> Result<Vec<FieldError>> {
+    let bars = trade_day_bars(std::slice::from_ref(trade)).await?;
+    Ok(market::validate_trade(trade, bars.get(&day_key(trade)), time_check, Utc::now()))
+}
+
+/// 交易记录对应的代码和买入日期（北京时间）
+fn day_key(trade: &Trade) -> (String, NaiveDate) {
+    let date = trade.buy_time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+    (trade.stock_code.clone(), date)
+}
+
+/// 各笔交易买入当日的日线：先读已保存的日线，缺少的沪深北证券从行情接口获取并保存，获取期间不持有数据库锁
+///
+/// 行情接口只返回最近的日线，超出范围或获取失败的交易不检查价格区间。
+async fn trade_day_bars(trades: &[Trade]) -> Result<HashMap<(String, NaiveDate), DailyBar>> {
+    let db = get_database()?;
+    let mut bars = HashMap::new();
+    let mut missing: HashMap<String, NaiveDate> = HashMap::new();
+    {
+        let db_lock = db.lock().await;
+        for trade in trades {
+            let key = day_key(trade);
+            if bars.contains_key(&key) {
+                continue;
+            }
+            match db_lock.get_daily_bars(&key.0, key.1, key.1).await?.into_iter().next() {
+                Some(bar) => {
+                    bars.insert(key, bar);
+                }
+                None if trade.security_id().is_some_and(|id| !id.is_overseas()) => {
+                    let earliest = missing.entry(key.0).or_insert(key.1);
+                    *earliest = (*earliest).min(key.1);
+                }
+                None => {}
+            }
+        }
+    }
+
+    let today = Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+    for (code, earliest) in missing {
+        // 交易日不多于自然日，按自然日估算需要的日线根数，超过接口上限时取上限内最早的日线
+        let days = (today - earliest).num_days() + 1;
+        if days < 1 {
+            continue;
+        }
+        match StockApi::get_daily_bars(&code, (days as usize).min(MAX_DAILY_BARS)).await {
+            Ok(fetched) => {
+                if let Some(first) = fetched.iter().map(|bar| bar.date).min().filter(|first| *first > earliest) {
+                    println!("{} 的日线只能取到 {} 之后，更早的交易未检查买入价格", code, first);
+                }
+                db.lock().await.upsert_daily_bars(&code, &fetched).await?;
+                for bar in fetched {
+                    bars.insert((code.clone(), bar.date), bar);
+                }
+            }
+            Err(e) => println!("获取 {} 日线失败: {}", code, e),
+        }
+    }
+
+    for trade in trades {
+        let key = day_key(trade);
+        if !bars.contains_key(&key) {
+            println!("没有 {} 在 {} 的日线，未检查买入价格", key.0, key.1);
+        }
+    }
+    Ok(bars)
+}
+
+/// 按交易规则检查 CSV 预览中解析成功的行
+async fn check_csv_trade_rules(preview: &mut CsvImportPreview) -> Result<()> {
+    let trades: Vec<Trade> = preview.rows.iter().filter_map(|row| row.trade.clone()).collect();
+    let bars = trade_day_bars(&trades).await?;
+    let now = Utc::now();
+    CsvImporter::check_trade_rules(preview, |trade, time_check| {
+        market::validate_trade(trade, bars.get(&day_key(trade)), time_check, now)
+    });
+    Ok(())
+}
+
+// CSV 导入相关命令
+
+#[command]
+pub async fn preview_trades_csv(file_path: String, options: CsvImportOptions) -> Result<CsvImportPreview> {
+    let content = CsvImporter::read_file(std::path::Path::new(&file_path))?;
+
+    let db = get_database()?;
+    let existing = db.lock().await.get_all_trades().await?;
+
+    let mut preview = CsvImporter::preview(&content, &options, &existing)?;
+    check_csv_trade_rules(&mut preview).await?;
+    Ok(preview)
 }
 
 #[command]
-pub async fn get_stock_price(stock_code: String) -> Result<crate::models::StockPriceResponse, String> {
-    match StockApi::get_stock_info(&stock_code).await {
-        Ok(stock_info) => {
-            Ok(crate::models::StockPriceResponse {
-                code: stock_info.code,
-                name: stock_info.name,
-                price: stock_info.current_price,
-                change: stock_info.change,
-                change_percent: stock_info.change_percent,
-                timestamp: stock_info.timestamp,
-            })
+pub async fn import_trades_csv(file_path: String, options: CsvImportOptions) -> Result<CsvImportResult> {
+    let content = CsvImporter::read_file(std::path::Path::new(&file_path))?;
+
+    let db = get_database()?;
+    let existing = db.lock().await.get_all_trades().await?;
+
+    let mut preview = CsvImporter::preview(&content, &options, &existing)?;
+    check_csv_trade_rules(&mut preview).await?;
+    let trades = CsvImporter::trades_to_import(&preview, options.skip_duplicates)?;
+    let trade_ids = db.lock().await.create_trades(&trades).await?;
+
+    Ok(CsvImportResult {
+        imported: trade_ids.len(),
+        skipped_duplicates: preview.duplicate_count,
+        trade_ids,
+    })
+}
+
+// 数据导出相关命令
+
+async fn build_export(filter: ExportFilter) -> Result<ExportDocument> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let data = db_lock.export_data().await?;
+
+    Ok(ExportDocument::build(filter, data, Utc::now()))
+}
+
+#[command]
+pub async fn export_csv(entity: ExportEntity, file_path: String, filter: ExportFilter) -> Result<()> {
+    build_export(filter)
+        .await?
+        .write_csv(entity, std::path::Path::new(&file_path))
+        .map_err(AppError::from)
+}
+
+#[command]
+pub async fn export_json(file_path: String, filter: ExportFilter) -> Result<()> {
+    build_export(filter)
+        .await?
+        .write_json(std::path::Path::new(&file_path))
+        .map_err(AppError::from)
+}
+
+#[command]
+pub async fn export_xlsx(file_path: String, filter: ExportFilter) -> Result<()> {
+    build_export(filter)
+        .await?
+        .write_xlsx(std::path::Path::new(&file_path))
+        .map_err(AppError::from)
+}
+
+/// 导出 Beancount 账本，price 指令取自保存的日线收盘价和实时行情；获取不到实时行情时只使用日线
+#[command]
+pub async fn export_beancount(file_path: String, filter: ExportFilter) -> Result<()> {
+    let document = build_export(filter).await?;
+    let today = Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+
+    // 每只股票从第一次买入开始的日线
+    let mut first_dates: HashMap<String, NaiveDate> = HashMap::new();
+    for trade in &document.trades {
+        let date = trade.buy_time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+        first_dates
+            .entry(trade.stock_code.clone())
+            .and_modify(|d| *d = (*d).min(date))
+            .or_insert(date);
+    }
+
+    let mut prices = Vec::new();
+    {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        for (code, start) in &first_dates {
+            for bar in db_lock.get_daily_bars(code, *start, today).await? {
+                prices.push(beancount::PricePoint {
+                    stock_code: code.clone(),
+                    date: bar.date,
+                    price: bar.close,
+                });
+            }
         }
-        Err(e) => Err(e.to_string())
     }
+
+    let codes: Vec<String> = first_dates.into_keys().collect();
+    match StockApi::fetch_real_stock_quotes(&codes).await {
+        Ok(quotes) => prices.extend(quotes.into_values().map(|quote| beancount::PricePoint {
+            date: quote.timestamp.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive(),
+            stock_code: quote.code,
+            price: quote.current_price,
+        })),
+        Err(e) => println!("获取实时行情失败，只使用日线收盘价: {}", e),
+    }
+
+    let journal = beancount::render_journal(
+        &document.trades,
+        &document.broker_transactions,
+        &prices,
+        document.fx_rates.as_deref().unwrap_or_default(),
+        Utc::now(),
+    );
+    std::fs::write(&file_path, journal).map_err(AppError::from)
 }
 
+/// 导入 JSON 导出文件；replace 为 false 时与现有数据合并，跳过重复的交易并保留现有设置
 #[command]
-pub async fn validate_stock_code(stock_code: String) -> Result<bool, String> {
-    Ok(StockApi::validate_stock_code(&stock_code))
+pub async fn import_json(file_path: String, replace: bool) -> Result<usize> {
+    let mut document = ExportDocument::read_json(std::path::Path::new(&file_path))?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+
+    let mut known_trade_ids = HashMap::new();
+    if !replace {
+        let existing = db_lock.get_all_trades().await?;
+        let existing_ids: HashMap<_, _> =
+            existing.iter().filter_map(|t| t.id.map(|id| (trade_key(t), id))).collect();
+
+        // 重复的交易不再导入，文件中的 ID 对应到现有交易，供网格计划和策略参数使用
+        let mut trades = Vec::new();
+        for trade in std::mem::take(&mut document.trades) {
+            match existing_ids.get(&trade_key(&trade)) {
+                Some(&id) => {
+                    if let Some(old_id) = trade.id {
+                        known_trade_ids.insert(old_id, id);
+                    }
+                }
+                None => trades.push(trade),
+            }
+        }
+
+        // 只导入随新交易一起导入的提醒，避免重复
+        let imported_ids: std::collections::HashSet<i64> = trades.iter().filter_map(|t| t.id).collect();
+        document
+            .alert_history
+            .retain(|a| a.trade_id.map(|id| imported_ids.contains(&id)).unwrap_or(false));
+        document.trades = trades;
+    }
+
+    db_lock.import_snapshot(&document, known_trade_ids, replace).await
 }
 
+// 券商交割单相关命令
+
 #[command]
-pub async fn calculate_price_targets(
-    trade_id: i64,
-    buy_step_percentage: f64,
-    annual_return_rate: f64,
-) -> Result<PriceCalculation, String> {
-    let db = get_database();
+pub async fn preview_broker_statement(file_path: String, broker: Option<Broker>) -> Result<BrokerStatement> {
+    parse_broker_statement(&file_path, broker).await
+}
+
+#[command]
+pub async fn import_broker_statement(file_path: String, broker: Option<Broker>) -> Result<u64> {
+    let statement = parse_broker_statement(&file_path, broker).await?;
+
+    let db = get_database()?;
     let db_lock = db.lock().await;
+    db_lock
+        .insert_broker_transactions(&statement.transactions)
+        .await
+}
 
-    // 获取交易记录
-    let trades = db_lock.get_all_trades().await.map_err(|e| e.to_string())?;
-    let trade = trades
-        .into_iter()
-        .find(|t| t.id == Some(trade_id))
-        .ok_or("交易记录不存在")?;
+/// 解析交割单，并按交易规则检查其中的证券买入，不符合的买入记入 errors
+async fn parse_broker_statement(file_path: &str, broker: Option<Broker>) -> Result<BrokerStatement> {
+    let mut statement = BrokerStatementParser::parse_file(std::path::Path::new(file_path), broker)?;
+
+    let trades: Vec<Trade> = statement.transactions.iter().filter_map(broker_import::buy_trade).collect();
+    let bars = trade_day_bars(&trades).await?;
+    let now = Utc::now();
+    broker_import::check_trade_rules(&mut statement, |trade, time_check| {
+        market::validate_trade(trade, bars.get(&day_key(trade)), time_check, now)
+    });
+    Ok(statement)
+}
+
+#[command]
+pub async fn reconcile_broker_trades() -> Result<ReconciliationReport> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let transactions = db_lock.get_broker_transactions().await?;
+    let trades = db_lock.get_all_trades().await?;
+
+    Ok(broker_import::reconcile(&transactions, &trades))
+}
+
+#[command]
+pub async fn get_stock_price(stock_code: String) -> Result<crate::models::StockPriceResponse> {
+    let stock_info = StockApi::get_stock_info(&stock_code).await?;
+    cache_quote(&stock_info.code, &stock_info.name, stock_info.current_price).await;
+    Ok(crate::models::StockPriceResponse {
+        code: stock_info.code,
+        name: stock_info.name,
+        price: stock_info.current_price,
+        change: stock_info.change,
+        change_percent: stock_info.change_percent,
+        timestamp: stock_info.timestamp,
+    })
+}
+
+#[command]
+pub async fn validate_stock_code(stock_code: String) -> Result<bool> {
+    Ok(StockApi::validate_stock_code(&stock_code))
+}
+
+#[command]
+pub async fn calculate_price_targets(trade_id: i64) -> Result<PriceCalculation> {
+    let db = get_database()?;
+
+    // 获取交易记录和策略设置
+    let (trade, mut resolver) = {
+        let db_lock = db.lock().await;
+        (db_lock.get_trade(trade_id).await?, StrategyResolver::load(&db_lock).await?)
+    };
+
+    // 按波动率计算台阶时先联网补齐日线，并获取当前行情，联网期间不持有数据库锁；真实行情不可用时才使用模拟股价
+    let fetched = resolver.fetch_bars(&[trade.stock_code.as_str()]).await;
+    let real_quote = StockApi::fetch_real_stock_info(&trade.stock_code).await.ok();
+    let real_price = real_quote.as_ref().map(|q| q.current_price);
+    let current_price = match real_price {
+        Some(price) => Some(price),
+        None => StockApi::get_stock_price(&trade.stock_code).await.ok(),
+    };
+    let db_lock = db.lock().await;
     
     // 计算持有天数
     let days_held = (Utc::now() - trade.buy_time).num_days();
     
-    // 计算目标价格
-    let sell_target = PriceCalculator::calculate_sell_target_price(
+    // 按交易、股票、账户和全局设置解析策略参数后计算目标价格
+    resolver
+        .load_bars(&db_lock, &[trade.stock_code.as_str()], &fetched)
+        .await?;
+    let params = resolver.resolve(&trade);
+    let buy_step = resolver.buy_step(&trade);
+    
+    // 跟踪止损需要买入以来的最高价，只用真实行情更新
+    let stored_high = db_lock
+        .get_high_water_marks()
+        .await?
+        .remove(&trade_id);
+    let high_water_price = match real_price {
+        Some(price) => strategy::update_high_water_mark(&db_lock, &trade, price, stored_high)
+            .await?,
+        None => stored_high.unwrap_or(trade.buy_price),
+    };
+    
+    // 判断价格目标，目标价按报价单位取整
+    let security = trade.security_id();
+    let tick = security.as_ref().map(|id| id.tick_size()).unwrap_or(Money::FEN);
+    let evaluation = params.evaluate(
         trade.buy_price,
-        annual_return_rate,
+        trade.quantity as i64,
         days_held,
+        current_price,
+        high_water_price,
+        tick,
     );
     
-    let buy_target = PriceCalculator::calculate_buy_target_price(
-        sell_target,
-        buy_step_percentage,
-    );
-    
-    // 获取当前股价
-    let current_price = match StockApi::get_stock_price(&trade.stock_code).await {
-        Ok(price) => Some(price),
-        Err(_) => None,
-    };
-    
-    // 判断价格目标
-    let price_reached = if let Some(price) = current_price {
-        PriceCalculator::check_price_target(price, sell_target, buy_target)
-    } else {
-        "none".to_string()
-    };
+    // 按前收盘价计算当日涨跌停价，超出的目标当日无法成交
+    let limits = real_quote.as_ref().zip(security.as_ref()).and_then(|(q, id)| {
+        let name = if q.name.is_empty() { &trade.stock_name } else { &q.name };
+        PriceLimits::from_prev_close(q.current_price - q.change, id, market::is_st(name))
+    });
     
     Ok(PriceCalculation {
-        sell_target_price: sell_target,
-        buy_target_price: buy_target,
+        sell_target_price: evaluation.sell_target,
+        buy_target_price: evaluation.buy_target,
         days_since_purchase: days_held,
         current_price,
-        price_reached,
+        signal: evaluation.signal,
+        stop_loss_price: evaluation.stop_loss_price,
+        trailing_stop_price: evaluation.trailing_stop_price,
+        high_water_price: Some(high_water_price),
+        security,
+        limit_up_price: limits.map(|l| l.limit_up),
+        limit_down_price: limits.map(|l| l.limit_down),
+        sell_target_beyond_limit: limits.map(|l| l.sell_beyond_limit(evaluation.sell_target)).unwrap_or(false),
+        buy_target_beyond_limit: limits.map(|l| l.buy_beyond_limit(evaluation.buy_target)).unwrap_or(false),
+        annual_return_rate: params.annual_return_rate,
+        buy_step_percentage: params.buy_step_percentage,
+        buy_step_mode: buy_step.mode,
+        volatility: buy_step.volatility,
+        buy_step_reason: buy_step.reason,
+        min_holding_days: params.min_holding_days,
+        growth_model: params.growth_model,
+        day_count_basis: params.day_count_basis,
     })
 }
 
+/// 投资组合分析，批量获取行情后在后端统一计算
+#[command]
+pub async fn get_portfolio_summary(account: Option<String>) -> Result<PortfolioSummary> {
+    let trades = account_trades(account.as_deref()).await?;
+    let quotes = fetch_quotes(&trades).await;
+    let strategy = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        let mut strategy = StrategyResolver::load(&db_lock).await?;
+        let codes: Vec<&str> = quotes.keys().map(String::as_str).collect();
+        strategy.load_bars(&db_lock, &codes, &HashMap::new()).await?;
+        strategy
+    };
+
+    let high_water = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        db_lock.get_high_water_marks().await?
+    };
+
+    let base = base_currency().await?;
+    let fx_rates = trade_fx_rates(&trades, base).await?;
+
+    Ok(portfolio::summarize_portfolio(
+        &trades,
+        &quotes,
+        &strategy,
+        &high_water,
+        &fx_rates,
+        base,
+        Utc::now(),
+    ))
+}
+
+/// 组合合计使用的基准货币，未设置时为人民币
+async fn base_currency() -> Result<Currency> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    Ok(db_lock
+        .get_setting("base_currency")
+        .await?
+        .and_then(|value| value.parse::<Currency>().ok())
+        .unwrap_or_default())
+}
+
+/// 折算交易金额用的汇率，有外币交易或基准货币不是人民币时先更新汇率
+async fn trade_fx_rates(trades: &[Trade], base: Currency) -> Result<FxRates> {
+    let update = base != Currency::Cny || trades.iter().any(|t| t.currency() != Currency::Cny);
+    fx::load_fx_rates(update).await.map_err(AppError::from)
+}
+
+/// 已保存的全部汇率，按货币和日期升序
+#[command]
+pub async fn get_fx_rates() -> Result<Vec<FxRate>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .get_fx_rates()
+        .await
+}
+
+/// 手动录入某日汇率，用于补充无法联网获取的日期
+#[command]
+pub async fn set_fx_rate(rate: FxRate) -> Result<()> {
+    if rate.currency == Currency::Cny {
+        return Err(AppError::Validation("人民币不需要设置汇率".to_string()));
+    }
+    if !(rate.rate.is_finite() && rate.rate > 0.0) {
+        return Err(AppError::Validation("汇率必须大于 0".to_string()));
+    }
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .upsert_fx_rates(&[rate])
+        .await
+}
+
+/// 获取港币和美元兑人民币的最新汇率，返回保存的条数
+#[command]
+pub async fn update_fx_rates() -> Result<usize> {
+    fx::update_fx_rates().await.map_err(AppError::from)
+}
+
+/// 每个持仓、账户和整体的资金加权年化收益率（XIRR）
+#[command]
+pub async fn get_portfolio_returns(account: Option<String>) -> Result<ReturnReport> {
+    let trades = account_trades(account.as_deref()).await?;
+    let prices: HashMap<String, Money> = fetch_quotes(&trades)
+        .await
+        .into_iter()
+        .map(|(code, quote)| (code, quote.current_price))
+        .collect();
+
+    let base = base_currency().await?;
+    let fx_rates = trade_fx_rates(&trades, base).await?;
+
+    Ok(analytics::build_return_report(&trades, &prices, &fx_rates, base, Utc::now()))
+}
+
+/// 从第一笔交易开始重新拉取日线并重建全部估值快照，报告交易日数量和日线被截断的股票
+#[command]
+pub async fn backfill_valuation_snapshots() -> Result<SnapshotReport> {
+    valuation::record_snapshots(true).await.map_err(AppError::from)
+}
+
+/// 资金曲线及最大回撤、波动率，日期为闭区间
 #[command]
-pub async fn get_setting(key: String) -> Result<Option<String>, String> {
-    let db = get_database();
+pub async fn get_equity_curve(
+    account: Option<String>,
+    start_date: Option<NaiveDate>,
+    end_date: Option<NaiveDate>,
+) -> Result<EquityCurve> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let snapshots: Vec<_> = db_lock
+        .get_valuation_snapshots(account.as_deref())
+        .await?
+        .into_iter()
+        .filter(|s| start_date.map(|d| s.snapshot_date >= d).unwrap_or(true))
+        .filter(|s| end_date.map(|d| s.snapshot_date <= d).unwrap_or(true))
+        .collect();
+
+    Ok(valuation::build_equity_curve(&snapshots))
+}
+
+/// 所有基准指数的实时行情
+#[command]
+pub async fn get_benchmark_quotes() -> Result<Vec<StockInfo>> {
+    let mut quotes = Vec::new();
+    for (symbol, _) in BENCHMARK_INDICES {
+        match StockApi::get_index_quote(symbol).await {
+            Ok(quote) => quotes.push(quote),
+            Err(e) => println!("获取指数 {} 行情失败: {}", symbol, e),
+        }
+    }
+    Ok(quotes)
+}
+
+/// 组合与基准指数在指定区间内的对比，基于每日估值快照
+#[command]
+pub async fn get_benchmark_comparison(
+    benchmark: String,
+    period: BenchmarkPeriod,
+    account: Option<String>,
+) -> Result<BenchmarkComparison> {
+    if !StockApi::is_benchmark_index(&benchmark) {
+        return Err(AppError::Validation(format!("不支持的基准指数: {}", benchmark)));
+    }
+
+    let today = Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+    let snapshots = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        db_lock
+            .get_valuation_snapshots(account.as_deref())
+            .await?
+    };
+
+    let start = valuation::period_start(period, today)
+        .into_iter()
+        .chain(snapshots.first().map(|s| s.snapshot_date))
+        .max()
+        .unwrap_or(today);
+    let snapshots: Vec<_> = snapshots.into_iter().filter(|s| s.snapshot_date >= start).collect();
+
+    // 补齐区间内的指数日线，获取失败时使用已保存的历史
+    let days = (today - start).num_days() as usize + 1;
+    if let Err(e) = valuation::update_daily_bars(&benchmark, days).await {
+        println!("获取指数 {} 日线失败: {}", benchmark, e);
+    }
+
+    let bars = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        db_lock
+            .get_daily_bars(&benchmark, start, today)
+            .await?
+    };
+
+    let curve = valuation::build_equity_curve(&snapshots);
+    Ok(valuation::build_benchmark_comparison(&benchmark, period, &curve, &bars))
+}
+
+/// 回测使用的日线来源
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BacktestSource {
+    /// 离线 CSV 文件，交易规则按 stock_code 确定，未指定代码时按人民币计价的 A 股主板规则；currency 覆盖计价货币
+    Csv {
+        file_path: String,
+        #[serde(default)]
+        stock_code: Option<String>,
+        #[serde(default)]
+        currency: Option<Currency>,
+    },
+    /// 保存的历史日线，回测前尝试联网补齐
+    History {
+        stock_code: String,
+        start_date: Option<NaiveDate>,
+        end_date: Option<NaiveDate>,
+    },
+}
+
+/// 用历史日线回测卖出目标价 / 买入台阶策略
+#[command]
+pub async fn run_backtest(config: BacktestConfig, source: BacktestSource) -> Result<BacktestReport> {
+    let dataset = load_backtest_bars(source).await?;
+    let fx_rates = fx::load_fx_rates(dataset.instrument.currency != Currency::Cny).await?;
+    backtest::run_backtest(&dataset.bars, &dataset.instrument, &fx_rates, &config).map_err(AppError::from)
+}
+
+/// 在一只或多只股票的历史日线上搜索策略参数
+#[command]
+pub async fn run_parameter_sweep(config: SweepConfig, sources: Vec<BacktestSource>) -> Result<SweepReport> {
+    let mut datasets = Vec::with_capacity(sources.len());
+    for source in sources {
+        datasets.push(load_backtest_bars(source).await?);
+    }
+    let fx_rates = fx::load_fx_rates(datasets.iter().any(|d| d.instrument.currency != Currency::Cny)).await?;
+
+    // 组合较多时计算量大，放到阻塞线程池中避免占用异步运行时
+    tokio::task::spawn_blocking(move || optimizer::run_sweep(&datasets, &fx_rates, &config))
+        .await
+        .map_err(anyhow::Error::from)?
+        .map_err(AppError::from)
+}
+
+/// 从 CSV 导入某只股票的日线，供无法联网时计算波动率台阶和回测使用，返回导入的条数
+#[command]
+pub async fn import_daily_bars_csv(stock_code: String, file_path: String) -> Result<usize> {
+    let content = CsvImporter::read_file(std::path::Path::new(&file_path))?;
+    let bars = backtest::load_bars_csv(&content)?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .upsert_daily_bars(&stock_code, &bars)
+        .await?;
+
+    Ok(bars.len())
+}
+
+/// 读取回测日线，计价货币、报价单位和买入数量规则按证券所在市场和板块确定
+async fn load_backtest_bars(source: BacktestSource) -> Result<SweepDataset> {
+    match source {
+        BacktestSource::Csv { file_path, stock_code, currency } => {
+            let content = CsvImporter::read_file(std::path::Path::new(&file_path))?;
+            let bars = backtest::load_bars_csv(&content)?;
+            let mut instrument = stock_code.as_deref().map(Instrument::for_code).unwrap_or_default();
+            if let Some(currency) = currency {
+                instrument.currency = currency;
+            }
+            Ok(SweepDataset { instrument, bars })
+        }
+        BacktestSource::History { stock_code, start_date, end_date } => {
+            if let Err(e) = valuation::update_daily_bars(&stock_code, usize::MAX).await {
+                println!("获取 {} 日线失败: {}", stock_code, e);
+            }
+
+            let db = get_database()?;
+            let db_lock = db.lock().await;
+            let bars = db_lock
+                .get_daily_bars(
+                    &stock_code,
+                    start_date.unwrap_or(NaiveDate::from_ymd_opt(1990, 1, 1).unwrap()),
+                    end_date.unwrap_or_else(|| {
+                        Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+                    }),
+                )
+                .await?;
+            Ok(SweepDataset { instrument: Instrument::for_code(&stock_code), bars })
+        }
+    }
+}
+
+/// 创建模拟盘，使用与回测相同的策略参数和费用
+#[command]
+pub async fn create_paper_portfolio(name: String, config: BacktestConfig) -> Result<i64> {
+    let portfolio = paper::new_portfolio(&name, &config)?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .create_paper_portfolio(&portfolio)
+        .await
+}
+
+#[command]
+pub async fn get_paper_portfolios() -> Result<Vec<PaperPortfolio>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .get_paper_portfolios()
+        .await
+}
+
+#[command]
+pub async fn delete_paper_portfolio(id: i64) -> Result<()> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .delete_paper_portfolio(id)
+        .await
+}
+
+/// 按实时行情在模拟盘中手动买入，未指定数量时买入策略设置的手数
+#[command]
+pub async fn paper_buy(portfolio_id: i64, stock_code: String, quantity: Option<i64>) -> Result<PaperFill> {
+    let errors = paper::validate_buy(&stock_code, quantity);
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors));
+    }
+
+    let quote = StockApi::fetch_real_stock_quotes(std::slice::from_ref(&stock_code))
+        .await?
+        .remove(&stock_code)
+        .ok_or_else(|| AppError::ProviderParse(format!("无法获取 {} 的实时行情", stock_code)))?;
+    cache_quote(&quote.code, &quote.name, quote.current_price).await;
+
+    // 模拟盘资金为人民币，外币股票按当天汇率折算
+    let currency = SecurityId::parse(&stock_code).map(|id| id.currency()).unwrap_or_default();
+    let today = Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+    let fx_rate = fx::load_fx_rates(currency != Currency::Cny)
+        .await?
+        .cny_rate(currency, today)
+        .ok_or_else(|| AppError::Validation(format!("缺少 {} 汇率，无法买入", currency.code())))?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let portfolio = db_lock
+        .get_paper_portfolios()
+        .await?
+        .into_iter()
+        .find(|p| p.id == Some(portfolio_id))
+        .ok_or_else(|| AppError::NotFound("模拟盘不存在".to_string()))?;
+
+    let mut cash = portfolio.cash;
+    let quantity = quantity.unwrap_or_else(|| paper::default_quantity(&portfolio, &stock_code));
+    let (position, fill) = paper::buy(
+        &portfolio,
+        &mut cash,
+        &stock_code,
+        &quote.name,
+        quote.current_price,
+        fx_rate,
+        quantity,
+        None,
+        Utc::now(),
+    )
+    .ok_or_else(|| AppError::Validation("可用资金不足最低买入数量".to_string()))?;
+
+    db_lock
+        .apply_paper_fills(portfolio_id, cash, &[], &[position], std::slice::from_ref(&fill))
+        .await?;
+    Ok(fill)
+}
+
+#[command]
+pub async fn get_paper_fills(portfolio_id: Option<i64>) -> Result<Vec<PaperFill>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .get_paper_fills(portfolio_id)
+        .await
+}
+
+/// 立即按最新行情检查全部模拟盘，返回新产生的成交
+#[command]
+pub async fn check_paper_fills() -> Result<Vec<PaperFill>> {
+    paper::process_fills(Utc::now()).await.map_err(AppError::from)
+}
+
+/// 模拟盘与真实账户的收益对比
+#[command]
+pub async fn get_paper_comparison() -> Result<PaperComparison> {
+    let trades = account_trades(None).await?;
+    paper::build_comparison(&trades, Utc::now())
+        .await
+        .map_err(AppError::from)
+}
+
+/// 预览网格计划，不保存
+#[command]
+pub async fn preview_ladder_plan(request: LadderRequest) -> Result<LadderPlan> {
+    build_ladder_plan(request).await
+}
+
+/// 生成并保存网格计划
+#[command]
+pub async fn create_ladder_plan(request: LadderRequest) -> Result<LadderPlan> {
+    let mut plan = build_ladder_plan(request).await?;
+    plan.created_at = Some(Utc::now());
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let id = db_lock.create_ladder_plan(&plan).await?;
+    plan.id = Some(id);
+    for rung in plan.rungs.iter_mut() {
+        rung.plan_id = Some(id);
+    }
+
+    Ok(plan)
+}
+
+/// 读取网格计划，并按最新的交易记录更新各档的成交状态
+#[command]
+pub async fn get_ladder_plans() -> Result<Vec<LadderPlan>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let trades = db_lock.get_all_trades().await?;
+    let mut plans = db_lock.get_ladder_plans().await?;
+
+    for plan in plans.iter_mut() {
+        if ladder::match_fills(plan, &trades) {
+            db_lock
+                .update_ladder_fills(&plan.rungs)
+                .await?;
+        }
+    }
+
+    Ok(plans)
+}
+
+#[command]
+pub async fn delete_ladder_plan(id: i64) -> Result<()> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .delete_ladder_plan(id)
+        .await
+}
+
+/// 补齐锚定价格、股票名称和台阶后生成网格计划
+async fn build_ladder_plan(request: LadderRequest) -> Result<LadderPlan> {
+    let (last_trade, mut params) = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        let trades = db_lock.get_all_trades().await?;
+        let mut strategy = StrategyResolver::load(&db_lock).await?;
+        strategy
+            .load_bars(&db_lock, &[request.stock_code.as_str()], &HashMap::new())
+            .await?;
+        let last_trade = trades
+            .into_iter()
+            .filter(|t| t.account == request.account && t.stock_code == request.stock_code)
+            .max_by_key(|t| t.buy_time);
+        (last_trade, strategy.resolve_for(None, &request.stock_code, &request.account))
+    };
+    if let Some(step) = request.step_percentage {
+        params.buy_step_percentage = step;
+    }
+
+    let mut stock_name = request
+        .stock_name
+        .clone()
+        .or_else(|| last_trade.as_ref().map(|t| t.stock_name.clone()));
+    let mut anchor_price = request.anchor_price.or_else(|| last_trade.as_ref().map(|t| t.buy_price));
+
+    if anchor_price.is_none() || stock_name.is_none() {
+        let quote = StockApi::fetch_real_stock_quotes(std::slice::from_ref(&request.stock_code))
+            .await?
+            .remove(&request.stock_code)
+            .ok_or_else(|| AppError::ProviderParse(format!("无法获取 {} 的实时行情", request.stock_code)))?;
+        anchor_price = anchor_price.or(Some(quote.current_price));
+        stock_name = stock_name.or(Some(quote.name));
+    }
+
+    ladder::build_plan(
+        &request.account,
+        &request.stock_code,
+        stock_name.as_deref().unwrap_or(&request.stock_code),
+        anchor_price.unwrap_or_default(),
+        request.budget,
+        request.rungs,
+        &params,
+    )
+    .map_err(AppError::from)
+}
+
+#[command]
+pub async fn get_strategy_overrides() -> Result<Vec<StrategyOverride>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .get_strategy_overrides()
+        .await
+}
+
+/// 保存某笔交易、某只股票或某个账户的策略参数，参数全部为空时删除该覆盖
+#[command]
+pub async fn set_strategy_override(item: StrategyOverride) -> Result<()> {
+    strategy::validate_override(&item)?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .set_strategy_override(&item)
+        .await
+}
+
+#[command]
+pub async fn delete_strategy_override(scope: OverrideScope, scope_key: String) -> Result<()> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .delete_strategy_override(scope, &scope_key)
+        .await
+}
+
+/// 某笔交易实际使用的策略参数，不指定交易时返回全局设置
+#[command]
+pub async fn get_strategy_params(trade_id: Option<i64>) -> Result<StrategyParams> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let mut resolver = StrategyResolver::load(&db_lock).await?;
+
+    let Some(trade_id) = trade_id else {
+        return Ok(resolver.global());
+    };
+    let trade = db_lock
+        .get_all_trades()
+        .await?
+        .into_iter()
+        .find(|t| t.id == Some(trade_id))
+        .ok_or_else(|| AppError::NotFound("交易记录不存在".to_string()))?;
+    resolver
+        .load_bars(&db_lock, &[trade.stock_code.as_str()], &HashMap::new())
+        .await?;
+
+    Ok(resolver.resolve(&trade))
+}
+
+/// 读取交易记录，指定账户时只保留该账户的交易
+async fn account_trades(account: Option<&str>) -> Result<Vec<Trade>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let trades = db_lock.get_all_trades().await?;
+
+    Ok(trades
+        .into_iter()
+        .filter(|t| account.map(|a| a == t.account).unwrap_or(true))
+        .collect())
+}
+
+/// 批量获取交易涉及的所有股票行情并写入缓存
+async fn fetch_quotes(trades: &[Trade]) -> HashMap<String, StockInfo> {
+    let mut codes: Vec<String> = trades.iter().map(|t| t.stock_code.clone()).collect();
+    codes.sort();
+    codes.dedup();
+
+    let quotes = StockApi::get_stock_quotes(&codes).await;
+    for quote in quotes.values() {
+        cache_quote(&quote.code, &quote.name, quote.current_price).await;
+    }
+
+    quotes
+}
+
+#[command]
+pub async fn get_setting(key: String) -> Result<Option<String>> {
+    let db = get_database()?;
     let db_lock = db.lock().await;
     db_lock
         .get_setting(&key)
         .await
-        .map_err(|e| e.to_string())
 }
 
 #[command]
-pub async fn set_setting(key: String, value: String) -> Result<(), String> {
-    let db = get_database();
+pub async fn set_setting(key: String, value: String) -> Result<()> {
+    let db = get_database()?;
     let db_lock = db.lock().await;
     db_lock
         .set_setting(&key, &value)
         .await
-        .map_err(|e| e.to_string())
+}
+
+// 数据库加密相关命令
+
+#[command]
+pub async fn get_database_status() -> Result<DatabaseStatus> {
+    let Ok(db) = get_database() else {
+        return Ok(DatabaseStatus {
+            initialized: false,
+            encrypted: database::is_encrypted_file(&database::default_database_path()),
+        });
+    };
+    let db_lock = db.lock().await;
+    Ok(DatabaseStatus {
+        initialized: true,
+        encrypted: db_lock.is_encrypted(),
+    })
+}
+
+#[command]
+pub async fn unlock_database(passphrase: String, remember: bool) -> Result<()> {
+    if database::is_database_initialized() {
+        return Ok(());
+    }
+
+    database::open_database(Some(&passphrase))
+        .await?;
+
+    if remember {
+        keychain::save_secret(DATABASE_PASSPHRASE_KEY, &passphrase)?;
+    }
+
+    Ok(())
+}
+
+#[command]
+pub async fn enable_database_encryption(passphrase: String, remember: bool) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(AppError::Validation("密码不能为空".to_string()));
+    }
+
+    let db = get_database()?;
+    let mut db_lock = db.lock().await;
+    db_lock
+        .enable_encryption(&passphrase)
+        .await?;
+
+    remember_passphrase(&passphrase, remember)
+}
+
+#[command]
+pub async fn disable_database_encryption() -> Result<()> {
+    let db = get_database()?;
+    let mut db_lock = db.lock().await;
+    db_lock
+        .disable_encryption()
+        .await?;
+
+    keychain::delete_secret(DATABASE_PASSPHRASE_KEY).map_err(AppError::from)
 }
 
 #[command]
-pub async fn search_stocks(query: String) -> Result<Vec<crate::models::StockSearchResult>, String> {
+pub async fn change_database_passphrase(passphrase: String, remember: bool) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(AppError::Validation("密码不能为空".to_string()));
+    }
+
+    let db = get_database()?;
+    let mut db_lock = db.lock().await;
+    db_lock
+        .change_passphrase(&passphrase)
+        .await?;
+
+    remember_passphrase(&passphrase, remember)
+}
+
+/// 按用户选择在系统钥匙串中保存或清除数据库密码
+fn remember_passphrase(passphrase: &str, remember: bool) -> Result<()> {
+    let result = if remember {
+        keychain::save_secret(DATABASE_PASSPHRASE_KEY, passphrase)
+    } else {
+        keychain::delete_secret(DATABASE_PASSPHRASE_KEY)
+    };
+
+    result.map_err(AppError::from)
+}
+
+#[command]
+pub async fn search_stocks(query: String) -> Result<Vec<crate::models::StockSearchResult>> {
     StockApi::search_stocks(&query)
         .await
-        .map_err(|e| e.to_string())
 }
 
 #[command]
-pub async fn get_stock_info(stock_code: String) -> Result<crate::models::StockInfo, String> {
-    StockApi::get_stock_info(&stock_code)
-        .await
-        .map_err(|e| e.to_string())
+pub async fn get_stock_info(stock_code: String) -> Result<crate::models::StockInfo> {
+    let stock_info = StockApi::get_stock_info(&stock_code)
+        .await?;
+
+    cache_quote(&stock_info.code, &stock_info.name, stock_info.current_price).await;
+    Ok(stock_info)
+}
+
+/// 缓存行情供导出价格等离线功能使用，失败时不影响行情查询
+async fn cache_quote(code: &str, name: &str, price: Money) {
+    let Ok(db) = get_database() else {
+        return;
+    };
+    let db_lock = db.lock().await;
+    if let Err(e) = db_lock.cache_stock_price(code, name, price).await {
+        println!("缓存行情失败: {}", e);
+    }
 }
 
 // 通知相关命令
@@ -163,7 +1158,7 @@ pub async fn send_notification(
     title: String,
     body: String,
     icon: Option<String>
-) -> Result<(), String> {
+) -> Result<()> {
     let mut notification = Notification::new(&app_handle.config().tauri.bundle.identifier)
         .title(title)
         .body(body);
@@ -172,78 +1167,253 @@ pub async fn send_notification(
         notification = notification.icon(icon_path);
     }
 
-    notification.show().map_err(|e| e.to_string())
+    notification.show().map_err(|e| AppError::Internal(e.into()))
 }
 
 #[command]
-pub async fn check_price_alerts_and_notify(
-    app_handle: tauri::AppHandle,
-    buy_step_percentage: f64,
-    annual_return_rate: f64,
-) -> Result<Vec<String>, String> {
-    let db = get_database();
-    let db_lock = db.lock().await;
+pub async fn check_price_alerts_and_notify(app_handle: tauri::AppHandle) -> Result<Vec<String>> {
+    let db = get_database()?;
 
-    // 获取所有交易记录
-    let trades = db_lock.get_all_trades().await.map_err(|e| e.to_string())?;
+    // 获取所有交易记录和策略设置
+    let (trades, mut strategy) = {
+        let db_lock = db.lock().await;
+        (db_lock.get_all_trades().await?, StrategyResolver::load(&db_lock).await?)
+    };
+    let mut codes: Vec<&str> = trades.iter().map(|t| t.stock_code.as_str()).collect();
+    codes.sort();
+    codes.dedup();
+
+    // 按波动率计算台阶时先联网补齐日线，联网期间不持有数据库锁
+    let fetched = strategy.fetch_bars(&codes).await;
+    let db_lock = db.lock().await;
+    strategy.load_bars(&db_lock, &codes, &fetched).await?;
+    let high_water = db_lock.get_high_water_marks().await?;
     let mut alerts = Vec::new();
+    let mut prices: HashMap<String, Money> = HashMap::new();
 
-    for trade in trades {
+    for trade in &trades {
         if let Some(trade_id) = trade.id {
             // 计算价格目标
             let days_held = (Utc::now() - trade.buy_time).num_days();
-            let sell_target = PriceCalculator::calculate_sell_target_price(
-                trade.buy_price,
-                annual_return_rate,
-                days_held,
-            );
-            let buy_target = PriceCalculator::calculate_buy_target_price(
-                sell_target,
-                buy_step_percentage,
-            );
-
-            // 获取当前股价
-            if let Ok(current_price) = StockApi::get_stock_price(&trade.stock_code).await {
-                let price_reached = PriceCalculator::check_price_target(
-                    current_price,
-                    sell_target,
-                    buy_target,
+            let params = strategy.resolve(trade);
+
+            // 获取当前股价，真实行情不可用时才使用模拟数据
+            let real_price = StockApi::fetch_real_stock_price(&trade.stock_code).await.ok();
+            let current_price = match real_price {
+                Some(price) => Ok(price),
+                None => StockApi::get_stock_price(&trade.stock_code).await,
+            };
+            if let Ok(current_price) = current_price {
+                let _ = db_lock.cache_stock_price(&trade.stock_code, &trade.stock_name, current_price).await;
+                prices.insert(trade.stock_code.clone(), current_price);
+
+                // 跟踪止损需要买入以来的最高价，只用真实行情更新
+                let stored_high = high_water.get(&trade_id).copied();
+                let high_water_price = match real_price {
+                    Some(price) => match strategy::update_high_water_mark(&db_lock, trade, price, stored_high).await {
+                        Ok(high) => high,
+                        Err(e) => {
+                            println!("更新最高价失败: {}", e);
+                            stored_high.unwrap_or(trade.buy_price).max(price)
+                        }
+                    },
+                    None => stored_high.unwrap_or(trade.buy_price),
+                };
+                let tick = trade.security_id().map(|id| id.tick_size()).unwrap_or(Money::FEN);
+                let evaluation = params.evaluate(
+                    trade.buy_price,
+                    trade.quantity as i64,
+                    days_held,
+                    Some(current_price),
+                    high_water_price,
+                    tick,
                 );
+                let Some(target) = evaluation.signal.target().copied() else {
+                    continue;
+                };
+                let symbol = trade.currency().symbol();
+                let (title, message) = match evaluation.signal {
+                    PriceSignal::Sell(_) => (
+                        "🔔 卖出提醒",
+                        format!(
+                            "{}({}) 已达到卖出目标价格 {}{}，当前价格 {}{}，按目标价盈亏 {}{}",
+                            trade.stock_name, trade.stock_code, symbol, target.target_price, symbol, current_price, symbol, target.projected_pnl
+                        ),
+                    ),
+                    PriceSignal::Buy(_) => (
+                        "🔔 买入提醒",
+                        format!(
+                            "{}({}) 已达到买入目标价格 {}{}，当前价格 {}{}",
+                            trade.stock_name, trade.stock_code, symbol, target.target_price, symbol, current_price
+                        ),
+                    ),
+                    PriceSignal::Stop { reason, .. } => (
+                        "🔔 止损提醒",
+                        format!(
+                            "{}({}) 已触发{} {}{}，当前价格 {}{}，按止损价盈亏 {}{}",
+                            trade.stock_name, trade.stock_code, reason.label(), symbol, target.target_price, symbol, current_price, symbol, target.projected_pnl
+                        ),
+                    ),
+                    PriceSignal::Hold(_) | PriceSignal::NoQuote => continue,
+                };
 
-                match price_reached.as_str() {
-                    "sell" => {
-                        let message = format!(
-                            "{}({}) 已达到卖出目标价格 ¥{:.2}，当前价格 ¥{:.2}",
-                            trade.stock_name, trade.stock_code, sell_target, current_price
-                        );
+                // 发送通知
+                let _ = Notification::new(&app_handle.config().tauri.bundle.identifier)
+                    .title(title)
+                    .body(&message)
+                    .show();
+
+                let alert_type = evaluation.signal.kind();
+                record_alert(&db_lock, trade, trade_id, alert_type, target.target_price, current_price, &message).await;
+                alerts.push(message);
+            }
+        }
+    }
 
-                        // 发送通知
-                        let _ = Notification::new(&app_handle.config().tauri.bundle.identifier)
-                            .title("🔔 卖出提醒")
-                            .body(&message)
-                            .show();
+    // 网格计划按档提醒，先用最新的交易记录更新各档的成交状态；每档在同一成交状态下每个方向只提醒一次
+    let plans = db_lock.get_ladder_plans().await?;
+    for mut plan in plans {
+        let mut changed = ladder::match_fills(&mut plan, &trades);
 
-                        alerts.push(message);
-                    }
-                    "buy" => {
-                        let message = format!(
-                            "{}({}) 已达到买入目标价格 ¥{:.2}，当前价格 ¥{:.2}",
-                            trade.stock_name, trade.stock_code, buy_target, current_price
-                        );
-
-                        // 发送通知
-                        let _ = Notification::new(&app_handle.config().tauri.bundle.identifier)
-                            .title("🔔 买入提醒")
-                            .body(&message)
-                            .show();
-
-                        alerts.push(message);
-                    }
-                    _ => {}
+        let price = match prices.get(&plan.stock_code) {
+            Some(price) => Some(*price),
+            None => match StockApi::get_stock_price(&plan.stock_code).await {
+                Ok(price) => {
+                    prices.insert(plan.stock_code.clone(), price);
+                    Some(price)
                 }
+                Err(_) => None,
+            },
+        };
+
+        if let Some(current_price) = price {
+            let symbol = SecurityId::parse(&plan.stock_code).map(|id| id.currency()).unwrap_or_default().symbol();
+            let triggered: Vec<(TradeSide, LadderRung)> = ladder::triggered_rungs(&plan, current_price)
+                .into_iter()
+                .map(|(side, rung)| (side, rung.clone()))
+                .collect();
+            for (side, rung) in triggered {
+                let (alert_type, title, action, target_price) = match side {
+                    TradeSide::Buy => ("buy", "🔔 网格买入提醒", "买入", rung.buy_price),
+                    TradeSide::Sell => ("sell", "🔔 网格卖出提醒", "卖出", rung.sell_price),
+                };
+                let message = format!(
+                    "{}({}) 网格第{}档已达到{}价格 {}{}，当前价格 {}{}，数量 {} 股",
+                    plan.stock_name, plan.stock_code, rung.level, action, symbol, target_price, symbol, current_price, rung.quantity
+                );
+
+                let _ = Notification::new(&app_handle.config().tauri.bundle.identifier)
+                    .title(title)
+                    .body(&message)
+                    .show();
+
+                let alert = AlertRecord {
+                    id: None,
+                    trade_id: rung.filled_trade_id,
+                    stock_code: plan.stock_code.clone(),
+                    stock_name: plan.stock_name.clone(),
+                    alert_type: alert_type.to_string(),
+                    target_price,
+                    current_price,
+                    message: message.clone(),
+                    triggered_at: Utc::now(),
+                };
+                if let Err(e) = db_lock.insert_alert(&alert).await {
+                    println!("保存提醒历史失败: {}", e);
+                }
+                alerts.push(message);
+
+                if let Some(alerted) = plan.rungs.iter_mut().find(|r| r.id == rung.id) {
+                    alerted.alerted_side = Some(side);
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            if let Err(e) = db_lock.update_ladder_fills(&plan.rungs).await {
+                println!("更新网格成交状态失败: {}", e);
             }
         }
     }
 
     Ok(alerts)
 }
+
+/// 保存提醒历史，失败时不影响通知
+async fn record_alert(
+    db: &Database,
+    trade: &Trade,
+    trade_id: i64,
+    alert_type: &str,
+    target_price: Money,
+    current_price: Money,
+    message: &str,
+) {
+    let alert = AlertRecord {
+        id: None,
+        trade_id: Some(trade_id),
+        stock_code: trade.stock_code.clone(),
+        stock_name: trade.stock_name.clone(),
+        alert_type: alert_type.to_string(),
+        target_price,
+        current_price,
+        message: message.to_string(),
+        triggered_at: Utc::now(),
+    };
+
+    if let Err(e) = db.insert_alert(&alert).await {
+        println!("保存提醒历史失败: {}", e);
+    }
+}
+
+#[command]
+pub async fn get_alert_history() -> Result<Vec<AlertRecord>> {
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock
+        .get_alert_history()
+        .await
+}
+
+// OneDrive 备份相关命令
+
+#[command]
+pub async fn onedrive_start_login() -> Result<DeviceCodeInfo> {
+    let config = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        OneDriveConfig::load(&db_lock).await?
+    };
+
+    OneDriveClient::new(config)?
+        .start_device_login()
+        .await
+        .map_err(AppError::from)
+}
+
+#[command]
+pub async fn onedrive_finish_login(device: DeviceCodeInfo) -> Result<()> {
+    let config = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        OneDriveConfig::load(&db_lock).await?
+    };
+
+    OneDriveClient::new(config)?
+        .finish_device_login(&device)
+        .await
+        .map_err(AppError::from)
+}
+
+#[command]
+pub async fn onedrive_logout() -> Result<()> {
+    OneDriveClient::logout().map_err(AppError::from)
+}
+
+#[command]
+pub async fn onedrive_backup_now() -> Result<OneDriveItem> {
+    onedrive::backup_database()
+        .await
+        .map_err(AppError::from)
+}
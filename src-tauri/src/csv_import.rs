@@ -0,0 +1,651 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use crate::error::FieldError;
+use crate::fx::Currency;
+use crate::market::TimeCheck;
+use crate::models::{default_account, Trade};
+use crate::money::{Money, Rounding};
+use crate::stock_api::StockApi;
+
+/// 自动识别时依次尝试的日期格式，"rfc3339" 表示带时区的 ISO 8601 时间
+const DATE_FORMATS: &[&str] = &[
+    "rfc3339",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y/%m/%d %H:%M:%S",
+    "%Y/%m/%d %H:%M",
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%Y%m%d %H:%M:%S",
+    "%Y%m%d",
+    "%Y.%m.%d",
+    "%Y年%m月%d日",
+    "%d/%m/%Y",
+    "%m/%d/%Y",
+];
+
+/// 各字段在未配置映射时自动匹配的表头
+const STOCK_CODE_HEADERS: &[&str] = &["stock_code", "代码", "证券代码", "股票代码"];
+const STOCK_NAME_HEADERS: &[&str] = &["stock_name", "名称", "证券名称", "股票名称"];
+const BUY_PRICE_HEADERS: &[&str] = &["buy_price", "价格", "买入价格", "成交价格", "成交均价"];
+const BUY_TIME_HEADERS: &[&str] = &["buy_time", "时间", "日期", "买入时间", "成交时间", "成交日期"];
+const QUANTITY_HEADERS: &[&str] = &["quantity", "数量", "成交数量", "买入数量"];
+const NOTES_HEADERS: &[&str] = &["notes", "备注"];
+const CURRENCY_HEADERS: &[&str] = &["currency", "币种", "货币"];
+
+/// A股一手的股数
+pub(crate) const LOT_SIZE: i32 = 100;
+
+/// CSV 表头到交易字段的映射，未配置的字段按常见表头自动匹配
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CsvColumnMapping {
+    pub stock_code: Option<String>,
+    pub stock_name: Option<String>,
+    pub buy_price: Option<String>,
+    pub buy_time: Option<String>,
+    pub quantity: Option<String>,
+    pub notes: Option<String>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberFormat {
+    /// 根据数据自动识别
+    #[default]
+    Auto,
+    /// 1,234.56
+    Standard,
+    /// 1.234,56
+    DecimalComma,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CsvImportOptions {
+    #[serde(default)]
+    pub mapping: CsvColumnMapping,
+    /// 分隔符，未指定时自动识别
+    pub delimiter: Option<char>,
+    /// chrono 日期格式，未指定时自动识别
+    pub date_format: Option<String>,
+    #[serde(default)]
+    pub number_format: NumberFormat,
+    /// 导入时跳过与已有记录重复的行，否则存在重复时整体失败
+    #[serde(default)]
+    pub skip_duplicates: bool,
+    /// 导入到的账户，未指定时使用默认账户
+    pub account: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CsvImportRow {
+    /// CSV 文件中的行号（含表头，从 1 开始）
+    pub line: usize,
+    pub trade: Option<Trade>,
+    pub errors: Vec<String>,
+    pub duplicate: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CsvImportPreview {
+    pub headers: Vec<String>,
+    pub date_format: Option<String>,
+    /// 日和月的顺序无法确定时可用的日期格式（如 03/04/2024），需要指定 date_format 后重新预览
+    pub ambiguous_date_formats: Vec<String>,
+    pub number_format: NumberFormat,
+    pub rows: Vec<CsvImportRow>,
+    pub valid_count: usize,
+    pub error_count: usize,
+    pub duplicate_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CsvImportResult {
+    pub imported: usize,
+    pub skipped_duplicates: usize,
+    pub trade_ids: Vec<i64>,
+}
+
+enum DateDetection {
+    Format(String),
+    Ambiguous(Vec<String>),
+    Unknown,
+}
+
+struct ResolvedColumns {
+    stock_code: usize,
+    stock_name: usize,
+    buy_price: usize,
+    buy_time: usize,
+    quantity: usize,
+    notes: Option<usize>,
+    currency: Option<usize>,
+}
+
+/// 交易记录 CSV 导入
+pub struct CsvImporter;
+
+impl CsvImporter {
+    /// 读取 CSV 文件内容，去掉 Excel 写入的 BOM
+    pub fn read_file(path: &Path) -> Result<String> {
+        let bytes = std::fs::read(path)?;
+        let content = String::from_utf8(bytes).map_err(|_| anyhow!("CSV 文件不是 UTF-8 编码"))?;
+        Ok(content.trim_start_matches('\u{feff}').to_string())
+    }
+
+    /// 解析并校验 CSV 内容，不写入数据库
+    pub fn preview(content: &str, options: &CsvImportOptions, existing: &[Trade]) -> Result<CsvImportPreview> {
+        let delimiter = options.delimiter.unwrap_or_else(|| Self::detect_delimiter(content));
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter as u8)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(content.as_bytes());
+
+        let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_string()).collect();
+        let columns = Self::resolve_columns(&headers, &options.mapping)?;
+        let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+
+        let field = |record: &csv::StringRecord, index: usize| record.get(index).unwrap_or("").to_string();
+
+        let (date_format, ambiguous_date_formats) = match &options.date_format {
+            Some(format) => (Some(format.clone()), Vec::new()),
+            None => {
+                let values: Vec<String> = records.iter().map(|r| field(r, columns.buy_time)).collect();
+                match Self::detect_date_format(&values) {
+                    DateDetection::Format(format) => (Some(format), Vec::new()),
+                    DateDetection::Ambiguous(formats) => (None, formats),
+                    DateDetection::Unknown => (None, Vec::new()),
+                }
+            }
+        };
+
+        let number_format = match options.number_format {
+            NumberFormat::Auto => {
+                let values: Vec<String> = records.iter().map(|r| field(r, columns.buy_price)).collect();
+                Self::detect_number_format(&values)
+            }
+            format => format,
+        };
+
+        let existing_keys: HashSet<_> = existing.iter().map(trade_key).collect();
+        let mut seen_keys = HashSet::new();
+        let mut rows = Vec::with_capacity(records.len());
+
+        for (index, record) in records.iter().enumerate() {
+            let mut errors = Vec::new();
+
+            let stock_code = normalize_stock_code(&field(record, columns.stock_code));
+            if !StockApi::validate_stock_code(&stock_code) {
+                errors.push(format!("股票代码格式错误: {}", stock_code));
+            }
+
+            let stock_name = field(record, columns.stock_name);
+            if stock_name.is_empty() {
+                errors.push("股票名称为空".to_string());
+            }
+
+            let buy_price = parse_number(&field(record, columns.buy_price), number_format).map(Money::from_f64);
+            match buy_price {
+                Some(price) if price.is_positive() => {}
+                Some(price) => errors.push(format!("买入价格必须大于0: {}", price)),
+                None => errors.push(format!("无法解析买入价格: {}", field(record, columns.buy_price))),
+            }
+
+            let raw_time = field(record, columns.buy_time);
+            let buy_time = date_format.as_deref().and_then(|format| parse_datetime(&raw_time, format));
+            if !ambiguous_date_formats.is_empty() {
+                errors.push(format!(
+                    "买入时间可按 {} 解析，请指定日期格式: {}",
+                    ambiguous_date_formats.join(" 或 "),
+                    raw_time
+                ));
+            } else if buy_time.is_none() {
+                errors.push(format!("无法解析买入时间: {}", raw_time));
+            }
+
+            // 按板块的买入数量规则由 check_trade_rules 检查
+            let quantity = parse_number(&field(record, columns.quantity), number_format)
+                .filter(|q| q.fract() == 0.0 && *q > 0.0 && *q <= i32::MAX as f64)
+                .map(|q| q as i32);
+            if quantity.is_none() {
+                errors.push(format!("数量必须为正整数: {}", field(record, columns.quantity)));
+            }
+
+            let notes = columns
+                .notes
+                .map(|i| field(record, i))
+                .filter(|n| !n.is_empty());
+
+            // 币种列可省略，为空时按代码推断
+            let currency = match columns.currency.map(|i| field(record, i)).filter(|c| !c.is_empty()) {
+                Some(text) => match text.parse::<Currency>() {
+                    Ok(currency) => Some(currency),
+                    Err(e) => {
+                        errors.push(e.to_string());
+                        None
+                    }
+                },
+                None => None,
+            };
+
+            let trade = match (buy_price, buy_time, quantity) {
+                (Some(buy_price), Some(buy_time), Some(quantity)) if errors.is_empty() => Some(Trade {
+                    id: None,
+                    stock_code,
+                    stock_name,
+                    buy_price,
+                    buy_time,
+                    quantity,
+                    notes,
+                    account: options.account.clone().unwrap_or_else(default_account),
+                    security: None,
+                    currency,
+                    created_at: None,
+                }),
+                _ => None,
+            };
+
+            // 与数据库或文件中前面的行完全相同视为重复
+            let duplicate = trade
+                .as_ref()
+                .map(|t| {
+                    let key = trade_key(t);
+                    existing_keys.contains(&key) || !seen_keys.insert(key)
+                })
+                .unwrap_or(false);
+
+            rows.push(CsvImportRow {
+                line: index + 2,
+                trade,
+                errors,
+                duplicate,
+            });
+        }
+
+        let error_count = rows.iter().filter(|r| !r.errors.is_empty()).count();
+        let duplicate_count = rows.iter().filter(|r| r.duplicate).count();
+
+        Ok(CsvImportPreview {
+            headers,
+            date_format,
+            ambiguous_date_formats,
+            number_format,
+            valid_count: rows.len() - error_count - duplicate_count,
+            error_count,
+            duplicate_count,
+            rows,
+        })
+    }
+
+    /// 按交易规则（代码、数量、价格区间、交易时段）检查预览中解析成功的行，不符合的行改为错误行
+    ///
+    /// 日期格式不含时间时只检查买入日期是否为交易日。
+    pub fn check_trade_rules(preview: &mut CsvImportPreview, mut validate: impl FnMut(&Trade, TimeCheck) -> Vec<FieldError>) {
+        let time_check = match preview.date_format.as_deref() {
+            Some(format) if format != "rfc3339" && !format.contains("%H") => TimeCheck::TradingDay,
+            _ => TimeCheck::Session,
+        };
+
+        for row in &mut preview.rows {
+            let Some(trade) = &row.trade else { continue };
+            let errors = validate(trade, time_check);
+            if !errors.is_empty() {
+                row.errors.extend(errors.into_iter().map(|e| e.message));
+                row.trade = None;
+                row.duplicate = false;
+            }
+        }
+
+        preview.error_count = preview.rows.iter().filter(|r| !r.errors.is_empty()).count();
+        preview.duplicate_count = preview.rows.iter().filter(|r| r.duplicate).count();
+        preview.valid_count = preview.rows.len() - preview.error_count - preview.duplicate_count;
+    }
+
+    /// 从预览结果中取出待导入的交易，存在错误时整体拒绝
+    pub fn trades_to_import(preview: &CsvImportPreview, skip_duplicates: bool) -> Result<Vec<Trade>> {
+        if preview.error_count > 0 {
+            let lines: Vec<String> = preview
+                .rows
+                .iter()
+                .filter(|r| !r.errors.is_empty())
+                .map(|r| format!("第{}行: {}", r.line, r.errors.join("; ")))
+                .collect();
+            return Err(anyhow!("{} 行数据有误，未导入任何记录\n{}", preview.error_count, lines.join("\n")));
+        }
+
+        if preview.duplicate_count > 0 && !skip_duplicates {
+            return Err(anyhow!("发现 {} 条重复记录，未导入任何记录", preview.duplicate_count));
+        }
+
+        Ok(preview
+            .rows
+            .iter()
+            .filter(|r| !r.duplicate)
+            .filter_map(|r| r.trade.clone())
+            .collect())
+    }
+
+    fn detect_delimiter(content: &str) -> char {
+        let first_line = content.lines().next().unwrap_or("");
+        [',', ';', '\t', '|']
+            .into_iter()
+            .max_by_key(|d| first_line.matches(*d).count())
+            .unwrap_or(',')
+    }
+
+    fn resolve_columns(headers: &[String], mapping: &CsvColumnMapping) -> Result<ResolvedColumns> {
+        let find = |configured: &Option<String>, aliases: &[&str]| -> Option<usize> {
+            match configured {
+                Some(name) => headers.iter().position(|h| h == name.trim()),
+                None => headers
+                    .iter()
+                    .position(|h| aliases.iter().any(|a| h.eq_ignore_ascii_case(a))),
+            }
+        };
+        let require = |configured: &Option<String>, aliases: &[&str], label: &str| -> Result<usize> {
+            find(configured, aliases).ok_or_else(|| {
+                anyhow!("找不到{}列: {}", label, configured.as_deref().unwrap_or(aliases[0]))
+            })
+        };
+
+        Ok(ResolvedColumns {
+            stock_code: require(&mapping.stock_code, STOCK_CODE_HEADERS, "股票代码")?,
+            stock_name: require(&mapping.stock_name, STOCK_NAME_HEADERS, "股票名称")?,
+            buy_price: require(&mapping.buy_price, BUY_PRICE_HEADERS, "买入价格")?,
+            buy_time: require(&mapping.buy_time, BUY_TIME_HEADERS, "买入时间")?,
+            quantity: require(&mapping.quantity, QUANTITY_HEADERS, "数量")?,
+            notes: find(&mapping.notes, NOTES_HEADERS),
+            currency: find(&mapping.currency, CURRENCY_HEADERS),
+        })
+    }
+
+    /// 选择能解析全部非空值的第一个日期格式；交换日和月后的格式同样能解析全部值时无法确定
+    fn detect_date_format(values: &[String]) -> DateDetection {
+        let samples: Vec<&str> = values.iter().map(|v| v.trim()).filter(|v| !v.is_empty()).collect();
+        if samples.is_empty() {
+            return DateDetection::Unknown;
+        }
+
+        let matches: Vec<&str> = DATE_FORMATS
+            .iter()
+            .copied()
+            .filter(|format| samples.iter().all(|v| parse_datetime(v, format).is_some()))
+            .collect();
+        let Some(&format) = matches.first() else {
+            return DateDetection::Unknown;
+        };
+
+        let swapped = format.replace("%d", "\0").replace("%m", "%d").replace('\0', "%m");
+        if swapped != format && matches.contains(&swapped.as_str()) {
+            DateDetection::Ambiguous(vec![format.to_string(), swapped])
+        } else {
+            DateDetection::Format(format.to_string())
+        }
+    }
+
+    /// 出现 "12,50" 这类逗号后仅有1-2位数字的值时按逗号小数处理
+    fn detect_number_format(values: &[String]) -> NumberFormat {
+        let decimal_comma = values.iter().any(|v| {
+            let v = v.trim();
+            match (v.rfind(','), v.rfind('.')) {
+                (Some(comma), dot) if dot.map(|d| d < comma).unwrap_or(true) => {
+                    (1..=2).contains(&(v.len() - comma - 1))
+                }
+                _ => false,
+            }
+        });
+
+        if decimal_comma {
+            NumberFormat::DecimalComma
+        } else {
+            NumberFormat::Standard
+        }
+    }
+}
+
+/// 用于重复检测的键：账户、代码、时间、价格（取整到分）、数量
+pub(crate) fn trade_key(trade: &Trade) -> (String, String, i64, Money, i32) {
+    (
+        trade.account.clone(),
+        trade.stock_code.clone(),
+        trade.buy_time.timestamp(),
+        trade.buy_price.round_to(Money::FEN, Rounding::HalfUp),
+        trade.quantity,
+    )
+}
+
+/// 统一股票代码写法：去掉 sh/sz/bj 前缀、.SH 等后缀和 Excel 文本标记，补齐被去掉的前导零
+pub(crate) fn normalize_stock_code(raw: &str) -> String {
+    let code = raw
+        .trim()
+        .trim_matches(|c| c == '=' || c == '"' || c == '\'')
+        .to_ascii_lowercase();
+    let code = code
+        .strip_prefix("sh")
+        .or_else(|| code.strip_prefix("sz"))
+        .or_else(|| code.strip_prefix("bj"))
+        .unwrap_or(&code);
+    let code = code.split('.').next().unwrap_or("");
+
+    if !code.is_empty() && code.len() < 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        format!("{:0>6}", code)
+    } else {
+        code.to_string()
+    }
+}
+
+pub(crate) fn parse_number(raw: &str, format: NumberFormat) -> Option<f64> {
+    let cleaned: String = raw
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '¥' | '￥' | '元' | '股' | ' ' | '\u{a0}'))
+        .collect();
+
+    let normalized = match format {
+        NumberFormat::DecimalComma => cleaned.replace('.', "").replace(',', "."),
+        _ => cleaned.replace(',', ""),
+    };
+
+    normalized.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+/// 按指定格式解析时间；无时区的时间按北京时间处理，只有日期时记为当日收盘
+pub(crate) fn parse_datetime(raw: &str, format: &str) -> Option<DateTime<Utc>> {
+    let raw = raw.trim();
+    if format == "rfc3339" {
+        return DateTime::parse_from_rfc3339(raw).ok().map(|t| t.with_timezone(&Utc));
+    }
+
+    let naive = NaiveDateTime::parse_from_str(raw, format).ok().or_else(|| {
+        NaiveDate::parse_from_str(raw, format)
+            .ok()
+            .map(|date| date.and_time(NaiveTime::from_hms_opt(15, 0, 0).unwrap()))
+    })?;
+
+    FixedOffset::east_opt(8 * 3600)?
+        .from_local_datetime(&naive)
+        .single()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// 依次尝试所有支持的日期格式解析单个值
+pub(crate) fn parse_datetime_auto(raw: &str) -> Option<DateTime<Utc>> {
+    DATE_FORMATS.iter().find_map(|format| parse_datetime(raw, format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    fn preview(content: &str, options: &CsvImportOptions, existing: &[Trade]) -> CsvImportPreview {
+        CsvImporter::preview(content, options, existing).unwrap()
+    }
+
+    #[test]
+    fn detects_date_formats_and_flags_ambiguous_day_month() {
+        let detect = |values: &[&str]| match CsvImporter::detect_date_format(&strings(values)) {
+            DateDetection::Format(format) => Ok(format),
+            DateDetection::Ambiguous(formats) => Err(formats),
+            DateDetection::Unknown => Err(Vec::new()),
+        };
+
+        assert_eq!(detect(&["2024-01-15 10:30:00", "2024-02-01 14:00:00"]), Ok("%Y-%m-%d %H:%M:%S".to_string()));
+        assert_eq!(detect(&["2024/01/15"]), Ok("%Y/%m/%d".to_string()));
+        assert_eq!(detect(&["2024年1月15日"]), Ok("%Y年%m月%d日".to_string()));
+        // 有一个值的日大于 12 即可确定顺序
+        assert_eq!(detect(&["03/04/2024", "25/04/2024"]), Ok("%d/%m/%Y".to_string()));
+        assert_eq!(detect(&["03/04/2024", "04/25/2024"]), Ok("%m/%d/%Y".to_string()));
+        assert_eq!(
+            detect(&["03/04/2024", "05/06/2024"]),
+            Err(strings(&["%d/%m/%Y", "%m/%d/%Y"]))
+        );
+        assert_eq!(detect(&["昨天"]), Err(Vec::new()));
+    }
+
+    #[test]
+    fn detects_number_formats() {
+        assert_eq!(CsvImporter::detect_number_format(&strings(&["12.50", "1,234.56"])), NumberFormat::Standard);
+        assert_eq!(CsvImporter::detect_number_format(&strings(&["12,50", "8"])), NumberFormat::DecimalComma);
+        assert_eq!(parse_number("1,234.56", NumberFormat::Standard), Some(1234.56));
+        assert_eq!(parse_number("1.234,56", NumberFormat::DecimalComma), Some(1234.56));
+        assert_eq!(parse_number("¥12.5元", NumberFormat::Standard), Some(12.5));
+        assert_eq!(parse_number("abc", NumberFormat::Standard), None);
+    }
+
+    #[test]
+    fn validates_rows() {
+        let content = "代码,名称,价格,日期,数量\n\
+            sh600000,浦发银行,10.50,2024-01-15,200\n\
+            1,平安银行,12.00,2024-01-16,100\n\
+            999999,,0,2024-01-17,150\n\
+            600036,招商银行,35.2,2024-13-01,100\n";
+        let result = preview(content, &CsvImportOptions::default(), &[]);
+
+        assert_eq!(result.date_format.as_deref(), None);
+        let options = CsvImportOptions { date_format: Some("%Y-%m-%d".to_string()), ..Default::default() };
+        let result = preview(content, &options, &[]);
+        assert_eq!((result.valid_count, result.error_count), (2, 2));
+
+        let first = result.rows[0].trade.as_ref().unwrap();
+        assert_eq!(first.stock_code, "600000");
+        assert_eq!(first.buy_price, "10.50".parse().unwrap());
+        assert_eq!(first.account, "default");
+        // 只有日期时记为北京时间收盘
+        assert_eq!(first.buy_time.to_rfc3339(), "2024-01-15T07:00:00+00:00");
+        assert_eq!(result.rows[1].trade.as_ref().unwrap().stock_code, "000001");
+
+        assert_eq!(result.rows[2].line, 4);
+        assert_eq!(result.rows[2].errors.len(), 3);
+        assert!(result.rows[3].errors[0].contains("无法解析买入时间"));
+        assert!(CsvImporter::trades_to_import(&result, true).is_err());
+    }
+
+    #[test]
+    fn reads_optional_currency_column() {
+        let content = "stock_code,stock_name,buy_price,currency,buy_time,quantity\n\
+            hk00700,腾讯控股,300,HKD,2024-01-15T10:00:00+08:00,100\n\
+            600000,浦发银行,10.5,,2024-01-15T10:00:00+08:00,100\n\
+            600036,招商银行,35.2,EUR,2024-01-15T10:00:00+08:00,100\n";
+        let result = preview(content, &CsvImportOptions::default(), &[]);
+
+        assert_eq!(result.rows[0].trade.as_ref().unwrap().currency, Some(Currency::Hkd));
+        assert_eq!(result.rows[1].trade.as_ref().unwrap().currency, None);
+        assert!(result.rows[2].errors[0].contains("不支持的货币"));
+    }
+
+    #[test]
+    fn applies_trade_rules_to_parsed_rows() {
+        let content = "代码,名称,价格,日期,数量\n\
+            600000,浦发银行,10.50,2024-01-15,150\n\
+            688981,中芯国际,50,2024-01-15,201\n\
+            600036,招商银行,35.2,2024-01-13,100\n";
+        let mut result = preview(content, &CsvImportOptions::default(), &[]);
+        assert_eq!(result.valid_count, 3);
+
+        let mut checks = Vec::new();
+        CsvImporter::check_trade_rules(&mut result, |trade, time_check| {
+            checks.push(time_check);
+            crate::market::validate_trade(trade, None, time_check, Utc::now())
+        });
+
+        // 只有日期时只检查是否为交易日，2024-01-13 为周六
+        assert_eq!(checks, [TimeCheck::TradingDay; 3]);
+        assert_eq!((result.valid_count, result.error_count), (1, 2));
+        assert!(result.rows[0].errors[0].contains("整数倍"));
+        assert!(result.rows[0].trade.is_none());
+        assert!(result.rows[1].errors.is_empty());
+        assert!(result.rows[2].errors[0].contains("交易日"));
+    }
+
+    #[test]
+    fn rejects_ambiguous_dates_until_format_is_given() {
+        let content = "代码,名称,价格,日期,数量\n600000,浦发银行,10.50,03/04/2024,100\n";
+        let result = preview(content, &CsvImportOptions::default(), &[]);
+        assert_eq!(result.ambiguous_date_formats, strings(&["%d/%m/%Y", "%m/%d/%Y"]));
+        assert_eq!(result.error_count, 1);
+        assert!(result.rows[0].errors[0].contains("请指定日期格式"));
+
+        let options = CsvImportOptions { date_format: Some("%m/%d/%Y".to_string()), ..Default::default() };
+        let result = preview(content, &options, &[]);
+        assert_eq!(result.error_count, 0);
+        assert_eq!(result.rows[0].trade.as_ref().unwrap().buy_time.date_naive().to_string(), "2024-03-04");
+    }
+
+    #[test]
+    fn detects_duplicates_in_file_and_database() {
+        let content = "stock_code,stock_name,buy_price,buy_time,quantity\n\
+            600000,浦发银行,10.50,2024-01-15 10:00:00,100\n\
+            600000,浦发银行,10.50,2024-01-15 10:00:00,100\n\
+            600036,招商银行,35.20,2024-01-16 10:00:00,100\n";
+        let existing = preview(content, &CsvImportOptions::default(), &[]).rows[2].trade.clone().unwrap();
+        let result = preview(content, &CsvImportOptions::default(), &[existing]);
+
+        let duplicates: Vec<bool> = result.rows.iter().map(|r| r.duplicate).collect();
+        assert_eq!(duplicates, [false, true, true]);
+        assert_eq!(result.valid_count, 1);
+
+        // 不跳过重复时整体拒绝，跳过时只导入不重复的行
+        assert!(CsvImporter::trades_to_import(&result, false).is_err());
+        let trades = CsvImporter::trades_to_import(&result, true).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].stock_code, "600000");
+    }
+
+    #[test]
+    fn uses_configured_mapping_and_delimiter() {
+        let content = "Symbol;Name;Px;When;Qty;Memo\n600000;浦发银行;10,50;2024-01-15 10:00;1.000;补录\n";
+        let options = CsvImportOptions {
+            mapping: CsvColumnMapping {
+                stock_code: Some("Symbol".to_string()),
+                stock_name: Some("Name".to_string()),
+                buy_price: Some("Px".to_string()),
+                buy_time: Some("When".to_string()),
+                quantity: Some("Qty".to_string()),
+                notes: Some("Memo".to_string()),
+                currency: None,
+            },
+            ..Default::default()
+        };
+        let result = preview(content, &options, &[]);
+
+        assert_eq!(result.number_format, NumberFormat::DecimalComma);
+        let trade = result.rows[0].trade.as_ref().unwrap();
+        assert_eq!((trade.buy_price, trade.quantity), ("10.5".parse().unwrap(), 1000));
+        assert_eq!(trade.notes.as_deref(), Some("补录"));
+
+        let missing = CsvImportOptions {
+            mapping: CsvColumnMapping { stock_code: Some("Code".to_string()), ..Default::default() },
+            ..Default::default()
+        };
+        assert!(CsvImporter::preview(content, &missing, &[]).is_err());
+    }
+}
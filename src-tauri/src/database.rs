@@ -1,30 +1,50 @@
-use sqlx::{sqlite::SqlitePool, Row};
-use anyhow::Result;
-use std::path::PathBuf;
+use sqlx::{sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions}, Row};
+use crate::error::{AppError, Result};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock};
 use tokio::sync::Mutex;
 use tauri::api::path::app_data_dir;
-use crate::models::Trade;
+use crate::models::{
+    AlertRecord, BrokerTransaction, DailyBar, FxRate, LadderPlan, LadderRung, OverrideScope, PaperFill, PaperPortfolio, PaperPosition, Settings,
+    StrategyOverride, Trade, ValuationSnapshot,
+};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use crate::export::{ExportData, ExportDocument};
+use crate::keychain;
+use crate::money::Money;
+use crate::security::SecurityId;
+
+/// 系统钥匙串中保存数据库密码的键名
+pub const DATABASE_PASSPHRASE_KEY: &str = "database_passphrase";
+
+/// 未加密 SQLite 文件的文件头
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
 
 pub struct Database {
     pool: SqlitePool,
+    path: Option<PathBuf>,
+    /// 当前使用的密码，替换文件失败时用它重新打开原来的数据库
+    passphrase: Option<String>,
 }
 
 impl Database {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(passphrase: Option<&str>) -> Result<Self> {
         // 首先尝试使用当前目录，这样更简单可靠
-        let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
-        let db_path = current_dir.join("stock_trader.db");
-        let database_url = format!("sqlite:{}", db_path.display());
+        let db_path = default_database_path();
 
-        println!("尝试连接数据库: {}", database_url);
+        println!("尝试连接数据库: {}", db_path.display());
 
-        match SqlitePool::connect(&database_url).await {
+        match Self::connect_file(&db_path, passphrase).await {
             Ok(pool) => {
                 println!("数据库连接成功");
-                return Ok(Database { pool });
+                return Ok(Database::with_pool(pool, Some(db_path), passphrase));
             }
             Err(e) => {
+                // 加密数据库解锁失败时不能回退到其他位置，否则会看不到已有数据
+                if is_encrypted_file(&db_path) {
+                    return Err(e);
+                }
                 println!("当前目录数据库连接失败: {}, 尝试应用数据目录", e);
             }
         }
@@ -36,15 +56,17 @@ impl Database {
 
             if let Ok(_) = std::fs::create_dir_all(&app_data_path) {
                 let db_path = app_data_path.join("stock_trader.db");
-                let database_url = format!("sqlite:{}", db_path.display());
-                println!("连接应用数据目录数据库: {}", database_url);
+                println!("连接应用数据目录数据库: {}", db_path.display());
 
-                match SqlitePool::connect(&database_url).await {
+                match Self::connect_file(&db_path, passphrase).await {
                     Ok(pool) => {
                         println!("应用数据目录数据库连接成功");
-                        return Ok(Database { pool });
+                        return Ok(Database::with_pool(pool, Some(db_path), passphrase));
                     }
                     Err(e) => {
+                        if is_encrypted_file(&db_path) {
+                            return Err(e);
+                        }
                         println!("应用数据目录数据库连接失败: {}", e);
                     }
                 }
@@ -57,7 +79,144 @@ impl Database {
         let pool = SqlitePool::connect(database_url).await?;
         println!("内存数据库连接成功");
 
-        Ok(Database { pool })
+        Ok(Database::with_pool(pool, None, None))
+    }
+
+    fn with_pool(pool: SqlitePool, path: Option<PathBuf>, passphrase: Option<&str>) -> Self {
+        // 未加密的文件不需要记住密码
+        let passphrase = passphrase.filter(|_| path.as_deref().map(is_encrypted_file).unwrap_or(false));
+        Database { pool, path, passphrase: passphrase.map(str::to_string) }
+    }
+
+    /// 连接数据库文件，文件已加密时使用密码解锁
+    async fn connect_file(path: &Path, passphrase: Option<&str>) -> Result<SqlitePool> {
+        let mut options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+
+        if is_encrypted_file(path) {
+            let passphrase = passphrase
+                .ok_or(AppError::DatabaseLocked)?;
+            options = options.pragma("key", quote_sql_literal(passphrase));
+        }
+
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+
+        // 密码错误时连接本身不会失败，需要读取一次数据库才能发现
+        if sqlx::query("SELECT count(*) FROM sqlite_master")
+            .fetch_one(&pool)
+            .await
+            .is_err()
+        {
+            pool.close().await;
+            return Err(AppError::InvalidPassphrase);
+        }
+
+        Ok(pool)
+    }
+
+    /// 数据库文件是否已加密
+    pub fn is_encrypted(&self) -> bool {
+        self.path.as_deref().map(is_encrypted_file).unwrap_or(false)
+    }
+
+    /// 为未加密的数据库启用加密
+    pub async fn enable_encryption(&mut self, passphrase: &str) -> Result<()> {
+        let path = self.file_path()?;
+        if is_encrypted_file(&path) {
+            return Err(AppError::Validation("数据库已经加密".to_string()));
+        }
+
+        let temp_path = path.with_extension("db.encrypting");
+        self.export_to(&temp_path, passphrase).await?;
+        self.replace_file(&path, &temp_path, Some(passphrase)).await
+    }
+
+    /// 解除数据库加密
+    pub async fn disable_encryption(&mut self) -> Result<()> {
+        let path = self.file_path()?;
+        if !is_encrypted_file(&path) {
+            return Err(AppError::Validation("数据库未加密".to_string()));
+        }
+
+        // 以空密钥导出即为明文数据库
+        let temp_path = path.with_extension("db.decrypting");
+        self.export_to(&temp_path, "").await?;
+        self.replace_file(&path, &temp_path, None).await
+    }
+
+    /// 修改加密数据库的密码
+    pub async fn change_passphrase(&mut self, new_passphrase: &str) -> Result<()> {
+        let path = self.file_path()?;
+        if !is_encrypted_file(&path) {
+            return Err(AppError::Validation("数据库未加密".to_string()));
+        }
+
+        {
+            let mut conn = self.pool.acquire().await?;
+            sqlx::query(&format!("PRAGMA rekey = {}", quote_sql_literal(new_passphrase)))
+                .execute(&mut *conn)
+                .await?;
+        }
+
+        // 连接池中的其他连接仍使用旧密码，需要全部重建
+        self.pool.close().await;
+        self.pool = Self::connect_file(&path, Some(new_passphrase)).await?;
+        self.passphrase = Some(new_passphrase.to_string());
+
+        Ok(())
+    }
+
+    fn file_path(&self) -> Result<PathBuf> {
+        self.path
+            .clone()
+            .ok_or_else(|| AppError::Validation("内存数据库不支持加密".to_string()))
+    }
+
+    /// 使用 sqlcipher_export 将当前数据库完整导出到指定密钥的新文件
+    async fn export_to(&self, target: &Path, key: &str) -> Result<()> {
+        if target.exists() {
+            std::fs::remove_file(target)?;
+        }
+
+        // ATTACH 只对当前连接生效，三条语句必须在同一个连接上执行
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("ATTACH DATABASE ? AS export_target KEY ?")
+            .bind(target.to_string_lossy().to_string())
+            .bind(key)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("SELECT sqlcipher_export('export_target')")
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DETACH DATABASE export_target")
+            .execute(&mut *conn)
+            .await?;
+
+        Ok(())
+    }
+
+    /// 用导出的新文件替换当前数据库文件并重新连接，失败时恢复原文件并用原来的密码重新连接
+    async fn replace_file(&mut self, path: &Path, new_file: &Path, passphrase: Option<&str>) -> Result<()> {
+        self.pool.close().await;
+
+        let backup_path = path.with_extension("db.bak");
+        std::fs::rename(path, &backup_path)?;
+        std::fs::rename(new_file, path)?;
+
+        match Self::connect_file(path, passphrase).await {
+            Ok(pool) => {
+                self.pool = pool;
+                self.passphrase = passphrase.map(str::to_string);
+                std::fs::remove_file(&backup_path)?;
+                Ok(())
+            }
+            Err(e) => {
+                std::fs::rename(&backup_path, path)?;
+                self.pool = Self::connect_file(path, self.passphrase.as_deref()).await?;
+                Err(e)
+            }
+        }
     }
 
     pub async fn init_tables(&self) -> Result<()> {
@@ -68,16 +227,22 @@ impl Database {
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 stock_code TEXT NOT NULL,
                 stock_name TEXT NOT NULL,
-                buy_price REAL NOT NULL,
+                buy_price TEXT NOT NULL,
                 buy_time DATETIME NOT NULL,
                 quantity INTEGER NOT NULL,
                 notes TEXT,
+                account TEXT NOT NULL DEFAULT 'default',
                 created_at DATETIME DEFAULT CURRENT_TIMESTAMP
             )
             "#,
         )
         .execute(&self.pool)
         .await?;
+        self.ensure_column("trades", "account", "TEXT NOT NULL DEFAULT 'default'").await?;
+        self.ensure_column("trades", "security", "TEXT").await?;
+        self.ensure_column("trades", "currency", "TEXT").await?;
+        self.migrate_money_columns("trades", &["buy_price"]).await?;
+        self.backfill_trade_securities().await?;
 
         // 创建股票信息表
         sqlx::query(
@@ -85,13 +250,14 @@ impl Database {
             CREATE TABLE IF NOT EXISTS stocks (
                 code TEXT PRIMARY KEY,
                 name TEXT NOT NULL,
-                current_price REAL,
+                current_price TEXT,
                 last_updated DATETIME
             )
             "#,
         )
         .execute(&self.pool)
         .await?;
+        self.migrate_money_columns("stocks", &["current_price"]).await?;
 
         // 创建用户配置表
         sqlx::query(
@@ -105,21 +271,385 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // 创建券商交割单流水表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS broker_transactions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                broker TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                trade_time DATETIME NOT NULL,
+                stock_code TEXT,
+                stock_name TEXT,
+                operation TEXT NOT NULL,
+                price TEXT NOT NULL,
+                quantity INTEGER NOT NULL,
+                amount TEXT NOT NULL,
+                net_amount TEXT NOT NULL,
+                fees TEXT NOT NULL,
+                contract_no TEXT,
+                imported_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("broker_transactions", &["price", "amount", "net_amount", "fees"]).await?;
+
+        // 重复导入同一份交割单时忽略已有流水
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_broker_transactions_unique
+            ON broker_transactions (broker, trade_time, operation, IFNULL(stock_code, ''), quantity, net_amount, IFNULL(contract_no, ''))
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 创建价格提醒历史表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS alert_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                trade_id INTEGER,
+                stock_code TEXT NOT NULL,
+                stock_name TEXT NOT NULL,
+                alert_type TEXT NOT NULL,
+                target_price TEXT NOT NULL,
+                current_price TEXT NOT NULL,
+                message TEXT NOT NULL,
+                triggered_at DATETIME NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("alert_history", &["target_price", "current_price"]).await?;
+
+        // 创建日线行情表，股票和指数共用
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS daily_prices (
+                code TEXT NOT NULL,
+                trade_date DATE NOT NULL,
+                open TEXT NOT NULL,
+                high TEXT NOT NULL,
+                low TEXT NOT NULL,
+                close TEXT NOT NULL,
+                volume INTEGER NOT NULL,
+                PRIMARY KEY (code, trade_date)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("daily_prices", &["open", "high", "low", "close"]).await?;
+
+        // 创建每日汇率表，rate 为 1 单位外币折合的人民币
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS fx_rates (
+                currency TEXT NOT NULL,
+                rate_date DATE NOT NULL,
+                rate REAL NOT NULL,
+                PRIMARY KEY (currency, rate_date)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // 创建每日持仓估值快照表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS valuation_snapshots (
+                snapshot_date DATE NOT NULL,
+                account TEXT NOT NULL,
+                stock_code TEXT NOT NULL,
+                stock_name TEXT NOT NULL,
+                quantity INTEGER NOT NULL,
+                close_price TEXT NOT NULL,
+                market_value TEXT NOT NULL,
+                cost TEXT NOT NULL,
+                PRIMARY KEY (snapshot_date, account, stock_code)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("valuation_snapshots", &["close_price", "market_value", "cost"]).await?;
+
+        // 创建模拟盘表，与真实交易分开保存
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS paper_portfolios (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                initial_cash TEXT NOT NULL,
+                cash TEXT NOT NULL,
+                annual_return_rate REAL NOT NULL,
+                buy_step_percentage REAL NOT NULL,
+                min_holding_days INTEGER NOT NULL,
+                growth_model TEXT NOT NULL DEFAULT 'simple',
+                day_count_basis TEXT NOT NULL DEFAULT 'days360',
+                lots_per_trade INTEGER NOT NULL,
+                commission_rate REAL NOT NULL,
+                min_commission TEXT NOT NULL,
+                stamp_tax_rate REAL NOT NULL,
+                transfer_fee_rate REAL NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.ensure_column("paper_portfolios", "growth_model", "TEXT NOT NULL DEFAULT 'simple'").await?;
+        self.ensure_column("paper_portfolios", "day_count_basis", "TEXT NOT NULL DEFAULT 'days360'").await?;
+        self.migrate_money_columns("paper_portfolios", &["initial_cash", "cash", "min_commission"]).await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS paper_positions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                portfolio_id INTEGER NOT NULL,
+                stock_code TEXT NOT NULL,
+                stock_name TEXT NOT NULL,
+                buy_price TEXT NOT NULL,
+                quantity INTEGER NOT NULL,
+                buy_fees TEXT NOT NULL,
+                buy_time DATETIME NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("paper_positions", &["buy_price", "buy_fees"]).await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS paper_fills (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                portfolio_id INTEGER NOT NULL,
+                stock_code TEXT NOT NULL,
+                stock_name TEXT NOT NULL,
+                side TEXT NOT NULL,
+                price TEXT NOT NULL,
+                quantity INTEGER NOT NULL,
+                fees TEXT NOT NULL,
+                realized_pnl TEXT,
+                target_price TEXT,
+                filled_at DATETIME NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("paper_fills", &["price", "fees", "realized_pnl", "target_price"]).await?;
+
+        // 创建策略参数覆盖表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS strategy_overrides (
+                scope TEXT NOT NULL,
+                scope_key TEXT NOT NULL,
+                annual_return_rate REAL,
+                buy_step_percentage REAL,
+                min_holding_days INTEGER,
+                stop_loss_percent REAL,
+                stop_loss_price TEXT,
+                time_stop_days INTEGER,
+                trailing_stop_percent REAL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (scope, scope_key)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        for column in ["stop_loss_percent", "trailing_stop_percent"] {
+            self.ensure_column("strategy_overrides", column, "REAL").await?;
+        }
+        self.ensure_column("strategy_overrides", "stop_loss_price", "TEXT").await?;
+        self.ensure_column("strategy_overrides", "time_stop_days", "INTEGER").await?;
+        self.migrate_money_columns("strategy_overrides", &["stop_loss_price"]).await?;
+
+        // 创建持仓最高价表，用于跟踪止损
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS trade_high_water (
+                trade_id INTEGER PRIMARY KEY,
+                high_price TEXT NOT NULL,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("trade_high_water", &["high_price"]).await?;
+
+        // 创建网格加仓计划表
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS ladder_plans (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                account TEXT NOT NULL DEFAULT 'default',
+                stock_code TEXT NOT NULL,
+                stock_name TEXT NOT NULL,
+                anchor_price TEXT NOT NULL,
+                budget TEXT NOT NULL,
+                step_percentage REAL NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("ladder_plans", &["anchor_price", "budget"]).await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS ladder_rungs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                plan_id INTEGER NOT NULL,
+                level INTEGER NOT NULL,
+                buy_price TEXT NOT NULL,
+                sell_price TEXT NOT NULL,
+                quantity INTEGER NOT NULL,
+                filled_trade_id INTEGER,
+                alerted_side TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        self.migrate_money_columns("ladder_rungs", &["buy_price", "sell_price"]).await?;
+        self.ensure_column("ladder_rungs", "alerted_side", "TEXT").await?;
+
         // 插入默认配置
         self.init_default_settings().await?;
 
         Ok(())
     }
 
+    /// 为旧版本数据库补充新增的列
+    async fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let columns = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+
+        if !columns.iter().any(|row| row.get::<String, _>("name") == column) {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// 旧版本把金额保存在 REAL 列中，重建为 TEXT 列并按 0.0001 元取整写成与 [`Money`] 一致的十进制文本
+    async fn migrate_money_columns(&self, table: &str, columns: &[&str]) -> Result<()> {
+        let info = sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(&self.pool)
+            .await?;
+        let real: Vec<&str> = columns
+            .iter()
+            .copied()
+            .filter(|column| {
+                info.iter().any(|row| {
+                    row.get::<String, _>("name") == *column && row.get::<String, _>("type").eq_ignore_ascii_case("REAL")
+                })
+            })
+            .collect();
+        if real.is_empty() {
+            return Ok(());
+        }
+
+        let sql: String = sqlx::query_scalar("SELECT sql FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_one(&self.pool)
+            .await?;
+        let names: Vec<String> = info.iter().map(|row| row.get("name")).collect();
+        let names = names.join(", ");
+        let backup = format!("{}_real_backup", table);
+
+        // 整张表在一个事务中重建，原表上的索引随原表一起删除，之后由建表流程重新创建
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(&format!("ALTER TABLE {} RENAME TO {}", table, backup))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&retype_columns(&sql, &real, "TEXT"))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!("INSERT INTO {} ({}) SELECT {} FROM {}", table, names, names, backup))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!("DROP TABLE {}", backup))
+            .execute(&mut *tx)
+            .await?;
+
+        for column in real {
+            let rows = sqlx::query(&format!("SELECT rowid AS row_id, {} AS value FROM {} WHERE {} IS NOT NULL", column, table, column))
+                .fetch_all(&mut *tx)
+                .await?;
+            for row in rows {
+                sqlx::query(&format!("UPDATE {} SET {} = ? WHERE rowid = ?", table, column))
+                    .bind(row.try_get::<Money, _>("value")?)
+                    .bind(row.get::<i64, _>("row_id"))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 为旧版本保存的交易按代码规则补充证券标识和计价货币，无法识别的代码保持为空
+    async fn backfill_trade_securities(&self) -> Result<()> {
+        let rows = sqlx::query("SELECT id, stock_code FROM trades WHERE security IS NULL OR currency IS NULL")
+            .fetch_all(&self.pool)
+            .await?;
+
+        for row in rows {
+            let Some(security) = SecurityId::parse(&row.get::<String, _>("stock_code")) else {
+                continue;
+            };
+            sqlx::query("UPDATE trades SET security = COALESCE(security, ?), currency = COALESCE(currency, ?) WHERE id = ?")
+                .bind(&security)
+                .bind(security.currency())
+                .bind(row.get::<i64, _>("id"))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
     async fn init_default_settings(&self) -> Result<()> {
         let default_settings = vec![
             ("buy_step_percentage", "0.05"),  // 5%
             ("annual_return_rate", "0.20"),   // 20%
+            ("min_holding_days", "30"),
+            ("buy_step_mode", "fixed"),       // 固定台阶
+            ("buy_step_multiplier", "2.0"),   // 波动率台阶 = 倍数 × 波动率
+            ("buy_step_floor", "0.02"),
+            ("buy_step_ceiling", "0.15"),
+            ("stop_loss_percent", ""),        // 为空表示不启用
+            ("time_stop_days", ""),
+            ("trailing_stop_percent", ""),
+            ("growth_model", "simple"),       // 单利
+            ("day_count_basis", "days360"),
+            ("base_currency", "CNY"),         // 组合合计使用的基准货币
             ("notification_enabled", "true"),
             ("sound_enabled", "true"),
             ("auto_backup_enabled", "false"),
             ("backup_interval", "24"),        // 24小时
             ("onedrive_enabled", "false"),
+            ("onedrive_client_id", ""),
+            ("onedrive_graph_base_url", crate::onedrive::DEFAULT_GRAPH_BASE_URL),
+            ("onedrive_device_code_endpoint", crate::onedrive::DEFAULT_DEVICE_CODE_ENDPOINT),
+            ("onedrive_token_endpoint", crate::onedrive::DEFAULT_TOKEN_ENDPOINT),
+            ("onedrive_backup_folder", "stock-trader"),
             ("webdav_enabled", "false"),
         ];
 
@@ -138,8 +668,8 @@ impl Database {
     pub async fn create_trade(&self, trade: &Trade) -> Result<i64> {
         let result = sqlx::query(
             r#"
-            INSERT INTO trades (stock_code, stock_name, buy_price, buy_time, quantity, notes)
-            VALUES (?, ?, ?, ?, ?, ?)
+            INSERT INTO trades (stock_code, stock_name, buy_price, buy_time, quantity, notes, account, security, currency)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
             "#,
         )
         .bind(&trade.stock_code)
@@ -148,15 +678,49 @@ impl Database {
         .bind(trade.buy_time)
         .bind(trade.quantity)
         .bind(&trade.notes)
+        .bind(&trade.account)
+        .bind(trade.security_id())
+        .bind(trade.currency())
         .execute(&self.pool)
         .await?;
 
         Ok(result.last_insert_rowid())
     }
 
+    /// 在同一个事务中批量插入交易记录，任意一条失败则全部回滚
+    pub async fn create_trades(&self, trades: &[Trade]) -> Result<Vec<i64>> {
+        let mut tx = self.pool.begin().await?;
+        let mut ids = Vec::with_capacity(trades.len());
+
+        for trade in trades {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO trades (stock_code, stock_name, buy_price, buy_time, quantity, notes, account, security, currency)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&trade.stock_code)
+            .bind(&trade.stock_name)
+            .bind(trade.buy_price)
+            .bind(trade.buy_time)
+            .bind(trade.quantity)
+            .bind(&trade.notes)
+            .bind(&trade.account)
+            .bind(trade.security_id())
+            .bind(trade.currency())
+            .execute(&mut *tx)
+            .await?;
+
+            ids.push(result.last_insert_rowid());
+        }
+
+        tx.commit().await?;
+        Ok(ids)
+    }
+
     pub async fn get_all_trades(&self) -> Result<Vec<Trade>> {
         let trades = sqlx::query_as::<_, Trade>(
-            "SELECT id, stock_code, stock_name, buy_price, buy_time, quantity, notes, created_at FROM trades ORDER BY buy_time DESC"
+            "SELECT id, stock_code, stock_name, buy_price, buy_time, quantity, notes, account, security, currency, created_at FROM trades ORDER BY buy_time DESC"
         )
         .fetch_all(&self.pool)
         .await?;
@@ -164,11 +728,22 @@ impl Database {
         Ok(trades)
     }
 
+    /// 按 ID 读取交易记录，不存在时返回 NotFound
+    pub async fn get_trade(&self, id: i64) -> Result<Trade> {
+        sqlx::query_as::<_, Trade>(
+            "SELECT id, stock_code, stock_name, buy_price, buy_time, quantity, notes, account, security, currency, created_at FROM trades WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("交易记录不存在".to_string()))
+    }
+
     pub async fn update_trade(&self, trade: &Trade) -> Result<()> {
-        sqlx::query(
+        let result = sqlx::query(
             r#"
             UPDATE trades 
-            SET stock_code = ?, stock_name = ?, buy_price = ?, buy_time = ?, quantity = ?, notes = ?
+            SET stock_code = ?, stock_name = ?, buy_price = ?, buy_time = ?, quantity = ?, notes = ?, account = ?, security = ?, currency = ?
             WHERE id = ?
             "#,
         )
@@ -178,52 +753,1061 @@ impl Database {
         .bind(trade.buy_time)
         .bind(trade.quantity)
         .bind(&trade.notes)
+        .bind(&trade.account)
+        .bind(trade.security_id())
+        .bind(trade.currency())
         .bind(trade.id)
         .execute(&self.pool)
         .await?;
 
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("交易记录不存在".to_string()));
+        }
         Ok(())
     }
 
     pub async fn delete_trade(&self, id: i64) -> Result<()> {
-        sqlx::query("DELETE FROM trades WHERE id = ?")
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM trades WHERE id = ?")
             .bind(id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::NotFound("交易记录不存在".to_string()));
+        }
 
-        Ok(())
-    }
-
-
-
-    // 配置操作
-    pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
-        let row = sqlx::query("SELECT value FROM settings WHERE key = ?")
-            .bind(key)
-            .fetch_optional(&self.pool)
+        // 同时删除这笔交易单独设置的策略参数和最高价记录
+        sqlx::query("DELETE FROM strategy_overrides WHERE scope = ? AND scope_key = ?")
+            .bind(OverrideScope::Trade)
+            .bind(id.to_string())
+            .execute(&mut *tx)
             .await?;
-
-        Ok(row.map(|r| r.get("value")))
-    }
-
-    pub async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
-        sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)")
-            .bind(key)
-            .bind(value)
-            .execute(&self.pool)
+        sqlx::query("DELETE FROM trade_high_water WHERE trade_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
             .await?;
 
+        tx.commit().await?;
         Ok(())
     }
-}
 
-// 使用线程安全的全局数据库实例
+    // 行情缓存操作
+    /// 缓存最近获取到的股价
+    pub async fn cache_stock_price(&self, code: &str, name: &str, price: Money) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO stocks (code, name, current_price, last_updated)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(code) DO UPDATE SET
+                name = excluded.name,
+                current_price = excluded.current_price,
+                last_updated = excluded.last_updated
+            "#,
+        )
+        .bind(code)
+        .bind(name)
+        .bind(price)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // 价格提醒历史操作
+    pub async fn insert_alert(&self, alert: &AlertRecord) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO alert_history (trade_id, stock_code, stock_name, alert_type, target_price, current_price, message, triggered_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(alert.trade_id)
+        .bind(&alert.stock_code)
+        .bind(&alert.stock_name)
+        .bind(&alert.alert_type)
+        .bind(alert.target_price)
+        .bind(alert.current_price)
+        .bind(&alert.message)
+        .bind(alert.triggered_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn get_alert_history(&self) -> Result<Vec<AlertRecord>> {
+        let alerts = sqlx::query_as::<_, AlertRecord>(
+            r#"
+            SELECT id, trade_id, stock_code, stock_name, alert_type, target_price, current_price, message, triggered_at
+            FROM alert_history ORDER BY triggered_at DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(alerts)
+    }
+
+    // 券商交割单操作
+    /// 批量写入交割单流水，已存在的流水会被忽略，返回新增条数
+    pub async fn insert_broker_transactions(&self, transactions: &[BrokerTransaction]) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut inserted = 0;
+
+        for t in transactions {
+            let result = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO broker_transactions
+                    (broker, kind, trade_time, stock_code, stock_name, operation, price, quantity, amount, net_amount, fees, contract_no)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(t.broker)
+            .bind(t.kind)
+            .bind(t.trade_time)
+            .bind(&t.stock_code)
+            .bind(&t.stock_name)
+            .bind(&t.operation)
+            .bind(t.price)
+            .bind(t.quantity)
+            .bind(t.amount)
+            .bind(t.net_amount)
+            .bind(t.fees)
+            .bind(&t.contract_no)
+            .execute(&mut *tx)
+            .await?;
+
+            inserted += result.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(inserted)
+    }
+
+    pub async fn get_broker_transactions(&self) -> Result<Vec<BrokerTransaction>> {
+        let transactions = sqlx::query_as::<_, BrokerTransaction>(
+            r#"
+            SELECT id, broker, kind, trade_time, stock_code, stock_name, operation, price, quantity, amount, net_amount, fees, contract_no
+            FROM broker_transactions ORDER BY trade_time
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(transactions)
+    }
+
+    // 日线行情操作
+    /// 写入日线行情，同一天的数据会被覆盖
+    pub async fn upsert_daily_bars(&self, code: &str, bars: &[DailyBar]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for bar in bars {
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO daily_prices (code, trade_date, open, high, low, close, volume)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(code)
+            .bind(bar.date)
+            .bind(bar.open)
+            .bind(bar.high)
+            .bind(bar.low)
+            .bind(bar.close)
+            .bind(bar.volume)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 读取最近保存的 count 根日线，不限日期，按日期升序
+    pub async fn get_latest_daily_bars(&self, code: &str, count: usize) -> Result<Vec<DailyBar>> {
+        let mut bars = sqlx::query_as::<_, DailyBar>(
+            r#"
+            SELECT trade_date AS date, open, high, low, close, volume
+            FROM daily_prices WHERE code = ?
+            ORDER BY trade_date DESC LIMIT ?
+            "#,
+        )
+        .bind(code)
+        .bind(count as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        bars.reverse();
+        Ok(bars)
+    }
+
+    /// 读取日期闭区间内的日线行情，按日期升序
+    pub async fn get_daily_bars(&self, code: &str, start: NaiveDate, end: NaiveDate) -> Result<Vec<DailyBar>> {
+        let bars = sqlx::query_as::<_, DailyBar>(
+            r#"
+            SELECT trade_date AS date, open, high, low, close, volume
+            FROM daily_prices WHERE code = ? AND trade_date BETWEEN ? AND ?
+            ORDER BY trade_date
+            "#,
+        )
+        .bind(code)
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(bars)
+    }
+
+    // 汇率操作
+    /// 写入每日汇率，同一货币同一天的汇率会被覆盖
+    pub async fn upsert_fx_rates(&self, rates: &[FxRate]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for rate in rates {
+            sqlx::query("INSERT OR REPLACE INTO fx_rates (currency, rate_date, rate) VALUES (?, ?, ?)")
+                .bind(rate.currency)
+                .bind(rate.rate_date)
+                .bind(rate.rate)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 读取全部汇率，按货币和日期升序
+    pub async fn get_fx_rates(&self) -> Result<Vec<FxRate>> {
+        let rates = sqlx::query_as::<_, FxRate>(
+            "SELECT currency, rate_date, rate FROM fx_rates ORDER BY currency, rate_date"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rates)
+    }
+
+    // 估值快照操作
+    /// 用新的快照替换 from 当天及之后的全部快照
+    pub async fn replace_valuation_snapshots(&self, from: NaiveDate, snapshots: &[ValuationSnapshot]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM valuation_snapshots WHERE snapshot_date >= ?")
+            .bind(from)
+            .execute(&mut *tx)
+            .await?;
+
+        for s in snapshots {
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO valuation_snapshots
+                    (snapshot_date, account, stock_code, stock_name, quantity, close_price, market_value, cost)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(s.snapshot_date)
+            .bind(&s.account)
+            .bind(&s.stock_code)
+            .bind(&s.stock_name)
+            .bind(s.quantity)
+            .bind(s.close_price)
+            .bind(s.market_value)
+            .bind(s.cost)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_valuation_snapshots(&self, account: Option<&str>) -> Result<Vec<ValuationSnapshot>> {
+        let snapshots = sqlx::query_as::<_, ValuationSnapshot>(
+            r#"
+            SELECT snapshot_date, account, stock_code, stock_name, quantity, close_price, market_value, cost
+            FROM valuation_snapshots WHERE ? IS NULL OR account = ?
+            ORDER BY snapshot_date, account, stock_code
+            "#,
+        )
+        .bind(account)
+        .bind(account)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(snapshots)
+    }
+
+    pub async fn latest_valuation_date(&self) -> Result<Option<NaiveDate>> {
+        let date = sqlx::query_scalar::<_, Option<NaiveDate>>("SELECT MAX(snapshot_date) FROM valuation_snapshots")
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(date)
+    }
+
+    // 模拟盘操作
+    pub async fn create_paper_portfolio(&self, portfolio: &PaperPortfolio) -> Result<i64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO paper_portfolios
+                (name, initial_cash, cash, annual_return_rate, buy_step_percentage, min_holding_days, growth_model,
+                 day_count_basis, lots_per_trade, commission_rate, min_commission, stamp_tax_rate, transfer_fee_rate,
+                 created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&portfolio.name)
+        .bind(portfolio.initial_cash)
+        .bind(portfolio.cash)
+        .bind(portfolio.annual_return_rate)
+        .bind(portfolio.buy_step_percentage)
+        .bind(portfolio.min_holding_days)
+        .bind(portfolio.growth_model)
+        .bind(portfolio.day_count_basis)
+        .bind(portfolio.lots_per_trade)
+        .bind(portfolio.commission_rate)
+        .bind(portfolio.min_commission)
+        .bind(portfolio.stamp_tax_rate)
+        .bind(portfolio.transfer_fee_rate)
+        .bind(portfolio.created_at.unwrap_or_else(chrono::Utc::now))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.last_insert_rowid())
+    }
+
+    pub async fn get_paper_portfolios(&self) -> Result<Vec<PaperPortfolio>> {
+        let portfolios = sqlx::query_as::<_, PaperPortfolio>(
+            r#"
+            SELECT id, name, initial_cash, cash, annual_return_rate, buy_step_percentage, min_holding_days, growth_model,
+                   day_count_basis, lots_per_trade, commission_rate, min_commission, stamp_tax_rate, transfer_fee_rate,
+                   created_at
+            FROM paper_portfolios ORDER BY id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(portfolios)
+    }
+
+    /// 删除模拟盘及其持仓和成交记录
+    pub async fn delete_paper_portfolio(&self, id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for table in ["paper_fills", "paper_positions"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE portfolio_id = ?", table))
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM paper_portfolios WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_paper_positions(&self, portfolio_id: Option<i64>) -> Result<Vec<PaperPosition>> {
+        let positions = sqlx::query_as::<_, PaperPosition>(
+            r#"
+            SELECT id, portfolio_id, stock_code, stock_name, buy_price, quantity, buy_fees, buy_time
+            FROM paper_positions WHERE ? IS NULL OR portfolio_id = ?
+            ORDER BY buy_time, id
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(positions)
+    }
+
+    pub async fn get_paper_fills(&self, portfolio_id: Option<i64>) -> Result<Vec<PaperFill>> {
+        let fills = sqlx::query_as::<_, PaperFill>(
+            r#"
+            SELECT id, portfolio_id, stock_code, stock_name, side, price, quantity, fees, realized_pnl, target_price, filled_at
+            FROM paper_fills WHERE ? IS NULL OR portfolio_id = ?
+            ORDER BY filled_at DESC, id DESC
+            "#,
+        )
+        .bind(portfolio_id)
+        .bind(portfolio_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(fills)
+    }
+
+    /// 在同一个事务中记录模拟成交：删除卖出的持仓、写入新买入的持仓和成交记录，并更新可用资金
+    ///
+    /// 卖出的持仓已不存在时说明成交计划基于过期的数据，整个事务回滚并返回 NotFound。
+    pub async fn apply_paper_fills(
+        &self,
+        portfolio_id: i64,
+        cash: Money,
+        closed: &[i64],
+        opened: &[PaperPosition],
+        fills: &[PaperFill],
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for id in closed {
+            let result = sqlx::query("DELETE FROM paper_positions WHERE id = ? AND portfolio_id = ?")
+                .bind(id)
+                .bind(portfolio_id)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() != 1 {
+                return Err(AppError::NotFound(format!("模拟持仓 {} 不存在或已卖出", id)));
+            }
+        }
+
+        for p in opened {
+            sqlx::query(
+                r#"
+                INSERT INTO paper_positions (portfolio_id, stock_code, stock_name, buy_price, quantity, buy_fees, buy_time)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(portfolio_id)
+            .bind(&p.stock_code)
+            .bind(&p.stock_name)
+            .bind(p.buy_price)
+            .bind(p.quantity)
+            .bind(p.buy_fees)
+            .bind(p.buy_time)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for f in fills {
+            sqlx::query(
+                r#"
+                INSERT INTO paper_fills
+                    (portfolio_id, stock_code, stock_name, side, price, quantity, fees, realized_pnl, target_price, filled_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(portfolio_id)
+            .bind(&f.stock_code)
+            .bind(&f.stock_name)
+            .bind(f.side)
+            .bind(f.price)
+            .bind(f.quantity)
+            .bind(f.fees)
+            .bind(f.realized_pnl)
+            .bind(f.target_price)
+            .bind(f.filled_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query("UPDATE paper_portfolios SET cash = ? WHERE id = ?")
+            .bind(cash)
+            .bind(portfolio_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // 网格加仓计划操作
+    pub async fn create_ladder_plan(&self, plan: &LadderPlan) -> Result<i64> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO ladder_plans (account, stock_code, stock_name, anchor_price, budget, step_percentage, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&plan.account)
+        .bind(&plan.stock_code)
+        .bind(&plan.stock_name)
+        .bind(plan.anchor_price)
+        .bind(plan.budget)
+        .bind(plan.step_percentage)
+        .bind(plan.created_at.unwrap_or_else(chrono::Utc::now))
+        .execute(&mut *tx)
+        .await?;
+        let plan_id = result.last_insert_rowid();
+
+        for rung in &plan.rungs {
+            sqlx::query(
+                r#"
+                INSERT INTO ladder_rungs (plan_id, level, buy_price, sell_price, quantity, filled_trade_id, alerted_side)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(plan_id)
+            .bind(rung.level)
+            .bind(rung.buy_price)
+            .bind(rung.sell_price)
+            .bind(rung.quantity)
+            .bind(rung.filled_trade_id)
+            .bind(rung.alerted_side)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(plan_id)
+    }
+
+    /// 读取全部网格计划及其各档
+    pub async fn get_ladder_plans(&self) -> Result<Vec<LadderPlan>> {
+        let mut plans = sqlx::query_as::<_, LadderPlan>(
+            r#"
+            SELECT id, account, stock_code, stock_name, anchor_price, budget, step_percentage, created_at
+            FROM ladder_plans ORDER BY id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let rungs = sqlx::query_as::<_, LadderRung>(
+            r#"
+            SELECT id, plan_id, level, buy_price, sell_price, quantity, filled_trade_id, alerted_side
+            FROM ladder_rungs ORDER BY plan_id, level
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for rung in rungs {
+            if let Some(plan) = plans.iter_mut().find(|p| p.id == rung.plan_id) {
+                plan.rungs.push(rung);
+            }
+        }
+
+        Ok(plans)
+    }
+
+    pub async fn delete_ladder_plan(&self, id: i64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM ladder_rungs WHERE plan_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM ladder_plans WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// 更新各档对应的成交交易和已提醒的方向
+    pub async fn update_ladder_fills(&self, rungs: &[LadderRung]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for rung in rungs {
+            sqlx::query("UPDATE ladder_rungs SET filled_trade_id = ?, alerted_side = ? WHERE id = ?")
+                .bind(rung.filled_trade_id)
+                .bind(rung.alerted_side)
+                .bind(rung.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // 策略参数覆盖操作
+    pub async fn get_strategy_overrides(&self) -> Result<Vec<StrategyOverride>> {
+        let overrides = sqlx::query_as::<_, StrategyOverride>(
+            r#"
+            SELECT scope, scope_key, annual_return_rate, buy_step_percentage, min_holding_days,
+                   stop_loss_percent, stop_loss_price, time_stop_days, trailing_stop_percent
+            FROM strategy_overrides ORDER BY scope, scope_key
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(overrides)
+    }
+
+    /// 保存策略参数覆盖，所有参数都为空时删除该条覆盖
+    pub async fn set_strategy_override(&self, item: &StrategyOverride) -> Result<()> {
+        let empty = item.annual_return_rate.is_none()
+            && item.buy_step_percentage.is_none()
+            && item.min_holding_days.is_none()
+            && item.stop_loss_percent.is_none()
+            && item.stop_loss_price.is_none()
+            && item.time_stop_days.is_none()
+            && item.trailing_stop_percent.is_none();
+        if empty {
+            return self.delete_strategy_override(item.scope, &item.scope_key).await;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO strategy_overrides
+                (scope, scope_key, annual_return_rate, buy_step_percentage, min_holding_days,
+                 stop_loss_percent, stop_loss_price, time_stop_days, trailing_stop_percent, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(item.scope)
+        .bind(&item.scope_key)
+        .bind(item.annual_return_rate)
+        .bind(item.buy_step_percentage)
+        .bind(item.min_holding_days)
+        .bind(item.stop_loss_percent)
+        .bind(item.stop_loss_price)
+        .bind(item.time_stop_days)
+        .bind(item.trailing_stop_percent)
+        .bind(chrono::Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_strategy_override(&self, scope: OverrideScope, scope_key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM strategy_overrides WHERE scope = ? AND scope_key = ?")
+            .bind(scope)
+            .bind(scope_key)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // 持仓最高价操作
+    pub async fn get_high_water_marks(&self) -> Result<std::collections::HashMap<i64, Money>> {
+        let rows = sqlx::query_as::<_, (i64, Money)>("SELECT trade_id, high_price FROM trade_high_water")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().collect())
+    }
+
+    pub async fn set_high_water_mark(&self, trade_id: i64, high_price: Money) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO trade_high_water (trade_id, high_price, updated_at) VALUES (?, ?, ?)")
+            .bind(trade_id)
+            .bind(high_price)
+            .bind(chrono::Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // 配置操作
+    pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let row = sqlx::query("SELECT value FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("value")))
+    }
+
+    pub async fn get_all_settings(&self) -> Result<Vec<Settings>> {
+        let settings = sqlx::query_as::<_, Settings>("SELECT key, value FROM settings ORDER BY key")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(settings)
+    }
+
+    pub async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query("INSERT OR REPLACE INTO settings (key, value) VALUES (?, ?)")
+            .bind(key)
+            .bind(value)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // 导入导出操作
+    /// 读取导出需要的全部数据
+    pub async fn export_data(&self) -> Result<ExportData> {
+        Ok(ExportData {
+            trades: self.get_all_trades().await?,
+            alert_history: self.get_alert_history().await?,
+            broker_transactions: self.get_broker_transactions().await?,
+            settings: self.get_all_settings().await?,
+            valuation_snapshots: self.get_valuation_snapshots(None).await?,
+            paper_portfolios: self.get_paper_portfolios().await?,
+            paper_positions: self.get_paper_positions(None).await?,
+            paper_fills: self.get_paper_fills(None).await?,
+            strategy_overrides: self.get_strategy_overrides().await?,
+            ladder_plans: self.get_ladder_plans().await?,
+            fx_rates: self.get_fx_rates().await?,
+        })
+    }
+
+    /// 在同一个事务中导入导出文件，返回导入的交易数
+    ///
+    /// replace 为 true 时先清空已有的交易、提醒和交割单，以及文件中包含的其余部分；
+    /// 为 false 时与现有数据合并，已有的设置、快照、汇率和策略参数保持不变，
+    /// 已存在的模拟盘和网格计划（名称或股票相同且创建时间相同）不再重复导入。
+    /// 文件中的交易 ID 会映射到导入后新生成的 ID，known_trade_ids 为合并时跳过的重复交易对应的现有 ID
+    pub async fn import_snapshot(
+        &self,
+        document: &ExportDocument,
+        known_trade_ids: HashMap<i64, i64>,
+        replace: bool,
+    ) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let conflict = if replace { "REPLACE" } else { "IGNORE" };
+
+        if replace {
+            let mut tables = vec!["trades", "alert_history", "broker_transactions", "trade_high_water"];
+            if document.valuation_snapshots.is_some() {
+                tables.push("valuation_snapshots");
+            }
+            if document.paper_portfolios.is_some() {
+                tables.extend(["paper_fills", "paper_positions", "paper_portfolios"]);
+            }
+            if document.ladder_plans.is_some() {
+                tables.extend(["ladder_rungs", "ladder_plans"]);
+            }
+            if document.strategy_overrides.is_some() {
+                tables.push("strategy_overrides");
+            }
+            if document.fx_rates.is_some() {
+                tables.push("fx_rates");
+            }
+            for table in tables {
+                sqlx::query(&format!("DELETE FROM {}", table))
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            // 旧版本文件不含策略参数，交易 ID 会重新生成，按交易设置的策略参数无法再对应
+            sqlx::query("DELETE FROM strategy_overrides WHERE scope = ?")
+                .bind(OverrideScope::Trade)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        let mut trade_ids = known_trade_ids;
+        for trade in &document.trades {
+            let result = sqlx::query(
+                r#"
+                INSERT INTO trades (stock_code, stock_name, buy_price, buy_time, quantity, notes, account, security, currency, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&trade.stock_code)
+            .bind(&trade.stock_name)
+            .bind(trade.buy_price)
+            .bind(trade.buy_time)
+            .bind(trade.quantity)
+            .bind(&trade.notes)
+            .bind(&trade.account)
+            .bind(trade.security_id())
+            .bind(trade.currency())
+            .bind(trade.created_at.unwrap_or_else(chrono::Utc::now))
+            .execute(&mut *tx)
+            .await?;
+
+            if let Some(old_id) = trade.id {
+                trade_ids.insert(old_id, result.last_insert_rowid());
+            }
+        }
+
+        for alert in &document.alert_history {
+            sqlx::query(
+                r#"
+                INSERT INTO alert_history (trade_id, stock_code, stock_name, alert_type, target_price, current_price, message, triggered_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(alert.trade_id.and_then(|id| trade_ids.get(&id).copied()))
+            .bind(&alert.stock_code)
+            .bind(&alert.stock_name)
+            .bind(&alert.alert_type)
+            .bind(alert.target_price)
+            .bind(alert.current_price)
+            .bind(&alert.message)
+            .bind(alert.triggered_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for t in &document.broker_transactions {
+            sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO broker_transactions
+                    (broker, kind, trade_time, stock_code, stock_name, operation, price, quantity, amount, net_amount, fees, contract_no)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(t.broker)
+            .bind(t.kind)
+            .bind(t.trade_time)
+            .bind(&t.stock_code)
+            .bind(&t.stock_name)
+            .bind(&t.operation)
+            .bind(t.price)
+            .bind(t.quantity)
+            .bind(t.amount)
+            .bind(t.net_amount)
+            .bind(t.fees)
+            .bind(&t.contract_no)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for setting in &document.settings {
+            sqlx::query(&format!("INSERT OR {} INTO settings (key, value) VALUES (?, ?)", conflict))
+                .bind(&setting.key)
+                .bind(&setting.value)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for s in document.valuation_snapshots.iter().flatten() {
+            sqlx::query(&format!(
+                r#"
+                INSERT OR {} INTO valuation_snapshots
+                    (snapshot_date, account, stock_code, stock_name, quantity, close_price, market_value, cost)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                conflict
+            ))
+            .bind(s.snapshot_date)
+            .bind(&s.account)
+            .bind(&s.stock_code)
+            .bind(&s.stock_name)
+            .bind(s.quantity)
+            .bind(s.close_price)
+            .bind(s.market_value)
+            .bind(s.cost)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for rate in document.fx_rates.iter().flatten() {
+            sqlx::query(&format!("INSERT OR {} INTO fx_rates (currency, rate_date, rate) VALUES (?, ?, ?)", conflict))
+                .bind(rate.currency)
+                .bind(rate.rate_date)
+                .bind(rate.rate)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for item in document.strategy_overrides.iter().flatten() {
+            // 按交易设置的参数改挂到新的交易 ID，对应的交易没有导入时丢弃
+            let scope_key = match item.scope {
+                OverrideScope::Trade => match item.scope_key.parse().ok().and_then(|id: i64| trade_ids.get(&id)) {
+                    Some(id) => id.to_string(),
+                    None => continue,
+                },
+                OverrideScope::Stock | OverrideScope::Account => item.scope_key.clone(),
+            };
+
+            sqlx::query(&format!(
+                r#"
+                INSERT OR {} INTO strategy_overrides
+                    (scope, scope_key, annual_return_rate, buy_step_percentage, min_holding_days,
+                     stop_loss_percent, stop_loss_price, time_stop_days, trailing_stop_percent, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                conflict
+            ))
+            .bind(item.scope)
+            .bind(scope_key)
+            .bind(item.annual_return_rate)
+            .bind(item.buy_step_percentage)
+            .bind(item.min_holding_days)
+            .bind(item.stop_loss_percent)
+            .bind(item.stop_loss_price)
+            .bind(item.time_stop_days)
+            .bind(item.trailing_stop_percent)
+            .bind(chrono::Utc::now())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let mut portfolio_ids = HashMap::new();
+        for p in document.paper_portfolios.iter().flatten() {
+            let existing = sqlx::query_scalar::<_, i64>("SELECT id FROM paper_portfolios WHERE name = ? AND created_at IS ?")
+                .bind(&p.name)
+                .bind(p.created_at)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if existing.is_some() {
+                continue;
+            }
+
+            let result = sqlx::query(
+                r#"
+                INSERT INTO paper_portfolios
+                    (name, initial_cash, cash, annual_return_rate, buy_step_percentage, min_holding_days, growth_model,
+                     day_count_basis, lots_per_trade, commission_rate, min_commission, stamp_tax_rate, transfer_fee_rate,
+                     created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&p.name)
+            .bind(p.initial_cash)
+            .bind(p.cash)
+            .bind(p.annual_return_rate)
+            .bind(p.buy_step_percentage)
+            .bind(p.min_holding_days)
+            .bind(p.growth_model)
+            .bind(p.day_count_basis)
+            .bind(p.lots_per_trade)
+            .bind(p.commission_rate)
+            .bind(p.min_commission)
+            .bind(p.stamp_tax_rate)
+            .bind(p.transfer_fee_rate)
+            .bind(p.created_at)
+            .execute(&mut *tx)
+            .await?;
+
+            if let Some(old_id) = p.id {
+                portfolio_ids.insert(old_id, result.last_insert_rowid());
+            }
+        }
+
+        for p in document.paper_positions.iter().flatten() {
+            let Some(portfolio_id) = portfolio_ids.get(&p.portfolio_id) else { continue };
+            sqlx::query(
+                r#"
+                INSERT INTO paper_positions (portfolio_id, stock_code, stock_name, buy_price, quantity, buy_fees, buy_time)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(portfolio_id)
+            .bind(&p.stock_code)
+            .bind(&p.stock_name)
+            .bind(p.buy_price)
+            .bind(p.quantity)
+            .bind(p.buy_fees)
+            .bind(p.buy_time)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for f in document.paper_fills.iter().flatten() {
+            let Some(portfolio_id) = portfolio_ids.get(&f.portfolio_id) else { continue };
+            sqlx::query(
+                r#"
+                INSERT INTO paper_fills
+                    (portfolio_id, stock_code, stock_name, side, price, quantity, fees, realized_pnl, target_price, filled_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(portfolio_id)
+            .bind(&f.stock_code)
+            .bind(&f.stock_name)
+            .bind(f.side)
+            .bind(f.price)
+            .bind(f.quantity)
+            .bind(f.fees)
+            .bind(f.realized_pnl)
+            .bind(f.target_price)
+            .bind(f.filled_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for plan in document.ladder_plans.iter().flatten() {
+            let existing = sqlx::query_scalar::<_, i64>(
+                "SELECT id FROM ladder_plans WHERE account = ? AND stock_code = ? AND created_at IS ?",
+            )
+            .bind(&plan.account)
+            .bind(&plan.stock_code)
+            .bind(plan.created_at)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if existing.is_some() {
+                continue;
+            }
+
+            let result = sqlx::query(
+                r#"
+                INSERT INTO ladder_plans (account, stock_code, stock_name, anchor_price, budget, step_percentage, created_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&plan.account)
+            .bind(&plan.stock_code)
+            .bind(&plan.stock_name)
+            .bind(plan.anchor_price)
+            .bind(plan.budget)
+            .bind(plan.step_percentage)
+            .bind(plan.created_at)
+            .execute(&mut *tx)
+            .await?;
+            let plan_id = result.last_insert_rowid();
+
+            for rung in &plan.rungs {
+                sqlx::query(
+                    r#"
+                    INSERT INTO ladder_rungs (plan_id, level, buy_price, sell_price, quantity, filled_trade_id, alerted_side)
+                    VALUES (?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(plan_id)
+                .bind(rung.level)
+                .bind(rung.buy_price)
+                .bind(rung.sell_price)
+                .bind(rung.quantity)
+                .bind(rung.filled_trade_id.and_then(|id| trade_ids.get(&id).copied()))
+                .bind(rung.alerted_side)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(document.trades.len())
+    }
+
+    /// 将数据库一致性快照写入指定文件（用于备份）
+    pub async fn backup_to(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+
+        sqlx::query("VACUUM INTO ?")
+            .bind(path.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// 使用线程安全的全局数据库实例
 static DATABASE: OnceLock<Arc<Mutex<Database>>> = OnceLock::new();
 
 pub async fn init_database() -> Result<()> {
+    // 已加密的数据库优先使用系统钥匙串中保存的密码解锁
+    let passphrase = keychain::load_secret(DATABASE_PASSPHRASE_KEY).unwrap_or_else(|e| {
+        println!("读取系统钥匙串失败: {}", e);
+        None
+    });
+
+    open_database(passphrase.as_deref()).await
+}
+
+/// 使用指定密码打开数据库并设置全局实例
+pub async fn open_database(passphrase: Option<&str>) -> Result<()> {
     println!("开始初始化数据库...");
 
-    match Database::new().await {
+    match Database::new(passphrase).await {
         Ok(db) => {
             println!("数据库连接成功");
 
@@ -234,7 +1818,7 @@ pub async fn init_database() -> Result<()> {
                     // 线程安全地设置全局数据库实例
                     let db_arc = Arc::new(Mutex::new(db));
                     if DATABASE.set(db_arc).is_err() {
-                        return Err(anyhow::anyhow!("数据库已经初始化"));
+                        return Err(AppError::Validation("数据库已经初始化".to_string()));
                     }
 
                     println!("数据库初始化完成");
@@ -253,9 +1837,552 @@ pub async fn init_database() -> Result<()> {
     }
 }
 
-pub fn get_database() -> Arc<Mutex<Database>> {
-    DATABASE
-        .get()
-        .expect("数据库未初始化")
-        .clone()
+/// 数据库是否已初始化（加密数据库在解锁前为 false）
+pub fn is_database_initialized() -> bool {
+    DATABASE.get().is_some()
+}
+
+/// 默认数据库文件路径
+pub fn default_database_path() -> PathBuf {
+    let current_dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    current_dir.join("stock_trader.db")
+}
+
+/// 根据文件头判断数据库文件是否已被 SQLCipher 加密
+pub fn is_encrypted_file(path: &Path) -> bool {
+    use std::io::Read;
+
+    let mut header = [0u8; 16];
+    match std::fs::File::open(path).and_then(|mut f| f.read_exact(&mut header)) {
+        Ok(_) => &header != SQLITE_HEADER,
+        Err(_) => false,
+    }
+}
+
+fn quote_sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// 把建表语句中这些 REAL 列的类型改为 type_name；列名前必须是分隔符，避免 amount 匹配到 net_amount
+fn retype_columns(sql: &str, columns: &[&str], type_name: &str) -> String {
+    let mut sql = sql.to_string();
+    for column in columns {
+        let pattern = format!("{} REAL", column);
+        let mut from = 0;
+        while let Some(pos) = sql[from..].find(&pattern).map(|offset| from + offset) {
+            let separated = sql[..pos]
+                .chars()
+                .next_back()
+                .is_none_or(|c| c.is_whitespace() || c == ',' || c == '(' || c == '"');
+            if separated {
+                sql.replace_range(pos + column.len() + 1..pos + pattern.len(), type_name);
+                break;
+            }
+            from = pos + pattern.len();
+        }
+    }
+    sql
+}
+
+/// 获取全局数据库实例，加密数据库解锁前返回 NotInitialized
+pub fn get_database() -> Result<Arc<Mutex<Database>>> {
+    DATABASE.get().cloned().ok_or(AppError::NotInitialized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 临时目录下的数据库文件，每个测试使用不同的名字
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stock_trader_db_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("stock_trader.db")
+    }
+
+    async fn open(path: &Path, passphrase: Option<&str>) -> Result<Database> {
+        let pool = Database::connect_file(path, passphrase).await?;
+        Ok(Database::with_pool(pool, Some(path.to_path_buf()), passphrase))
+    }
+
+    async fn marker(db: &Database) -> Option<String> {
+        db.get_setting("marker").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn encrypts_rekeys_and_decrypts() {
+        let path = temp_path("rekey");
+        let mut db = open(&path, None).await.unwrap();
+        db.init_tables().await.unwrap();
+        db.set_setting("marker", "42").await.unwrap();
+
+        db.enable_encryption("first").await.unwrap();
+        assert!(db.is_encrypted());
+        assert_eq!(marker(&db).await.as_deref(), Some("42"));
+        assert!(matches!(open(&path, None).await, Err(AppError::DatabaseLocked)));
+        assert!(matches!(open(&path, Some("wrong")).await, Err(AppError::InvalidPassphrase)));
+
+        db.change_passphrase("second").await.unwrap();
+        assert_eq!(marker(&db).await.as_deref(), Some("42"));
+        assert!(matches!(open(&path, Some("first")).await, Err(AppError::InvalidPassphrase)));
+        assert_eq!(marker(&open(&path, Some("second")).await.unwrap()).await.as_deref(), Some("42"));
+
+        db.disable_encryption().await.unwrap();
+        assert!(!db.is_encrypted());
+        assert_eq!(marker(&open(&path, None).await.unwrap()).await.as_deref(), Some("42"));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn batch_insert_rolls_back_on_failure() {
+        let path = temp_path("batch");
+        let db = open(&path, None).await.unwrap();
+        db.init_tables().await.unwrap();
+        sqlx::query(
+            "CREATE TRIGGER reject_trade BEFORE INSERT ON trades WHEN NEW.stock_name = '失败' BEGIN SELECT RAISE(ABORT, 'rejected'); END",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+        let trade = |name: &str| Trade {
+            id: None,
+            stock_code: "600000".to_string(),
+            stock_name: name.to_string(),
+            buy_price: "10.5".parse().unwrap(),
+            buy_time: chrono::Utc::now(),
+            quantity: 100,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        };
+
+        assert!(db.create_trades(&[trade("浦发银行"), trade("失败")]).await.is_err());
+        assert!(db.get_all_trades().await.unwrap().is_empty());
+
+        assert_eq!(db.create_trades(&[trade("浦发银行"), trade("浦发银行")]).await.unwrap().len(), 2);
+        assert_eq!(db.get_all_trades().await.unwrap().len(), 2);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn missing_trades_are_not_found() {
+        let path = temp_path("missing_trade");
+        let db = open(&path, None).await.unwrap();
+        db.init_tables().await.unwrap();
+
+        let mut trade = Trade {
+            id: None,
+            stock_code: "600000".to_string(),
+            stock_name: "浦发银行".to_string(),
+            buy_price: "10.5".parse().unwrap(),
+            buy_time: chrono::Utc::now(),
+            quantity: 100,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        };
+        let id = db.create_trade(&trade).await.unwrap();
+        assert_eq!(db.get_trade(id).await.unwrap().stock_code, "600000");
+
+        trade.id = Some(id + 1);
+        assert!(matches!(db.update_trade(&trade).await, Err(AppError::NotFound(_))));
+        assert!(matches!(db.delete_trade(id + 1).await, Err(AppError::NotFound(_))));
+        assert!(matches!(db.get_trade(id + 1).await, Err(AppError::NotFound(_))));
+
+        db.delete_trade(id).await.unwrap();
+        assert!(matches!(db.get_trade(id).await, Err(AppError::NotFound(_))));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_swap_reopens_encrypted_original() {
+        let path = temp_path("swap");
+        let mut db = open(&path, None).await.unwrap();
+        db.init_tables().await.unwrap();
+        db.set_setting("marker", "42").await.unwrap();
+        db.enable_encryption("secret").await.unwrap();
+
+        // 新文件用另一个密码加密，按错误的密码打开会失败
+        let new_file = path.with_extension("db.new");
+        db.export_to(&new_file, "other").await.unwrap();
+        let result = db.replace_file(&path, &new_file, Some("wrong")).await;
+        assert!(matches!(result, Err(AppError::InvalidPassphrase)));
+
+        // 原来的加密文件已恢复，并用原来的密码重新连接
+        assert!(db.is_encrypted());
+        assert_eq!(marker(&db).await.as_deref(), Some("42"));
+        db.set_setting("marker", "43").await.unwrap();
+        assert_eq!(marker(&open(&path, Some("secret")).await.unwrap()).await.as_deref(), Some("43"));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    /// 各张表都写入一条记录的数据库，网格的一档由这笔交易成交
+    async fn seeded(name: &str) -> Database {
+        let db = open(&temp_path(name), None).await.unwrap();
+        db.init_tables().await.unwrap();
+        db.set_setting("annual_return_rate", "0.3").await.unwrap();
+
+        let created_at = Some("2024-01-02T01:00:00Z".parse().unwrap());
+        let trade_id = db
+            .create_trade(&Trade {
+                id: None,
+                stock_code: "hk00700".to_string(),
+                stock_name: "腾讯控股".to_string(),
+                buy_price: "320.2".parse().unwrap(),
+                buy_time: "2024-01-02T02:00:00Z".parse().unwrap(),
+                quantity: 100,
+                notes: Some("港股".to_string()),
+                account: "hk".to_string(),
+                security: None,
+                currency: None,
+                created_at,
+            })
+            .await
+            .unwrap();
+
+        db.set_strategy_override(&StrategyOverride {
+            scope: OverrideScope::Trade,
+            scope_key: trade_id.to_string(),
+            annual_return_rate: Some(0.25),
+            buy_step_percentage: None,
+            min_holding_days: None,
+            stop_loss_percent: None,
+            stop_loss_price: Some("280".parse().unwrap()),
+            time_stop_days: None,
+            trailing_stop_percent: None,
+        })
+        .await
+        .unwrap();
+
+        db.upsert_fx_rates(&[FxRate {
+            currency: crate::fx::Currency::Hkd,
+            rate_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            rate: 0.91,
+        }])
+        .await
+        .unwrap();
+
+        db.replace_valuation_snapshots(
+            NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            &[ValuationSnapshot {
+                snapshot_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+                account: "hk".to_string(),
+                stock_code: "hk00700".to_string(),
+                stock_name: "腾讯控股".to_string(),
+                quantity: 100,
+                close_price: "321".parse().unwrap(),
+                market_value: "32100".parse().unwrap(),
+                cost: "32020".parse().unwrap(),
+            }],
+        )
+        .await
+        .unwrap();
+
+        let portfolio_id = db
+            .create_paper_portfolio(&PaperPortfolio {
+                id: None,
+                name: "模拟".to_string(),
+                initial_cash: "100000".parse().unwrap(),
+                cash: "89000".parse().unwrap(),
+                annual_return_rate: 0.2,
+                buy_step_percentage: 0.05,
+                min_holding_days: 30,
+                growth_model: Default::default(),
+                day_count_basis: Default::default(),
+                lots_per_trade: 1,
+                commission_rate: 0.00025,
+                min_commission: "5".parse().unwrap(),
+                stamp_tax_rate: 0.0005,
+                transfer_fee_rate: 0.00001,
+                created_at,
+            })
+            .await
+            .unwrap();
+        let buy_time = "2024-01-03T02:00:00Z".parse().unwrap();
+        db.apply_paper_fills(
+            portfolio_id,
+            "89000".parse().unwrap(),
+            &[],
+            &[PaperPosition {
+                id: None,
+                portfolio_id,
+                stock_code: "600000".to_string(),
+                stock_name: "浦发银行".to_string(),
+                buy_price: "10.95".parse().unwrap(),
+                quantity: 1000,
+                buy_fees: "5".parse().unwrap(),
+                buy_time,
+            }],
+            &[PaperFill {
+                id: None,
+                portfolio_id,
+                stock_code: "600000".to_string(),
+                stock_name: "浦发银行".to_string(),
+                side: crate::models::TradeSide::Buy,
+                price: "10.95".parse().unwrap(),
+                quantity: 1000,
+                fees: "5".parse().unwrap(),
+                realized_pnl: None,
+                target_price: None,
+                filled_at: buy_time,
+            }],
+        )
+        .await
+        .unwrap();
+
+        db.create_ladder_plan(&LadderPlan {
+            id: None,
+            account: "hk".to_string(),
+            stock_code: "hk00700".to_string(),
+            stock_name: "腾讯控股".to_string(),
+            anchor_price: "320.2".parse().unwrap(),
+            budget: "64040".parse().unwrap(),
+            step_percentage: 0.05,
+            created_at,
+            rungs: vec![LadderRung {
+                level: 1,
+                buy_price: "320.2".parse().unwrap(),
+                sell_price: "384.24".parse().unwrap(),
+                quantity: 100,
+                filled_trade_id: Some(trade_id),
+                ..Default::default()
+            }],
+        })
+        .await
+        .unwrap();
+
+        db
+    }
+
+    async fn export_file(db: &Database, name: &str) -> PathBuf {
+        let document = ExportDocument::build(
+            Default::default(),
+            db.export_data().await.unwrap(),
+            "2024-02-01T00:00:00Z".parse().unwrap(),
+        );
+        let file = temp_path(name).with_file_name("export.json");
+        document.write_json(&file).unwrap();
+        file
+    }
+
+    #[tokio::test]
+    async fn export_round_trips_through_replace_import() {
+        let source = seeded("export_source").await;
+        let file = export_file(&source, "export_file").await;
+
+        let target = open(&temp_path("export_target"), None).await.unwrap();
+        target.init_tables().await.unwrap();
+        target.set_setting("annual_return_rate", "0.1").await.unwrap();
+        target.set_setting("marker", "42").await.unwrap();
+
+        let document = ExportDocument::read_json(&file).unwrap();
+        assert_eq!(document.version, crate::export::EXPORT_VERSION);
+        assert_eq!(target.import_snapshot(&document, HashMap::new(), true).await.unwrap(), 1);
+
+        let trades = target.get_all_trades().await.unwrap();
+        assert_eq!(trades.len(), 1);
+        let trade = &trades[0];
+        let original = &source.get_all_trades().await.unwrap()[0];
+        assert_eq!(trade.buy_price, original.buy_price);
+        assert_eq!(trade.buy_time, original.buy_time);
+        assert_eq!(trade.currency(), crate::fx::Currency::Hkd);
+        assert_eq!(trade.security, original.security);
+        assert_eq!(trade.notes, original.notes);
+
+        assert_eq!(target.get_setting("annual_return_rate").await.unwrap().as_deref(), Some("0.3"));
+
+        let overrides = target.get_strategy_overrides().await.unwrap();
+        assert_eq!(overrides.len(), 1);
+        assert_eq!(overrides[0].scope_key, trade.id.unwrap().to_string());
+        assert_eq!(overrides[0].stop_loss_price, Some("280".parse().unwrap()));
+
+        let plans = target.get_ladder_plans().await.unwrap();
+        assert_eq!(plans.len(), 1);
+        assert_eq!(plans[0].rungs.len(), 1);
+        assert_eq!(plans[0].rungs[0].filled_trade_id, trade.id);
+        assert_eq!(plans[0].rungs[0].sell_price, "384.24".parse().unwrap());
+
+        let portfolios = target.get_paper_portfolios().await.unwrap();
+        assert_eq!(portfolios.len(), 1);
+        assert_eq!(portfolios[0].cash, "89000".parse().unwrap());
+        let positions = target.get_paper_positions(portfolios[0].id).await.unwrap();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].buy_price, "10.95".parse().unwrap());
+        assert_eq!(target.get_paper_fills(portfolios[0].id).await.unwrap().len(), 1);
+
+        let snapshots = target.get_valuation_snapshots(None).await.unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].market_value, "32100".parse().unwrap());
+
+        let rates = target.get_fx_rates().await.unwrap();
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].rate, 0.91);
+
+        // 再次导出的内容与原来的导出一致（ID 除外）
+        let again_file = export_file(&target, "export_again").await;
+        let again = ExportDocument::read_json(&again_file).unwrap();
+        assert_eq!(again.trades.len(), 1);
+        assert_eq!(again.positions.len(), document.positions.len());
+        assert_eq!(again.positions[0].total_cost, document.positions[0].total_cost);
+
+        for db in [&source, &target] {
+            std::fs::remove_dir_all(db.path.as_ref().unwrap().parent().unwrap()).unwrap();
+        }
+        for file in [file, again_file] {
+            std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn merge_import_keeps_existing_data() {
+        let db = seeded("export_merge").await;
+        let file = export_file(&db, "export_merge_file").await;
+        db.set_setting("annual_return_rate", "0.1").await.unwrap();
+
+        // 合并导入自己的导出文件不应产生任何重复
+        let document = ExportDocument::read_json(&file).unwrap();
+        let trade = &document.trades[0];
+        let known = HashMap::from([(trade.id.unwrap(), trade.id.unwrap())]);
+        let merged = ExportDocument { trades: Vec::new(), alert_history: Vec::new(), ..document };
+        assert_eq!(db.import_snapshot(&merged, known, false).await.unwrap(), 0);
+
+        assert_eq!(db.get_setting("annual_return_rate").await.unwrap().as_deref(), Some("0.1"));
+        assert_eq!(db.get_all_trades().await.unwrap().len(), 1);
+        assert_eq!(db.get_strategy_overrides().await.unwrap().len(), 1);
+        assert_eq!(db.get_paper_portfolios().await.unwrap().len(), 1);
+        assert_eq!(db.get_paper_positions(None).await.unwrap().len(), 1);
+        assert_eq!(db.get_ladder_plans().await.unwrap().len(), 1);
+        assert_eq!(db.get_valuation_snapshots(None).await.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(db.path.as_ref().unwrap().parent().unwrap()).unwrap();
+        std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn paper_fills_for_closed_positions_roll_back() {
+        let db = seeded("paper_stale").await;
+        let portfolio_id = db.get_paper_portfolios().await.unwrap()[0].id.unwrap();
+        let position_id = db.get_paper_positions(None).await.unwrap()[0].id.unwrap();
+
+        db.apply_paper_fills(portfolio_id, "99000".parse().unwrap(), &[position_id], &[], &[])
+            .await
+            .unwrap();
+        // 基于过期数据再次卖出同一笔持仓时不能改动资金
+        let stale = db
+            .apply_paper_fills(portfolio_id, "109000".parse().unwrap(), &[position_id], &[], &[])
+            .await;
+        assert!(matches!(stale, Err(AppError::NotFound(_))));
+        assert_eq!(db.get_paper_portfolios().await.unwrap()[0].cash, "99000".parse().unwrap());
+
+        std::fs::remove_dir_all(db.path.as_ref().unwrap().parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn latest_daily_bars_ignore_dates() {
+        let path = temp_path("latest_bars");
+        let db = open(&path, None).await.unwrap();
+        db.init_tables().await.unwrap();
+
+        // 从 CSV 导入的多年前的日线同样要读出来
+        let bars: Vec<DailyBar> = (1..=5)
+            .map(|day| DailyBar {
+                date: NaiveDate::from_ymd_opt(2015, 6, day).unwrap(),
+                open: Money::from_yuan(10),
+                high: Money::from_yuan(11),
+                low: Money::from_yuan(9),
+                close: Money::from_yuan(10 + day as i64),
+                volume: 100,
+            })
+            .collect();
+        db.upsert_daily_bars("600000", &bars).await.unwrap();
+
+        let latest = db.get_latest_daily_bars("600000", 3).await.unwrap();
+        let closes: Vec<Money> = latest.iter().map(|bar| bar.close).collect();
+        assert_eq!(closes, [13, 14, 15].map(Money::from_yuan));
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrates_real_money_columns_to_text() {
+        let path = temp_path("money_columns");
+        let db = open(&path, None).await.unwrap();
+
+        // 旧版本的表结构，金额保存在 REAL 列中，stop_loss_price 由 ALTER TABLE 补充
+        for sql in [
+            "CREATE TABLE trades (id INTEGER PRIMARY KEY AUTOINCREMENT, stock_code TEXT NOT NULL, stock_name TEXT NOT NULL, buy_price REAL NOT NULL, buy_time DATETIME NOT NULL, quantity INTEGER NOT NULL, notes TEXT, created_at DATETIME DEFAULT CURRENT_TIMESTAMP)",
+            "INSERT INTO trades (stock_code, stock_name, buy_price, buy_time, quantity) VALUES ('600000', '浦发银行', 10.58, '2024-01-15T02:00:00Z', 100)",
+            "CREATE TABLE broker_transactions (id INTEGER PRIMARY KEY AUTOINCREMENT, broker TEXT NOT NULL, kind TEXT NOT NULL, trade_time DATETIME NOT NULL, stock_code TEXT, stock_name TEXT, operation TEXT NOT NULL, price REAL NOT NULL, quantity INTEGER NOT NULL, amount REAL NOT NULL, net_amount REAL NOT NULL, fees REAL NOT NULL, contract_no TEXT, imported_at DATETIME DEFAULT CURRENT_TIMESTAMP)",
+            "INSERT INTO broker_transactions (broker, kind, trade_time, operation, price, quantity, amount, net_amount, fees) VALUES ('huatai', 'buy', '2024-01-15T02:00:00Z', '证券买入', 10.58, 300, 3174.0, -3179.3, 0.30000000000000004)",
+            "CREATE TABLE strategy_overrides (scope TEXT NOT NULL, scope_key TEXT NOT NULL, annual_return_rate REAL, buy_step_percentage REAL, min_holding_days INTEGER, updated_at DATETIME DEFAULT CURRENT_TIMESTAMP, PRIMARY KEY (scope, scope_key))",
+            "ALTER TABLE strategy_overrides ADD COLUMN stop_loss_price REAL",
+            "INSERT INTO strategy_overrides (scope, scope_key, stop_loss_price) VALUES ('stock', '600000', 9.5)",
+        ] {
+            sqlx::query(sql).execute(&db.pool).await.unwrap();
+        }
+        db.init_tables().await.unwrap();
+
+        let column_type = |table: &'static str, column: &'static str| {
+            let pool = db.pool.clone();
+            async move {
+                sqlx::query(&format!("PRAGMA table_info({})", table))
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .find(|row| row.get::<String, _>("name") == column)
+                    .map(|row| row.get::<String, _>("type"))
+                    .unwrap()
+            }
+        };
+        for (table, column) in [
+            ("trades", "buy_price"),
+            ("broker_transactions", "amount"),
+            ("broker_transactions", "net_amount"),
+            ("strategy_overrides", "stop_loss_price"),
+        ] {
+            assert_eq!(column_type(table, column).await, "TEXT", "{}.{}", table, column);
+        }
+        assert_eq!(column_type("strategy_overrides", "annual_return_rate").await, "REAL");
+
+        let stored: Vec<(String, String, String)> =
+            sqlx::query_as("SELECT price, amount, fees FROM broker_transactions")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, [("10.58".to_string(), "3174.00".to_string(), "0.30".to_string())]);
+
+        let trade = &db.get_all_trades().await.unwrap()[0];
+        assert_eq!(trade.buy_price, "10.58".parse().unwrap());
+        assert_eq!(trade.currency, Some(crate::fx::Currency::Cny));
+        let broker = &db.get_broker_transactions().await.unwrap()[0];
+        assert_eq!(broker.net_amount, "-3179.3".parse().unwrap());
+        let overrides = db.get_strategy_overrides().await.unwrap();
+        assert_eq!(overrides[0].stop_loss_price, Some("9.5".parse().unwrap()));
+
+        // 迁移后再次启动不会重建
+        db.init_tables().await.unwrap();
+        assert_eq!(db.get_all_trades().await.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn retypes_only_whole_column_names() {
+        let sql = "CREATE TABLE t (amount REAL NOT NULL, net_amount REAL NOT NULL, rate REAL)";
+        assert_eq!(
+            retype_columns(sql, &["net_amount", "amount"], "TEXT"),
+            "CREATE TABLE t (amount TEXT NOT NULL, net_amount TEXT NOT NULL, rate REAL)"
+        );
+    }
 }
@@ -0,0 +1,169 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use thiserror::Error;
+
+/// 单个字段的校验错误，field 为前端使用的字段名
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        FieldError { field, message: message.into() }
+    }
+}
+
+/// 后端命令返回给前端的错误，序列化为 `{code, message, details}`，前端按 code 区分处理
+#[derive(Debug, Error)]
+pub enum AppError {
+    /// 请求的记录不存在
+    #[error("{0}")]
+    NotFound(String),
+    /// 参数不合法
+    #[error("{0}")]
+    Validation(String),
+    /// 多个字段校验失败，序列化时附带 fields 列表
+    #[error("{}", field_messages(.0))]
+    InvalidFields(Vec<FieldError>),
+    /// 网络请求失败
+    #[error("网络请求失败")]
+    Network(#[source] reqwest::Error),
+    /// 行情接口返回的数据无法解析
+    #[error("{0}")]
+    ProviderParse(String),
+    /// 数据库操作失败
+    #[error("数据库操作失败")]
+    Database(#[from] sqlx::Error),
+    /// 数据库尚未打开，加密数据库在解锁前处于此状态
+    #[error("数据库未初始化")]
+    NotInitialized,
+    /// 数据库已加密，需要密码解锁
+    #[error("数据库已加密，请输入密码解锁")]
+    DatabaseLocked,
+    /// 数据库密码错误
+    #[error("数据库密码错误")]
+    InvalidPassphrase,
+    /// 文件读写失败
+    #[error("文件读写失败")]
+    Io(#[from] std::io::Error),
+    /// 其他模块返回的错误
+    #[error("{0}")]
+    Internal(anyhow::Error),
+}
+
+pub type Result<T, E = AppError> = std::result::Result<T, E>;
+
+impl AppError {
+    /// 前端用于区分错误类型的代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Validation(_) | AppError::InvalidFields(_) => "validation",
+            AppError::Network(_) => "network",
+            AppError::ProviderParse(_) => "provider_parse",
+            AppError::Database(_) => "database",
+            AppError::NotInitialized => "not_initialized",
+            AppError::DatabaseLocked => "database_locked",
+            AppError::InvalidPassphrase => "invalid_passphrase",
+            AppError::Io(_) => "io",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// 底层错误的详细信息，只用于排查问题
+    pub fn details(&self) -> Option<String> {
+        match self {
+            AppError::Network(e) => Some(e.to_string()),
+            AppError::Database(e) => Some(e.to_string()),
+            AppError::Io(e) => Some(e.to_string()),
+            AppError::Internal(e) => {
+                let causes: Vec<String> = e.chain().skip(1).map(|c| c.to_string()).collect();
+                (!causes.is_empty()).then(|| causes.join(": "))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        // 响应体不是预期格式属于接口数据问题，而不是网络问题
+        if e.is_decode() {
+            AppError::ProviderParse(format!("无法解析行情接口返回的数据: {}", e))
+        } else {
+            AppError::Network(e)
+        }
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(e: anyhow::Error) -> Self {
+        // 其他模块用 ? 透传的 AppError 保留原来的类型
+        match e.downcast::<AppError>() {
+            Ok(app_error) => app_error,
+            Err(e) => AppError::Internal(e),
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        match self {
+            AppError::InvalidFields(fields) => state.serialize_field("fields", fields)?,
+            _ => state.skip_field("fields")?,
+        }
+        state.end()
+    }
+}
+
+fn field_messages(fields: &[FieldError]) -> String {
+    fields.iter().map(|f| f.message.as_str()).collect::<Vec<_>>().join("；")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn serializes_code_message_details_and_fields() {
+        let error = AppError::InvalidFields(vec![
+            FieldError::new("buyPrice", "买入价格必须大于0"),
+            FieldError::new("quantity", "买入数量不能少于 100"),
+        ]);
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({
+                "code": "validation",
+                "message": "买入价格必须大于0；买入数量不能少于 100",
+                "details": null,
+                "fields": [
+                    {"field": "buyPrice", "message": "买入价格必须大于0"},
+                    {"field": "quantity", "message": "买入数量不能少于 100"},
+                ],
+            })
+        );
+
+        let error = AppError::NotFound("交易记录不存在".to_string());
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            json!({"code": "not_found", "message": "交易记录不存在", "details": null})
+        );
+    }
+
+    #[test]
+    fn keeps_app_errors_passed_through_anyhow() {
+        let error = AppError::from(anyhow::Error::from(AppError::Validation("买入台阶必须在0到1之间".to_string())));
+        assert_eq!(error.code(), "validation");
+
+        let error = AppError::from(anyhow::anyhow!("底层错误").context("导入失败"));
+        assert_eq!(error.code(), "internal");
+        assert_eq!(error.to_string(), "导入失败");
+        assert_eq!(error.details().as_deref(), Some("底层错误"));
+    }
+}
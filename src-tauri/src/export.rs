@@ -0,0 +1,430 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use crate::models::{
+    AlertRecord, BrokerTransaction, FxRate, LadderPlan, OverrideScope, PaperFill, PaperPortfolio, PaperPosition,
+    Position, Settings, StrategyOverride, Trade, ValuationSnapshot,
+};
+use crate::money::Money;
+use crate::portfolio::build_positions;
+
+/// JSON 导出文件的格式标识和版本号，格式变化时递增版本号
+pub const EXPORT_FORMAT: &str = "stock-trader-export";
+/// 版本 2 起导出交易的证券和币种，并包含估值快照、模拟盘、策略参数、网格计划和汇率
+pub const EXPORT_VERSION: u32 = 2;
+
+/// 导出过滤条件，日期为闭区间
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExportFilter {
+    pub account: Option<String>,
+    pub start_date: Option<DateTime<Utc>>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+impl ExportFilter {
+    fn in_range(&self, time: DateTime<Utc>) -> bool {
+        self.start_date.map(|start| time >= start).unwrap_or(true)
+            && self.end_date.map(|end| time <= end).unwrap_or(true)
+    }
+
+    /// 按北京时间的日期比较，用于只有日期的快照
+    fn in_date_range(&self, date: NaiveDate) -> bool {
+        self.start_date.map(|start| date >= trade_date(start)).unwrap_or(true)
+            && self.end_date.map(|end| date <= trade_date(end)).unwrap_or(true)
+    }
+
+    fn account_matches(&self, account: &str) -> bool {
+        self.account.as_deref().map(|a| a == account).unwrap_or(true)
+    }
+}
+
+/// 从数据库读出的全部可导出数据，由 ExportDocument::build 按过滤条件筛选
+#[derive(Debug, Clone, Default)]
+pub struct ExportData {
+    pub trades: Vec<Trade>,
+    pub alert_history: Vec<AlertRecord>,
+    pub broker_transactions: Vec<BrokerTransaction>,
+    pub settings: Vec<Settings>,
+    pub valuation_snapshots: Vec<ValuationSnapshot>,
+    pub paper_portfolios: Vec<PaperPortfolio>,
+    pub paper_positions: Vec<PaperPosition>,
+    pub paper_fills: Vec<PaperFill>,
+    pub strategy_overrides: Vec<StrategyOverride>,
+    pub ladder_plans: Vec<LadderPlan>,
+    pub fx_rates: Vec<FxRate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportEntity {
+    Trades,
+    Positions,
+    AlertHistory,
+    BrokerTransactions,
+    Settings,
+}
+
+/// 完整导出文档，可通过 import_json 原样导回
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExportDocument {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    #[serde(default)]
+    pub filter: ExportFilter,
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+    /// 由交易记录汇总得到，仅供查看，导入时忽略
+    #[serde(default)]
+    pub positions: Vec<Position>,
+    #[serde(default)]
+    pub alert_history: Vec<AlertRecord>,
+    #[serde(default)]
+    pub broker_transactions: Vec<BrokerTransaction>,
+    #[serde(default)]
+    pub settings: Vec<Settings>,
+    /// 以下部分从版本 2 开始导出；旧文件中为 None，覆盖导入时保留数据库中的现有数据
+    #[serde(default)]
+    pub valuation_snapshots: Option<Vec<ValuationSnapshot>>,
+    #[serde(default)]
+    pub paper_portfolios: Option<Vec<PaperPortfolio>>,
+    #[serde(default)]
+    pub paper_positions: Option<Vec<PaperPosition>>,
+    #[serde(default)]
+    pub paper_fills: Option<Vec<PaperFill>>,
+    #[serde(default)]
+    pub strategy_overrides: Option<Vec<StrategyOverride>>,
+    /// 网格计划连同各档一起导出
+    #[serde(default)]
+    pub ladder_plans: Option<Vec<LadderPlan>>,
+    #[serde(default)]
+    pub fx_rates: Option<Vec<FxRate>>,
+}
+
+impl ExportDocument {
+    /// 按过滤条件组装导出文档；模拟盘、股票级策略参数和汇率与账户无关，总是全部导出
+    pub fn build(filter: ExportFilter, data: ExportData, exported_at: DateTime<Utc>) -> Self {
+        // 提醒记录和按交易设置的策略参数按所属交易的账户过滤
+        let account_trade_ids: HashSet<i64> = data
+            .trades
+            .iter()
+            .filter(|t| filter.account_matches(&t.account))
+            .filter_map(|t| t.id)
+            .collect();
+
+        let trades: Vec<Trade> = data
+            .trades
+            .into_iter()
+            .filter(|t| filter.account_matches(&t.account) && filter.in_range(t.buy_time))
+            .collect();
+
+        let alert_history = data
+            .alert_history
+            .into_iter()
+            .filter(|a| filter.in_range(a.triggered_at))
+            .filter(|a| {
+                filter.account.is_none()
+                    || a.trade_id.map(|id| account_trade_ids.contains(&id)).unwrap_or(false)
+            })
+            .collect();
+
+        // 交割单流水不区分账户，只按日期过滤
+        let broker_transactions = data
+            .broker_transactions
+            .into_iter()
+            .filter(|t| filter.in_range(t.trade_time))
+            .collect();
+
+        let valuation_snapshots = data
+            .valuation_snapshots
+            .into_iter()
+            .filter(|s| filter.account_matches(&s.account) && filter.in_date_range(s.snapshot_date))
+            .collect();
+
+        let strategy_overrides = data
+            .strategy_overrides
+            .into_iter()
+            .filter(|o| match o.scope {
+                OverrideScope::Trade => {
+                    filter.account.is_none()
+                        || o.scope_key.parse().map(|id: i64| account_trade_ids.contains(&id)).unwrap_or(false)
+                }
+                OverrideScope::Stock => true,
+                OverrideScope::Account => filter.account_matches(&o.scope_key),
+            })
+            .collect();
+
+        let ladder_plans = data
+            .ladder_plans
+            .into_iter()
+            .filter(|p| filter.account_matches(&p.account))
+            .collect();
+
+        ExportDocument {
+            format: EXPORT_FORMAT.to_string(),
+            version: EXPORT_VERSION,
+            exported_at,
+            positions: build_positions(&trades),
+            filter,
+            trades,
+            alert_history,
+            broker_transactions,
+            settings: data.settings,
+            valuation_snapshots: Some(valuation_snapshots),
+            paper_portfolios: Some(data.paper_portfolios),
+            paper_positions: Some(data.paper_positions),
+            paper_fills: Some(data.paper_fills),
+            strategy_overrides: Some(strategy_overrides),
+            ladder_plans: Some(ladder_plans),
+            fx_rates: Some(data.fx_rates),
+        }
+    }
+
+    /// 读取并校验 JSON 导出文件
+    pub fn read_json(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let document: ExportDocument = serde_json::from_str(&content)?;
+
+        if document.format != EXPORT_FORMAT {
+            return Err(anyhow!("不是本软件导出的文件"));
+        }
+        if document.version > EXPORT_VERSION {
+            return Err(anyhow!(
+                "导出文件版本 {} 高于当前支持的版本 {}，请升级软件",
+                document.version,
+                EXPORT_VERSION
+            ));
+        }
+
+        Ok(document)
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 导出单个实体为 CSV，写入 BOM 以便 Excel 正确识别 UTF-8 中文
+    pub fn write_csv(&self, entity: ExportEntity, path: &Path) -> Result<()> {
+        let (headers, rows) = self.table(entity);
+
+        let mut file = std::fs::File::create(path)?;
+        file.write_all("\u{feff}".as_bytes())?;
+
+        let mut writer = csv::Writer::from_writer(file);
+        writer.write_record(headers)?;
+        for row in rows {
+            writer.write_record(row.iter().map(Cell::to_text))?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// 导出为 Excel，每个实体一个工作表
+    pub fn write_xlsx(&self, path: &Path) -> Result<()> {
+        let mut workbook = Workbook::new();
+        let header_format = Format::new().set_bold();
+
+        let sheets = [
+            (ExportEntity::Trades, "交易记录"),
+            (ExportEntity::Positions, "持仓"),
+            (ExportEntity::AlertHistory, "提醒历史"),
+            (ExportEntity::BrokerTransactions, "交割单"),
+            (ExportEntity::Settings, "设置"),
+        ];
+
+        for (entity, name) in sheets {
+            let (headers, rows) = self.table(entity);
+            let worksheet = workbook.add_worksheet();
+            worksheet.set_name(name)?;
+
+            for (col, header) in headers.iter().enumerate() {
+                worksheet.write_string_with_format(0, col as u16, *header, &header_format)?;
+            }
+
+            for (row_index, row) in rows.iter().enumerate() {
+                let row_num = row_index as u32 + 1;
+                for (col, cell) in row.iter().enumerate() {
+                    match cell {
+                        Cell::Number(v) => {
+                            worksheet.write_number(row_num, col as u16, *v)?;
+                        }
+                        Cell::Money(v) => {
+                            worksheet.write_number(row_num, col as u16, v.to_f64())?;
+                        }
+                        Cell::Text(v) => {
+                            worksheet.write_string(row_num, col as u16, v)?;
+                        }
+                        Cell::Empty => {}
+                    }
+                }
+            }
+        }
+
+        workbook.save(path)?;
+        Ok(())
+    }
+
+    fn table(&self, entity: ExportEntity) -> (&'static [&'static str], Vec<Vec<Cell>>) {
+        match entity {
+            ExportEntity::Trades => (Trade::HEADERS, self.trades.iter().map(Tabular::cells).collect()),
+            ExportEntity::Positions => (Position::HEADERS, self.positions.iter().map(Tabular::cells).collect()),
+            ExportEntity::AlertHistory => (AlertRecord::HEADERS, self.alert_history.iter().map(Tabular::cells).collect()),
+            ExportEntity::BrokerTransactions => (
+                BrokerTransaction::HEADERS,
+                self.broker_transactions.iter().map(Tabular::cells).collect(),
+            ),
+            ExportEntity::Settings => (Settings::HEADERS, self.settings.iter().map(Tabular::cells).collect()),
+        }
+    }
+}
+
+/// 表格单元格，Excel 中数字需要按数字类型写入
+enum Cell {
+    Text(String),
+    Number(f64),
+    /// 金额在 CSV 中按十进制文本原样输出
+    Money(Money),
+    Empty,
+}
+
+impl Cell {
+    fn to_text(&self) -> String {
+        match self {
+            Cell::Text(v) => v.clone(),
+            Cell::Number(v) => v.to_string(),
+            Cell::Money(v) => v.to_string(),
+            Cell::Empty => String::new(),
+        }
+    }
+
+    fn time(time: DateTime<Utc>) -> Cell {
+        Cell::Text(time.to_rfc3339())
+    }
+
+    fn optional(value: &Option<String>) -> Cell {
+        value.clone().map(Cell::Text).unwrap_or(Cell::Empty)
+    }
+
+    fn id(value: Option<i64>) -> Cell {
+        value.map(|v| Cell::Number(v as f64)).unwrap_or(Cell::Empty)
+    }
+}
+
+/// 可导出为表格的实体，表头与字段名一致，交易记录的 CSV 可直接用 CSV 导入功能导回
+trait Tabular {
+    const HEADERS: &'static [&'static str];
+    fn cells(&self) -> Vec<Cell>;
+}
+
+impl Tabular for Trade {
+    const HEADERS: &'static [&'static str] = &[
+        "id", "account", "stock_code", "stock_name", "buy_price", "currency", "buy_time", "quantity", "notes", "created_at",
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::id(self.id),
+            Cell::Text(self.account.clone()),
+            Cell::Text(self.stock_code.clone()),
+            Cell::Text(self.stock_name.clone()),
+            Cell::Money(self.buy_price),
+            Cell::Text(self.currency().code().to_string()),
+            Cell::time(self.buy_time),
+            Cell::Number(self.quantity as f64),
+            Cell::optional(&self.notes),
+            self.created_at.map(Cell::time).unwrap_or(Cell::Empty),
+        ]
+    }
+}
+
+impl Tabular for Position {
+    const HEADERS: &'static [&'static str] = &[
+        "account", "stock_code", "stock_name", "quantity", "average_cost", "total_cost", "currency", "first_buy_time",
+        "last_buy_time",
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::Text(self.account.clone()),
+            Cell::Text(self.stock_code.clone()),
+            Cell::Text(self.stock_name.clone()),
+            Cell::Number(self.quantity as f64),
+            Cell::Money(self.average_cost),
+            Cell::Money(self.total_cost),
+            Cell::Text(self.currency.code().to_string()),
+            Cell::time(self.first_buy_time),
+            Cell::time(self.last_buy_time),
+        ]
+    }
+}
+
+impl Tabular for AlertRecord {
+    const HEADERS: &'static [&'static str] = &[
+        "id", "trade_id", "stock_code", "stock_name", "alert_type", "target_price", "current_price", "message", "triggered_at",
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::id(self.id),
+            Cell::id(self.trade_id),
+            Cell::Text(self.stock_code.clone()),
+            Cell::Text(self.stock_name.clone()),
+            Cell::Text(self.alert_type.clone()),
+            Cell::Money(self.target_price),
+            Cell::Money(self.current_price),
+            Cell::Text(self.message.clone()),
+            Cell::time(self.triggered_at),
+        ]
+    }
+}
+
+impl Tabular for BrokerTransaction {
+    const HEADERS: &'static [&'static str] = &[
+        "id", "broker", "kind", "trade_time", "stock_code", "stock_name", "operation", "price", "quantity", "amount",
+        "net_amount", "fees", "contract_no",
+    ];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![
+            Cell::id(self.id),
+            Cell::Text(enum_text(&self.broker)),
+            Cell::Text(enum_text(&self.kind)),
+            Cell::time(self.trade_time),
+            Cell::optional(&self.stock_code),
+            Cell::optional(&self.stock_name),
+            Cell::Text(self.operation.clone()),
+            Cell::Money(self.price),
+            Cell::Number(self.quantity as f64),
+            Cell::Money(self.amount),
+            Cell::Money(self.net_amount),
+            Cell::Money(self.fees),
+            Cell::optional(&self.contract_no),
+        ]
+    }
+}
+
+impl Tabular for Settings {
+    const HEADERS: &'static [&'static str] = &["key", "value"];
+
+    fn cells(&self) -> Vec<Cell> {
+        vec![Cell::Text(self.key.clone()), Cell::Text(self.value.clone())]
+    }
+}
+
+fn trade_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+/// 枚举按 serde 的名称输出，与 JSON 导出保持一致
+fn enum_text<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use crate::database::get_database;
+use crate::models::FxRate;
+use crate::stock_api::StockApi;
+
+/// 交易和估值使用的货币
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, sqlx::Type)]
+#[serde(rename_all = "UPPERCASE")]
+#[sqlx(type_name = "TEXT", rename_all = "UPPERCASE")]
+pub enum Currency {
+    #[default]
+    Cny,
+    Hkd,
+    Usd,
+}
+
+impl Currency {
+    /// 需要汇率换算成人民币的外币
+    pub const FOREIGN: &'static [Currency] = &[Currency::Hkd, Currency::Usd];
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Cny => "CNY",
+            Currency::Hkd => "HKD",
+            Currency::Usd => "USD",
+        }
+    }
+
+    /// 金额前的货币符号，用于提醒等文字消息
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::Cny => "¥",
+            Currency::Hkd => "HK$",
+            Currency::Usd => "US$",
+        }
+    }
+}
+
+impl std::str::FromStr for Currency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_uppercase().as_str() {
+            "CNY" => Ok(Currency::Cny),
+            "HKD" => Ok(Currency::Hkd),
+            "USD" => Ok(Currency::Usd),
+            _ => Err(anyhow::anyhow!("不支持的货币: {}", s)),
+        }
+    }
+}
+
+/// 每日汇率表，保存的汇率为 1 单位外币折合的人民币
+#[derive(Debug, Default, Clone)]
+pub struct FxRates {
+    rates: HashMap<Currency, BTreeMap<NaiveDate, f64>>,
+}
+
+impl FxRates {
+    pub fn new(rates: &[FxRate]) -> Self {
+        let mut table = FxRates::default();
+        for rate in rates.iter().filter(|r| r.rate > 0.0) {
+            table.rates.entry(rate.currency).or_default().insert(rate.rate_date, rate.rate);
+        }
+        table
+    }
+
+    /// 某日 1 单位货币折合的人民币，取当日或之前最近一天的汇率，更早没有记录时取之后最近一天的汇率
+    pub fn cny_rate(&self, currency: Currency, date: NaiveDate) -> Option<f64> {
+        if currency == Currency::Cny {
+            return Some(1.0);
+        }
+
+        let rates = self.rates.get(&currency)?;
+        rates
+            .range(..=date)
+            .next_back()
+            .or_else(|| rates.range(date..).next())
+            .map(|(_, rate)| *rate)
+    }
+
+    /// 某日 1 单位 from 折合多少 to，经人民币交叉换算
+    pub fn rate(&self, from: Currency, to: Currency, date: NaiveDate) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
+        }
+        Some(self.cny_rate(from, date)? / self.cny_rate(to, date)?)
+    }
+}
+
+/// 获取港币和美元兑人民币的最新汇率并按日期保存，返回保存的条数
+pub async fn update_fx_rates() -> Result<usize> {
+    let rates = StockApi::fetch_fx_rates(Currency::FOREIGN).await?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock.upsert_fx_rates(&rates).await?;
+
+    Ok(rates.len())
+}
+
+/// 读取已保存的汇率表，update 为 true 时先获取最新汇率，获取失败时使用已保存的汇率
+pub async fn load_fx_rates(update: bool) -> Result<FxRates> {
+    if update {
+        if let Err(e) = update_fx_rates().await {
+            println!("更新汇率失败: {}", e);
+        }
+    }
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    Ok(FxRates::new(&db_lock.get_fx_rates().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn rate(currency: Currency, day: u32, rate: f64) -> FxRate {
+        FxRate { currency, rate_date: date(day), rate }
+    }
+
+    #[test]
+    fn uses_latest_rate_on_or_before_date() {
+        let rates = FxRates::new(&[
+            rate(Currency::Usd, 2, 7.10),
+            rate(Currency::Usd, 5, 7.20),
+            rate(Currency::Hkd, 3, 0.91),
+        ]);
+
+        assert_eq!(rates.cny_rate(Currency::Usd, date(4)), Some(7.10));
+        assert_eq!(rates.cny_rate(Currency::Usd, date(5)), Some(7.20));
+        // 更早没有记录时使用之后最近的汇率
+        assert_eq!(rates.cny_rate(Currency::Usd, date(1)), Some(7.10));
+        assert_eq!(rates.cny_rate(Currency::Cny, date(1)), Some(1.0));
+
+        let usd_hkd = rates.rate(Currency::Usd, Currency::Hkd, date(5)).unwrap();
+        assert!((usd_hkd - 7.20 / 0.91).abs() < 1e-12);
+        assert_eq!(FxRates::default().rate(Currency::Hkd, Currency::Cny, date(5)), None);
+    }
+}
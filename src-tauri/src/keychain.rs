@@ -0,0 +1,57 @@
+pub use store::{delete_secret, load_secret, save_secret};
+
+#[cfg(not(test))]
+mod store {
+    use anyhow::Result;
+    use keyring::Entry;
+
+    /// 系统钥匙串中使用的服务名
+    const SERVICE_NAME: &str = "stock-trader";
+
+    /// 从系统钥匙串读取敏感信息，不存在时返回 None
+    pub fn load_secret(key: &str) -> Result<Option<String>> {
+        match Entry::new(SERVICE_NAME, key)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 将敏感信息写入系统钥匙串
+    pub fn save_secret(key: &str, value: &str) -> Result<()> {
+        Entry::new(SERVICE_NAME, key)?.set_password(value)?;
+        Ok(())
+    }
+
+    /// 删除系统钥匙串中的敏感信息
+    pub fn delete_secret(key: &str) -> Result<()> {
+        match Entry::new(SERVICE_NAME, key)?.delete_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// 测试时保存在内存中，不读写开发机上的系统钥匙串
+#[cfg(test)]
+mod store {
+    use anyhow::Result;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    static SECRETS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+    pub fn load_secret(key: &str) -> Result<Option<String>> {
+        Ok(SECRETS.lock().unwrap().get_or_insert_with(HashMap::new).get(key).cloned())
+    }
+
+    pub fn save_secret(key: &str, value: &str) -> Result<()> {
+        SECRETS.lock().unwrap().get_or_insert_with(HashMap::new).insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn delete_secret(key: &str) -> Result<()> {
+        SECRETS.lock().unwrap().get_or_insert_with(HashMap::new).remove(key);
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashSet;
+use crate::error::{AppError, FieldError};
+use crate::security;
+use crate::models::{default_account, LadderPlan, LadderRung, StrategyParams, Trade, TradeSide};
+use crate::money::{Money, Rounding};
+
+/// 单个网格计划最多的档数
+const MAX_RUNGS: usize = 20;
+
+/// 生成网格计划的参数
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LadderRequest {
+    #[serde(default = "default_account")]
+    pub account: String,
+    pub stock_code: String,
+    pub stock_name: Option<String>,
+    /// 第 0 档价格，不指定时使用该账户最近一次买入价，没有持仓时使用现价
+    pub anchor_price: Option<Money>,
+    pub budget: Money,
+    pub rungs: usize,
+    /// 每档的下跌幅度，不指定时使用解析出的买入台阶
+    pub step_percentage: Option<f64>,
+}
+
+/// 从锚定价格开始逐档下移一个台阶，每档平均分配预算，数量按该股票的买入数量规则向下取整
+///
+/// 第 i 档买入价为 锚定价格 × (1 - 台阶)^i，卖出价为该档买入价按策略参数计算的卖出目标（按最短持有天数）。
+/// 买入价向下、卖出价向上取整到该股票的报价单位。
+pub fn build_plan(
+    account: &str,
+    stock_code: &str,
+    stock_name: &str,
+    anchor_price: Money,
+    budget: Money,
+    rungs: usize,
+    params: &StrategyParams,
+) -> Result<LadderPlan> {
+    let mut errors = Vec::new();
+    if !anchor_price.is_positive() {
+        errors.push(FieldError::new("anchorPrice", "锚定价格必须大于0"));
+    }
+    if !budget.is_positive() {
+        errors.push(FieldError::new("budget", "预算必须大于0"));
+    }
+    if rungs == 0 || rungs > MAX_RUNGS {
+        errors.push(FieldError::new("rungs", format!("档数必须在1到{}之间", MAX_RUNGS)));
+    }
+    if params.buy_step_percentage <= 0.0 || params.buy_step_percentage >= 1.0 {
+        errors.push(FieldError::new("stepPercentage", "买入台阶必须在0到1之间"));
+    }
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors).into());
+    }
+
+    let tick = security::tick_size(stock_code);
+    let rule = security::buy_quantity_rule(stock_code);
+    let symbol = security::SecurityId::parse(stock_code).map(|id| id.currency()).unwrap_or_default().symbol();
+    let per_rung = budget / rungs as i64;
+    let rungs = (1..=rungs)
+        .map(|level| {
+            let buy_price = anchor_price
+                .mul_f64((1.0 - params.buy_step_percentage).powi(level as i32))
+                .round_to(tick, Rounding::Down);
+            let quantity = security::round_buy_quantity(per_rung.units_of(buy_price), rule);
+            if quantity == 0 {
+                return Err(AppError::InvalidFields(vec![FieldError::new(
+                    "budget",
+                    format!("预算不足以在第{}档（{}{}）买入 {} 股", level, symbol, buy_price, rule.0),
+                )])
+                .into());
+            }
+
+            Ok(LadderRung {
+                id: None,
+                plan_id: None,
+                level: level as i64,
+                buy_price,
+                sell_price: params.sell_target(buy_price, 0).round_to(tick, Rounding::Up),
+                quantity,
+                filled_trade_id: None,
+                alerted_side: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(LadderPlan {
+        id: None,
+        account: account.to_string(),
+        stock_code: stock_code.to_string(),
+        stock_name: stock_name.to_string(),
+        anchor_price: anchor_price.round_to(tick, Rounding::HalfUp),
+        budget,
+        step_percentage: params.buy_step_percentage,
+        created_at: None,
+        rungs,
+    })
+}
+
+/// 用计划创建之后的交易记录标记已成交的档位，返回是否有变化
+///
+/// 已删除的交易对应的档位恢复为未成交；每笔新交易成交价格不高于档位价格的最高一档，一笔交易只对应一档。
+/// 成交价高于档位价格不超过一个最小报价单位时仍视为该档成交。成交状态变化的档位清空已提醒的方向。
+pub fn match_fills(plan: &mut LadderPlan, trades: &[Trade]) -> bool {
+    let tolerance = security::tick_size(&plan.stock_code);
+    let mut candidates: Vec<&Trade> = trades
+        .iter()
+        .filter(|t| t.account == plan.account && t.stock_code == plan.stock_code)
+        .filter(|t| plan.created_at.map(|created| t.buy_time >= created).unwrap_or(true))
+        .collect();
+    candidates.sort_by_key(|t| t.buy_time);
+
+    let mut changed = false;
+    let existing: HashSet<i64> = candidates.iter().filter_map(|t| t.id).collect();
+    for rung in plan.rungs.iter_mut() {
+        if rung.filled_trade_id.map(|id| !existing.contains(&id)).unwrap_or(false) {
+            rung.filled_trade_id = None;
+            rung.alerted_side = None;
+            changed = true;
+        }
+    }
+
+    let used: HashSet<i64> = plan.rungs.iter().filter_map(|r| r.filled_trade_id).collect();
+    for trade in candidates {
+        let Some(trade_id) = trade.id.filter(|id| !used.contains(id)) else {
+            continue;
+        };
+
+        let rung = plan
+            .rungs
+            .iter_mut()
+            .filter(|r| r.filled_trade_id.is_none() && trade.buy_price <= r.buy_price + tolerance)
+            .min_by_key(|r| r.level);
+        if let Some(rung) = rung {
+            rung.filled_trade_id = Some(trade_id);
+            rung.alerted_side = None;
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+/// 现价触发的档位：未成交且跌到买入价的档位提示买入，已成交且涨到卖出价的档位提示卖出
+///
+/// 当前成交状态下已经提醒过同一方向的档位不再返回。
+pub fn triggered_rungs(plan: &LadderPlan, current_price: Money) -> Vec<(TradeSide, &LadderRung)> {
+    plan.rungs
+        .iter()
+        .filter_map(|rung| match rung.filled_trade_id {
+            None if current_price <= rung.buy_price => Some((TradeSide::Buy, rung)),
+            Some(_) if current_price >= rung.sell_price => Some((TradeSide::Sell, rung)),
+            _ => None,
+        })
+        .filter(|(side, rung)| rung.alerted_side != Some(*side))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{DayCountBasis, GrowthModel, StopRules};
+    use chrono::{Duration, Utc};
+
+    fn params() -> StrategyParams {
+        StrategyParams {
+            annual_return_rate: 0.2,
+            buy_step_percentage: 0.05,
+            min_holding_days: 30,
+            growth_model: GrowthModel::Simple,
+            day_count_basis: DayCountBasis::Days360,
+            stops: StopRules::default(),
+        }
+    }
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    fn trade(id: i64, price: &str, minutes_after: i64, plan: &LadderPlan) -> Trade {
+        Trade {
+            id: Some(id),
+            stock_code: plan.stock_code.clone(),
+            stock_name: plan.stock_name.clone(),
+            buy_price: money(price),
+            buy_time: plan.created_at.unwrap() + Duration::minutes(minutes_after),
+            quantity: 100,
+            notes: None,
+            account: plan.account.clone(),
+            security: None,
+            currency: None,
+            created_at: None,
+        }
+    }
+
+    fn plan() -> LadderPlan {
+        let mut plan = build_plan("default", "600000", "浦发银行", money("10"), money("30000"), 3, &params()).unwrap();
+        plan.created_at = Some(Utc::now());
+        plan
+    }
+
+    #[test]
+    fn builds_geometric_rungs_rounded_to_lots() {
+        let plan = plan();
+        let prices: Vec<Money> = plan.rungs.iter().map(|r| r.buy_price).collect();
+        assert_eq!(prices, vec![money("9.5"), money("9.02"), money("8.57")]);
+
+        let quantities: Vec<i64> = plan.rungs.iter().map(|r| r.quantity).collect();
+        assert_eq!(quantities, vec![1000, 1100, 1100]);
+
+        // 每档卖出价按最短持有 30 天的单利目标计算
+        assert_eq!(plan.rungs[0].sell_price, money("9.66"));
+    }
+
+    #[test]
+    fn sizes_rungs_with_the_quantity_rule() {
+        // 科创板 200 股起按 1 股递增
+        let plan = build_plan("default", "688981", "中芯国际", money("50"), money("30000"), 3, &params()).unwrap();
+        assert_eq!(plan.rungs[0].buy_price, money("47.5"));
+        assert_eq!(plan.rungs[0].quantity, 210);
+
+        let error = build_plan("default", "688981", "中芯国际", money("50"), money("27000"), 3, &params()).unwrap_err();
+        assert_eq!(AppError::from(error).code(), "validation");
+    }
+
+    #[test]
+    fn rejects_budget_below_one_lot_per_rung() {
+        let error = build_plan("default", "600000", "浦发银行", money("100"), money("15000"), 3, &params()).unwrap_err();
+        assert_eq!(AppError::from(error).code(), "validation");
+    }
+
+    #[test]
+    fn matches_trades_to_rungs_and_releases_deleted_trades() {
+        let mut plan = plan();
+        let trades = vec![trade(1, "9.5", 1, &plan), trade(2, "8.9", 2, &plan)];
+
+        assert!(match_fills(&mut plan, &trades));
+        let fills: Vec<Option<i64>> = plan.rungs.iter().map(|r| r.filled_trade_id).collect();
+        assert_eq!(fills, vec![Some(1), Some(2), None]);
+        assert!(!match_fills(&mut plan, &trades));
+
+        assert!(match_fills(&mut plan, &trades[1..]));
+        let fills: Vec<Option<i64>> = plan.rungs.iter().map(|r| r.filled_trade_id).collect();
+        assert_eq!(fills, vec![None, Some(2), None]);
+    }
+
+    #[test]
+    fn ignores_trades_before_plan_was_created() {
+        let mut plan = plan();
+        let early = trade(1, "9.0", -10, &plan);
+
+        assert!(!match_fills(&mut plan, &[early]));
+    }
+
+    #[test]
+    fn triggers_per_rung() {
+        let mut plan = plan();
+        plan.rungs[0].filled_trade_id = Some(1);
+
+        let buys = triggered_rungs(&plan, money("9.0"));
+        assert_eq!(buys.len(), 1);
+        assert_eq!((buys[0].0, buys[0].1.level), (TradeSide::Buy, 2));
+
+        let sells = triggered_rungs(&plan, money("9.7"));
+        assert_eq!(sells.len(), 1);
+        assert_eq!((sells[0].0, sells[0].1.level), (TradeSide::Sell, 1));
+    }
+
+    #[test]
+    fn alerts_each_rung_once_per_fill_state() {
+        let mut plan = plan();
+        plan.rungs[0].alerted_side = Some(TradeSide::Buy);
+        assert!(triggered_rungs(&plan, money("9.4")).is_empty());
+
+        // 成交后可以提醒卖出，交易删除后重新提醒买入
+        let trades = vec![trade(1, "9.5", 1, &plan)];
+        assert!(match_fills(&mut plan, &trades));
+        assert_eq!(plan.rungs[0].alerted_side, None);
+        plan.rungs[0].alerted_side = Some(TradeSide::Sell);
+        assert!(triggered_rungs(&plan, money("9.7")).is_empty());
+
+        assert!(match_fills(&mut plan, &[]));
+        let buys = triggered_rungs(&plan, money("9.4"));
+        assert_eq!((buys.len(), buys[0].1.level), (1, 1));
+    }
+}
@@ -6,6 +6,26 @@ mod api;
 mod models;
 mod commands;
 mod stock_api;
+mod keychain;
+mod onedrive;
+mod csv_import;
+mod broker_import;
+mod portfolio;
+mod export;
+mod beancount;
+mod analytics;
+mod valuation;
+mod backtest;
+mod optimizer;
+mod paper;
+mod strategy;
+mod ladder;
+mod volatility;
+mod market;
+mod money;
+mod error;
+mod security;
+mod fx;
 
 
 
@@ -23,15 +43,62 @@ fn main() {
             commands::get_all_trades,
             commands::update_trade,
             commands::delete_trade,
+            commands::preview_trades_csv,
+            commands::import_trades_csv,
+            commands::preview_broker_statement,
+            commands::import_broker_statement,
+            commands::reconcile_broker_trades,
+            commands::export_csv,
+            commands::export_json,
+            commands::export_xlsx,
+            commands::export_beancount,
+            commands::import_json,
             commands::get_stock_price,
             commands::validate_stock_code,
             commands::search_stocks,
             commands::get_stock_info,
             commands::calculate_price_targets,
+            commands::get_strategy_overrides,
+            commands::set_strategy_override,
+            commands::delete_strategy_override,
+            commands::get_strategy_params,
+            commands::preview_ladder_plan,
+            commands::create_ladder_plan,
+            commands::get_ladder_plans,
+            commands::delete_ladder_plan,
+            commands::get_portfolio_summary,
+            commands::get_fx_rates,
+            commands::set_fx_rate,
+            commands::update_fx_rates,
+            commands::get_portfolio_returns,
+            commands::get_equity_curve,
+            commands::backfill_valuation_snapshots,
+            commands::get_benchmark_quotes,
+            commands::get_benchmark_comparison,
+            commands::run_backtest,
+            commands::run_parameter_sweep,
+            commands::import_daily_bars_csv,
+            commands::create_paper_portfolio,
+            commands::get_paper_portfolios,
+            commands::delete_paper_portfolio,
+            commands::paper_buy,
+            commands::get_paper_fills,
+            commands::check_paper_fills,
+            commands::get_paper_comparison,
             commands::get_setting,
             commands::set_setting,
+            commands::get_database_status,
+            commands::unlock_database,
+            commands::enable_database_encryption,
+            commands::disable_database_encryption,
+            commands::change_database_passphrase,
             commands::send_notification,
-            commands::check_price_alerts_and_notify
+            commands::check_price_alerts_and_notify,
+            commands::get_alert_history,
+            commands::onedrive_start_login,
+            commands::onedrive_finish_login,
+            commands::onedrive_logout,
+            commands::onedrive_backup_now
         ])
         .setup(|_app| {
             // 初始化数据库
@@ -39,7 +106,16 @@ fn main() {
                 if let Err(e) = database::init_database().await {
                     eprintln!("数据库初始化失败: {}", e);
                 }
+
+                // 加密数据库可能稍后才由用户解锁，自动备份任务会自行等待数据库就绪
+                onedrive::run_auto_backup().await;
             });
+
+            // 每日估值快照
+            tauri::async_runtime::spawn(valuation::run_daily_snapshots());
+
+            // 模拟盘自动成交
+            tauri::async_runtime::spawn(paper::run_paper_trading());
             Ok(())
         })
         .run(tauri::generate_context!())
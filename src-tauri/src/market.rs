@@ -0,0 +1,345 @@
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, NaiveTime, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use crate::error::FieldError;
+use crate::models::{DailyBar, Trade};
+use crate::money::{Money, Rounding};
+use crate::security::SecurityId;
+
+/// 交易板块，与品种类型一起决定报价单位、涨跌幅限制和买入数量规则
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Board {
+    /// 沪深主板，±10%，ST ±5%
+    Main,
+    /// 创业板，±20%
+    ChiNext,
+    /// 科创板，±20%
+    Star,
+    /// 北交所，±30%
+    Beijing,
+}
+
+/// 名称带 ST 或 *ST 的为风险警示股票
+pub fn is_st(stock_name: &str) -> bool {
+    stock_name.to_uppercase().contains("ST")
+}
+
+/// 是否处于连续竞价时段：工作日 9:30-11:30、13:00-15:00，time 为北京时间
+pub fn is_continuous_session(time: DateTime<FixedOffset>) -> bool {
+    if matches!(time.weekday(), Weekday::Sat | Weekday::Sun) {
+        return false;
+    }
+
+    let time = time.time();
+    let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    (time >= at(9, 30) && time <= at(11, 30)) || (time >= at(13, 0) && time <= at(15, 0))
+}
+
+/// 是否处于可以成交的交易时段：连续竞价时段加上工作日 9:15-9:25 的开盘集合竞价，time 为北京时间
+pub fn is_trading_session(time: DateTime<FixedOffset>) -> bool {
+    let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    let auction = !matches!(time.weekday(), Weekday::Sat | Weekday::Sun)
+        && time.time() >= at(9, 15)
+        && time.time() <= at(9, 25);
+    auction || is_continuous_session(time)
+}
+
+/// 是否处于港股交易时段：工作日 9:00-9:20 开市前时段、9:30-12:00、13:00-16:00，香港与北京同一时区
+pub fn is_hk_trading_session(time: DateTime<FixedOffset>) -> bool {
+    if matches!(time.weekday(), Weekday::Sat | Weekday::Sun) {
+        return false;
+    }
+
+    let time = time.time();
+    let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    (time >= at(9, 0) && time <= at(9, 20))
+        || (time >= at(9, 30) && time <= at(12, 0))
+        || (time >= at(13, 0) && time <= at(16, 0))
+}
+
+/// 是否处于美股常规交易时段：纽约时间工作日 9:30-16:00
+pub fn is_us_trading_session(time: DateTime<Utc>) -> bool {
+    let time = time.with_timezone(&us_eastern_offset(time));
+    if matches!(time.weekday(), Weekday::Sat | Weekday::Sun) {
+        return false;
+    }
+
+    let time = time.time();
+    let at = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+    time >= at(9, 30) && time <= at(16, 0)
+}
+
+/// 是否有任一市场处于交易时段：A 股、港股或美股
+pub fn is_any_trading_session(time: DateTime<Utc>) -> bool {
+    let beijing = time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap());
+    is_trading_session(beijing) || is_hk_trading_session(beijing) || is_us_trading_session(time)
+}
+
+/// 纽约时间的时区偏移：三月第二个周日 2:00 至十一月第一个周日 2:00 为夏令时 UTC-4，其余为 UTC-5
+fn us_eastern_offset(time: DateTime<Utc>) -> FixedOffset {
+    let nth_sunday = |month: u32, n: i64| {
+        let first = NaiveDate::from_ymd_opt(time.year(), month, 1).unwrap();
+        let to_sunday = (7 - first.weekday().num_days_from_sunday() as i64) % 7;
+        first + Duration::days(to_sunday + 7 * (n - 1))
+    };
+    let dst_start = nth_sunday(3, 2).and_hms_opt(7, 0, 0).unwrap().and_utc();
+    let dst_end = nth_sunday(11, 1).and_hms_opt(6, 0, 0).unwrap().and_utc();
+
+    let hours = if time >= dst_start && time < dst_end { -4 } else { -5 };
+    FixedOffset::east_opt(hours * 3600).unwrap()
+}
+
+/// 买入时间的检查方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeCheck {
+    /// 必须在该市场的交易时段内
+    Session,
+    /// 只检查是否为工作日，用于只有日期、没有时间的记录
+    TradingDay,
+    /// 不检查，用于编辑时没有改动的买入时间
+    Unchanged,
+}
+
+/// 检查交易记录是否符合交易规则，day_bar 为买入当日的日线，没有时不检查价格区间
+pub fn validate_trade(trade: &Trade, day_bar: Option<&DailyBar>, time_check: TimeCheck, now: DateTime<Utc>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    let security = trade.security_id();
+    match &security {
+        None => errors.push(FieldError::new("stockCode", format!("股票代码无效: {}", trade.stock_code))),
+        Some(id) if !id.is_tradable() => {
+            errors.push(FieldError::new("stockCode", format!("指数不能交易: {}", trade.stock_code)))
+        }
+        Some(_) => {}
+    }
+    if trade.stock_name.trim().is_empty() {
+        errors.push(FieldError::new("stockName", "股票名称不能为空"));
+    }
+
+    if !trade.buy_price.is_positive() {
+        errors.push(FieldError::new("buyPrice", "买入价格必须大于0"));
+    } else if let Some(bar) = day_bar {
+        let (low, high) = (bar.low, bar.high);
+        if trade.buy_price < low || trade.buy_price > high {
+            errors.push(FieldError::new(
+                "buyPrice",
+                format!("买入价格 {} 超出当日最低价 {} 到最高价 {} 的范围", trade.buy_price, low, high),
+            ));
+        }
+    }
+
+    let quantity = trade.quantity as i64;
+    if let Some(id) = &security {
+        let (minimum, step) = id.buy_quantity_rule();
+        if quantity < minimum {
+            errors.push(FieldError::new("quantity", format!("买入数量不能少于 {}", minimum)));
+        } else if step > 1 && quantity % step != 0 {
+            errors.push(FieldError::new("quantity", format!("买入数量必须是 {} 的整数倍", step)));
+        }
+    } else if quantity <= 0 {
+        errors.push(FieldError::new("quantity", "买入数量必须大于0"));
+    }
+    if trade.buy_price.checked_mul(quantity).is_err() {
+        errors.push(FieldError::new("quantity", "买入金额超出范围"));
+    }
+
+    let beijing = trade.buy_time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap());
+    match time_check {
+        TimeCheck::Unchanged => {}
+        _ if trade.buy_time > now => errors.push(FieldError::new("buyTime", "买入时间不能晚于当前时间")),
+        TimeCheck::TradingDay => {
+            if matches!(beijing.weekday(), Weekday::Sat | Weekday::Sun) {
+                errors.push(FieldError::new("buyTime", "买入日期不是交易日"));
+            }
+        }
+        TimeCheck::Session => {
+            let in_session = match &security {
+                Some(id) => id.is_trading_session(trade.buy_time),
+                None => is_trading_session(beijing),
+            };
+            if !in_session {
+                errors.push(FieldError::new("buyTime", "买入时间不在该市场的交易时段内"));
+            }
+        }
+    }
+
+    errors
+}
+
+/// 当日涨停价和跌停价
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceLimits {
+    pub limit_up: Money,
+    pub limit_down: Money,
+}
+
+impl PriceLimits {
+    /// 前收盘价 × (1 ± 涨跌幅比例)，按报价单位四舍五入，指数没有涨跌幅限制
+    pub fn from_prev_close(prev_close: Money, security: &SecurityId, is_st: bool) -> Option<Self> {
+        let percent = security.limit_percent(is_st)?;
+        let tick = security.tick_size();
+        Some(PriceLimits {
+            limit_up: prev_close.mul_f64(1.0 + percent).round_to(tick, Rounding::HalfUp),
+            limit_down: prev_close.mul_f64(1.0 - percent).round_to(tick, Rounding::HalfUp),
+        })
+    }
+
+    /// 卖出目标高于涨停价，当日无法成交
+    pub fn sell_beyond_limit(&self, sell_target: Money) -> bool {
+        sell_target > self.limit_up
+    }
+
+    /// 买入目标低于跌停价，当日无法成交
+    pub fn buy_beyond_limit(&self, buy_target: Money) -> bool {
+        buy_target < self.limit_down
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone};
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    fn security(code: &str) -> SecurityId {
+        SecurityId::parse(code).unwrap()
+    }
+
+    #[test]
+    fn computes_limits_by_board_and_st() {
+        let main = PriceLimits::from_prev_close(money("12.34"), &security("600000"), false).unwrap();
+        assert_eq!((main.limit_up, main.limit_down), (money("13.57"), money("11.11")));
+
+        let st = PriceLimits::from_prev_close(money("10.0"), &security("600221"), is_st("*ST海航")).unwrap();
+        assert_eq!((st.limit_up, st.limit_down), (money("10.5"), money("9.5")));
+
+        // 创业板 ST 仍为 ±20%
+        let chinext = PriceLimits::from_prev_close(money("10.0"), &security("300750"), true).unwrap();
+        assert_eq!((chinext.limit_up, chinext.limit_down), (money("12.0"), money("8.0")));
+
+        let fund = PriceLimits::from_prev_close(money("1.234"), &security("510300"), false).unwrap();
+        assert_eq!((fund.limit_up, fund.limit_down), (money("1.357"), money("1.111")));
+
+        let beijing = PriceLimits::from_prev_close(money("10.0"), &security("830799"), false).unwrap();
+        assert_eq!((beijing.limit_up, beijing.limit_down), (money("13.0"), money("7.0")));
+
+        assert!(PriceLimits::from_prev_close(money("3500"), &security("sh000300"), false).is_none());
+
+        assert!(main.sell_beyond_limit(money("13.58")));
+        assert!(!main.sell_beyond_limit(money("13.57")));
+        assert!(main.buy_beyond_limit(money("11.1")));
+        assert!(!main.buy_beyond_limit(money("11.11")));
+    }
+
+    fn trade(code: &str, price: &str, quantity: i32, buy_time: DateTime<Utc>) -> Trade {
+        Trade {
+            id: None,
+            stock_code: code.to_string(),
+            stock_name: "测试".to_string(),
+            buy_price: money(price),
+            buy_time,
+            quantity,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        }
+    }
+
+    fn beijing(h: u32, m: u32) -> DateTime<Utc> {
+        // 2024-01-15 为周一
+        FixedOffset::east_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 1, 15, h, m, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|e| e.field).collect()
+    }
+
+    #[test]
+    fn us_session_follows_daylight_saving() {
+        let utc = |m, d, h, min| Utc.with_ymd_and_hms(2024, m, d, h, min, 0).unwrap();
+        // 2024-03-10 起为夏令时，开盘为 UTC 13:30
+        assert!(!is_us_trading_session(utc(3, 8, 14, 0)));
+        assert!(is_us_trading_session(utc(3, 8, 14, 30)));
+        assert!(is_us_trading_session(utc(3, 11, 13, 30)));
+        // 2024-11-03 起恢复标准时间，收盘为 UTC 21:00
+        assert!(is_us_trading_session(utc(11, 4, 20, 30)));
+        assert!(!is_us_trading_session(utc(11, 1, 20, 30)));
+        assert!(!is_us_trading_session(utc(3, 9, 15, 0)));
+    }
+
+    #[test]
+    fn validates_quantity_by_board() {
+        let now = beijing(16, 0);
+        assert!(validate_trade(&trade("600000", "10", 300, beijing(10, 0)), None, TimeCheck::Session, now).is_empty());
+        assert_eq!(fields(&validate_trade(&trade("600000", "10", 150, beijing(10, 0)), None, TimeCheck::Session, now)), ["quantity"]);
+        assert_eq!(fields(&validate_trade(&trade("600000", "10", 0, beijing(10, 0)), None, TimeCheck::Session, now)), ["quantity"]);
+
+        // 科创板 200 股起，按 1 股递增
+        assert!(validate_trade(&trade("688981", "50", 201, beijing(10, 0)), None, TimeCheck::Session, now).is_empty());
+        assert_eq!(fields(&validate_trade(&trade("688981", "50", 100, beijing(10, 0)), None, TimeCheck::Session, now)), ["quantity"]);
+
+        // 北交所 100 股起，按 1 股递增
+        assert!(validate_trade(&trade("830799", "20", 101, beijing(10, 0)), None, TimeCheck::Session, now).is_empty());
+
+        // 价格 × 数量超出金额范围
+        assert_eq!(fields(&validate_trade(&trade("600000", "900000000000", 10000, beijing(10, 0)), None, TimeCheck::Session, now)), ["quantity"]);
+    }
+
+    #[test]
+    fn validates_price_and_time() {
+        let now = beijing(16, 0);
+        let bar = DailyBar {
+            date: NaiveDate::from_ymd_opt(2024, 1, 15).unwrap(),
+            open: money("10"),
+            high: money("10.5"),
+            low: money("9.8"),
+            close: money("10.2"),
+            volume: 1000,
+        };
+        assert!(validate_trade(&trade("600000", "10.5", 100, beijing(10, 0)), Some(&bar), TimeCheck::Session, now).is_empty());
+        assert_eq!(fields(&validate_trade(&trade("600000", "10.51", 100, beijing(10, 0)), Some(&bar), TimeCheck::Session, now)), ["buyPrice"]);
+        assert_eq!(fields(&validate_trade(&trade("600000", "-1", 100, beijing(10, 0)), None, TimeCheck::Session, now)), ["buyPrice"]);
+
+        // 午间休市、收盘后和未来时间
+        assert_eq!(fields(&validate_trade(&trade("600000", "10", 100, beijing(12, 0)), None, TimeCheck::Session, now)), ["buyTime"]);
+        assert_eq!(fields(&validate_trade(&trade("600000", "10", 100, beijing(15, 30)), None, TimeCheck::Session, now)), ["buyTime"]);
+        assert_eq!(fields(&validate_trade(&trade("600000", "10", 100, beijing(10, 0)), None, TimeCheck::Session, beijing(9, 0))), ["buyTime"]);
+
+        let errors = validate_trade(&trade("700000", "0", 100, beijing(10, 0)), None, TimeCheck::Session, now);
+        assert_eq!(fields(&errors), ["stockCode", "buyPrice"]);
+        assert_eq!(fields(&validate_trade(&trade("sh000300", "10", 100, beijing(10, 0)), None, TimeCheck::Session, now)), ["stockCode"]);
+
+        // 港股 15:30 仍在交易，零股数量不受整手限制
+        assert!(validate_trade(&trade("hk00700", "300", 50, beijing(15, 30)), None, TimeCheck::Session, now).is_empty());
+        assert_eq!(fields(&validate_trade(&trade("hk00700", "300", 50, beijing(12, 30)), None, TimeCheck::Session, now)), ["buyTime"]);
+    }
+
+    #[test]
+    fn accepts_opening_auction_and_checks_time_by_mode() {
+        let now = beijing(16, 0);
+        let at = |h, m| trade("600000", "10", 100, beijing(h, m));
+
+        // 开盘集合竞价 9:15-9:25 的成交有效，9:25-9:30 之间没有成交
+        assert!(validate_trade(&at(9, 25), None, TimeCheck::Session, now).is_empty());
+        assert!(validate_trade(&at(9, 15), None, TimeCheck::Session, now).is_empty());
+        assert_eq!(fields(&validate_trade(&at(9, 27), None, TimeCheck::Session, now)), ["buyTime"]);
+        assert!(validate_trade(&trade("hk00700", "300", 100, beijing(9, 20)), None, TimeCheck::Session, now).is_empty());
+
+        // 只有日期的记录只检查是否为工作日，未改动的买入时间不再检查
+        assert!(validate_trade(&at(0, 0), None, TimeCheck::TradingDay, now).is_empty());
+        let saturday = trade("600000", "10", 100, beijing(10, 0) - Duration::days(2));
+        assert_eq!(fields(&validate_trade(&saturday, None, TimeCheck::TradingDay, now)), ["buyTime"]);
+        assert!(validate_trade(&saturday, None, TimeCheck::Unchanged, now).is_empty());
+        assert!(validate_trade(&at(12, 0), None, TimeCheck::Unchanged, now).is_empty());
+    }
+}
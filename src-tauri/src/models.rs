@@ -1,47 +1,555 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::api::{DayCountBasis, GrowthModel, PriceSignal, StopRules};
+use crate::fx::Currency;
+use crate::money::Money;
+use crate::security::SecurityId;
+use crate::volatility::StepMode;
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+/// 未指定账户时使用的默认账户
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+pub fn default_account() -> String {
+    DEFAULT_ACCOUNT.to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Trade {
     pub id: Option<i64>,
     pub stock_code: String,
     pub stock_name: String,
-    pub buy_price: f64,
+    pub buy_price: Money,
     pub buy_time: DateTime<Utc>,
     pub quantity: i32,
     pub notes: Option<String>,
+    #[serde(default = "default_account")]
+    pub account: String,
+    /// 保存交易时按代码规则确定的交易所、板块和品种
+    #[serde(default)]
+    pub security: Option<SecurityId>,
+    /// 买入价的计价货币，为空时按证券所在市场确定
+    #[serde(default)]
+    pub currency: Option<Currency>,
     pub created_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+impl Trade {
+    /// 交易的证券标识，已保存的标识与代码不一致时按代码重新确定
+    pub fn security_id(&self) -> Option<SecurityId> {
+        self.security
+            .clone()
+            .filter(|id| id.matches(&self.stock_code))
+            .or_else(|| SecurityId::parse(&self.stock_code))
+    }
+
+    /// 交易的计价货币，未指定时港股为港币、美股为美元，其余为人民币
+    pub fn currency(&self) -> Currency {
+        self.currency
+            .or_else(|| self.security_id().map(|id| id.currency()))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Stock {
     pub code: String,
     pub name: String,
-    pub current_price: Option<f64>,
+    pub current_price: Option<Money>,
     pub last_updated: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Settings {
     pub key: String,
     pub value: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PriceCalculation {
-    pub sell_target_price: f64,
-    pub buy_target_price: f64,
+    pub sell_target_price: Money,
+    pub buy_target_price: Money,
     pub days_since_purchase: i64,
-    pub current_price: Option<f64>,
-    pub price_reached: String, // "sell", "buy", "none"
+    pub current_price: Option<Money>,
+    /// 现价触发的信号，带有与目标价的差距和按目标价成交的盈亏
+    pub signal: PriceSignal,
+    pub stop_loss_price: Option<Money>,
+    pub trailing_stop_price: Option<Money>,
+    /// 买入以来的最高价，用于跟踪止损
+    pub high_water_price: Option<Money>,
+    /// 交易所、板块和品种，代码无法识别时为空
+    pub security: Option<SecurityId>,
+    /// 按前收盘价计算的当日涨停价和跌停价，没有真实行情时为空
+    pub limit_up_price: Option<Money>,
+    pub limit_down_price: Option<Money>,
+    /// 卖出目标高于涨停价，当日无法达到
+    pub sell_target_beyond_limit: bool,
+    /// 买入目标低于跌停价，当日无法达到
+    pub buy_target_beyond_limit: bool,
+    pub annual_return_rate: f64,
+    pub buy_step_percentage: f64,
+    /// 实际采用的台阶方式，日线不足时回退为固定台阶
+    pub buy_step_mode: StepMode,
+    /// 计算台阶使用的相对波动率（日）
+    pub volatility: Option<f64>,
+    pub buy_step_reason: String,
+    pub min_holding_days: i64,
+    pub growth_model: GrowthModel,
+    pub day_count_basis: DayCountBasis,
+}
+
+/// 已触发的价格提醒记录
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AlertRecord {
+    pub id: Option<i64>,
+    pub trade_id: Option<i64>,
+    pub stock_code: String,
+    pub stock_name: String,
+    pub alert_type: String, // "sell", "buy", "stop"
+    pub target_price: Money,
+    pub current_price: Money,
+    pub message: String,
+    pub triggered_at: DateTime<Utc>,
+}
+
+/// 按账户和股票汇总的持仓
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Position {
+    pub account: String,
+    pub stock_code: String,
+    pub stock_name: String,
+    pub quantity: i64,
+    pub average_cost: Money,
+    pub total_cost: Money,
+    /// 成本使用的交易货币，旧版本导出的文件中没有该字段
+    #[serde(default)]
+    pub currency: Currency,
+    pub first_buy_time: DateTime<Utc>,
+    pub last_buy_time: DateTime<Utc>,
+}
+
+/// 达到目标价格的交易信号，只包含卖出、买入和止损信号
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeSignal {
+    pub trade_id: i64,
+    #[serde(flatten)]
+    pub signal: PriceSignal,
+}
+
+/// 单个持仓的分析结果，没有行情时市值按成本计算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionSummary {
+    pub account: String,
+    pub stock_code: String,
+    pub stock_name: String,
+    /// 成本、市值和盈亏的计价货币
+    pub currency: Currency,
+    pub quantity: i64,
+    pub average_cost: Money,
+    pub total_cost: Money,
+    pub current_price: Option<Money>,
+    pub market_value: Money,
+    pub unrealized_pnl: Money,
+    pub unrealized_pnl_percent: f64,
+    pub day_change: Money,
+    pub day_change_percent: f64,
+    /// 按买入日汇率折算的基准货币成本，缺少汇率时为空
+    pub total_cost_base: Option<Money>,
+    /// 按最新汇率折算的基准货币市值，缺少汇率时为空
+    pub market_value_base: Option<Money>,
+    /// 价格变动带来的基准货币盈亏
+    pub price_pnl: Option<Money>,
+    /// 汇率变动带来的基准货币盈亏
+    pub fx_pnl: Option<Money>,
+    /// 市值占组合总市值的百分比
+    pub weight: f64,
+    pub signals: Vec<TradeSignal>,
+}
+
+/// 投资组合分析结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioSummary {
+    /// 组合合计金额使用的基准货币
+    pub base_currency: Currency,
+    pub total_cost: Money,
+    pub market_value: Money,
+    pub unrealized_pnl: Money,
+    pub unrealized_pnl_percent: f64,
+    pub day_change: Money,
+    pub day_change_percent: f64,
+    /// 未实现盈亏中价格变动和汇率变动各自的部分
+    pub price_pnl: Money,
+    pub fx_pnl: Money,
+    /// 缺少汇率的货币，这些持仓未计入合计
+    pub missing_fx_rates: Vec<Currency>,
+    /// 取不到行情的股票代码，这些持仓的市值按成本计算
+    pub missing_quotes: Vec<String>,
+    pub sell_signals: usize,
+    pub buy_signals: usize,
+    pub stop_signals: usize,
+    pub total_trades: usize,
+    pub total_stocks: usize,
+    pub positions: Vec<PositionSummary>,
+    pub quoted_at: DateTime<Utc>,
+}
+
+/// 一组交易的资金加权年化收益率（XIRR），无法求解时为空
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReturnSummary {
+    pub account: Option<String>,
+    pub stock_code: Option<String>,
+    pub stock_name: Option<String>,
+    pub invested: Money,
+    pub market_value: Money,
+    pub xirr: Option<f64>,
+}
+
+/// 按持仓、账户和整体汇总的收益率
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReturnReport {
+    /// 投入和市值使用的基准货币
+    pub base_currency: Currency,
+    pub overall: ReturnSummary,
+    pub accounts: Vec<ReturnSummary>,
+    pub positions: Vec<ReturnSummary>,
+    /// 缺少汇率、未计入收益率的货币
+    pub missing_fx_rates: Vec<Currency>,
+    pub as_of: DateTime<Utc>,
+}
+
+/// 日线行情，股票和指数共用
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DailyBar {
+    pub date: NaiveDate,
+    pub open: Money,
+    pub high: Money,
+    pub low: Money,
+    pub close: Money,
+    pub volume: i64,
+}
+
+/// 某日 1 单位外币折合的人民币
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct FxRate {
+    pub currency: Currency,
+    pub rate_date: NaiveDate,
+    pub rate: f64,
+}
+
+/// 某个交易日收盘后单个持仓的估值快照
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ValuationSnapshot {
+    pub snapshot_date: NaiveDate,
+    pub account: String,
+    pub stock_code: String,
+    pub stock_name: String,
+    pub quantity: i64,
+    pub close_price: Money,
+    pub market_value: Money,
+    pub cost: Money,
+}
+
+/// 资金曲线上的一个交易日
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EquityPoint {
+    pub date: NaiveDate,
+    pub market_value: Money,
+    pub cost: Money,
+    pub pnl: Money,
+    /// 剔除当日资金进出后的收益率，第一天为空
+    pub daily_return: Option<f64>,
+    /// 以第一天为 1 的时间加权净值
+    pub nav: f64,
+}
+
+/// 资金曲线及风险统计，收益率均为小数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EquityCurve {
+    pub points: Vec<EquityPoint>,
+    pub time_weighted_return: Option<f64>,
+    pub annualized_return: Option<f64>,
+    pub max_drawdown: f64,
+    pub max_drawdown_start: Option<NaiveDate>,
+    pub max_drawdown_end: Option<NaiveDate>,
+    /// 日收益率标准差按 252 个交易日年化
+    pub volatility: Option<f64>,
+}
+
+/// 重建估值快照的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotReport {
+    /// 写入快照的交易日数量
+    pub trading_days: usize,
+    /// 行情接口只返回最近的日线，缺少早期日线的股票，这些日期的快照按成本估值
+    pub truncated: Vec<TruncatedHistory>,
+}
+
+/// 日线历史被截断的股票
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TruncatedHistory {
+    pub stock_code: String,
+    /// 需要日线的第一天
+    pub required_from: NaiveDate,
+    /// 实际能取到的最早日线
+    pub available_from: NaiveDate,
+}
+
+/// 基准对比的统计区间
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BenchmarkPeriod {
+    OneMonth,
+    ThreeMonths,
+    SixMonths,
+    YearToDate,
+    OneYear,
+    All,
+}
+
+/// 组合与基准在同一交易日的净值，均以区间第一天为 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkPoint {
+    pub date: NaiveDate,
+    pub portfolio_nav: f64,
+    pub benchmark_nav: f64,
+}
+
+/// 组合相对基准指数的表现，收益率均为小数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkComparison {
+    pub benchmark: String,
+    pub benchmark_name: String,
+    pub period: BenchmarkPeriod,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub portfolio_return: f64,
+    pub benchmark_return: f64,
+    pub excess_return: f64,
+    pub beta: Option<f64>,
+    /// 日超额收益标准差按 252 个交易日年化
+    pub tracking_error: Option<f64>,
+    pub points: Vec<BenchmarkPoint>,
+}
+
+/// 策略参数覆盖的作用范围，解析时按 交易 → 股票 → 账户 → 全局设置 的顺序取第一个有值的
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum OverrideScope {
+    Trade,
+    Stock,
+    Account,
+}
+
+/// 某笔交易、某只股票或某个账户的策略参数，为空的字段沿用上一级
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct StrategyOverride {
+    pub scope: OverrideScope,
+    /// 交易 ID、股票代码或账户名
+    pub scope_key: String,
+    pub annual_return_rate: Option<f64>,
+    pub buy_step_percentage: Option<f64>,
+    pub min_holding_days: Option<i64>,
+    pub stop_loss_percent: Option<f64>,
+    pub stop_loss_price: Option<Money>,
+    pub time_stop_days: Option<i64>,
+    pub trailing_stop_percent: Option<f64>,
+}
+
+/// 解析后某笔交易实际使用的策略参数
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StrategyParams {
+    pub annual_return_rate: f64,
+    pub buy_step_percentage: f64,
+    pub min_holding_days: i64,
+    pub growth_model: GrowthModel,
+    pub day_count_basis: DayCountBasis,
+    pub stops: StopRules,
+}
+
+/// 网格加仓计划，从锚定价格开始每下跌一个台阶买入一档
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LadderPlan {
+    pub id: Option<i64>,
+    #[serde(default = "default_account")]
+    pub account: String,
+    pub stock_code: String,
+    pub stock_name: String,
+    pub anchor_price: Money,
+    /// 计划投入的资金，平均分配到各档
+    pub budget: Money,
+    pub step_percentage: f64,
+    pub created_at: Option<DateTime<Utc>>,
+    #[sqlx(skip)]
+    pub rungs: Vec<LadderRung>,
+}
+
+/// 网格中的一档，买入后按自己的买入价计算卖出目标
+#[derive(Debug, Clone, Default, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct LadderRung {
+    pub id: Option<i64>,
+    pub plan_id: Option<i64>,
+    /// 从 1 开始，越大价格越低
+    pub level: i64,
+    pub buy_price: Money,
+    pub sell_price: Money,
+    pub quantity: i64,
+    /// 成交这一档的交易记录
+    pub filled_trade_id: Option<i64>,
+    /// 当前成交状态下已经提醒过的方向，成交状态变化时清空
+    #[serde(default)]
+    pub alerted_side: Option<TradeSide>,
+}
+
+/// 买卖方向，回测和模拟盘共用
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+/// 模拟盘组合，资金、持仓和成交都保存在独立的表中，不参与真实账户的统计
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperPortfolio {
+    pub id: Option<i64>,
+    pub name: String,
+    pub initial_cash: Money,
+    pub cash: Money,
+    pub annual_return_rate: f64,
+    pub buy_step_percentage: f64,
+    pub min_holding_days: i64,
+    pub growth_model: GrowthModel,
+    pub day_count_basis: DayCountBasis,
+    /// 每次自动买入的手数
+    pub lots_per_trade: i64,
+    pub commission_rate: f64,
+    pub min_commission: Money,
+    pub stamp_tax_rate: f64,
+    pub transfer_fee_rate: f64,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// 模拟盘中一笔未卖出的买入，与交易记录一样独立计算卖出目标价
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperPosition {
+    pub id: Option<i64>,
+    pub portfolio_id: i64,
+    pub stock_code: String,
+    pub stock_name: String,
+    pub buy_price: Money,
+    pub quantity: i64,
+    /// 按成交金额折算人民币后计算的买入费用
+    pub buy_fees: Money,
+    pub buy_time: DateTime<Utc>,
+}
+
+impl PaperPosition {
+    /// 持仓的计价货币，按证券所在市场确定
+    pub fn currency(&self) -> Currency {
+        SecurityId::parse(&self.stock_code).map(|id| id.currency()).unwrap_or_default()
+    }
+}
+
+/// 模拟成交记录，卖出时记录该笔持仓扣除买卖费用后的盈亏
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperFill {
+    pub id: Option<i64>,
+    pub portfolio_id: i64,
+    pub stock_code: String,
+    pub stock_name: String,
+    pub side: TradeSide,
+    pub price: Money,
+    pub quantity: i64,
+    /// 费用和已实现盈亏均为人民币
+    pub fees: Money,
+    pub realized_pnl: Option<Money>,
+    /// 触发成交的目标价，手动下单时为空
+    pub target_price: Option<Money>,
+    pub filled_at: DateTime<Utc>,
+}
+
+/// 模拟盘的一个持仓汇总
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperHolding {
+    pub stock_code: String,
+    pub stock_name: String,
+    #[serde(default)]
+    pub currency: Currency,
+    pub quantity: i64,
+    pub average_cost: Money,
+    pub current_price: Money,
+    pub market_value: Money,
+    /// 按最新汇率折算的人民币市值，缺少汇率时为空
+    #[serde(default)]
+    pub market_value_base: Option<Money>,
+    pub unrealized_pnl: Money,
+    /// 是否取到真实行情，没有时现价和市值按成本计算
+    pub priced: bool,
+}
+
+/// 模拟盘的资金和收益，资金按人民币计算，收益率均为小数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperPortfolioReport {
+    pub portfolio: PaperPortfolio,
+    /// 持仓的人民币市值合计，不含缺少汇率的持仓
+    pub market_value: Money,
+    pub equity: Money,
+    pub total_return: f64,
+    pub annualized_return: Option<f64>,
+    pub realized_pnl: Money,
+    pub total_fees: Money,
+    pub fill_count: usize,
+    pub holdings: Vec<PaperHolding>,
+    /// 缺少汇率的货币，这些持仓未计入市值和权益
+    #[serde(default)]
+    pub missing_fx_rates: Vec<Currency>,
+}
+
+/// 模拟盘与真实账户的收益对比，真实账户使用资金加权年化收益率
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaperComparison {
+    pub paper: Vec<PaperPortfolioReport>,
+    pub real_overall: ReturnSummary,
+    pub real_accounts: Vec<ReturnSummary>,
+    pub as_of: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DatabaseStatus {
+    pub initialized: bool,
+    pub encrypted: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StockPriceResponse {
     pub code: String,
     pub name: String,
-    pub price: f64,
-    pub change: f64,
+    pub price: Money,
+    pub change: Money,
     #[serde(rename = "changePercent")]
     pub change_percent: f64,
     pub timestamp: DateTime<Utc>,
@@ -61,14 +569,57 @@ pub struct StockInfo {
     pub code: String,
     pub name: String,
     #[serde(rename = "currentPrice")]
-    pub current_price: f64,
-    pub change: f64,
+    pub current_price: Money,
+    pub change: Money,
     #[serde(rename = "changePercent")]
     pub change_percent: f64,
-    pub open: f64,
-    pub high: f64,
-    pub low: f64,
+    pub open: Money,
+    pub high: Money,
+    pub low: Money,
     pub volume: i64,
-    pub turnover: i64,
+    pub turnover: Money,
     pub timestamp: DateTime<Utc>,
 }
+
+/// 交割单来源券商
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum Broker {
+    Tonghuashun, // 同花顺
+    Eastmoney,   // 东方财富
+    Huatai,      // 华泰证券
+}
+
+/// 交割单中的业务类型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+    Fee,
+    Dividend,
+    Transfer,
+    /// 新股、新债申购委托，不是成交，中签后另有成交流水
+    Subscription,
+    Other,
+}
+
+/// 从券商交割单导入的一条流水
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct BrokerTransaction {
+    pub id: Option<i64>,
+    pub broker: Broker,
+    pub kind: TransactionKind,
+    pub trade_time: DateTime<Utc>,
+    pub stock_code: Option<String>,
+    pub stock_name: Option<String>,
+    pub operation: String, // 交割单原始业务名称
+    pub price: Money,
+    pub quantity: i64,
+    pub amount: Money,     // 成交金额
+    pub net_amount: Money, // 发生金额，资金增加为正
+    pub fees: Money,       // 佣金、印花税、过户费等合计
+    pub contract_no: Option<String>,
+}
@@ -0,0 +1,407 @@
+use anyhow::{anyhow, Result};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::decode::Decode;
+use sqlx::encode::{Encode, IsNull};
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{Sqlite, SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Type, TypeInfo, ValueRef};
+use std::borrow::Cow;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+/// 每元的最小单位数，金额和价格统一精确到 0.0001 元
+const SCALE: i64 = 10_000;
+const DECIMALS: usize = 4;
+
+/// 金额和价格的定点数，内部以 0.0001 元为单位保存
+///
+/// 价格 × 数量和金额加减都是精确的整数运算；只有乘以收益率、费率这类比例或做除法时才需要取整，
+/// 取整方式由调用方通过 [`Rounding`] 明确指定。收益率、波动率等比例仍使用 `f64`。
+///
+/// JSON 中序列化为数字，与前端原有的格式一致；数据库中以十进制文本保存在 TEXT 列中，
+/// 读出旧版本遗留的浮点数时统一取整到 0.0001 元，因此不会带入浮点尾数。
+///
+/// 解析、反序列化和 `checked_*` 方法在超出范围时返回错误；运算符只用于已校验过范围的金额，溢出时直接 panic，不会静默回绕。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Money(i64);
+
+/// 取整方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// 四舍五入（远离零），用于费用、均价和涨跌停价
+    HalfUp,
+    /// 向上取整，用于卖出目标价和止损价
+    Up,
+    /// 向下取整，用于买入目标价和按预算计算的数量
+    Down,
+}
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+    /// 0.01 元，股票报价单位和资金结算单位
+    pub const FEN: Money = Money(SCALE / 100);
+    /// 0.001 元，场内基金报价单位
+    pub const LI: Money = Money(SCALE / 1000);
+
+    /// 整数元，用于常量和已校验的数值，外部输入使用 [`Money::checked_from_yuan`]
+    pub fn from_yuan(yuan: i64) -> Self {
+        Self::checked_from_yuan(yuan).expect("金额超出范围")
+    }
+
+    pub fn checked_from_yuan(yuan: i64) -> Result<Self> {
+        yuan.checked_mul(SCALE)
+            .map(Money)
+            .ok_or_else(|| anyhow!("金额超出范围: {}", yuan))
+    }
+
+    /// 从浮点数转换，四舍五入到 0.0001 元；超出范围时取最接近的可表示值，外部输入使用 [`Money::checked_from_f64`]
+    pub fn from_f64(value: f64) -> Self {
+        Money((value * SCALE as f64).round() as i64)
+    }
+
+    /// 从浮点数转换，不是有限数或超出范围时返回错误
+    pub fn checked_from_f64(value: f64) -> Result<Self> {
+        let units = (value * SCALE as f64).round();
+        if units.is_finite() && units >= i64::MIN as f64 && units < i64::MAX as f64 {
+            Ok(Money(units as i64))
+        } else {
+            Err(anyhow!("金额超出范围: {}", value))
+        }
+    }
+
+    /// 单价 × 数量，溢出时返回错误
+    pub fn checked_mul(self, quantity: i64) -> Result<Self> {
+        self.0
+            .checked_mul(quantity)
+            .map(Money)
+            .ok_or_else(|| anyhow!("金额超出范围: {} × {}", self, quantity))
+    }
+
+    pub fn checked_add(self, rhs: Money) -> Result<Self> {
+        self.0
+            .checked_add(rhs.0)
+            .map(Money)
+            .ok_or_else(|| anyhow!("金额超出范围: {} + {}", self, rhs))
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / SCALE as f64
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+
+    pub fn abs(self) -> Self {
+        Money(self.0.abs())
+    }
+
+    /// 取整到 unit 的整数倍，如报价单位或分
+    pub fn round_to(self, unit: Money, rounding: Rounding) -> Self {
+        if unit.0 <= 0 {
+            return self;
+        }
+        Money(divide(self.0, unit.0, rounding) * unit.0)
+    }
+
+    /// 乘以比例（增长倍数、费率等），四舍五入到 0.0001 元，需要按分或报价单位取整时再调用 [`Money::round_to`]
+    pub fn mul_f64(self, factor: f64) -> Self {
+        Money::from_f64(self.to_f64() * factor)
+    }
+
+    /// 与另一个金额的比值，基数不为正时返回 0
+    pub fn ratio(self, base: Money) -> f64 {
+        if base.0 > 0 {
+            self.0 as f64 / base.0 as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// 按单价向下取整得到能买的数量
+    pub fn units_of(self, price: Money) -> i64 {
+        if price.0 <= 0 {
+            return 0;
+        }
+        divide(self.0, price.0, Rounding::Down)
+    }
+}
+
+/// 整数除法，按指定方式取整
+fn divide(value: i64, divisor: i64, rounding: Rounding) -> i64 {
+    let quotient = value.div_euclid(divisor);
+    let remainder = value.rem_euclid(divisor);
+    if remainder == 0 {
+        return quotient;
+    }
+
+    match rounding {
+        Rounding::Down => quotient,
+        Rounding::Up => quotient + 1,
+        Rounding::HalfUp => {
+            // 负数远离零取整：余数恰好一半时向下（更负）一档
+            let twice = remainder * 2;
+            if twice > divisor || (twice == divisor && value > 0) {
+                quotient + 1
+            } else {
+                quotient
+            }
+        }
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, rhs: Money) -> Money {
+        self.checked_add(rhs).expect("金额溢出")
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, rhs: Money) -> Money {
+        Money(self.0.checked_sub(rhs.0).expect("金额溢出"))
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money(-self.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, rhs: Money) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Money {
+    fn sub_assign(&mut self, rhs: Money) {
+        *self = *self - rhs;
+    }
+}
+
+/// 单价 × 数量，精确计算；数量和价格来自外部输入时先用 [`Money::checked_mul`] 校验
+impl Mul<i64> for Money {
+    type Output = Money;
+
+    fn mul(self, quantity: i64) -> Money {
+        self.checked_mul(quantity).expect("金额溢出")
+    }
+}
+
+/// 除以数量（如求均价），四舍五入到 0.0001 元
+impl Div<i64> for Money {
+    type Output = Money;
+
+    fn div(self, quantity: i64) -> Money {
+        if quantity == 0 {
+            return Money::ZERO;
+        }
+        let (value, divisor) = if quantity < 0 { (-self.0, -quantity) } else { (self.0, quantity) };
+        Money(divide(value, divisor, Rounding::HalfUp))
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+/// 输出去掉多余的零，至少保留两位小数，如 10.58、1.234
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs();
+        let fraction = format!("{:0width$}", units % SCALE as u64, width = DECIMALS);
+        let fraction = fraction.trim_end_matches('0');
+        write!(f, "{}{}.{:0<2}", sign, units / SCALE as u64, fraction)
+    }
+}
+
+/// 解析十进制文本，超过四位的小数四舍五入
+impl FromStr for Money {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let text = s.trim().replace(',', "");
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(&text)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let valid = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !valid(whole) || !valid(fraction) {
+            return Err(anyhow!("无效的金额: {}", s));
+        }
+
+        let out_of_range = || anyhow!("金额超出范围: {}", s);
+        let whole: i64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| out_of_range())? };
+        let kept = &fraction[..fraction.len().min(DECIMALS)];
+        let round_up = fraction[kept.len()..].starts_with(['5', '6', '7', '8', '9']);
+        let units = whole
+            .checked_mul(SCALE)
+            .and_then(|units| units.checked_add(format!("{:0<width$}", kept, width = DECIMALS).parse::<i64>().unwrap_or(0)))
+            .and_then(|units| units.checked_add(round_up as i64))
+            .ok_or_else(out_of_range)?;
+
+        Ok(Money(if negative { -units } else { units }))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct MoneyVisitor;
+
+        impl<'de> Visitor<'de> for MoneyVisitor {
+            type Value = Money;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("金额数字或十进制文本")
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> std::result::Result<Money, E> {
+                Money::checked_from_f64(value).map_err(E::custom)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> std::result::Result<Money, E> {
+                Money::checked_from_yuan(value).map_err(E::custom)
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> std::result::Result<Money, E> {
+                i64::try_from(value)
+                    .map_err(|_| anyhow!("金额超出范围: {}", value))
+                    .and_then(Money::checked_from_yuan)
+                    .map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<Money, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+impl Type<Sqlite> for Money {
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <&str as Type<Sqlite>>::compatible(ty)
+            || <f64 as Type<Sqlite>>::compatible(ty)
+            || <i64 as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for Money {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        args.push(SqliteArgumentValue::Text(Cow::Owned(self.to_string())));
+        IsNull::No
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for Money {
+    fn decode(value: SqliteValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        if value.type_info().name() == "TEXT" {
+            Ok(<&str as Decode<Sqlite>>::decode(value)?.parse()?)
+        } else {
+            Ok(Money::checked_from_f64(<f64 as Decode<Sqlite>>::decode(value)?)?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(s: &str) -> Money {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn sums_without_drift() {
+        // 0.1 + 0.2 用 f64 累加一万次会偏离 3000
+        let total: Money = (0..10_000).map(|_| money("0.1") + money("0.2")).sum();
+        assert_eq!(total, Money::from_yuan(3000));
+        assert_eq!(money("10.58") * 300, money("3174"));
+    }
+
+    #[test]
+    fn parses_and_formats_decimal_text() {
+        assert_eq!(money("10.58").to_string(), "10.58");
+        assert_eq!(money("1.2340").to_string(), "1.234");
+        assert_eq!(money("-0.5").to_string(), "-0.50");
+        assert_eq!(money("12").to_string(), "12.00");
+        assert_eq!(money("1,234.5").to_string(), "1234.50");
+        assert_eq!(money("0.00005"), Money(1));
+        assert!("abc".parse::<Money>().is_err());
+        assert!("1.2.3".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn rejects_amounts_out_of_range() {
+        // 整数部分能放进 i64，但乘以 10000 后会溢出
+        assert!("922337203685478".parse::<Money>().is_err());
+        assert!("99999999999999999999".parse::<Money>().is_err());
+        assert_eq!("922337203685477.5807".parse::<Money>().unwrap(), Money(i64::MAX));
+        assert!(Money::checked_from_yuan(i64::MAX / 1000).is_err());
+        assert!(Money::checked_from_f64(f64::NAN).is_err());
+        assert!(Money::checked_from_f64(1e300).is_err());
+        assert!(money("1000000").checked_mul(i64::MAX / 1000).is_err());
+        assert!(Money(i64::MAX).checked_add(Money::FEN).is_err());
+        assert!(serde_json::from_str::<Money>("1e300").is_err());
+        assert!(serde_json::from_str::<Money>("18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn rounds_explicitly() {
+        let price = Money::from_f64(10.5833333);
+        assert_eq!(price.round_to(Money::FEN, Rounding::Up), money("10.59"));
+        assert_eq!(price.round_to(Money::FEN, Rounding::Down), money("10.58"));
+        assert_eq!(money("10.585").round_to(Money::FEN, Rounding::HalfUp), money("10.59"));
+        assert_eq!(money("-10.585").round_to(Money::FEN, Rounding::HalfUp), money("-10.59"));
+        assert_eq!(money("9.03").round_to(Money::FEN, Rounding::Up), money("9.03"));
+
+        // 均价四舍五入到 0.0001 元
+        assert_eq!(money("10") / 3, money("3.3333"));
+        assert_eq!(money("20") / 3, money("6.6667"));
+        assert_eq!(money("10000").units_of(money("9.03")), 1107);
+    }
+
+    #[test]
+    fn serializes_as_json_number() {
+        let json = serde_json::to_string(&money("10.58")).unwrap();
+        assert_eq!(json, "10.58");
+        assert_eq!(serde_json::from_str::<Money>("10.58").unwrap(), money("10.58"));
+        assert_eq!(serde_json::from_str::<Money>("\"10.58\"").unwrap(), money("10.58"));
+        assert_eq!(serde_json::from_str::<Money>("12").unwrap(), money("12"));
+    }
+}
@@ -0,0 +1,684 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::database::{get_database, is_database_initialized, Database};
+use crate::keychain;
+
+/// Microsoft Graph 默认地址
+pub const DEFAULT_GRAPH_BASE_URL: &str = "https://graph.microsoft.com/v1.0";
+/// 设备码授权默认地址
+pub const DEFAULT_DEVICE_CODE_ENDPOINT: &str =
+    "https://login.microsoftonline.com/consumers/oauth2/v2.0/devicecode";
+/// 令牌默认地址
+pub const DEFAULT_TOKEN_ENDPOINT: &str =
+    "https://login.microsoftonline.com/consumers/oauth2/v2.0/token";
+
+const SCOPES: &str = "Files.ReadWrite offline_access";
+/// 钥匙串中保存令牌的键名
+const TOKEN_SECRET_KEY: &str = "onedrive_tokens";
+/// 小于该大小的文件直接上传（Graph 限制为 4MB）
+const SIMPLE_UPLOAD_LIMIT: usize = 4 * 1024 * 1024;
+/// 分片大小，Graph 要求为 320 KiB 的整数倍
+const UPLOAD_CHUNK_SIZE: usize = 320 * 1024 * 10;
+/// 单个分片失败后的最大重试次数
+const MAX_CHUNK_RETRIES: u32 = 3;
+
+/// OneDrive 备份配置，全部从 settings 表读取
+#[derive(Debug, Clone)]
+pub struct OneDriveConfig {
+    pub client_id: String,
+    pub graph_base_url: String,
+    pub device_code_endpoint: String,
+    pub token_endpoint: String,
+    pub backup_folder: String,
+}
+
+impl OneDriveConfig {
+    pub async fn load(db: &Database) -> Result<Self> {
+        let client_id = db.get_setting("onedrive_client_id").await?.unwrap_or_default();
+        if client_id.trim().is_empty() {
+            return Err(anyhow!("未配置 OneDrive 应用 ID (onedrive_client_id)"));
+        }
+
+        Ok(OneDriveConfig {
+            client_id,
+            graph_base_url: Self::setting_or(db, "onedrive_graph_base_url", DEFAULT_GRAPH_BASE_URL).await?,
+            device_code_endpoint: Self::setting_or(db, "onedrive_device_code_endpoint", DEFAULT_DEVICE_CODE_ENDPOINT).await?,
+            token_endpoint: Self::setting_or(db, "onedrive_token_endpoint", DEFAULT_TOKEN_ENDPOINT).await?,
+            backup_folder: Self::setting_or(db, "onedrive_backup_folder", "stock-trader").await?,
+        })
+    }
+
+    async fn setting_or(db: &Database, key: &str, default: &str) -> Result<String> {
+        Ok(db
+            .get_setting(key)
+            .await?
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| default.to_string()))
+    }
+}
+
+/// 设备码登录信息，前端展示 user_code 和 verification_uri 给用户
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeviceCodeInfo {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_in: u64,
+    pub interval: u64,
+    pub message: Option<String>,
+}
+
+/// 上传完成后 OneDrive 返回的文件信息
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OneDriveItem {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredTokens {
+    access_token: String,
+    refresh_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct OAuthError {
+    error: String,
+    error_description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadSession {
+    #[serde(rename = "uploadUrl")]
+    upload_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadSessionStatus {
+    #[serde(rename = "nextExpectedRanges", default)]
+    next_expected_ranges: Vec<String>,
+}
+
+pub struct OneDriveClient {
+    config: OneDriveConfig,
+    http: reqwest::Client,
+}
+
+impl OneDriveClient {
+    pub fn new(config: OneDriveConfig) -> Result<Self> {
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(60))
+            .build()?;
+
+        Ok(OneDriveClient { config, http })
+    }
+
+    /// 发起设备码登录
+    pub async fn start_device_login(&self) -> Result<DeviceCodeInfo> {
+        let response = self
+            .http
+            .post(&self.config.device_code_endpoint)
+            .form(&[("client_id", self.config.client_id.as_str()), ("scope", SCOPES)])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::oauth_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// 轮询令牌地址直到用户完成授权，成功后将令牌保存到系统钥匙串
+    pub async fn finish_device_login(&self, device: &DeviceCodeInfo) -> Result<()> {
+        let deadline = Utc::now() + Duration::seconds(device.expires_in as i64);
+        let mut interval = device.interval.max(1);
+
+        while Utc::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+
+            let response = self
+                .http
+                .post(&self.config.token_endpoint)
+                .form(&[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                    ("client_id", self.config.client_id.as_str()),
+                    ("device_code", device.device_code.as_str()),
+                ])
+                .send()
+                .await?;
+
+            if response.status().is_success() {
+                let token: TokenResponse = response.json().await?;
+                Self::store_tokens(token, None)?;
+                return Ok(());
+            }
+
+            let error: OAuthError = response.json().await?;
+            match error.error.as_str() {
+                "authorization_pending" => {}
+                "slow_down" => interval += 5,
+                _ => {
+                    return Err(anyhow!(
+                        "OneDrive 授权失败: {}",
+                        error.error_description.unwrap_or(error.error)
+                    ))
+                }
+            }
+        }
+
+        Err(anyhow!("OneDrive 设备码已过期，请重新登录"))
+    }
+
+    /// 退出登录并清除保存的令牌
+    pub fn logout() -> Result<()> {
+        keychain::delete_secret(TOKEN_SECRET_KEY)
+    }
+
+    /// 是否已保存登录令牌
+    pub fn is_logged_in() -> Result<bool> {
+        Ok(keychain::load_secret(TOKEN_SECRET_KEY)?.is_some())
+    }
+
+    /// 将数据库快照上传到 OneDrive 备份目录
+    pub async fn upload_file(&self, local_path: &Path, remote_name: &str) -> Result<OneDriveItem> {
+        let data = tokio::fs::read(local_path).await?;
+
+        if data.len() <= SIMPLE_UPLOAD_LIMIT {
+            self.simple_upload(remote_name, data).await
+        } else {
+            self.session_upload(remote_name, &data).await
+        }
+    }
+
+    async fn simple_upload(&self, remote_name: &str, data: Vec<u8>) -> Result<OneDriveItem> {
+        let url = format!("{}:/content", self.item_path(remote_name));
+        let response = self
+            .send_authorized(|token| {
+                self.http
+                    .put(&url)
+                    .bearer_auth(token)
+                    .header("Content-Type", "application/octet-stream")
+                    .body(data.clone())
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("OneDrive 上传失败: HTTP {}", response.status()));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// 通过上传会话分片上传，分片失败时查询会话状态并从断点续传
+    async fn session_upload(&self, remote_name: &str, data: &[u8]) -> Result<OneDriveItem> {
+        let url = format!("{}:/createUploadSession", self.item_path(remote_name));
+        let body = serde_json::json!({
+            "item": { "@microsoft.graph.conflictBehavior": "replace" }
+        });
+        let response = self
+            .send_authorized(|token| self.http.post(&url).bearer_auth(token).json(&body))
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("创建 OneDrive 上传会话失败: HTTP {}", response.status()));
+        }
+
+        let session: UploadSession = response.json().await?;
+        let total = data.len();
+        let mut offset = 0usize;
+        let mut retries = 0u32;
+
+        loop {
+            let end = (offset + UPLOAD_CHUNK_SIZE).min(total);
+
+            // 上传地址自带授权信息，不能再附加 Authorization 头
+            let result = self
+                .http
+                .put(&session.upload_url)
+                .header("Content-Length", (end - offset).to_string())
+                .header("Content-Range", format!("bytes {}-{}/{}", offset, end - 1, total))
+                .body(data[offset..end].to_vec())
+                .send()
+                .await;
+
+            match result {
+                Ok(response) if response.status() == StatusCode::OK || response.status() == StatusCode::CREATED => {
+                    return Ok(response.json().await?);
+                }
+                Ok(response) if response.status() == StatusCode::ACCEPTED => {
+                    offset = end;
+                    retries = 0;
+                }
+                Ok(response) if response.status().is_client_error() && response.status() != StatusCode::REQUEST_TIMEOUT => {
+                    return Err(anyhow!("OneDrive 分片上传失败: HTTP {}", response.status()));
+                }
+                _ => {
+                    retries += 1;
+                    if retries > MAX_CHUNK_RETRIES {
+                        return Err(anyhow!("OneDrive 分片上传失败，已重试 {} 次", MAX_CHUNK_RETRIES));
+                    }
+
+                    tokio::time::sleep(std::time::Duration::from_secs(2u64.pow(retries))).await;
+                    offset = self.resume_offset(&session.upload_url).await?;
+                }
+            }
+        }
+    }
+
+    /// 查询上传会话，返回服务端期望的下一个字节位置
+    async fn resume_offset(&self, upload_url: &str) -> Result<usize> {
+        let status: UploadSessionStatus = self.http.get(upload_url).send().await?.json().await?;
+
+        status
+            .next_expected_ranges
+            .first()
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.parse().ok())
+            .ok_or_else(|| anyhow!("无法解析 OneDrive 上传会话状态"))
+    }
+
+    /// 发送带授权的请求，收到 401 时刷新令牌后重试一次
+    async fn send_authorized<F>(&self, build: F) -> Result<reqwest::Response>
+    where
+        F: Fn(&str) -> reqwest::RequestBuilder,
+    {
+        let token = self.access_token(false).await?;
+        let response = build(&token).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let token = self.access_token(true).await?;
+        Ok(build(&token).send().await?)
+    }
+
+    /// 获取访问令牌，过期（或强制）时使用刷新令牌换取新令牌
+    async fn access_token(&self, force_refresh: bool) -> Result<String> {
+        let stored = keychain::load_secret(TOKEN_SECRET_KEY)?
+            .ok_or_else(|| anyhow!("尚未登录 OneDrive"))?;
+        let tokens: StoredTokens = serde_json::from_str(&stored)?;
+
+        if !force_refresh && tokens.expires_at - Duration::seconds(60) > Utc::now() {
+            return Ok(tokens.access_token);
+        }
+
+        let response = self
+            .http
+            .post(&self.config.token_endpoint)
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", self.config.client_id.as_str()),
+                ("refresh_token", tokens.refresh_token.as_str()),
+                ("scope", SCOPES),
+            ])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::oauth_error(response).await);
+        }
+
+        let token: TokenResponse = response.json().await?;
+        let access_token = token.access_token.clone();
+        Self::store_tokens(token, Some(tokens.refresh_token))?;
+
+        Ok(access_token)
+    }
+
+    fn store_tokens(token: TokenResponse, previous_refresh_token: Option<String>) -> Result<()> {
+        let refresh_token = token
+            .refresh_token
+            .or(previous_refresh_token)
+            .ok_or_else(|| anyhow!("OneDrive 未返回刷新令牌"))?;
+
+        let stored = StoredTokens {
+            access_token: token.access_token,
+            refresh_token,
+            expires_at: Utc::now() + Duration::seconds(token.expires_in),
+        };
+
+        keychain::save_secret(TOKEN_SECRET_KEY, &serde_json::to_string(&stored)?)
+    }
+
+    async fn oauth_error(response: reqwest::Response) -> anyhow::Error {
+        let status = response.status();
+        match response.json::<OAuthError>().await {
+            Ok(error) => anyhow!(
+                "OneDrive 授权失败: {}",
+                error.error_description.unwrap_or(error.error)
+            ),
+            Err(_) => anyhow!("OneDrive 授权失败: HTTP {}", status),
+        }
+    }
+
+    /// 备份文件在 Graph 中的路径，备份目录可以有多级，每一级分别编码以保留 `/`
+    fn item_path(&self, remote_name: &str) -> String {
+        let segments: Vec<String> = self
+            .config
+            .backup_folder
+            .split('/')
+            .chain(std::iter::once(remote_name))
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| urlencoding::encode(segment).into_owned())
+            .collect();
+
+        format!("{}/me/drive/root:/{}", self.config.graph_base_url, segments.join("/"))
+    }
+}
+
+/// 生成数据库快照并上传到 OneDrive，完成后记录备份时间
+pub async fn backup_database() -> Result<OneDriveItem> {
+    let now = Utc::now();
+    let snapshot_path = std::env::temp_dir().join(format!("stock_trader_backup_{}.db", now.timestamp()));
+    let remote_name = format!("stock_trader_{}.db", now.format("%Y%m%d_%H%M%S"));
+
+    // 只在生成快照时持有数据库锁，上传过程中不阻塞其他命令
+    let config = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        let config = OneDriveConfig::load(&db_lock).await?;
+        db_lock.backup_to(&snapshot_path).await?;
+        config
+    };
+
+    let result = OneDriveClient::new(config)?.upload_file(&snapshot_path, &remote_name).await;
+    let _ = std::fs::remove_file(&snapshot_path);
+    let item = result?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock.set_setting("onedrive_last_backup", &now.to_rfc3339()).await?;
+
+    Ok(item)
+}
+
+/// 按 backup_interval 定期自动备份到 OneDrive
+pub async fn run_auto_backup() {
+    loop {
+        if let Err(e) = auto_backup_if_due().await {
+            println!("OneDrive 自动备份失败: {}", e);
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(600)).await;
+    }
+}
+
+async fn auto_backup_if_due() -> Result<()> {
+    if !is_database_initialized() {
+        return Ok(());
+    }
+
+    let (enabled, interval_hours, last_backup) = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        let enabled = db_lock.get_setting("auto_backup_enabled").await?.as_deref() == Some("true")
+            && db_lock.get_setting("onedrive_enabled").await?.as_deref() == Some("true");
+        let interval_hours: i64 = db_lock
+            .get_setting("backup_interval")
+            .await?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(24);
+        let last_backup = db_lock
+            .get_setting("onedrive_last_backup")
+            .await?
+            .and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+            .map(|t| t.with_timezone(&Utc));
+        (enabled, interval_hours, last_backup)
+    };
+
+    if !enabled || !OneDriveClient::is_logged_in()? {
+        return Ok(());
+    }
+
+    let due = match last_backup {
+        Some(last) => Utc::now() - last >= Duration::hours(interval_hours.max(1)),
+        None => true,
+    };
+
+    if due {
+        let item = backup_database().await?;
+        println!("OneDrive 自动备份完成: {}", item.name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Debug, Clone)]
+    struct MockRequest {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    type Handler = dyn Fn(&MockRequest, &str) -> (u16, String) + Send + Sync;
+
+    /// 在本地端口启动的模拟 Graph 和令牌服务，按顺序记录收到的请求
+    async fn mock_server(handler: Box<Handler>) -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let log = requests.clone();
+        let base = base_url.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let Some(request) = read_request(&mut socket).await else {
+                    continue;
+                };
+                let (status, body) = handler(&request, &base);
+                log.lock().unwrap().push(request);
+
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (base_url, requests)
+    }
+
+    async fn read_request(socket: &mut tokio::net::TcpStream) -> Option<MockRequest> {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 64 * 1024];
+        let header_end = loop {
+            let n = socket.read(&mut chunk).await.ok()?;
+            if n == 0 {
+                return None;
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+            if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split_whitespace();
+        let method = request_line.next()?.to_string();
+        let path = request_line.next()?.to_string();
+        let headers: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+            .collect();
+
+        let length: usize = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+        while buffer.len() < header_end + length {
+            let n = socket.read(&mut chunk).await.ok()?;
+            if n == 0 {
+                break;
+            }
+            buffer.extend_from_slice(&chunk[..n]);
+        }
+
+        Some(MockRequest { method, path, headers, body: buffer[header_end..].to_vec() })
+    }
+
+    fn config(base_url: &str, backup_folder: &str) -> OneDriveConfig {
+        OneDriveConfig {
+            client_id: "client".to_string(),
+            graph_base_url: base_url.to_string(),
+            device_code_endpoint: format!("{}/devicecode", base_url),
+            token_endpoint: format!("{}/token", base_url),
+            backup_folder: backup_folder.to_string(),
+        }
+    }
+
+    fn form(request: &MockRequest) -> HashMap<String, String> {
+        String::from_utf8_lossy(&request.body)
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k.to_string(), urlencoding::decode(v).unwrap().into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn encodes_each_folder_segment() {
+        let client = OneDriveClient::new(config("http://graph", "备份/stock trader/")).unwrap();
+        assert_eq!(
+            client.item_path("a b.db"),
+            "http://graph/me/drive/root:/%E5%A4%87%E4%BB%BD/stock%20trader/a%20b.db"
+        );
+    }
+
+    /// 设备码登录、令牌过期后刷新重试、小文件直传和分片上传断点续传的完整流程
+    #[tokio::test]
+    async fn backs_up_through_mock_graph() {
+        let polls = Arc::new(Mutex::new(0));
+        let chunk_failures = Arc::new(Mutex::new(1));
+        let received = Arc::new(Mutex::new(0usize));
+
+        let (polls_in, failures_in, received_in) = (polls.clone(), chunk_failures.clone(), received.clone());
+        let (base_url, requests) = mock_server(Box::new(move |request, base| {
+            let auth = request.headers.get("authorization").cloned().unwrap_or_default();
+            match (request.method.as_str(), request.path.as_str()) {
+                ("POST", "/devicecode") => (
+                    200,
+                    r#"{"device_code":"dc","user_code":"ABCD","verification_uri":"https://login","expires_in":60,"interval":1}"#.to_string(),
+                ),
+                ("POST", "/token") => match form(request).get("grant_type").map(String::as_str) {
+                    Some("refresh_token") => (200, r#"{"access_token":"fresh","refresh_token":"r2","expires_in":3600}"#.to_string()),
+                    _ => {
+                        let mut polls = polls_in.lock().unwrap();
+                        *polls += 1;
+                        if *polls == 1 {
+                            (400, r#"{"error":"authorization_pending"}"#.to_string())
+                        } else {
+                            (200, r#"{"access_token":"stale","refresh_token":"r1","expires_in":3600}"#.to_string())
+                        }
+                    }
+                },
+                (_, path) if path.starts_with("/me/drive/") && auth != "Bearer fresh" => {
+                    (401, r#"{"error":{"code":"InvalidAuthenticationToken"}}"#.to_string())
+                }
+                ("PUT", path) if path.ends_with(":/content") => {
+                    (201, format!(r#"{{"id":"1","name":"small.db","size":{}}}"#, request.body.len()))
+                }
+                ("POST", path) if path.ends_with(":/createUploadSession") => {
+                    (200, format!(r#"{{"uploadUrl":"{}/upload/session"}}"#, base))
+                }
+                ("GET", "/upload/session") => {
+                    (200, format!(r#"{{"nextExpectedRanges":["{}-"]}}"#, received_in.lock().unwrap()))
+                }
+                ("PUT", "/upload/session") => {
+                    let mut failures = failures_in.lock().unwrap();
+                    if *failures > 0 {
+                        *failures -= 1;
+                        return (503, String::new());
+                    }
+                    let range = request.headers.get("content-range").cloned().unwrap_or_default();
+                    let total: usize = range.rsplit('/').next().unwrap().parse().unwrap();
+                    let mut received = received_in.lock().unwrap();
+                    *received += request.body.len();
+                    if *received == total {
+                        (201, format!(r#"{{"id":"2","name":"large.db","size":{}}}"#, total))
+                    } else {
+                        (202, format!(r#"{{"nextExpectedRanges":["{}-"]}}"#, received))
+                    }
+                }
+                _ => (404, String::new()),
+            }
+        }))
+        .await;
+
+        OneDriveClient::logout().unwrap();
+        let client = OneDriveClient::new(config(&base_url, "备份/每日")).unwrap();
+        let device = client.start_device_login().await.unwrap();
+        assert_eq!(device.user_code, "ABCD");
+        client.finish_device_login(&device).await.unwrap();
+        assert!(OneDriveClient::is_logged_in().unwrap());
+        assert_eq!(*polls.lock().unwrap(), 2);
+
+        let dir = std::env::temp_dir().join(format!("stock_trader_onedrive_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let small = dir.join("small.db");
+        let large = dir.join("large.db");
+        std::fs::write(&small, vec![1u8; 1024]).unwrap();
+        std::fs::write(&large, vec![2u8; SIMPLE_UPLOAD_LIMIT + UPLOAD_CHUNK_SIZE / 2]).unwrap();
+
+        // 保存的访问令牌被拒绝后用刷新令牌换取新令牌再重试
+        let item = client.upload_file(&small, "small.db").await.unwrap();
+        assert_eq!(item.size, 1024);
+
+        // 第一个分片失败后查询会话状态，从服务端期望的位置继续上传
+        let item = client.upload_file(&large, "large.db").await.unwrap();
+        assert_eq!(item.size as usize, SIMPLE_UPLOAD_LIMIT + UPLOAD_CHUNK_SIZE / 2);
+        assert_eq!(*received.lock().unwrap(), item.size as usize);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let requests = requests.lock().unwrap();
+        let upload_paths: Vec<&str> = requests
+            .iter()
+            .filter(|r| r.path.starts_with("/me/drive/"))
+            .map(|r| r.path.as_str())
+            .collect();
+        assert_eq!(
+            upload_paths,
+            [
+                "/me/drive/root:/%E5%A4%87%E4%BB%BD/%E6%AF%8F%E6%97%A5/small.db:/content",
+                "/me/drive/root:/%E5%A4%87%E4%BB%BD/%E6%AF%8F%E6%97%A5/small.db:/content",
+                "/me/drive/root:/%E5%A4%87%E4%BB%BD/%E6%AF%8F%E6%97%A5/large.db:/createUploadSession",
+            ]
+        );
+        let refresh = requests
+            .iter()
+            .find(|r| r.path == "/token" && form(r).get("grant_type").map(String::as_str) == Some("refresh_token"))
+            .unwrap();
+        assert_eq!(form(refresh).get("refresh_token").map(String::as_str), Some("r1"));
+        // 上传地址自带授权，分片请求不附加 Authorization 头
+        assert!(requests
+            .iter()
+            .filter(|r| r.path == "/upload/session")
+            .all(|r| !r.headers.contains_key("authorization")));
+
+        OneDriveClient::logout().unwrap();
+    }
+}
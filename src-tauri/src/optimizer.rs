@@ -0,0 +1,554 @@
+use anyhow::Result;
+use chrono::NaiveDate;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use crate::backtest::{run_backtest, BacktestConfig, Instrument};
+use crate::error::AppError;
+use crate::fx::FxRates;
+use crate::models::DailyBar;
+
+/// 单次参数搜索最多评估的参数组合数
+const MAX_COMBINATIONS: usize = 20_000;
+
+/// 随机搜索时网格最多包含的组合数，超过时应缩小范围或加大步长
+const MAX_GRID_SIZE: usize = 1_000_000;
+
+/// 参数取值范围（闭区间），step 不大于 0 时只取 min
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct ParameterRange {
+    pub min: f64,
+    pub max: f64,
+    pub step: f64,
+}
+
+impl ParameterRange {
+    /// 取值个数，只做算术计算不生成取值；不是有限数或超过网格上限时返回 None
+    fn count(&self) -> Option<usize> {
+        if !(self.min.is_finite() && self.max.is_finite() && self.step.is_finite()) {
+            return None;
+        }
+        if self.step <= 0.0 || self.max <= self.min {
+            return Some(1);
+        }
+
+        let count = ((self.max - self.min) / self.step + 1e-9).floor() + 1.0;
+        (count <= MAX_GRID_SIZE as f64).then_some(count as usize)
+    }
+
+    fn value(&self, index: usize) -> f64 {
+        ((self.min + self.step * index as f64) * 1e6).round() / 1e6
+    }
+
+    /// 全部取值，调用前需要先用 count 检查范围
+    fn values(&self) -> Vec<f64> {
+        (0..self.count().unwrap_or(1)).map(|i| self.value(i)).collect()
+    }
+}
+
+/// 网格搜索评估全部组合；随机搜索从同一网格中不重复地抽取 samples 个组合，seed 相同结果可复现
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchMethod {
+    Grid,
+    Random { samples: usize, seed: u64 },
+}
+
+/// 滚动样本外检验：按日期把行情切成 folds 个窗口，每个窗口前 in_sample_ratio 用于选参，其余用于检验
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct WalkForward {
+    pub folds: usize,
+    pub in_sample_ratio: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SweepConfig {
+    pub annual_return_rate: ParameterRange,
+    pub buy_step_percentage: ParameterRange,
+    pub min_holding_days: ParameterRange,
+    pub method: SearchMethod,
+    /// 初始资金、每次买入手数和费用，参数字段会被搜索值覆盖
+    #[serde(default)]
+    pub base: BacktestConfig,
+    pub walk_forward: Option<WalkForward>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct SweepParams {
+    pub annual_return_rate: f64,
+    pub buy_step_percentage: f64,
+    pub min_holding_days: i64,
+}
+
+/// 一组参数在所有股票上的平均表现；启用样本外检验时样本内指标为各窗口的平均
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SweepResult {
+    pub params: SweepParams,
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub win_rate: Option<f64>,
+    pub trade_count: usize,
+    pub out_of_sample_return: Option<f64>,
+    pub out_of_sample_max_drawdown: Option<f64>,
+}
+
+/// 热力图矩阵，values[i][j] 为第 i 个年化收益率、第 j 个买入台阶下各最短持有天数中最好的收益率，
+/// 收益率与结果排序使用的一致
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SweepHeatmap {
+    pub annual_return_rates: Vec<f64>,
+    pub buy_step_percentages: Vec<f64>,
+    pub values: Vec<Vec<Option<f64>>>,
+}
+
+/// 单个窗口的样本外检验结果
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct WalkForwardFold {
+    pub fold: usize,
+    pub in_sample_start: NaiveDate,
+    pub in_sample_end: NaiveDate,
+    pub out_of_sample_start: NaiveDate,
+    pub out_of_sample_end: NaiveDate,
+    pub best_params: SweepParams,
+    pub in_sample_return: f64,
+    pub out_of_sample_return: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SweepReport {
+    /// 按收益率从高到低排列；启用样本外检验时按样本外收益率排列，没有样本外结果的排在最后
+    pub results: Vec<SweepResult>,
+    pub heatmap: SweepHeatmap,
+    pub walk_forward: Vec<WalkForwardFold>,
+    pub evaluated: usize,
+}
+
+/// 参数搜索使用的一只股票的日线及其交易规则
+#[derive(Debug, Clone)]
+pub struct SweepDataset {
+    pub instrument: Instrument,
+    pub bars: Vec<DailyBar>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Metrics {
+    total_return: f64,
+    max_drawdown: f64,
+    win_rate: Option<f64>,
+    trade_count: usize,
+}
+
+type DateRange = (NaiveDate, NaiveDate);
+
+/// 在一只或多只股票的日线上并行搜索策略参数，外币日线按 fx 折算为人民币回测
+pub fn run_sweep(datasets: &[SweepDataset], fx: &FxRates, config: &SweepConfig) -> Result<SweepReport> {
+    if datasets.iter().all(|dataset| dataset.bars.len() < 2) {
+        return Err(AppError::Validation("没有足够的日线数据".to_string()).into());
+    }
+
+    // 先按取值个数计算组合数并检查上限，再生成取值和组合
+    let counts = [
+        config.annual_return_rate.count(),
+        config.buy_step_percentage.count(),
+        config.min_holding_days.count(),
+    ];
+    let grid_size = counts
+        .iter()
+        .try_fold(1usize, |total, count| total.checked_mul((*count)?))
+        .filter(|total| *total <= MAX_GRID_SIZE)
+        .ok_or_else(|| {
+            AppError::Validation(format!("参数范围过大，网格最多 {} 个组合，请缩小范围或加大步长", MAX_GRID_SIZE))
+        })?;
+    let evaluated = match config.method {
+        SearchMethod::Grid => grid_size,
+        SearchMethod::Random { samples, .. } => samples.min(grid_size),
+    };
+    if evaluated > MAX_COMBINATIONS {
+        return Err(AppError::Validation(format!(
+            "参数组合过多（{}），请缩小范围或使用随机搜索，最多 {} 个",
+            evaluated, MAX_COMBINATIONS
+        ))
+        .into());
+    }
+
+    let rates = config.annual_return_rate.values();
+    let steps = config.buy_step_percentage.values();
+    let holding_days: Vec<i64> = config
+        .min_holding_days
+        .values()
+        .into_iter()
+        .map(|days| days.round() as i64)
+        .collect();
+
+    // 组合按 年化收益率 → 买入台阶 → 最短持有天数 的顺序编号
+    let combination = |index: usize| SweepParams {
+        annual_return_rate: rates[index / (steps.len() * holding_days.len())],
+        buy_step_percentage: steps[index / holding_days.len() % steps.len()],
+        min_holding_days: holding_days[index % holding_days.len()],
+    };
+    let combinations: Vec<SweepParams> = match config.method {
+        SearchMethod::Grid => (0..grid_size).map(combination).collect(),
+        SearchMethod::Random { samples, seed } => sample_indices(grid_size, samples, seed)
+            .into_iter()
+            .map(combination)
+            .collect(),
+    };
+
+    let windows = match config.walk_forward {
+        Some(walk_forward) => split_windows(datasets, walk_forward)?,
+        None => Vec::new(),
+    };
+
+    let mut results: Vec<SweepResult> = combinations
+        .par_iter()
+        .filter_map(|params| {
+            if windows.is_empty() {
+                let metrics = evaluate(datasets, fx, None, params, &config.base)?;
+                return Some(SweepResult {
+                    params: *params,
+                    total_return: metrics.total_return,
+                    max_drawdown: metrics.max_drawdown,
+                    win_rate: metrics.win_rate,
+                    trade_count: metrics.trade_count,
+                    out_of_sample_return: None,
+                    out_of_sample_max_drawdown: None,
+                });
+            }
+
+            let in_sample: Vec<Metrics> = windows
+                .iter()
+                .filter_map(|(range, _)| evaluate(datasets, fx, Some(*range), params, &config.base))
+                .collect();
+            let out_of_sample: Vec<Metrics> = windows
+                .iter()
+                .filter_map(|(_, range)| evaluate(datasets, fx, Some(*range), params, &config.base))
+                .collect();
+
+            let in_sample = average(&in_sample)?;
+            let out_of_sample = average(&out_of_sample);
+            Some(SweepResult {
+                params: *params,
+                total_return: in_sample.total_return,
+                max_drawdown: in_sample.max_drawdown,
+                win_rate: in_sample.win_rate,
+                trade_count: in_sample.trade_count,
+                out_of_sample_return: out_of_sample.map(|m| m.total_return),
+                out_of_sample_max_drawdown: out_of_sample.map(|m| m.max_drawdown),
+            })
+        })
+        .collect();
+
+    let ranked_by_out_of_sample = !windows.is_empty();
+    results.sort_by(|a, b| {
+        let score = |r: &SweepResult| score(r, ranked_by_out_of_sample).unwrap_or(f64::NEG_INFINITY);
+        score(b).total_cmp(&score(a)).then(b.total_return.total_cmp(&a.total_return))
+    });
+
+    let walk_forward = windows
+        .iter()
+        .enumerate()
+        .filter_map(|(fold, (in_range, out_range))| {
+            // 每个窗口只用样本内数据选出最优参数，再看它在紧随其后的样本外区间的表现
+            let (best_params, in_sample) = combinations
+                .par_iter()
+                .filter_map(|params| {
+                    evaluate(datasets, fx, Some(*in_range), params, &config.base).map(|m| (*params, m))
+                })
+                .max_by(|a, b| a.1.total_return.total_cmp(&b.1.total_return))?;
+
+            Some(WalkForwardFold {
+                fold: fold + 1,
+                in_sample_start: in_range.0,
+                in_sample_end: in_range.1,
+                out_of_sample_start: out_range.0,
+                out_of_sample_end: out_range.1,
+                best_params,
+                in_sample_return: in_sample.total_return,
+                out_of_sample_return: evaluate(datasets, fx, Some(*out_range), &best_params, &config.base)
+                    .map(|m| m.total_return),
+            })
+        })
+        .collect();
+
+    Ok(SweepReport {
+        heatmap: build_heatmap(&rates, &steps, &results, ranked_by_out_of_sample),
+        evaluated: results.len(),
+        results,
+        walk_forward,
+    })
+}
+
+/// 排序和热力图使用的收益率，启用样本外检验时为样本外收益率
+fn score(result: &SweepResult, out_of_sample: bool) -> Option<f64> {
+    if out_of_sample {
+        result.out_of_sample_return
+    } else {
+        Some(result.total_return)
+    }
+}
+
+/// 在日期区间内对所有股票回测并取平均，没有足够数据时返回 None
+fn evaluate(
+    datasets: &[SweepDataset],
+    fx: &FxRates,
+    range: Option<DateRange>,
+    params: &SweepParams,
+    base: &BacktestConfig,
+) -> Option<Metrics> {
+    let config = BacktestConfig {
+        annual_return_rate: params.annual_return_rate,
+        buy_step_percentage: params.buy_step_percentage,
+        min_holding_days: params.min_holding_days,
+        ..base.clone()
+    };
+
+    let metrics: Vec<Metrics> = datasets
+        .iter()
+        .filter_map(|dataset| {
+            let bars: Vec<DailyBar> = match range {
+                Some((start, end)) => dataset
+                    .bars
+                    .iter()
+                    .filter(|bar| bar.date >= start && bar.date <= end)
+                    .cloned()
+                    .collect(),
+                None => dataset.bars.clone(),
+            };
+            if bars.len() < 2 {
+                return None;
+            }
+
+            run_backtest(&bars, &dataset.instrument, fx, &config).ok().map(|report| Metrics {
+                total_return: report.total_return,
+                max_drawdown: report.max_drawdown,
+                win_rate: report.win_rate,
+                trade_count: report.trade_count,
+            })
+        })
+        .collect();
+
+    average(&metrics)
+}
+
+/// 收益率和胜率取平均，回撤取最差，成交笔数求和
+fn average(metrics: &[Metrics]) -> Option<Metrics> {
+    if metrics.is_empty() {
+        return None;
+    }
+
+    let n = metrics.len() as f64;
+    let win_rates: Vec<f64> = metrics.iter().filter_map(|m| m.win_rate).collect();
+
+    Some(Metrics {
+        total_return: metrics.iter().map(|m| m.total_return).sum::<f64>() / n,
+        max_drawdown: metrics.iter().map(|m| m.max_drawdown).fold(0.0, f64::max),
+        win_rate: (!win_rates.is_empty()).then(|| win_rates.iter().sum::<f64>() / win_rates.len() as f64),
+        trade_count: metrics.iter().map(|m| m.trade_count).sum(),
+    })
+}
+
+/// 按所有股票交易日的并集切分窗口，返回 (样本内区间, 样本外区间)
+fn split_windows(datasets: &[SweepDataset], walk_forward: WalkForward) -> Result<Vec<(DateRange, DateRange)>> {
+    let ratio = walk_forward.in_sample_ratio;
+    if walk_forward.folds == 0 || ratio <= 0.0 || ratio >= 1.0 {
+        return Err(AppError::Validation("样本外检验需要至少1个窗口，样本内比例必须在0到1之间".to_string()).into());
+    }
+
+    let dates: Vec<NaiveDate> = datasets
+        .iter()
+        .flat_map(|dataset| &dataset.bars)
+        .map(|bar| bar.date)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let window_len = dates.len() / walk_forward.folds;
+    let in_sample_len = (window_len as f64 * ratio).round() as usize;
+    if in_sample_len < 2 || window_len < in_sample_len + 2 {
+        return Err(AppError::Validation(format!("日线数据不足以切分 {} 个窗口", walk_forward.folds)).into());
+    }
+
+    Ok((0..walk_forward.folds)
+        .map(|fold| {
+            let start = fold * window_len;
+            let split = start + in_sample_len;
+            let end = if fold + 1 == walk_forward.folds { dates.len() } else { start + window_len };
+            ((dates[start], dates[split - 1]), (dates[split], dates[end - 1]))
+        })
+        .collect())
+}
+
+fn build_heatmap(rates: &[f64], steps: &[f64], results: &[SweepResult], out_of_sample: bool) -> SweepHeatmap {
+    let mut values = vec![vec![None; steps.len()]; rates.len()];
+
+    for result in results {
+        let row = rates.iter().position(|r| *r == result.params.annual_return_rate);
+        let col = steps.iter().position(|s| *s == result.params.buy_step_percentage);
+        if let (Some(row), Some(col), Some(value)) = (row, col, score(result, out_of_sample)) {
+            let cell: &mut Option<f64> = &mut values[row][col];
+            if cell.map(|v| value > v).unwrap_or(true) {
+                *cell = Some(value);
+            }
+        }
+    }
+
+    SweepHeatmap {
+        annual_return_rates: rates.to_vec(),
+        buy_step_percentages: steps.to_vec(),
+        values,
+    }
+}
+
+/// 用 Floyd 算法从 0..total 中不重复地抽取 samples 个下标，不需要生成全部下标；
+/// 随机数使用 SplitMix64，不引入额外的随机数依赖
+fn sample_indices(total: usize, samples: usize, seed: u64) -> Vec<usize> {
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let samples = samples.min(total);
+    let mut chosen = HashSet::with_capacity(samples);
+    let mut picked = Vec::with_capacity(samples);
+    for j in total - samples..total {
+        let candidate = (next() % (j as u64 + 1)) as usize;
+        let index = if chosen.insert(candidate) { candidate } else { j };
+        chosen.insert(index);
+        picked.push(index);
+    }
+
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::money::Money;
+
+    fn zigzag(days: usize) -> SweepDataset {
+        let start = NaiveDate::from_ymd_opt(2023, 1, 2).unwrap();
+        let bars = (0..days)
+            .map(|i| {
+                let close = 10.0 + ((i as f64) / 7.0).sin();
+                DailyBar {
+                    date: start + Duration::days(i as i64),
+                    open: Money::from_f64(close),
+                    high: Money::from_f64(close * 1.01),
+                    low: Money::from_f64(close * 0.99),
+                    close: Money::from_f64(close),
+                    volume: 0,
+                }
+            })
+            .collect();
+        SweepDataset { instrument: Instrument::default(), bars }
+    }
+
+    fn config(method: SearchMethod, walk_forward: Option<WalkForward>) -> SweepConfig {
+        SweepConfig {
+            annual_return_rate: ParameterRange { min: 0.1, max: 0.3, step: 0.1 },
+            buy_step_percentage: ParameterRange { min: 0.03, max: 0.05, step: 0.01 },
+            min_holding_days: ParameterRange { min: 10.0, max: 30.0, step: 20.0 },
+            method,
+            base: BacktestConfig::default(),
+            walk_forward,
+        }
+    }
+
+    #[test]
+    fn parameter_range_is_inclusive() {
+        let range = ParameterRange { min: 0.1, max: 0.3, step: 0.1 };
+        assert_eq!(range.values(), vec![0.1, 0.2, 0.3]);
+        assert_eq!(ParameterRange { min: 0.2, max: 0.2, step: 0.0 }.values(), vec![0.2]);
+    }
+
+    #[test]
+    fn grid_sweep_is_ranked_and_fills_heatmap() {
+        let report = run_sweep(&[zigzag(200)], &FxRates::default(), &config(SearchMethod::Grid, None)).unwrap();
+
+        assert_eq!(report.evaluated, 18);
+        assert!(report
+            .results
+            .windows(2)
+            .all(|pair| pair[0].total_return >= pair[1].total_return));
+        assert_eq!(report.heatmap.values.len(), 3);
+        assert!(report.heatmap.values.iter().flatten().all(|cell| cell.is_some()));
+    }
+
+    #[test]
+    fn random_sweep_is_reproducible() {
+        let method = SearchMethod::Random { samples: 5, seed: 42 };
+        let first = run_sweep(&[zigzag(120)], &FxRates::default(), &config(method, None)).unwrap();
+        let second = run_sweep(&[zigzag(120)], &FxRates::default(), &config(method, None)).unwrap();
+
+        assert_eq!(first.evaluated, 5);
+        let params = |report: &SweepReport| report.results.iter().map(|r| r.params).collect::<Vec<_>>();
+        assert_eq!(params(&first), params(&second));
+    }
+
+    #[test]
+    fn oversized_ranges_are_rejected_before_allocating() {
+        // 单个范围就有上万亿个取值，必须在生成取值之前拒绝
+        let mut huge = config(SearchMethod::Random { samples: 5, seed: 1 }, None);
+        huge.annual_return_rate = ParameterRange { min: 0.0, max: 1.0, step: 1e-12 };
+        assert!(run_sweep(&[zigzag(60)], &FxRates::default(), &huge).is_err());
+
+        let mut nan = config(SearchMethod::Grid, None);
+        nan.buy_step_percentage.step = f64::NAN;
+        assert!(run_sweep(&[zigzag(60)], &FxRates::default(), &nan).is_err());
+
+        // 几十万个组合的网格只能随机抽样，不能全部评估
+        let mut wide = config(SearchMethod::Grid, None);
+        wide.annual_return_rate = ParameterRange { min: 0.05, max: 0.5, step: 0.0001 };
+        wide.buy_step_percentage = ParameterRange { min: 0.01, max: 0.1, step: 0.001 };
+        wide.min_holding_days = ParameterRange { min: 30.0, max: 30.0, step: 0.0 };
+        assert!(run_sweep(&[zigzag(60)], &FxRates::default(), &wide).is_err());
+
+        wide.method = SearchMethod::Random { samples: 5, seed: 7 };
+        let report = run_sweep(&[zigzag(60)], &FxRates::default(), &wide).unwrap();
+        assert_eq!(report.evaluated, 5);
+        let distinct: HashSet<(u64, u64)> = report
+            .results
+            .iter()
+            .map(|r| (r.params.annual_return_rate.to_bits(), r.params.buy_step_percentage.to_bits()))
+            .collect();
+        assert_eq!(distinct.len(), 5);
+    }
+
+    #[test]
+    fn sampled_indices_are_distinct_and_in_range() {
+        let picked = sample_indices(10, 10, 3);
+        assert_eq!(picked.iter().collect::<HashSet<_>>().len(), 10);
+        assert!(picked.iter().all(|i| *i < 10));
+        assert_eq!(sample_indices(1_000_000, 4, 9), sample_indices(1_000_000, 4, 9));
+    }
+
+    #[test]
+    fn walk_forward_ranks_by_out_of_sample_return() {
+        let walk_forward = WalkForward { folds: 2, in_sample_ratio: 0.6 };
+        let report = run_sweep(&[zigzag(300)], &FxRates::default(), &config(SearchMethod::Grid, Some(walk_forward))).unwrap();
+
+        assert!(report
+            .results
+            .windows(2)
+            .all(|pair| pair[0].out_of_sample_return.unwrap() >= pair[1].out_of_sample_return.unwrap()));
+        let best = report.heatmap.values.iter().flatten().flatten().fold(f64::NEG_INFINITY, |a, b| a.max(*b));
+        assert_eq!(Some(best), report.results[0].out_of_sample_return);
+    }
+
+    #[test]
+    fn walk_forward_reports_out_of_sample_per_fold() {
+        let walk_forward = WalkForward { folds: 3, in_sample_ratio: 0.7 };
+        let report = run_sweep(&[zigzag(300)], &FxRates::default(), &config(SearchMethod::Grid, Some(walk_forward))).unwrap();
+
+        assert_eq!(report.walk_forward.len(), 3);
+        for fold in &report.walk_forward {
+            assert!(fold.in_sample_end < fold.out_of_sample_start);
+        }
+        assert!(report.results.iter().all(|r| r.out_of_sample_return.is_some()));
+    }
+}
@@ -0,0 +1,664 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap};
+use crate::analytics;
+use crate::api::{GrowthSettings, PriceCalculator};
+use crate::backtest::{BacktestConfig, FeeModel};
+use crate::csv_import::LOT_SIZE;
+use crate::database::{get_database, is_database_initialized};
+use crate::error::{AppError, FieldError};
+use crate::fx::{self, Currency, FxRates};
+use crate::market;
+use crate::security::{self, SecurityId};
+use crate::models::{
+    PaperComparison, PaperFill, PaperHolding, PaperPortfolio, PaperPortfolioReport, PaperPosition, StockInfo, Trade,
+    TradeSide,
+};
+use crate::money::Money;
+use crate::stock_api::StockApi;
+
+/// 交易时段内检查模拟成交的间隔
+const CHECK_INTERVAL_SECS: u64 = 60;
+
+/// 一次模拟的结果：卖出的持仓、新买入的持仓、成交记录和成交后的可用资金
+#[derive(Debug, Default)]
+pub struct FillPlan {
+    pub cash: Money,
+    pub closed: Vec<i64>,
+    pub opened: Vec<PaperPosition>,
+    pub fills: Vec<PaperFill>,
+}
+
+/// 用回测参数创建模拟盘，初始资金全部为可用资金
+pub fn new_portfolio(name: &str, config: &BacktestConfig) -> Result<PaperPortfolio> {
+    let mut errors = Vec::new();
+    if name.trim().is_empty() {
+        errors.push(FieldError::new("name", "模拟盘名称不能为空"));
+    }
+    errors.extend(config.validate());
+    if !errors.is_empty() {
+        return Err(AppError::InvalidFields(errors).into());
+    }
+
+    Ok(PaperPortfolio {
+        id: None,
+        name: name.trim().to_string(),
+        initial_cash: config.initial_cash,
+        cash: config.initial_cash,
+        annual_return_rate: config.annual_return_rate,
+        buy_step_percentage: config.buy_step_percentage,
+        min_holding_days: config.min_holding_days,
+        growth_model: config.growth.growth_model,
+        day_count_basis: config.growth.day_count_basis,
+        lots_per_trade: config.lots_per_trade,
+        commission_rate: config.fees.commission_rate,
+        min_commission: config.fees.min_commission,
+        stamp_tax_rate: config.fees.stamp_tax_rate,
+        transfer_fee_rate: config.fees.transfer_fee_rate,
+        created_at: None,
+    })
+}
+
+fn fee_model(portfolio: &PaperPortfolio) -> FeeModel {
+    FeeModel {
+        commission_rate: portfolio.commission_rate,
+        min_commission: portfolio.min_commission,
+        stamp_tax_rate: portfolio.stamp_tax_rate,
+        transfer_fee_rate: portfolio.transfer_fee_rate,
+    }
+}
+
+/// 按最新行情模拟成交，规则与回测一致
+///
+/// 每笔持仓独立计算卖出目标价，现价达到目标价且不是当天买入（T+1）时按现价卖出；
+/// 同一股票最近一笔持仓的买入目标价被跌破时，按现价加仓 lots_per_trade 手，资金不足时按买入数量规则减少数量。
+/// 只有处于所在市场交易时段的股票才成交；资金为人民币，外币成交金额按当天汇率折算，缺少汇率的股票不成交。
+pub fn simulate_fills(
+    portfolio: &PaperPortfolio,
+    positions: &[PaperPosition],
+    quotes: &HashMap<String, StockInfo>,
+    fx: &FxRates,
+    now: DateTime<Utc>,
+) -> FillPlan {
+    let portfolio_id = portfolio.id.unwrap_or_default();
+    let fees = fee_model(portfolio);
+    let mut plan = FillPlan {
+        cash: portfolio.cash,
+        ..FillPlan::default()
+    };
+
+    let mut by_stock: BTreeMap<&str, Vec<&PaperPosition>> = BTreeMap::new();
+    for position in positions.iter().filter(|p| p.portfolio_id == portfolio_id) {
+        by_stock.entry(position.stock_code.as_str()).or_default().push(position);
+    }
+
+    for (code, mut lots) in by_stock {
+        if !in_session(code, now) {
+            continue;
+        }
+        let Some(price) = quotes.get(code).map(|q| q.current_price).filter(|p| p.is_positive()) else {
+            continue;
+        };
+        lots.sort_by_key(|lot| lot.buy_time);
+        let currency = lots[0].currency();
+        let Some(rate) = fx.cny_rate(currency, local_date(now)) else {
+            continue;
+        };
+
+        let mut remaining = Vec::with_capacity(lots.len());
+        for lot in lots {
+            let sell_target = sell_target(portfolio, lot, now);
+            if local_date(now) > local_date(lot.buy_time) && price >= sell_target {
+                let amount = (price * lot.quantity).mul_f64(rate);
+                let sell_fees = fees.fees(amount, true);
+                // 成本按买入日汇率折算，已实现盈亏包含汇率变动
+                let buy_rate = fx.cny_rate(currency, local_date(lot.buy_time)).unwrap_or(rate);
+                let cost = (lot.buy_price * lot.quantity).mul_f64(buy_rate);
+
+                plan.cash += amount - sell_fees;
+                plan.closed.extend(lot.id);
+                plan.fills.push(PaperFill {
+                    id: None,
+                    portfolio_id,
+                    stock_code: lot.stock_code.clone(),
+                    stock_name: lot.stock_name.clone(),
+                    side: TradeSide::Sell,
+                    price,
+                    quantity: lot.quantity,
+                    fees: sell_fees,
+                    realized_pnl: Some(amount - sell_fees - cost - lot.buy_fees),
+                    target_price: Some(sell_target),
+                    filled_at: now,
+                });
+            } else {
+                remaining.push(lot);
+            }
+        }
+
+        // 全部卖出后不自动重新建仓，由用户手动买入
+        let Some(reference) = remaining.last() else {
+            continue;
+        };
+        let buy_target =
+            PriceCalculator::calculate_buy_target_price(sell_target(portfolio, reference, now), portfolio.buy_step_percentage);
+
+        if local_date(now) > local_date(reference.buy_time) && price <= buy_target {
+            let quantity = default_quantity(portfolio, code);
+            let name = reference.stock_name.clone();
+            if let Some((position, fill)) = buy(portfolio, &mut plan.cash, code, &name, price, rate, quantity, Some(buy_target), now) {
+                plan.opened.push(position);
+                plan.fills.push(fill);
+            }
+        }
+    }
+
+    plan
+}
+
+/// 每次买入的数量：策略设置的手数，不少于该证券的最低买入数量
+pub fn default_quantity(portfolio: &PaperPortfolio, stock_code: &str) -> i64 {
+    (portfolio.lots_per_trade * LOT_SIZE as i64).max(security::buy_quantity_rule(stock_code).0)
+}
+
+/// 检查手动买入的代码和数量：必须是可以交易的证券，指定的数量必须符合该证券的买入数量规则
+pub fn validate_buy(stock_code: &str, quantity: Option<i64>) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let security = SecurityId::parse(stock_code);
+    match &security {
+        None => errors.push(FieldError::new("stockCode", format!("无法识别的股票代码: {}", stock_code))),
+        Some(id) if !id.is_tradable() => errors.push(FieldError::new("stockCode", "指数不能买入")),
+        Some(_) => {}
+    }
+
+    match (quantity, &security) {
+        (Some(quantity), _) if quantity <= 0 => errors.push(FieldError::new("quantity", "买入数量必须大于0")),
+        (Some(quantity), Some(id)) => {
+            let (minimum, step) = id.buy_quantity_rule();
+            if quantity < minimum {
+                errors.push(FieldError::new("quantity", format!("买入数量不能少于 {}", minimum)));
+            } else if step > 1 && quantity % step != 0 {
+                errors.push(FieldError::new("quantity", format!("买入数量必须是 {} 的整数倍", step)));
+            }
+        }
+        _ => {}
+    }
+    errors
+}
+
+/// 按现价买入，成交金额按 fx_rate 折算为人民币后扣减资金和计算费用；
+/// 资金不足时按该证券的买入数量规则减少数量，最低数量也买不起时返回空
+#[allow(clippy::too_many_arguments)]
+pub fn buy(
+    portfolio: &PaperPortfolio,
+    cash: &mut Money,
+    stock_code: &str,
+    stock_name: &str,
+    price: Money,
+    fx_rate: f64,
+    quantity: i64,
+    target_price: Option<Money>,
+    now: DateTime<Utc>,
+) -> Option<(PaperPosition, PaperFill)> {
+    let fees = fee_model(portfolio);
+    let portfolio_id = portfolio.id.unwrap_or_default();
+
+    let rule = security::buy_quantity_rule(stock_code);
+    let (quantity, amount, buy_fees) =
+        fees.affordable_quantity(quantity, rule, *cash, |quantity| (price * quantity).mul_f64(fx_rate))?;
+    *cash -= amount + buy_fees;
+
+    let position = PaperPosition {
+        id: None,
+        portfolio_id,
+        stock_code: stock_code.to_string(),
+        stock_name: stock_name.to_string(),
+        buy_price: price,
+        quantity,
+        buy_fees,
+        buy_time: now,
+    };
+    let fill = PaperFill {
+        id: None,
+        portfolio_id,
+        stock_code: stock_code.to_string(),
+        stock_name: stock_name.to_string(),
+        side: TradeSide::Buy,
+        price,
+        quantity,
+        fees: buy_fees,
+        realized_pnl: None,
+        target_price,
+        filled_at: now,
+    };
+    Some((position, fill))
+}
+
+/// 汇总模拟盘的持仓和收益，没有行情的股票按成本估值
+///
+/// 持仓市值按最新汇率折算为人民币后与资金合计，缺少汇率的持仓不计入权益。
+pub fn build_report(
+    portfolio: &PaperPortfolio,
+    positions: &[PaperPosition],
+    fills: &[PaperFill],
+    quotes: &HashMap<String, StockInfo>,
+    fx: &FxRates,
+    now: DateTime<Utc>,
+) -> PaperPortfolioReport {
+    let portfolio_id = portfolio.id.unwrap_or_default();
+
+    let mut grouped: BTreeMap<&str, (String, Currency, i64, Money)> = BTreeMap::new();
+    for position in positions.iter().filter(|p| p.portfolio_id == portfolio_id) {
+        let entry = grouped
+            .entry(position.stock_code.as_str())
+            .or_insert_with(|| (position.stock_name.clone(), position.currency(), 0, Money::ZERO));
+        entry.2 += position.quantity;
+        entry.3 += position.buy_price * position.quantity;
+    }
+
+    let today = local_date(now);
+    let holdings: Vec<PaperHolding> = grouped
+        .into_iter()
+        .map(|(code, (name, currency, quantity, cost))| {
+            let average_cost = cost / quantity;
+            let quoted = quotes.get(code).map(|q| q.current_price).filter(|p| p.is_positive());
+            let current_price = quoted.unwrap_or(average_cost);
+            let market_value = current_price * quantity;
+
+            PaperHolding {
+                stock_code: code.to_string(),
+                stock_name: name,
+                currency,
+                quantity,
+                average_cost,
+                current_price,
+                market_value,
+                market_value_base: fx.cny_rate(currency, today).map(|rate| market_value.mul_f64(rate)),
+                unrealized_pnl: market_value - cost,
+                priced: quoted.is_some(),
+            }
+        })
+        .collect();
+
+    let mut missing_fx_rates: Vec<Currency> = holdings
+        .iter()
+        .filter(|h| h.market_value_base.is_none())
+        .map(|h| h.currency)
+        .collect();
+    missing_fx_rates.sort();
+    missing_fx_rates.dedup();
+
+    let fills: Vec<&PaperFill> = fills.iter().filter(|f| f.portfolio_id == portfolio_id).collect();
+    let market_value: Money = holdings.iter().filter_map(|h| h.market_value_base).sum();
+    let equity = portfolio.cash + market_value;
+    let total_return = equity.ratio(portfolio.initial_cash) - 1.0;
+    let days = portfolio
+        .created_at
+        .map(|created| (local_date(now) - local_date(created)).num_days())
+        .unwrap_or(0);
+
+    PaperPortfolioReport {
+        portfolio: portfolio.clone(),
+        market_value,
+        equity,
+        total_return,
+        annualized_return: analytics::annualize(total_return, days),
+        realized_pnl: fills.iter().filter_map(|f| f.realized_pnl).sum(),
+        total_fees: fills.iter().map(|f| f.fees).sum(),
+        fill_count: fills.len(),
+        holdings,
+        missing_fx_rates,
+    }
+}
+
+/// 模拟盘与真实账户的收益对比，真实账户的数据只来自交易记录
+pub async fn build_comparison(trades: &[Trade], now: DateTime<Utc>) -> Result<PaperComparison> {
+    let (portfolios, positions, fills) = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        (
+            db_lock.get_paper_portfolios().await?,
+            db_lock.get_paper_positions(None).await?,
+            db_lock.get_paper_fills(None).await?,
+        )
+    };
+
+    let mut codes: Vec<String> = positions
+        .iter()
+        .map(|p| p.stock_code.clone())
+        .chain(trades.iter().map(|t| t.stock_code.clone()))
+        .collect();
+    codes.sort();
+    codes.dedup();
+    // 只使用真实行情，取不到的持仓按成本估值并标记为未定价
+    let quotes = StockApi::fetch_real_stock_quotes(&codes).await.unwrap_or_else(|e| {
+        println!("获取模拟盘对比行情失败: {}", e);
+        HashMap::new()
+    });
+
+    let prices: HashMap<String, Money> = quotes.iter().map(|(code, q)| (code.clone(), q.current_price)).collect();
+    // 模拟盘资金为人民币，真实账户同样按人民币折算后对比
+    let foreign = trades.iter().any(|t| t.currency() != Currency::Cny) || positions.iter().any(|p| p.currency() != Currency::Cny);
+    let fx_rates = fx::load_fx_rates(foreign).await?;
+    let real = analytics::build_return_report(trades, &prices, &fx_rates, Currency::Cny, now);
+
+    Ok(PaperComparison {
+        paper: portfolios
+            .iter()
+            .map(|portfolio| build_report(portfolio, &positions, &fills, &quotes, &fx_rates, now))
+            .collect(),
+        real_overall: real.overall,
+        real_accounts: real.accounts,
+        as_of: now,
+    })
+}
+
+/// 后台任务：任一市场的交易时段内定时按实时行情模拟成交
+pub async fn run_paper_trading() {
+    loop {
+        if is_database_initialized() && market::is_any_trading_session(Utc::now()) {
+            if let Err(e) = process_fills(Utc::now()).await {
+                println!("模拟盘成交检查失败: {}", e);
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(CHECK_INTERVAL_SECS)).await;
+    }
+}
+
+/// 检查全部模拟盘并保存成交，返回本次产生的成交
+///
+/// 联网获取行情期间不持有数据库锁，重新加锁后再读取资金和持仓计算成交，
+/// 避免覆盖期间手动买入扣减的资金，或重复卖出另一次检查已经卖出的持仓。
+pub async fn process_fills(now: DateTime<Utc>) -> Result<Vec<PaperFill>> {
+    let positions = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        db_lock.get_paper_positions(None).await?
+    };
+
+    // 只为处于交易时段的股票获取行情
+    let mut codes: Vec<String> = positions
+        .iter()
+        .filter(|p| in_session(&p.stock_code, now))
+        .map(|p| p.stock_code.clone())
+        .collect();
+    codes.sort();
+    codes.dedup();
+    if codes.is_empty() {
+        return Ok(Vec::new());
+    }
+    // 模拟成交只使用真实行情，接口失败时跳过本次检查
+    let quotes = StockApi::fetch_real_stock_quotes(&codes).await?;
+    let fx_rates = fx::load_fx_rates(positions.iter().any(|p| p.currency() != Currency::Cny)).await?;
+
+    let mut all_fills = Vec::new();
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    let portfolios = db_lock.get_paper_portfolios().await?;
+    let positions = db_lock.get_paper_positions(None).await?;
+    for portfolio in &portfolios {
+        let Some(id) = portfolio.id else { continue };
+        let plan = simulate_fills(portfolio, &positions, &quotes, &fx_rates, now);
+        if plan.fills.is_empty() {
+            continue;
+        }
+
+        db_lock
+            .apply_paper_fills(id, plan.cash, &plan.closed, &plan.opened, &plan.fills)
+            .await?;
+        all_fills.extend(plan.fills);
+    }
+
+    Ok(all_fills)
+}
+
+fn sell_target(portfolio: &PaperPortfolio, position: &PaperPosition, now: DateTime<Utc>) -> Money {
+    PriceCalculator::calculate_sell_target_price(
+        position.buy_price,
+        portfolio.annual_return_rate,
+        (now - position.buy_time).num_days(),
+        portfolio.min_holding_days,
+        GrowthSettings {
+            growth_model: portfolio.growth_model,
+            day_count_basis: portfolio.day_count_basis,
+        },
+    )
+}
+
+/// 股票所在市场是否处于交易时段，无法识别的代码不成交
+fn in_session(stock_code: &str, now: DateTime<Utc>) -> bool {
+    SecurityId::parse(stock_code).is_some_and(|id| id.is_trading_session(now))
+}
+
+fn local_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::FxRate;
+    use chrono::{Duration, TimeZone};
+
+    fn portfolio() -> PaperPortfolio {
+        let mut portfolio = new_portfolio("测试", &BacktestConfig::default()).unwrap();
+        portfolio.id = Some(1);
+        portfolio
+    }
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    fn position(id: i64, buy_price: &str, buy_time: DateTime<Utc>) -> PaperPosition {
+        PaperPosition {
+            id: Some(id),
+            portfolio_id: 1,
+            stock_code: "600000".to_string(),
+            stock_name: "浦发银行".to_string(),
+            buy_price: money(buy_price),
+            quantity: 1000,
+            buy_fees: Money::from_yuan(5),
+            buy_time,
+        }
+    }
+
+    fn quote(price: &str) -> HashMap<String, StockInfo> {
+        quote_for("600000", price)
+    }
+
+    fn quote_for(code: &str, price: &str) -> HashMap<String, StockInfo> {
+        let price = money(price);
+        let info = StockInfo {
+            code: code.to_string(),
+            name: "浦发银行".to_string(),
+            current_price: price,
+            change: Money::ZERO,
+            change_percent: 0.0,
+            open: price,
+            high: price,
+            low: price,
+            volume: 0,
+            turnover: Money::ZERO,
+            timestamp: Utc::now(),
+        };
+        HashMap::from([(info.code.clone(), info)])
+    }
+
+    #[test]
+    fn sells_lot_when_price_reaches_target() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 3, 0, 0).unwrap();
+        let lot = position(7, "10", now - Duration::days(30));
+        let plan = simulate_fills(&portfolio(), &[lot], &quote("10.5"), &FxRates::new(&[]), now);
+
+        assert_eq!(plan.closed, vec![7]);
+        assert_eq!(plan.fills.len(), 1);
+        assert_eq!(plan.fills[0].side, TradeSide::Sell);
+        let pnl = plan.fills[0].realized_pnl.unwrap();
+        assert!(pnl.is_positive() && pnl < Money::from_yuan(500));
+        assert!(plan.cash > portfolio().cash + Money::from_yuan(10_000));
+    }
+
+    #[test]
+    fn does_not_sell_on_purchase_day() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 6, 0, 0).unwrap();
+        let lot = position(7, "10", now - Duration::hours(2));
+        let plan = simulate_fills(&portfolio(), &[lot], &quote("20"), &FxRates::new(&[]), now);
+
+        assert!(plan.fills.is_empty());
+    }
+
+    #[test]
+    fn adds_lot_below_buy_target() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 3, 0, 0).unwrap();
+        let lot = position(7, "10", now - Duration::days(5));
+        let plan = simulate_fills(&portfolio(), &[lot], &quote("9"), &FxRates::new(&[]), now);
+
+        assert!(plan.closed.is_empty());
+        assert_eq!(plan.opened.len(), 1);
+        assert_eq!(plan.opened[0].quantity, 1000);
+        assert_eq!(plan.fills[0].side, TradeSide::Buy);
+        assert_eq!(plan.cash, Money::from_yuan(91_000) - plan.fills[0].fees);
+    }
+
+    #[test]
+    fn buy_reduces_quantity_to_available_cash() {
+        let mut cash = Money::from_yuan(2_500);
+        let price = Money::from_yuan(10);
+        let (position, _) = buy(&portfolio(), &mut cash, "600000", "浦发银行", price, 1.0, 1000, None, Utc::now()).unwrap();
+
+        assert_eq!(position.quantity, 200);
+        assert!(buy(&portfolio(), &mut cash, "600000", "浦发银行", price, 1.0, 1000, None, Utc::now()).is_none());
+    }
+
+    #[test]
+    fn validates_manual_buy_code_and_quantity() {
+        assert!(validate_buy("600000", None).is_empty());
+        assert!(validate_buy("688981", Some(250)).is_empty());
+
+        let fields = |errors: Vec<FieldError>| errors.into_iter().map(|e| e.field).collect::<Vec<_>>();
+        assert_eq!(fields(validate_buy("sh000300", Some(100))), vec!["stockCode"]);
+        assert_eq!(fields(validate_buy("abc", None)), vec!["stockCode"]);
+        assert_eq!(fields(validate_buy("600000", Some(0))), vec!["quantity"]);
+        assert_eq!(fields(validate_buy("600000", Some(150))), vec!["quantity"]);
+        assert_eq!(fields(validate_buy("688981", Some(100))), vec!["quantity"]);
+    }
+
+    #[test]
+    fn report_values_holdings_at_quote() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 3, 0, 0).unwrap();
+        let mut portfolio = portfolio();
+        portfolio.cash = Money::from_yuan(90_000);
+        let report = build_report(&portfolio, &[position(1, "10", now)], &[], &quote("11"), &FxRates::new(&[]), now);
+
+        assert_eq!(report.holdings.len(), 1);
+        assert!(report.holdings[0].priced);
+        assert_eq!(report.equity, Money::from_yuan(101_000));
+        assert!((report.total_return - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn report_values_unquoted_holdings_at_cost() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 3, 0, 0).unwrap();
+        let mut portfolio = portfolio();
+        portfolio.cash = Money::from_yuan(90_000);
+        let report = build_report(&portfolio, &[position(1, "10", now)], &[], &HashMap::new(), &FxRates::new(&[]), now);
+
+        let holding = &report.holdings[0];
+        assert!(!holding.priced);
+        assert_eq!(holding.current_price, Money::from_yuan(10));
+        assert_eq!(holding.unrealized_pnl, Money::ZERO);
+        assert_eq!(report.equity, Money::from_yuan(100_000));
+    }
+
+    fn hk_position(buy_time: DateTime<Utc>) -> PaperPosition {
+        PaperPosition {
+            stock_code: "hk00700".to_string(),
+            stock_name: "腾讯控股".to_string(),
+            buy_price: Money::from_yuan(300),
+            quantity: 100,
+            ..position(7, "300", buy_time)
+        }
+    }
+
+    fn hkd_rates(rates: &[(NaiveDate, f64)]) -> FxRates {
+        let rates: Vec<FxRate> = rates
+            .iter()
+            .map(|(date, rate)| FxRate { currency: Currency::Hkd, rate_date: *date, rate: *rate })
+            .collect();
+        FxRates::new(&rates)
+    }
+
+    #[test]
+    fn converts_foreign_fills_to_cny() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 3, 0, 0).unwrap();
+        let lot = hk_position(now - Duration::days(30));
+        let fx = hkd_rates(&[(local_date(lot.buy_time), 0.9), (local_date(now), 0.92)]);
+        let mut portfolio = portfolio();
+        portfolio.stamp_tax_rate = 0.0;
+        portfolio.transfer_fee_rate = 0.0;
+        portfolio.min_commission = Money::ZERO;
+        portfolio.commission_rate = 0.0;
+        let plan = simulate_fills(&portfolio, &[lot], &quote_for("hk00700", "330"), &fx, now);
+
+        // 卖出 33000 港币按 0.92 折算，成本 30000 港币按买入日 0.9 折算
+        assert_eq!(plan.cash, portfolio.cash + Money::from_yuan(30_360));
+        assert_eq!(plan.fills[0].realized_pnl, Some(Money::from_yuan(30_360 - 27_000 - 5)));
+
+        // 缺少汇率时不成交
+        let lot = hk_position(now - Duration::days(30));
+        let plan = simulate_fills(&portfolio, &[lot], &quote_for("hk00700", "330"), &FxRates::new(&[]), now);
+        assert!(plan.fills.is_empty());
+    }
+
+    #[test]
+    fn fills_only_during_own_market_session() {
+        // 北京时间 20:00，A 股和港股已收盘
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        let lot = hk_position(now - Duration::days(30));
+        let fx = hkd_rates(&[(local_date(lot.buy_time), 0.9), (local_date(now), 0.92)]);
+        let plan = simulate_fills(&portfolio(), &[lot], &quote_for("hk00700", "330"), &fx, now);
+        assert!(plan.fills.is_empty());
+
+        let lot = position(7, "10", now - Duration::days(30));
+        let plan = simulate_fills(&portfolio(), &[lot], &quote("10.5"), &FxRates::new(&[]), now);
+        assert!(plan.fills.is_empty());
+    }
+
+    #[test]
+    fn buy_uses_security_quantity_rule() {
+        let mut portfolio = portfolio();
+        portfolio.lots_per_trade = 1;
+        let mut cash = Money::from_yuan(100_000);
+        let price = Money::from_yuan(10);
+        let (position, _) = buy(&portfolio, &mut cash, "gb_aapl", "苹果", price, 7.0, 5, None, Utc::now()).unwrap();
+        assert_eq!(position.quantity, 5);
+
+        // 科创板至少 200 股，超出部分按 1 股递增
+        assert_eq!(default_quantity(&portfolio, "688001"), 200);
+        let (position, _) = buy(&portfolio, &mut cash, "688001", "华兴源创", price, 1.0, 250, None, Utc::now()).unwrap();
+        assert_eq!(position.quantity, 250);
+    }
+
+    #[test]
+    fn report_converts_holdings_and_excludes_missing_rates() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 3, 0, 0).unwrap();
+        let mut portfolio = portfolio();
+        portfolio.cash = Money::from_yuan(50_000);
+        let positions = [position(1, "10", now), hk_position(now)];
+        let mut quotes = quote("11");
+        quotes.extend(quote_for("hk00700", "320"));
+
+        let report = build_report(&portfolio, &positions, &[], &quotes, &hkd_rates(&[(local_date(now), 0.9)]), now);
+        let hk = report.holdings.iter().find(|h| h.stock_code == "hk00700").unwrap();
+        assert_eq!(hk.currency, Currency::Hkd);
+        assert_eq!(hk.market_value, Money::from_yuan(32_000));
+        assert_eq!(hk.market_value_base, Some(Money::from_yuan(28_800)));
+        assert_eq!(report.market_value, Money::from_yuan(11_000 + 28_800));
+        assert!(report.missing_fx_rates.is_empty());
+
+        let report = build_report(&portfolio, &positions, &[], &quotes, &FxRates::new(&[]), now);
+        assert_eq!(report.missing_fx_rates, vec![Currency::Hkd]);
+        assert_eq!(report.equity, Money::from_yuan(61_000));
+    }
+}
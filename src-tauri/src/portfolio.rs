@@ -0,0 +1,391 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::api::PriceSignal;
+use crate::fx::{Currency, FxRates};
+use crate::money::Money;
+use crate::strategy::StrategyResolver;
+use crate::models::{PortfolioSummary, Position, PositionSummary, StockInfo, Trade, TradeSignal};
+
+/// 按账户和股票代码汇总交易记录得到持仓
+pub fn build_positions(trades: &[Trade]) -> Vec<Position> {
+    let mut positions: BTreeMap<(String, String), Position> = BTreeMap::new();
+
+    for trade in trades {
+        let cost = trade.buy_price * trade.quantity as i64;
+        positions
+            .entry((trade.account.clone(), trade.stock_code.clone()))
+            .and_modify(|p| {
+                p.quantity += trade.quantity as i64;
+                p.total_cost += cost;
+                p.first_buy_time = p.first_buy_time.min(trade.buy_time);
+                p.last_buy_time = p.last_buy_time.max(trade.buy_time);
+            })
+            .or_insert_with(|| Position {
+                account: trade.account.clone(),
+                stock_code: trade.stock_code.clone(),
+                stock_name: trade.stock_name.clone(),
+                quantity: trade.quantity as i64,
+                average_cost: Money::ZERO,
+                total_cost: cost,
+                currency: trade.currency(),
+                first_buy_time: trade.buy_time,
+                last_buy_time: trade.buy_time,
+            });
+    }
+
+    positions
+        .into_values()
+        .map(|mut p| {
+            if p.quantity > 0 {
+                p.average_cost = p.total_cost / p.quantity;
+            }
+            p
+        })
+        .collect()
+}
+
+/// 根据交易记录和行情计算投资组合分析结果
+///
+/// 行情以股票代码为键，缺少行情的持仓市值按成本计算，不计入当日涨跌。
+/// 每笔交易按解析出的策略参数计算目标价，达到目标或触发止损的交易记入对应持仓的信号。
+/// `high_water` 为已记录的买入以来最高价，没有记录时按买入价计算。
+/// 单个持仓的金额使用交易货币，组合合计按 `base` 折算：成本用买入日汇率，市值用当日汇率，
+/// 未实现盈亏拆分为按当日汇率折算的价格盈亏和汇率变动带来的汇兑盈亏。缺少汇率的持仓不计入合计。
+pub fn summarize_portfolio(
+    trades: &[Trade],
+    quotes: &HashMap<String, StockInfo>,
+    strategy: &StrategyResolver,
+    high_water: &HashMap<i64, Money>,
+    fx: &FxRates,
+    base: Currency,
+    now: DateTime<Utc>,
+) -> PortfolioSummary {
+    let today = local_date(now);
+    let mut signals: HashMap<(String, String), Vec<TradeSignal>> = HashMap::new();
+    let mut base_costs: HashMap<(String, String), (Currency, Option<Money>)> = HashMap::new();
+
+    for trade in trades {
+        let currency = trade.currency();
+        let cost = fx
+            .rate(currency, base, local_date(trade.buy_time))
+            .map(|rate| (trade.buy_price * trade.quantity as i64).mul_f64(rate));
+        base_costs
+            .entry((trade.account.clone(), trade.stock_code.clone()))
+            .and_modify(|(_, total)| *total = total.zip(cost).map(|(a, b)| a + b))
+            .or_insert((currency, cost));
+    }
+
+    for trade in trades {
+        let (Some(trade_id), Some(quote)) = (trade.id, quotes.get(&trade.stock_code)) else {
+            continue;
+        };
+
+        let days_held = (now - trade.buy_time).num_days();
+        let params = strategy.resolve(trade);
+        let high_water_price = high_water
+            .get(&trade_id)
+            .copied()
+            .unwrap_or(trade.buy_price)
+            .max(quote.current_price);
+        let tick = trade.security_id().map(|id| id.tick_size()).unwrap_or(Money::FEN);
+        let evaluation = params.evaluate(
+            trade.buy_price,
+            trade.quantity as i64,
+            days_held,
+            Some(quote.current_price),
+            high_water_price,
+            tick,
+        );
+
+        if matches!(evaluation.signal, PriceSignal::Sell(_) | PriceSignal::Buy(_) | PriceSignal::Stop { .. }) {
+            signals
+                .entry((trade.account.clone(), trade.stock_code.clone()))
+                .or_default()
+                .push(TradeSignal {
+                    trade_id,
+                    signal: evaluation.signal,
+                });
+        }
+    }
+
+    let mut positions: Vec<PositionSummary> = build_positions(trades)
+        .into_iter()
+        .map(|p| {
+            let quote = quotes.get(&p.stock_code);
+            let current_price = quote.map(|q| q.current_price);
+            let market_value = current_price
+                .map(|price| price * p.quantity)
+                .unwrap_or(p.total_cost);
+            let unrealized_pnl = market_value - p.total_cost;
+            let day_change = quote.map(|q| q.change * p.quantity).unwrap_or(Money::ZERO);
+
+            let key = (p.account.clone(), p.stock_code.clone());
+            let (currency, total_cost_base) = base_costs.remove(&key).unwrap_or((base, Some(p.total_cost)));
+            let rate_now = fx.rate(currency, base, today);
+            let market_value_base = rate_now.map(|rate| market_value.mul_f64(rate));
+            let price_pnl = rate_now.map(|rate| unrealized_pnl.mul_f64(rate));
+            let fx_pnl = match (market_value_base, total_cost_base, price_pnl) {
+                (Some(value), Some(cost), Some(price_pnl)) => Some(value - cost - price_pnl),
+                _ => None,
+            };
+
+            PositionSummary {
+                signals: signals.remove(&key).unwrap_or_default(),
+                account: p.account,
+                stock_code: p.stock_code,
+                stock_name: p.stock_name,
+                currency,
+                quantity: p.quantity,
+                average_cost: p.average_cost,
+                total_cost: p.total_cost,
+                current_price,
+                market_value,
+                unrealized_pnl,
+                unrealized_pnl_percent: percent(unrealized_pnl, p.total_cost),
+                day_change,
+                day_change_percent: percent(day_change, market_value - day_change),
+                total_cost_base,
+                market_value_base,
+                price_pnl,
+                fx_pnl,
+                weight: 0.0,
+            }
+        })
+        .collect();
+
+    let mut missing_fx_rates: Vec<Currency> = positions
+        .iter()
+        .filter(|p| p.total_cost_base.is_none() || p.market_value_base.is_none())
+        .map(|p| p.currency)
+        .collect();
+    missing_fx_rates.sort();
+    missing_fx_rates.dedup();
+
+    let mut missing_quotes: Vec<String> = positions
+        .iter()
+        .filter(|p| p.current_price.is_none())
+        .map(|p| p.stock_code.clone())
+        .collect();
+    missing_quotes.sort();
+    missing_quotes.dedup();
+
+    let converted: Vec<&PositionSummary> = positions
+        .iter()
+        .filter(|p| !missing_fx_rates.contains(&p.currency))
+        .collect();
+    let total_cost: Money = converted.iter().filter_map(|p| p.total_cost_base).sum();
+    let market_value: Money = converted.iter().filter_map(|p| p.market_value_base).sum();
+    let price_pnl: Money = converted.iter().filter_map(|p| p.price_pnl).sum();
+    let fx_pnl: Money = converted.iter().filter_map(|p| p.fx_pnl).sum();
+    let day_change: Money = converted
+        .iter()
+        .filter_map(|p| Some(p.day_change.mul_f64(fx.rate(p.currency, base, today)?)))
+        .sum();
+    let unrealized_pnl = market_value - total_cost;
+
+    for position in &mut positions {
+        position.weight = percent(position.market_value_base.unwrap_or(Money::ZERO), market_value);
+    }
+
+    let count_signals = |signal_type: &str| {
+        positions
+            .iter()
+            .flat_map(|p| &p.signals)
+            .filter(|s| s.signal.kind() == signal_type)
+            .count()
+    };
+    let sell_signals = count_signals("sell");
+    let buy_signals = count_signals("buy");
+    let stop_signals = count_signals("stop");
+    let total_stocks = positions
+        .iter()
+        .map(|p| p.stock_code.as_str())
+        .collect::<HashSet<_>>()
+        .len();
+
+    PortfolioSummary {
+        base_currency: base,
+        total_cost,
+        market_value,
+        unrealized_pnl,
+        unrealized_pnl_percent: percent(unrealized_pnl, total_cost),
+        day_change,
+        day_change_percent: percent(day_change, market_value - day_change),
+        price_pnl,
+        fx_pnl,
+        missing_fx_rates,
+        missing_quotes,
+        sell_signals,
+        buy_signals,
+        stop_signals,
+        total_trades: trades.len(),
+        total_stocks,
+        positions,
+        quoted_at: now,
+    }
+}
+
+fn percent(value: Money, base: Money) -> f64 {
+    value.ratio(base) * 100.0
+}
+
+fn local_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{DayCountBasis, GrowthModel, StopRules};
+    use crate::models::StrategyParams;
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    fn trade(id: i64, code: &str, price: &str, quantity: i32) -> Trade {
+        Trade {
+            id: Some(id),
+            stock_code: code.to_string(),
+            stock_name: code.to_string(),
+            buy_price: money(price),
+            buy_time: "2024-01-02T02:00:00Z".parse().unwrap(),
+            quantity,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        }
+    }
+
+    fn quote(code: &str, price: &str, change: &str) -> (String, StockInfo) {
+        let info = StockInfo {
+            code: code.to_string(),
+            name: code.to_string(),
+            current_price: money(price),
+            change: money(change),
+            change_percent: 0.0,
+            open: money(price),
+            high: money(price),
+            low: money(price),
+            volume: 0,
+            turnover: Money::ZERO,
+            timestamp: "2024-03-01T07:00:00Z".parse().unwrap(),
+        };
+        (code.to_string(), info)
+    }
+
+    fn strategy() -> StrategyResolver {
+        StrategyResolver::new(
+            StrategyParams {
+                annual_return_rate: 0.2,
+                buy_step_percentage: 0.05,
+                min_holding_days: 30,
+                growth_model: GrowthModel::Simple,
+                day_count_basis: DayCountBasis::Days360,
+                stops: StopRules::default(),
+            },
+            Vec::new(),
+        )
+    }
+
+    fn summarize(trades: &[Trade], quotes: &HashMap<String, StockInfo>) -> PortfolioSummary {
+        summarize_portfolio(
+            trades,
+            quotes,
+            &strategy(),
+            &HashMap::new(),
+            &FxRates::default(),
+            Currency::Cny,
+            "2024-03-01T07:00:00Z".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn sums_positions_weights_and_signals() {
+        let trades = [
+            trade(1, "600000", "10", 1000),
+            trade(2, "600000", "12", 1000),
+            trade(3, "000001", "9", 1000),
+        ];
+        let quotes = HashMap::from([quote("600000", "11.5", "0.1"), quote("000001", "8.5", "-0.2")]);
+
+        let summary = summarize(&trades, &quotes);
+        assert_eq!(summary.total_cost, money("31000"));
+        assert_eq!(summary.market_value, money("31500"));
+        assert_eq!(summary.unrealized_pnl, money("500"));
+        assert_eq!(summary.day_change, money("0"));
+        assert_eq!((summary.total_trades, summary.total_stocks), (3, 2));
+        assert!(summary.missing_quotes.is_empty());
+
+        let position = summary.positions.iter().find(|p| p.stock_code == "600000").unwrap();
+        assert_eq!(position.average_cost, money("11"));
+        assert_eq!(position.market_value, money("23000"));
+        assert_eq!(position.day_change, money("200"));
+        assert!((position.weight - 23000.0 / 31500.0 * 100.0).abs() < 1e-9);
+
+        // 持有 59 天，10 元买入的卖出目标 10.33 已达到；12 元买入的加仓价 11.77 和 9 元买入的 8.83 也已达到
+        assert_eq!((summary.sell_signals, summary.buy_signals), (1, 2));
+        let kinds: Vec<(i64, &str)> = position.signals.iter().map(|s| (s.trade_id, s.signal.kind())).collect();
+        assert_eq!(kinds, vec![(1, "sell"), (2, "buy")]);
+    }
+
+    #[test]
+    fn values_unquoted_positions_at_cost_and_reports_them() {
+        let trades = [trade(1, "600000", "10", 1000), trade(2, "000001", "9", 1000)];
+        let quotes = HashMap::from([quote("600000", "11", "0.1")]);
+
+        let summary = summarize(&trades, &quotes);
+        assert_eq!(summary.missing_quotes, vec!["000001".to_string()]);
+
+        let unquoted = summary.positions.iter().find(|p| p.stock_code == "000001").unwrap();
+        assert_eq!(unquoted.current_price, None);
+        assert_eq!(unquoted.market_value, money("9000"));
+        assert_eq!(unquoted.unrealized_pnl, Money::ZERO);
+        assert_eq!(unquoted.day_change, Money::ZERO);
+        assert!(unquoted.signals.is_empty());
+        assert_eq!(summary.market_value, money("20000"));
+    }
+
+    #[test]
+    fn splits_pnl_into_price_and_fx_and_excludes_missing_rates() {
+        use crate::models::FxRate;
+
+        let fx_rate = |month: u32, day: u32, rate: f64| FxRate {
+            currency: Currency::Hkd,
+            rate_date: NaiveDate::from_ymd_opt(2024, month, day).unwrap(),
+            rate,
+        };
+        let fx = FxRates::new(&[fx_rate(1, 1, 0.89), fx_rate(1, 2, 0.90), fx_rate(3, 1, 0.92)]);
+
+        // 北京时间 1 月 2 日凌晨买入，按 1 月 2 日而不是 UTC 的 1 月 1 日汇率折算成本
+        let mut hk = trade(1, "hk00700", "300", 100);
+        hk.buy_time = "2024-01-01T20:00:00Z".parse().unwrap();
+        let trades = [hk, trade(2, "600000", "10", 1000), trade(3, "gb_aapl", "180", 10)];
+        let quotes = HashMap::from([quote("hk00700", "320", "0"), quote("600000", "11", "0"), quote("gb_aapl", "190", "0")]);
+
+        let summary = summarize_portfolio(
+            &trades,
+            &quotes,
+            &strategy(),
+            &HashMap::new(),
+            &fx,
+            Currency::Cny,
+            "2024-03-01T07:00:00Z".parse().unwrap(),
+        );
+
+        let position = summary.positions.iter().find(|p| p.stock_code == "hk00700").unwrap();
+        assert_eq!(position.total_cost_base, Some(money("27000")));
+        assert_eq!(position.market_value_base, Some(money("29440")));
+        assert_eq!(position.price_pnl, Some(money("1840")));
+        assert_eq!(position.fx_pnl, Some(money("600")));
+
+        // 没有美元汇率的持仓不计入合计
+        assert_eq!(summary.missing_fx_rates, vec![Currency::Usd]);
+        assert_eq!(summary.total_cost, money("37000"));
+        assert_eq!(summary.market_value, money("40440"));
+        assert_eq!((summary.price_pnl, summary.fx_pnl), (money("2840"), money("600")));
+        assert_eq!(summary.price_pnl + summary.fx_pnl, summary.market_value - summary.total_cost);
+    }
+}
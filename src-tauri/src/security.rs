@@ -0,0 +1,410 @@
+use serde::{Deserialize, Serialize};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::sqlite::{Sqlite, SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef};
+use sqlx::{Decode, Encode, Type};
+use std::borrow::Cow;
+use chrono::{DateTime, FixedOffset, Utc};
+use crate::fx::Currency;
+use crate::market::{self, Board};
+use crate::money::Money;
+
+/// 交易所
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Exchange {
+    /// 上海证券交易所
+    Sh,
+    /// 深圳证券交易所
+    Sz,
+    /// 北京证券交易所
+    Bj,
+    /// 香港联合交易所，通过港股通或境外账户交易
+    Hk,
+    /// 美国交易所
+    Us,
+}
+
+impl Exchange {
+    /// 行情接口使用的小写交易所前缀，美股为新浪的 gb_
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Exchange::Sh => "sh",
+            Exchange::Sz => "sz",
+            Exchange::Bj => "bj",
+            Exchange::Hk => "hk",
+            Exchange::Us => "gb_",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix.to_ascii_lowercase().as_str() {
+            "sh" => Some(Exchange::Sh),
+            "sz" => Some(Exchange::Sz),
+            "bj" => Some(Exchange::Bj),
+            _ => None,
+        }
+    }
+}
+
+/// 品种类型
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum InstrumentType {
+    Stock,
+    Etf,
+    /// 上市开放式基金及其他场内基金
+    Lof,
+    /// 国债、企业债和可转债
+    Bond,
+    /// 指数，只有行情，不能交易
+    Index,
+}
+
+/// 代码规则表：交易所、代码前缀、板块、品种类型，按最长前缀匹配
+const CODE_RULES: &[(Exchange, &str, Board, InstrumentType)] = &[
+    // 上交所
+    (Exchange::Sh, "600", Board::Main, InstrumentType::Stock),
+    (Exchange::Sh, "601", Board::Main, InstrumentType::Stock),
+    (Exchange::Sh, "603", Board::Main, InstrumentType::Stock),
+    (Exchange::Sh, "605", Board::Main, InstrumentType::Stock),
+    (Exchange::Sh, "900", Board::Main, InstrumentType::Stock),
+    (Exchange::Sh, "688", Board::Star, InstrumentType::Stock),
+    (Exchange::Sh, "689", Board::Star, InstrumentType::Stock),
+    (Exchange::Sh, "50", Board::Main, InstrumentType::Lof),
+    (Exchange::Sh, "51", Board::Main, InstrumentType::Etf),
+    (Exchange::Sh, "52", Board::Main, InstrumentType::Etf),
+    (Exchange::Sh, "56", Board::Main, InstrumentType::Etf),
+    (Exchange::Sh, "58", Board::Main, InstrumentType::Etf),
+    (Exchange::Sh, "010", Board::Main, InstrumentType::Bond),
+    (Exchange::Sh, "019", Board::Main, InstrumentType::Bond),
+    (Exchange::Sh, "110", Board::Main, InstrumentType::Bond),
+    (Exchange::Sh, "111", Board::Main, InstrumentType::Bond),
+    (Exchange::Sh, "113", Board::Main, InstrumentType::Bond),
+    (Exchange::Sh, "118", Board::Main, InstrumentType::Bond),
+    (Exchange::Sh, "000", Board::Main, InstrumentType::Index),
+    // 深交所
+    (Exchange::Sz, "000", Board::Main, InstrumentType::Stock),
+    (Exchange::Sz, "001", Board::Main, InstrumentType::Stock),
+    (Exchange::Sz, "002", Board::Main, InstrumentType::Stock),
+    (Exchange::Sz, "003", Board::Main, InstrumentType::Stock),
+    (Exchange::Sz, "004", Board::Main, InstrumentType::Stock),
+    (Exchange::Sz, "200", Board::Main, InstrumentType::Stock),
+    (Exchange::Sz, "300", Board::ChiNext, InstrumentType::Stock),
+    (Exchange::Sz, "301", Board::ChiNext, InstrumentType::Stock),
+    (Exchange::Sz, "302", Board::ChiNext, InstrumentType::Stock),
+    (Exchange::Sz, "15", Board::Main, InstrumentType::Etf),
+    (Exchange::Sz, "16", Board::Main, InstrumentType::Lof),
+    (Exchange::Sz, "18", Board::Main, InstrumentType::Lof),
+    (Exchange::Sz, "10", Board::Main, InstrumentType::Bond),
+    (Exchange::Sz, "123", Board::Main, InstrumentType::Bond),
+    (Exchange::Sz, "127", Board::Main, InstrumentType::Bond),
+    (Exchange::Sz, "128", Board::Main, InstrumentType::Bond),
+    (Exchange::Sz, "399", Board::Main, InstrumentType::Index),
+    // 北交所
+    (Exchange::Bj, "43", Board::Beijing, InstrumentType::Stock),
+    (Exchange::Bj, "83", Board::Beijing, InstrumentType::Stock),
+    (Exchange::Bj, "87", Board::Beijing, InstrumentType::Stock),
+    (Exchange::Bj, "88", Board::Beijing, InstrumentType::Stock),
+    (Exchange::Bj, "920", Board::Beijing, InstrumentType::Stock),
+    (Exchange::Bj, "899", Board::Beijing, InstrumentType::Index),
+];
+
+/// 证券标识：沪深北为交易所加六位代码，板块和品种类型由代码规则表确定；
+/// 港股为五位代码，美股为股票代码，均不区分板块，记为主板股票
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct SecurityId {
+    pub exchange: Exchange,
+    pub code: String,
+    pub board: Board,
+    pub instrument_type: InstrumentType,
+}
+
+impl SecurityId {
+    /// 解析六位代码或带交易所前缀的代码（如 sh000300），以及港股 hk00700 和美股 gb_aapl，无法识别的代码返回 None；
+    /// 不带前缀时不会识别为指数，000001 即为深市平安银行而不是上证指数
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Some(id) = Self::parse_overseas(text) {
+            return Some(id);
+        }
+
+        let (exchange, code) = match text.get(..2).and_then(Exchange::from_prefix) {
+            Some(exchange) => (Some(exchange), &text[2..]),
+            None => (None, text),
+        };
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        CODE_RULES
+            .iter()
+            .filter(|(rule_exchange, prefix, _, instrument_type)| {
+                code.starts_with(prefix)
+                    && match exchange {
+                        Some(exchange) => *rule_exchange == exchange,
+                        None => *instrument_type != InstrumentType::Index,
+                    }
+            })
+            .max_by_key(|(_, prefix, _, _)| prefix.len())
+            .map(|&(exchange, _, board, instrument_type)| SecurityId {
+                exchange,
+                code: code.to_string(),
+                board,
+                instrument_type,
+            })
+    }
+
+    /// 港股为 hk 加五位数字，美股为 gb_ 加股票代码；港股必须带前缀，以免输错的沪深代码被当作港股
+    fn parse_overseas(text: &str) -> Option<Self> {
+        let lower = text.to_ascii_lowercase();
+        let (exchange, code) = if let Some(ticker) = lower.strip_prefix(Exchange::Us.prefix()) {
+            let valid = ticker.len() <= 8
+                && ticker.starts_with(|c: char| c.is_ascii_alphabetic())
+                && ticker.chars().all(|c| c.is_ascii_alphanumeric() || c == '.');
+            (Exchange::Us, valid.then(|| ticker.to_uppercase())?)
+        } else {
+            let code = lower.strip_prefix(Exchange::Hk.prefix())?;
+            let valid = code.len() == 5 && code.chars().all(|c| c.is_ascii_digit());
+            (Exchange::Hk, valid.then(|| code.to_string())?)
+        };
+
+        Some(SecurityId {
+            exchange,
+            code,
+            board: Board::Main,
+            instrument_type: InstrumentType::Stock,
+        })
+    }
+
+    /// 带交易所前缀的代码，如 sz000001、hk00700、gb_aapl，新浪等行情接口使用
+    pub fn symbol(&self) -> String {
+        format!("{}{}", self.exchange.prefix(), self.code.to_lowercase())
+    }
+
+    /// 港股和美股
+    pub fn is_overseas(&self) -> bool {
+        matches!(self.exchange, Exchange::Hk | Exchange::Us)
+    }
+
+    /// 报价货币：沪市 B 股为美元，深市 B 股为港币
+    pub fn currency(&self) -> Currency {
+        match self.exchange {
+            Exchange::Hk => Currency::Hkd,
+            Exchange::Us => Currency::Usd,
+            Exchange::Sh if self.code.starts_with("900") => Currency::Usd,
+            Exchange::Sz if self.code.starts_with("200") => Currency::Hkd,
+            Exchange::Sh | Exchange::Sz | Exchange::Bj => Currency::Cny,
+        }
+    }
+
+    /// 是否处于该交易所可以成交的交易时段，包括开盘集合竞价
+    pub fn is_trading_session(&self, time: DateTime<Utc>) -> bool {
+        let beijing = time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap());
+        match self.exchange {
+            Exchange::Sh | Exchange::Sz | Exchange::Bj => market::is_trading_session(beijing),
+            Exchange::Hk => market::is_hk_trading_session(beijing),
+            Exchange::Us => market::is_us_trading_session(time),
+        }
+    }
+
+    /// 文本是否表示这只证券，可以是六位代码或带前缀的代码
+    pub fn matches(&self, text: &str) -> bool {
+        text == self.code || text.eq_ignore_ascii_case(&self.symbol())
+    }
+
+    /// 指数不能交易
+    pub fn is_tradable(&self) -> bool {
+        self.instrument_type != InstrumentType::Index
+    }
+
+    /// 最小报价单位：股票和指数 0.01 元，基金和债券 0.001 元；港股价位随股价分档，统一按 0.001 取整
+    pub fn tick_size(&self) -> Money {
+        if self.is_overseas() {
+            return if self.exchange == Exchange::Hk { Money::LI } else { Money::FEN };
+        }
+        match self.instrument_type {
+            InstrumentType::Stock | InstrumentType::Index => Money::FEN,
+            InstrumentType::Etf | InstrumentType::Lof | InstrumentType::Bond => Money::LI,
+        }
+    }
+
+    /// 涨跌幅限制比例，ST 只影响主板股票，指数、港股和美股没有涨跌幅限制
+    pub fn limit_percent(&self, is_st: bool) -> Option<f64> {
+        if self.is_overseas() {
+            return None;
+        }
+        match self.instrument_type {
+            InstrumentType::Stock => Some(match self.board {
+                Board::Main if is_st => 0.05,
+                Board::Main => 0.10,
+                Board::ChiNext | Board::Star => 0.20,
+                Board::Beijing => 0.30,
+            }),
+            InstrumentType::Etf | InstrumentType::Lof => Some(0.10),
+            // 可转债 ±20%
+            InstrumentType::Bond => Some(0.20),
+            InstrumentType::Index => None,
+        }
+    }
+
+    /// 买入数量的最低数量和递增单位：科创板 200 股起按 1 股递增，北交所 100 股起按 1 股递增，
+    /// 债券按 10 张一手，其他按 100 股一手；港股每手股数因股票而异，与美股一样只要求至少 1 股
+    pub fn buy_quantity_rule(&self) -> (i64, i64) {
+        if self.is_overseas() {
+            return (1, 1);
+        }
+        match (self.instrument_type, self.board) {
+            (InstrumentType::Bond, _) => (10, 10),
+            (InstrumentType::Stock, Board::Star) => (200, 1),
+            (InstrumentType::Stock, Board::Beijing) => (100, 1),
+            _ => (100, 100),
+        }
+    }
+}
+
+/// 按代码取最小报价单位，无法识别的代码按 0.01 元
+pub fn tick_size(stock_code: &str) -> Money {
+    SecurityId::parse(stock_code).map(|id| id.tick_size()).unwrap_or(Money::FEN)
+}
+
+/// 按代码取买入数量规则，无法识别的代码按 100 股一手
+pub fn buy_quantity_rule(stock_code: &str) -> (i64, i64) {
+    SecurityId::parse(stock_code).map(|id| id.buy_quantity_rule()).unwrap_or((100, 100))
+}
+
+/// 按买入数量规则 (最低数量, 递增单位) 把数量向下调整为可以申报的数量，不足最低数量时为 0
+pub fn round_buy_quantity(quantity: i64, (minimum, step): (i64, i64)) -> i64 {
+    if quantity < minimum {
+        return 0;
+    }
+    minimum + (quantity - minimum) / step * step
+}
+
+/// 数据库中保存为带交易所前缀的代码，读取时重新按规则表确定板块和品种
+impl Type<Sqlite> for SecurityId {
+    fn type_info() -> SqliteTypeInfo {
+        <&str as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <&str as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for SecurityId {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        args.push(SqliteArgumentValue::Text(Cow::Owned(self.symbol())));
+        IsNull::No
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for SecurityId {
+    fn decode(value: SqliteValueRef<'r>) -> std::result::Result<Self, BoxDynError> {
+        let symbol = <&str as Decode<Sqlite>>::decode(value)?;
+        SecurityId::parse(symbol).ok_or_else(|| format!("无法识别的证券代码: {}", symbol).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> (Exchange, Board, InstrumentType) {
+        let id = SecurityId::parse(text).unwrap();
+        (id.exchange, id.board, id.instrument_type)
+    }
+
+    #[test]
+    fn classifies_codes_by_rule_table() {
+        assert_eq!(parse("600000"), (Exchange::Sh, Board::Main, InstrumentType::Stock));
+        assert_eq!(parse("000001"), (Exchange::Sz, Board::Main, InstrumentType::Stock));
+        assert_eq!(parse("300750"), (Exchange::Sz, Board::ChiNext, InstrumentType::Stock));
+        assert_eq!(parse("688981"), (Exchange::Sh, Board::Star, InstrumentType::Stock));
+        assert_eq!(parse("830799"), (Exchange::Bj, Board::Beijing, InstrumentType::Stock));
+        assert_eq!(parse("430047"), (Exchange::Bj, Board::Beijing, InstrumentType::Stock));
+        assert_eq!(parse("920002"), (Exchange::Bj, Board::Beijing, InstrumentType::Stock));
+        assert_eq!(parse("510300"), (Exchange::Sh, Board::Main, InstrumentType::Etf));
+        assert_eq!(parse("159915"), (Exchange::Sz, Board::Main, InstrumentType::Etf));
+        assert_eq!(parse("161725"), (Exchange::Sz, Board::Main, InstrumentType::Lof));
+        assert_eq!(parse("113050"), (Exchange::Sh, Board::Main, InstrumentType::Bond));
+        assert_eq!(parse("128136"), (Exchange::Sz, Board::Main, InstrumentType::Bond));
+
+        assert_eq!(SecurityId::parse("830799").unwrap().symbol(), "bj830799");
+        assert_eq!(SecurityId::parse("159915").unwrap().symbol(), "sz159915");
+        assert!(SecurityId::parse("700000").is_none());
+        assert!(SecurityId::parse("60000").is_none());
+    }
+
+    #[test]
+    fn parses_hong_kong_and_us_symbols() {
+        let tencent = SecurityId::parse("hk00700").unwrap();
+        assert_eq!((tencent.exchange, tencent.code.as_str()), (Exchange::Hk, "00700"));
+        assert_eq!(tencent.symbol(), "hk00700");
+        assert_eq!(tencent.currency(), Currency::Hkd);
+        // 港股必须带前缀
+        assert!(SecurityId::parse("09988").is_none());
+
+        let apple = SecurityId::parse("gb_aapl").unwrap();
+        assert_eq!((apple.exchange, apple.code.as_str()), (Exchange::Us, "AAPL"));
+        assert_eq!(apple.symbol(), "gb_aapl");
+        assert_eq!(apple.currency(), Currency::Usd);
+        assert!(apple.matches("GB_AAPL"));
+        assert_eq!(apple.limit_percent(false), None);
+        assert_eq!(apple.buy_quantity_rule(), (1, 1));
+        assert_eq!(SecurityId::parse("gb_brk.b").unwrap().code, "BRK.B");
+
+        assert!(SecurityId::parse("hk0700").is_none());
+        assert!(SecurityId::parse("gb_").is_none());
+        assert!(SecurityId::parse("gb_1abc").is_none());
+
+        // B 股以外币报价
+        assert_eq!(SecurityId::parse("900901").unwrap().currency(), Currency::Usd);
+        assert_eq!(SecurityId::parse("200002").unwrap().currency(), Currency::Hkd);
+        assert_eq!(SecurityId::parse("600000").unwrap().currency(), Currency::Cny);
+    }
+
+    #[test]
+    fn exchange_prefix_selects_index() {
+        assert_eq!(parse("sh000001"), (Exchange::Sh, Board::Main, InstrumentType::Index));
+        assert_eq!(parse("SZ000001"), (Exchange::Sz, Board::Main, InstrumentType::Stock));
+        assert_eq!(parse("sz399006"), (Exchange::Sz, Board::Main, InstrumentType::Index));
+        assert!(SecurityId::parse("399006").is_none());
+        assert!(SecurityId::parse("sz600000").is_none());
+
+        let index = SecurityId::parse("sh000300").unwrap();
+        assert!(!index.is_tradable());
+        assert_eq!(index.limit_percent(false), None);
+        assert!(index.matches("sh000300"));
+        assert!(index.matches("000300"));
+    }
+
+    #[test]
+    fn trading_rules_follow_instrument_and_board() {
+        let fund = SecurityId::parse("510300").unwrap();
+        assert_eq!(fund.tick_size(), Money::LI);
+        assert_eq!(fund.buy_quantity_rule(), (100, 100));
+
+        let star = SecurityId::parse("688981").unwrap();
+        assert_eq!(star.limit_percent(true), Some(0.20));
+        assert_eq!(star.buy_quantity_rule(), (200, 1));
+
+        let main = SecurityId::parse("600000").unwrap();
+        assert_eq!(main.limit_percent(true), Some(0.05));
+        assert_eq!(main.tick_size(), Money::FEN);
+
+        assert_eq!(SecurityId::parse("113050").unwrap().buy_quantity_rule(), (10, 10));
+        assert_eq!(tick_size("unknown"), Money::FEN);
+        assert_eq!(buy_quantity_rule("unknown"), (100, 100));
+    }
+
+    #[test]
+    fn rounds_buy_quantity_down_to_rule() {
+        assert_eq!(round_buy_quantity(1250, (100, 100)), 1200);
+        assert_eq!(round_buy_quantity(250, (200, 1)), 250);
+        assert_eq!(round_buy_quantity(150, (200, 1)), 0);
+        assert_eq!(round_buy_quantity(35, (10, 10)), 30);
+        assert_eq!(round_buy_quantity(0, (1, 1)), 0);
+    }
+}
@@ -1,5 +1,19 @@
-use crate::models::{StockSearchResult, StockInfo};
-use anyhow::Result;
+use crate::fx::Currency;
+use crate::models::{DailyBar, FxRate, StockSearchResult, StockInfo};
+use crate::money::{Money, Rounding};
+use crate::security::{Exchange, SecurityId};
+use crate::error::{AppError, Result};
+use std::collections::HashMap;
+
+/// 支持作为业绩基准的指数，代码带交易所前缀以免与同号股票混淆
+pub const BENCHMARK_INDICES: &[(&str, &str)] = &[
+    ("sh000300", "沪深300"),
+    ("sh000001", "上证指数"),
+    ("sz399006", "创业板指"),
+];
+
+/// 新浪K线接口单次最多返回的日线数量，接口只能取最近的日线，无法分页获取更早的历史
+pub const MAX_DAILY_BARS: usize = 1023;
 
 pub struct StockApi;
 
@@ -50,10 +64,18 @@ impl StockApi {
                         item.get("Name").and_then(|v| v.as_str()),
                         item.get("MktNum").and_then(|v| v.as_str()),
                     ) {
-                        let market = match market_code {
-                            "1" => "SH",
-                            "2" => "SZ",
-                            _ => "OTHER",
+                        // 优先按代码规则表确定交易所，规则表没有的代码再参考接口返回的市场
+                        let market = match SecurityId::parse(code).map(|id| id.exchange) {
+                            Some(Exchange::Sh) => "SH",
+                            Some(Exchange::Sz) => "SZ",
+                            Some(Exchange::Bj) => "BJ",
+                            Some(Exchange::Hk) => "HK",
+                            Some(Exchange::Us) => "US",
+                            None => match market_code {
+                                "1" => "SH",
+                                "2" => "SZ",
+                                _ => "OTHER",
+                            },
                         };
 
                         results.push(StockSearchResult {
@@ -150,9 +172,8 @@ impl StockApi {
     }
 
     /// 从新浪财经API获取真实股票信息
-    async fn fetch_real_stock_info(stock_code: &str) -> Result<StockInfo> {
-        let formatted_code = Self::format_stock_code_for_sina(stock_code);
-        let url = format!("https://hq.sinajs.cn/list={}", formatted_code);
+    pub(crate) async fn fetch_real_stock_info(stock_code: &str) -> Result<StockInfo> {
+        let url = format!("https://hq.sinajs.cn/list={}", Self::sina_symbol(stock_code)?);
 
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(10))
@@ -166,46 +187,217 @@ impl StockApi {
         // 格式: var hq_str_sh000001="股票名称,今开,昨收,现价,最高,最低,买一,卖一,成交量,成交额,..."
         if let Some(data_start) = text.find('"') {
             if let Some(data_end) = text.rfind('"') {
-                let data = &text[data_start + 1..data_end];
-                let parts: Vec<&str> = data.split(',').collect();
-
-                if parts.len() >= 10 {
-                    let name = parts[0].to_string();
-                    let open: f64 = parts[1].parse().unwrap_or(0.0);
-                    let prev_close: f64 = parts[2].parse().unwrap_or(0.0);
-                    let current_price: f64 = parts[3].parse().unwrap_or(0.0);
-                    let high: f64 = parts[4].parse().unwrap_or(0.0);
-                    let low: f64 = parts[5].parse().unwrap_or(0.0);
-                    let volume: i64 = parts[8].parse().unwrap_or(0);
-                    let turnover: i64 = parts[9].parse::<f64>().unwrap_or(0.0) as i64;
-
-                    let change = current_price - prev_close;
-                    let change_percent = if prev_close > 0.0 {
-                        (change / prev_close) * 100.0
-                    } else {
-                        0.0
-                    };
-
-                    if current_price > 0.0 && !name.is_empty() {
-                        return Ok(StockInfo {
-                            code: stock_code.to_string(),
-                            name,
-                            current_price,
-                            change,
-                            change_percent,
-                            open,
-                            high,
-                            low,
-                            volume,
-                            turnover,
-                            timestamp: chrono::Utc::now(),
-                        });
+                if let Some(stock_info) = Self::parse_sina_quote(stock_code, &text[data_start + 1..data_end]) {
+                    return Ok(stock_info);
+                }
+            }
+        }
+
+        Err(AppError::ProviderParse("无法解析股票信息".to_string()))
+    }
+
+    /// 批量获取股票行情，一次请求查询所有代码，未取到的再逐个获取
+    ///
+    /// 不回退到模拟数据，仍取不到行情的代码不在结果中，由调用方标记为缺少行情
+    pub async fn get_stock_quotes(stock_codes: &[String]) -> HashMap<String, StockInfo> {
+        let mut quotes = match Self::fetch_real_stock_quotes(stock_codes).await {
+            Ok(quotes) => quotes,
+            Err(e) => {
+                println!("批量获取股票行情失败: {}", e);
+                HashMap::new()
+            }
+        };
+
+        for code in stock_codes {
+            if !quotes.contains_key(code) {
+                match Self::fetch_real_stock_info(code).await {
+                    Ok(stock_info) => {
+                        quotes.insert(code.clone(), stock_info);
                     }
+                    Err(e) => println!("获取 {} 行情失败: {}", code, e),
                 }
             }
         }
 
-        Err(anyhow::anyhow!("无法解析股票信息"))
+        quotes
+    }
+
+    /// 从新浪财经API批量获取行情，每行返回一只股票，不回退到模拟数据
+    pub(crate) async fn fetch_real_stock_quotes(stock_codes: &[String]) -> Result<HashMap<String, StockInfo>> {
+        let mut quotes = HashMap::new();
+        if stock_codes.is_empty() {
+            return Ok(quotes);
+        }
+
+        // 无法识别的代码不请求，由调用方逐个回退
+        let sina_codes: HashMap<String, &String> = stock_codes
+            .iter()
+            .filter_map(|code| Some((Self::sina_symbol(code).ok()?, code)))
+            .collect();
+        if sina_codes.is_empty() {
+            return Ok(quotes);
+        }
+        let list: Vec<&str> = sina_codes.keys().map(|code| code.as_str()).collect();
+        let url = format!("https://hq.sinajs.cn/list={}", list.join(","));
+
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
+            .build()?;
+
+        let response = client.get(&url).send().await?;
+        let text = response.text().await?;
+
+        // 格式: var hq_str_sz000001="...";
+        for line in text.lines() {
+            let (Some(prefix_end), Some(data_start), Some(data_end)) =
+                (line.find("hq_str_"), line.find('"'), line.rfind('"'))
+            else {
+                continue;
+            };
+            if data_end <= data_start {
+                continue;
+            }
+
+            let sina_code = line[prefix_end + "hq_str_".len()..].split('=').next().unwrap_or("");
+            if let Some(code) = sina_codes.get(sina_code) {
+                if let Some(stock_info) = Self::parse_sina_quote(code, &line[data_start + 1..data_end]) {
+                    quotes.insert((*code).clone(), stock_info);
+                }
+            }
+        }
+
+        Ok(quotes)
+    }
+
+    /// 解析新浪财经单只股票的行情字段，港股和美股的字段顺序与沪深北不同
+    fn parse_sina_quote(stock_code: &str, data: &str) -> Option<StockInfo> {
+        let parts: Vec<&str> = data.split(',').collect();
+        let money = |index: usize| -> Money { parts.get(index).and_then(|v| v.parse().ok()).unwrap_or_default() };
+        let integer = |index: usize| -> i64 {
+            parts.get(index).and_then(|v| v.parse::<f64>().ok()).unwrap_or(0.0) as i64
+        };
+
+        // 下标依次为名称、今开、昨收、现价、最高、最低、成交量、成交额
+        let (min_len, fields) = match SecurityId::parse(stock_code).map(|id| id.exchange) {
+            // 格式: 英文名,中文名,今开,昨收,最高,最低,现价,涨跌,涨跌幅,买一,卖一,成交额,成交量,...
+            Some(Exchange::Hk) => (13, [1, 2, 3, 6, 4, 5, 12, 11]),
+            // 格式: 名称,现价,涨跌幅,时间,涨跌,今开,最高,最低,52周最高,52周最低,成交量,...,昨收(26),...，没有成交额
+            Some(Exchange::Us) => (27, [0, 5, 26, 1, 6, 7, 10, usize::MAX]),
+            _ => (10, [0, 1, 2, 3, 4, 5, 8, 9]),
+        };
+        if parts.len() < min_len {
+            return None;
+        }
+
+        let name = parts[fields[0]].to_string();
+        let open = money(fields[1]);
+        let prev_close = money(fields[2]);
+        let current_price = money(fields[3]);
+        let high = money(fields[4]);
+        let low = money(fields[5]);
+        let volume = integer(fields[6]);
+        let turnover = money(fields[7]);
+
+        let change = current_price - prev_close;
+        let change_percent = change.ratio(prev_close) * 100.0;
+
+        if !current_price.is_positive() || name.is_empty() {
+            return None;
+        }
+
+        Some(StockInfo {
+            code: stock_code.to_string(),
+            name,
+            current_price,
+            change,
+            change_percent,
+            open,
+            high,
+            low,
+            volume,
+            turnover,
+            timestamp: chrono::Utc::now(),
+        })
+    }
+
+    /// 获取最近 days 个交易日的日线行情，按日期升序
+    pub async fn get_daily_bars(stock_code: &str, days: usize) -> Result<Vec<DailyBar>> {
+        if SecurityId::parse(stock_code).is_some_and(|id| id.is_overseas()) {
+            return Err(AppError::Validation("暂不支持获取港股和美股的日线".to_string()));
+        }
+        Self::fetch_daily_bars(&Self::sina_symbol(stock_code)?, days).await
+    }
+
+    /// 从新浪财经K线接口获取日线，symbol 为带交易所前缀的代码
+    async fn fetch_daily_bars(symbol: &str, days: usize) -> Result<Vec<DailyBar>> {
+        let url = format!(
+            "https://money.finance.sina.com.cn/quotes_service/api/json_v2.php/CN_MarketData.getKLineData?symbol={}&scale=240&ma=no&datalen={}",
+            symbol,
+            days.clamp(1, MAX_DAILY_BARS)
+        );
+
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36")
+            .build()?;
+
+        let response = client.get(&url).send().await?;
+        let json: serde_json::Value = response.json().await?;
+
+        // 格式: [{"day":"2024-01-02","open":"9.390","high":"9.420","low":"9.210","close":"9.210","volume":"..."}]
+        let number = |item: &serde_json::Value, key: &str| -> Option<f64> {
+            item.get(key).and_then(|v| v.as_str()).and_then(|v| v.parse().ok())
+        };
+        let price = |item: &serde_json::Value, key: &str| -> Option<Money> {
+            item.get(key).and_then(|v| v.as_str()).and_then(|v| v.parse().ok())
+        };
+
+        let mut bars = Vec::new();
+        for item in json.as_array().ok_or_else(|| AppError::ProviderParse("无法解析日线数据".to_string()))? {
+            let date = item
+                .get("day")
+                .and_then(|v| v.as_str())
+                .and_then(|v| chrono::NaiveDate::parse_from_str(&v[..v.len().min(10)], "%Y-%m-%d").ok());
+
+            if let (Some(date), Some(open), Some(high), Some(low), Some(close)) = (
+                date,
+                price(item, "open"),
+                price(item, "high"),
+                price(item, "low"),
+                price(item, "close"),
+            ) {
+                bars.push(DailyBar {
+                    date,
+                    open,
+                    high,
+                    low,
+                    close,
+                    volume: number(item, "volume").unwrap_or(0.0) as i64,
+                });
+            }
+        }
+
+        Ok(bars)
+    }
+
+    /// 获取指数实时行情，不使用模拟数据
+    pub async fn get_index_quote(symbol: &str) -> Result<StockInfo> {
+        if !Self::is_benchmark_index(symbol) {
+            return Err(AppError::Validation(format!("不支持的指数代码: {}", symbol)));
+        }
+        Self::fetch_real_stock_info(symbol).await
+    }
+
+    pub fn is_benchmark_index(symbol: &str) -> bool {
+        BENCHMARK_INDICES.iter().any(|(code, _)| *code == symbol)
+    }
+
+    pub fn benchmark_name(symbol: &str) -> Option<&'static str> {
+        BENCHMARK_INDICES
+            .iter()
+            .find(|(code, _)| *code == symbol)
+            .map(|(_, name)| *name)
     }
 
     /// 获取模拟股票信息（作为后备方案）
@@ -227,33 +419,27 @@ impl StockApi {
         let stock_info = StockInfo {
             code: stock_code.to_string(),
             name: name.to_string(),
-            current_price: price,
-            change,
+            current_price: Money::from_f64(price),
+            change: Money::from_f64(change),
             change_percent: (change / price) * 100.0,
-            open,
-            high,
-            low,
+            open: Money::from_f64(open),
+            high: Money::from_f64(high),
+            low: Money::from_f64(low),
             volume: (get_random_f64() * 1000000.0) as i64,
-            turnover: (get_random_f64() * 100000000.0) as i64,
+            turnover: Money::from_f64(get_random_f64() * 100000000.0),
             timestamp: chrono::Utc::now(),
         };
 
         Ok(stock_info)
     }
 
-    /// 验证股票代码格式
+    /// 代码是否为规则表中可交易的沪深北证券
     pub fn validate_stock_code(stock_code: &str) -> bool {
-        // 简单的股票代码验证
-        if stock_code.len() != 6 {
-            return false;
-        }
-
-        // 检查是否全为数字
-        stock_code.chars().all(|c| c.is_ascii_digit())
+        SecurityId::parse(stock_code).map(|id| id.is_tradable()).unwrap_or(false)
     }
 
     /// 获取股票实时价格
-    pub async fn get_stock_price(stock_code: &str) -> Result<f64> {
+    pub async fn get_stock_price(stock_code: &str) -> Result<Money> {
         // 尝试从新浪财经API获取真实股价
         match Self::fetch_real_stock_price(stock_code).await {
             Ok(price) => Ok(price),
@@ -266,10 +452,29 @@ impl StockApi {
     }
 
     /// 从新浪财经API获取真实股价
-    async fn fetch_real_stock_price(stock_code: &str) -> Result<f64> {
-        // 构建新浪财经API URL
-        let formatted_code = Self::format_stock_code_for_sina(stock_code);
-        let url = format!("https://hq.sinajs.cn/list={}", formatted_code);
+    pub(crate) async fn fetch_real_stock_price(stock_code: &str) -> Result<Money> {
+        Ok(Self::fetch_real_stock_info(stock_code).await?.current_price)
+    }
+
+    /// 新浪财经API使用的带交易所前缀的代码，如 bj830799、hk00700、gb_aapl，代码规则表中没有的代码返回错误
+    pub(crate) fn sina_symbol(stock_code: &str) -> Result<String> {
+        SecurityId::parse(stock_code)
+            .map(|id| id.symbol())
+            .ok_or_else(|| AppError::Validation(format!("无法识别的证券代码: {}", stock_code)))
+    }
+
+    /// 从新浪财经外汇行情获取外币兑人民币的最新汇率，不使用模拟数据
+    pub async fn fetch_fx_rates(currencies: &[Currency]) -> Result<Vec<FxRate>> {
+        let symbols: HashMap<String, Currency> = currencies
+            .iter()
+            .filter(|c| **c != Currency::Cny)
+            .map(|c| (format!("fx_s{}cny", c.code().to_lowercase()), *c))
+            .collect();
+        if symbols.is_empty() {
+            return Ok(Vec::new());
+        }
+        let list: Vec<&str> = symbols.keys().map(|s| s.as_str()).collect();
+        let url = format!("https://hq.sinajs.cn/list={}", list.join(","));
 
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(10))
@@ -279,40 +484,46 @@ impl StockApi {
         let response = client.get(&url).send().await?;
         let text = response.text().await?;
 
-        // 解析新浪财经返回的数据
-        // 格式: var hq_str_sh000001="上证指数,3000.00,2990.00,3010.00,3020.00,2980.00,..."
-        if let Some(data_start) = text.find('"') {
-            if let Some(data_end) = text.rfind('"') {
-                let data = &text[data_start + 1..data_end];
-                let parts: Vec<&str> = data.split(',').collect();
-
-                if parts.len() >= 4 {
-                    let current_price: f64 = parts[3].parse()
-                        .map_err(|_| anyhow::anyhow!("无法解析股价数据"))?;
+        // 格式: var hq_str_fx_susdcny="时间,买入价,卖出价,昨收,点差,今开,最高,最低,最新价,名称,...,日期";
+        let today = chrono::Utc::now().with_timezone(&chrono::FixedOffset::east_opt(8 * 3600).unwrap()).date_naive();
+        let mut rates = Vec::new();
+        for line in text.lines() {
+            let (Some(prefix_end), Some(data_start), Some(data_end)) =
+                (line.find("hq_str_"), line.find('"'), line.rfind('"'))
+            else {
+                continue;
+            };
+            if data_end <= data_start {
+                continue;
+            }
 
-                    if current_price > 0.0 {
-                        return Ok(current_price);
-                    }
-                }
+            let symbol = line[prefix_end + "hq_str_".len()..].split('=').next().unwrap_or("");
+            let Some(currency) = symbols.get(symbol) else {
+                continue;
+            };
+            let parts: Vec<&str> = line[data_start + 1..data_end].split(',').collect();
+            let rate = [8, 1]
+                .iter()
+                .filter_map(|i| parts.get(*i).and_then(|v| v.parse::<f64>().ok()))
+                .find(|rate| *rate > 0.0);
+            let rate_date = parts
+                .last()
+                .and_then(|v| chrono::NaiveDate::parse_from_str(v.trim(), "%Y-%m-%d").ok())
+                .unwrap_or(today);
+
+            if let Some(rate) = rate {
+                rates.push(FxRate { currency: *currency, rate_date, rate });
             }
         }
 
-        Err(anyhow::anyhow!("无法解析股价数据"))
-    }
-
-    /// 格式化股票代码为新浪财经API格式
-    fn format_stock_code_for_sina(stock_code: &str) -> String {
-        if stock_code.starts_with("6") {
-            format!("sh{}", stock_code) // 上海交易所
-        } else if stock_code.starts_with("0") || stock_code.starts_with("3") {
-            format!("sz{}", stock_code) // 深圳交易所
-        } else {
-            format!("sh{}", stock_code) // 默认上海交易所
+        if rates.is_empty() {
+            return Err(AppError::ProviderParse("无法解析汇率数据".to_string()));
         }
+        Ok(rates)
     }
 
     /// 获取模拟股价（作为后备方案）
-    fn get_mock_stock_price(stock_code: &str) -> Result<f64> {
+    fn get_mock_stock_price(stock_code: &str) -> Result<Money> {
         let base_price = match stock_code {
             "000001" => 12.50,
             "000002" => 8.80,
@@ -328,7 +539,7 @@ impl StockApi {
         let fluctuation = (get_random_f64() - 0.5) * 0.1; // ±5%的波动
         let current_price = base_price * (1.0 + fluctuation);
 
-        Ok(current_price)
+        Ok(Money::from_f64(current_price).round_to(Money::FEN, Rounding::HalfUp))
     }
 }
 
@@ -347,3 +558,44 @@ fn get_random_f64() -> f64 {
     let hash = hasher.finish();
     (hash as f64) / (u64::MAX as f64)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    /// 新浪行情接口返回的一行中引号内的字段
+    fn fields(line: &str) -> &str {
+        &line[line.find('"').unwrap() + 1..line.rfind('"').unwrap()]
+    }
+
+    #[test]
+    fn parses_hk_and_us_quote_fields() {
+        let hk = r#"var hq_str_hk00700="TENCENT,腾讯控股,368.000,366.400,372.000,365.200,370.800,4.400,1.201,370.600,370.800,5885404651,15938723,0.000,0.000,417.800,260.200,2024/05/10,16:08";"#;
+        let quote = StockApi::parse_sina_quote("hk00700", fields(hk)).unwrap();
+        assert_eq!(quote.name, "腾讯控股");
+        assert_eq!(
+            (quote.open, quote.high, quote.low, quote.current_price),
+            (money("368"), money("372"), money("365.2"), money("370.8"))
+        );
+        assert_eq!(quote.change, money("4.4"));
+        assert_eq!((quote.volume, quote.turnover), (15938723, money("5885404651")));
+
+        let us = r#"var hq_str_gb_aapl="苹果,183.0500,0.93,2024-05-11 08:00:00,1.6900,182.3500,184.2000,180.4200,199.6200,164.0800,50759496,57291024,2823232000000,6.43,28.470000,0.00,0.00,0.96,0.00,15441900000,64,0.0000,0.00,0.00,,May 10 04:00PM EDT,181.3600,0,1,2024,9290834876.0000,182.4000,181.3200,6389.8400,-0.9600,183.0500";"#;
+        let quote = StockApi::parse_sina_quote("gb_aapl", fields(us)).unwrap();
+        assert_eq!(quote.name, "苹果");
+        assert_eq!(
+            (quote.open, quote.high, quote.low, quote.current_price),
+            (money("182.35"), money("184.2"), money("180.42"), money("183.05"))
+        );
+        // 昨收在第 26 个字段，涨跌按现价与昨收计算，美股没有成交额
+        assert_eq!(quote.change, money("1.69"));
+        assert_eq!((quote.volume, quote.turnover), (50759496, Money::ZERO));
+
+        // 字段不全时不返回行情
+        assert!(StockApi::parse_sina_quote("gb_aapl", "苹果,183.0500,0.93").is_none());
+    }
+}
@@ -0,0 +1,534 @@
+use anyhow::Result;
+use chrono::{FixedOffset, Utc};
+use std::collections::HashMap;
+use crate::api::{GrowthSettings, PriceCalculator, PriceSignal, SignalTarget, StopRules, MIN_HOLDING_DAYS};
+use crate::database::Database;
+use crate::error::{AppError, FieldError};
+use crate::money::{Money, Rounding};
+use crate::models::{DailyBar, OverrideScope, StrategyOverride, StrategyParams, Trade};
+use crate::volatility::{self, BuyStep, StepMode, StepSettings};
+
+const DEFAULT_ANNUAL_RETURN_RATE: f64 = 0.20;
+const DEFAULT_BUY_STEP_PERCENTAGE: f64 = 0.05;
+
+/// 预计卖出目标价涨过现价的天数最多向后推算十年
+const MAX_PROJECTION_DAYS: i64 = 3650;
+
+impl StrategyParams {
+    /// 按持有天数计算卖出目标价
+    pub fn sell_target(&self, buy_price: Money, days_held: i64) -> Money {
+        PriceCalculator::calculate_sell_target_price(
+            buy_price,
+            self.annual_return_rate,
+            days_held,
+            self.min_holding_days,
+            GrowthSettings {
+                growth_model: self.growth_model,
+                day_count_basis: self.day_count_basis,
+            },
+        )
+    }
+
+    pub fn buy_target(&self, sell_target: Money) -> Money {
+        PriceCalculator::calculate_buy_target_price(sell_target, self.buy_step_percentage)
+    }
+
+    /// 计算一笔持仓的全部目标价并判断现价触发了哪一个
+    ///
+    /// 目标价按报价单位取整：卖出目标和止损价向上取整，买入目标向下取整，保证不低于设定的收益率。
+    /// 信号的优先顺序见 [`PriceSignal`]，盈亏按 quantity 股计算。
+    pub fn evaluate(
+        &self,
+        buy_price: Money,
+        quantity: i64,
+        days_held: i64,
+        current_price: Option<Money>,
+        high_water_price: Money,
+        tick: Money,
+    ) -> TargetEvaluation {
+        let raw_sell_target = self.sell_target(buy_price, days_held);
+        let sell_target = raw_sell_target.round_to(tick, Rounding::Up);
+        let buy_target = self.buy_target(raw_sell_target).round_to(tick, Rounding::Down);
+        let stop_loss_price = PriceCalculator::calculate_stop_loss_price(buy_price, &self.stops)
+            .map(|price| price.round_to(tick, Rounding::Up));
+        let trailing_stop_price = PriceCalculator::calculate_trailing_stop_price(high_water_price, &self.stops)
+            .map(|price| price.round_to(tick, Rounding::Up));
+        let stop = current_price.and_then(|price| {
+            PriceCalculator::check_stop(buy_price, price, days_held, high_water_price, &self.stops)
+                .map(|(kind, stop_price)| (kind, stop_price.round_to(tick, Rounding::Up)))
+        });
+
+        let signal = match current_price {
+            None => PriceSignal::NoQuote,
+            Some(price) => {
+                let target = |target_price| SignalTarget::new(target_price, price, buy_price, quantity);
+                if price >= sell_target {
+                    PriceSignal::Sell(SignalTarget {
+                        days_until_target_passes: self.days_until_target_passes(buy_price, days_held, price, tick),
+                        ..target(sell_target)
+                    })
+                } else if let Some((reason, stop_price)) = stop {
+                    PriceSignal::Stop {
+                        reason,
+                        target: target(stop_price),
+                    }
+                } else if price <= buy_target {
+                    PriceSignal::Buy(target(buy_target))
+                } else {
+                    PriceSignal::Hold(target(sell_target))
+                }
+            }
+        };
+
+        TargetEvaluation {
+            sell_target,
+            buy_target,
+            stop_loss_price,
+            trailing_stop_price,
+            signal,
+        }
+    }
+
+    /// 按设定的收益率逐日推算，取整后的卖出目标价第一次高于现价时距今的天数
+    fn days_until_target_passes(&self, buy_price: Money, days_held: i64, current_price: Money, tick: Money) -> Option<i64> {
+        (1..=MAX_PROJECTION_DAYS)
+            .find(|days| self.sell_target(buy_price, days_held + days).round_to(tick, Rounding::Up) > current_price)
+    }
+}
+
+/// 一笔持仓的目标价和现价触发的结果
+#[derive(Debug, Clone, Copy)]
+pub struct TargetEvaluation {
+    pub sell_target: Money,
+    pub buy_target: Money,
+    pub stop_loss_price: Option<Money>,
+    pub trailing_stop_price: Option<Money>,
+    pub signal: PriceSignal,
+}
+
+/// 为每笔交易解析策略参数，每个参数按 交易 → 股票 → 账户 → 全局设置 的顺序独立取值
+///
+/// 买入台阶按波动率计算时，交易或股票单独设置的台阶仍然优先，其余情况用已加载的日线波动率替换账户和全局的固定台阶。
+#[derive(Debug, Clone)]
+pub struct StrategyResolver {
+    global: StrategyParams,
+    overrides: HashMap<(OverrideScope, String), StrategyOverride>,
+    step: StepSettings,
+    /// 按股票代码保存的相对波动率，None 表示日线不足
+    volatility: HashMap<String, Option<f64>>,
+}
+
+impl StrategyResolver {
+    pub fn new(global: StrategyParams, overrides: Vec<StrategyOverride>) -> Self {
+        StrategyResolver {
+            global,
+            overrides: overrides
+                .into_iter()
+                .map(|o| ((o.scope, o.scope_key.clone()), o))
+                .collect(),
+            step: StepSettings::default(),
+            volatility: HashMap::new(),
+        }
+    }
+
+    pub fn with_step_settings(mut self, step: StepSettings) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// 用日线计算股票的波动率，供之后解析买入台阶使用
+    pub fn set_bars(&mut self, stock_code: &str, bars: &[DailyBar]) {
+        if self.step.mode != StepMode::Fixed {
+            self.volatility.insert(stock_code.to_string(), self.step.measure(bars));
+        }
+    }
+
+    /// 联网获取这些股票计算波动率所需的日线，固定台阶时不获取；不要在持有数据库锁时调用
+    pub async fn fetch_bars(&self, stock_codes: &[&str]) -> HashMap<String, Vec<DailyBar>> {
+        if self.step.mode == StepMode::Fixed {
+            return HashMap::new();
+        }
+        volatility::fetch_bars(stock_codes, self.step.bars_needed()).await
+    }
+
+    /// 先保存 fetch_bars 取到的日线，再为这些股票读取日线并计算波动率，固定台阶时不读取
+    pub async fn load_bars(
+        &mut self,
+        db: &Database,
+        stock_codes: &[&str],
+        fetched: &HashMap<String, Vec<DailyBar>>,
+    ) -> Result<()> {
+        if self.step.mode == StepMode::Fixed {
+            return Ok(());
+        }
+
+        for (code, bars) in fetched {
+            db.upsert_daily_bars(code, bars).await?;
+        }
+        for code in stock_codes {
+            if !self.volatility.contains_key(*code) {
+                let bars = volatility::recent_bars(db, code, self.step.bars_needed()).await?;
+                self.set_bars(code, &bars);
+            }
+        }
+        Ok(())
+    }
+
+    /// 从数据库读取全局设置和全部覆盖
+    pub async fn load(db: &Database) -> Result<Self> {
+        let setting = |value: Option<String>| value.filter(|v| !v.trim().is_empty());
+
+        let annual_return_rate = setting(db.get_setting("annual_return_rate").await?)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_ANNUAL_RETURN_RATE);
+        let buy_step_percentage = setting(db.get_setting("buy_step_percentage").await?)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(DEFAULT_BUY_STEP_PERCENTAGE);
+        let min_holding_days = setting(db.get_setting("min_holding_days").await?)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(MIN_HOLDING_DAYS);
+        let growth = GrowthSettings::from_settings(
+            db.get_setting("growth_model").await?.as_deref(),
+            db.get_setting("day_count_basis").await?.as_deref(),
+        );
+        // 止损规则默认关闭，全局设置为空时不启用；止损价只能按交易或股票单独设置
+        let stops = StopRules {
+            stop_loss_percent: setting(db.get_setting("stop_loss_percent").await?)
+                .and_then(|v| v.trim().parse().ok()),
+            stop_loss_price: None,
+            time_stop_days: setting(db.get_setting("time_stop_days").await?)
+                .and_then(|v| v.trim().parse().ok()),
+            trailing_stop_percent: setting(db.get_setting("trailing_stop_percent").await?)
+                .and_then(|v| v.trim().parse().ok()),
+        };
+
+        let global = StrategyParams {
+            annual_return_rate,
+            buy_step_percentage,
+            min_holding_days,
+            growth_model: growth.growth_model,
+            day_count_basis: growth.day_count_basis,
+            stops,
+        };
+
+        let step = StepSettings::from_settings(
+            db.get_setting("buy_step_mode").await?.as_deref(),
+            db.get_setting("buy_step_multiplier").await?.as_deref(),
+            db.get_setting("buy_step_floor").await?.as_deref(),
+            db.get_setting("buy_step_ceiling").await?.as_deref(),
+        );
+
+        Ok(Self::new(global, db.get_strategy_overrides().await?).with_step_settings(step))
+    }
+
+    pub fn global(&self) -> StrategyParams {
+        self.global
+    }
+
+    pub fn resolve(&self, trade: &Trade) -> StrategyParams {
+        self.resolve_for(trade.id, &trade.stock_code, &trade.account)
+    }
+
+    /// 尚未有交易记录时（如网格计划）按股票和账户解析
+    pub fn resolve_for(&self, trade_id: Option<i64>, stock_code: &str, account: &str) -> StrategyParams {
+        let chain = self.chain(trade_id, stock_code, account);
+
+        StrategyParams {
+            annual_return_rate: chain
+                .iter()
+                .find_map(|o| o.annual_return_rate)
+                .unwrap_or(self.global.annual_return_rate),
+            buy_step_percentage: self.buy_step_for(trade_id, stock_code, account).step,
+            min_holding_days: chain
+                .iter()
+                .find_map(|o| o.min_holding_days)
+                .unwrap_or(self.global.min_holding_days),
+            stops: StopRules {
+                stop_loss_percent: chain
+                    .iter()
+                    .find_map(|o| o.stop_loss_percent)
+                    .or(self.global.stops.stop_loss_percent),
+                stop_loss_price: chain
+                    .iter()
+                    .find_map(|o| o.stop_loss_price)
+                    .or(self.global.stops.stop_loss_price),
+                time_stop_days: chain
+                    .iter()
+                    .find_map(|o| o.time_stop_days)
+                    .or(self.global.stops.time_stop_days),
+                trailing_stop_percent: chain
+                    .iter()
+                    .find_map(|o| o.trailing_stop_percent)
+                    .or(self.global.stops.trailing_stop_percent),
+            },
+            ..self.global
+        }
+    }
+
+    pub fn buy_step(&self, trade: &Trade) -> BuyStep {
+        self.buy_step_for(trade.id, &trade.stock_code, &trade.account)
+    }
+
+    /// 解析买入台阶并说明来源
+    pub fn buy_step_for(&self, trade_id: Option<i64>, stock_code: &str, account: &str) -> BuyStep {
+        let chain = self.chain(trade_id, stock_code, account);
+        let explicit = chain
+            .iter()
+            .filter(|o| o.scope != OverrideScope::Account)
+            .find_map(|o| o.buy_step_percentage);
+        if let Some(step) = explicit {
+            return BuyStep::fixed(step, format!("单独设置的台阶 {:.2}%", step * 100.0));
+        }
+
+        let fixed_step = chain
+            .iter()
+            .find_map(|o| o.buy_step_percentage)
+            .unwrap_or(self.global.buy_step_percentage);
+        self.step
+            .buy_step(fixed_step, self.volatility.get(stock_code).copied().flatten())
+    }
+
+    fn chain(&self, trade_id: Option<i64>, stock_code: &str, account: &str) -> Vec<&StrategyOverride> {
+        [
+            trade_id.map(|id| (OverrideScope::Trade, id.to_string())),
+            Some((OverrideScope::Stock, stock_code.to_string())),
+            Some((OverrideScope::Account, account.to_string())),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|key| self.overrides.get(&key))
+        .collect()
+    }
+}
+
+/// 更新并返回买入以来的最高价
+///
+/// 第一次记录时用已保存的日线补齐买入后的最高价，之后只随现价上移。
+pub async fn update_high_water_mark(
+    db: &Database,
+    trade: &Trade,
+    current_price: Money,
+    stored: Option<Money>,
+) -> Result<Money> {
+    let previous = match stored {
+        Some(high) => high,
+        None => {
+            let beijing = FixedOffset::east_opt(8 * 3600).unwrap();
+            let bought = trade.buy_time.with_timezone(&beijing).date_naive();
+            let today = Utc::now().with_timezone(&beijing).date_naive();
+            // 买入当天只计买入之后的价格，日线最高价可能出现在买入之前
+            let history = db
+                .get_daily_bars(&trade.stock_code, bought.succ_opt().unwrap_or(bought), today)
+                .await?;
+            history
+                .iter()
+                .map(|bar| bar.high)
+                .fold(trade.buy_price, Money::max)
+        }
+    };
+
+    let high = previous.max(current_price);
+    if stored != Some(high) {
+        if let Some(trade_id) = trade.id {
+            db.set_high_water_mark(trade_id, high).await?;
+        }
+    }
+
+    Ok(high)
+}
+
+/// 检查覆盖参数的取值范围，返回全部不合法的字段
+pub fn validate_override(item: &StrategyOverride) -> Result<()> {
+    let mut errors = Vec::new();
+
+    if item.scope_key.trim().is_empty() {
+        errors.push(FieldError::new("scopeKey", "覆盖对象不能为空"));
+    } else if item.scope == OverrideScope::Trade && item.scope_key.parse::<i64>().is_err() {
+        errors.push(FieldError::new("scopeKey", format!("交易 ID 无效: {}", item.scope_key)));
+    }
+    if item.annual_return_rate.map(|r| r <= 0.0).unwrap_or(false) {
+        errors.push(FieldError::new("annualReturnRate", "年化收益率必须大于0"));
+    }
+    if item.buy_step_percentage.map(|s| !(0.0..1.0).contains(&s)).unwrap_or(false) {
+        errors.push(FieldError::new("buyStepPercentage", "买入台阶必须在0到1之间"));
+    }
+    if item.min_holding_days.map(|d| d < 0).unwrap_or(false) {
+        errors.push(FieldError::new("minHoldingDays", "最短持有天数不能为负数"));
+    }
+    let invalid_percent = |p: Option<f64>| p.map(|p| p <= 0.0 || p >= 1.0).unwrap_or(false);
+    if invalid_percent(item.stop_loss_percent) {
+        errors.push(FieldError::new("stopLossPercent", "止损比例必须在0到1之间"));
+    }
+    if invalid_percent(item.trailing_stop_percent) {
+        errors.push(FieldError::new("trailingStopPercent", "止损比例必须在0到1之间"));
+    }
+    if item.stop_loss_price.map(|p| !p.is_positive()).unwrap_or(false) {
+        errors.push(FieldError::new("stopLossPrice", "止损价必须大于0"));
+    }
+    if item.time_stop_days.map(|d| d <= 0).unwrap_or(false) {
+        errors.push(FieldError::new("timeStopDays", "时间止损天数必须大于0"));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::InvalidFields(errors).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{DayCountBasis, GrowthModel, StopKind, StopRules};
+    use chrono::Utc;
+
+    fn global() -> StrategyParams {
+        StrategyParams {
+            annual_return_rate: 0.2,
+            buy_step_percentage: 0.05,
+            min_holding_days: 30,
+            growth_model: GrowthModel::Simple,
+            day_count_basis: DayCountBasis::Days360,
+            stops: StopRules::default(),
+        }
+    }
+
+    fn trade(id: i64, code: &str, account: &str) -> Trade {
+        Trade {
+            id: Some(id),
+            stock_code: code.to_string(),
+            stock_name: code.to_string(),
+            buy_price: Money::from_yuan(10),
+            buy_time: Utc::now(),
+            quantity: 100,
+            notes: None,
+            account: account.to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        }
+    }
+
+    fn item(scope: OverrideScope, key: &str, rate: Option<f64>, step: Option<f64>, days: Option<i64>) -> StrategyOverride {
+        StrategyOverride {
+            scope,
+            scope_key: key.to_string(),
+            annual_return_rate: rate,
+            buy_step_percentage: step,
+            min_holding_days: days,
+            stop_loss_percent: None,
+            stop_loss_price: None,
+            time_stop_days: None,
+            trailing_stop_percent: None,
+        }
+    }
+
+    #[test]
+    fn resolves_each_parameter_from_most_specific_scope() {
+        let resolver = StrategyResolver::new(
+            global(),
+            vec![
+                item(OverrideScope::Trade, "1", Some(0.5), None, None),
+                item(OverrideScope::Stock, "300750", Some(0.3), Some(0.08), None),
+                item(OverrideScope::Account, "margin", None, Some(0.1), Some(10)),
+            ],
+        );
+
+        let params = resolver.resolve(&trade(1, "300750", "margin"));
+        assert_eq!(params.annual_return_rate, 0.5);
+        assert_eq!(params.buy_step_percentage, 0.08);
+        assert_eq!(params.min_holding_days, 10);
+
+        let params = resolver.resolve(&trade(2, "300750", "default"));
+        assert_eq!(params.annual_return_rate, 0.3);
+        assert_eq!(params.min_holding_days, 30);
+
+        assert_eq!(resolver.resolve(&trade(3, "601398", "default")), global());
+    }
+
+    #[test]
+    fn volatility_step_replaces_account_and_global_steps_only() {
+        let mut resolver = StrategyResolver::new(
+            global(),
+            vec![
+                item(OverrideScope::Stock, "600900", None, Some(0.03), None),
+                item(OverrideScope::Account, "margin", None, Some(0.1), None),
+            ],
+        )
+        .with_step_settings(StepSettings {
+            mode: StepMode::Atr,
+            ..StepSettings::default()
+        });
+        resolver.volatility.insert("300750".to_string(), Some(0.04));
+        resolver.volatility.insert("600900".to_string(), Some(0.01));
+
+        let adaptive = resolver.buy_step(&trade(1, "300750", "margin"));
+        assert_eq!(adaptive.mode, StepMode::Atr);
+        assert!((adaptive.step - 0.08).abs() < 1e-9);
+
+        // 单独设置了台阶的股票不按波动率调整
+        let explicit = resolver.buy_step(&trade(2, "600900", "default"));
+        assert_eq!((explicit.mode, explicit.step), (StepMode::Fixed, 0.03));
+
+        // 没有日线时回退到账户设置的台阶
+        assert_eq!(resolver.resolve(&trade(3, "000001", "margin")).buy_step_percentage, 0.1);
+    }
+
+    #[test]
+    fn stop_takes_priority_over_buy_but_not_sell() {
+        let mut params = global();
+        params.stops.stop_loss_percent = Some(0.08);
+
+        let money = |value: &str| value.parse::<Money>().unwrap();
+        let ten = money("10");
+
+        let kind = |current: &str| params.evaluate(ten, 1000, 10, Some(money(current)), ten, Money::FEN).signal.kind();
+        assert_eq!(kind("9"), "stop");
+        assert_eq!(kind("9.5"), "buy");
+        assert_eq!(kind("10.2"), "sell");
+        assert_eq!(params.evaluate(ten, 1000, 10, None, ten, Money::FEN).signal, PriceSignal::NoQuote);
+
+        params.stops.trailing_stop_percent = Some(0.1);
+        let evaluation = params.evaluate(ten, 1000, 10, Some(money("9.9")), money("11.5"), Money::FEN);
+        assert!(matches!(evaluation.signal, PriceSignal::Stop { reason: StopKind::TrailingStop, .. }));
+    }
+
+    #[test]
+    fn signal_carries_distance_and_projection() {
+        let params = global();
+        let money = |value: &str| value.parse::<Money>().unwrap();
+        let ten = money("10");
+
+        // 卖出目标 10 × (1 + 0.2 / 360 × 30) ≈ 10.1667，向上取整为 10.17
+        let evaluation = params.evaluate(ten, 1000, 10, Some(money("10.5")), ten, Money::FEN);
+        let PriceSignal::Sell(target) = evaluation.signal else {
+            panic!("expected sell signal, got {:?}", evaluation.signal);
+        };
+        assert_eq!(target.target_price, money("10.17"));
+        assert_eq!(target.distance, money("-0.33"));
+        assert!((target.distance_percent + 0.33 / 10.5 * 100.0).abs() < 1e-9);
+        assert_eq!(target.projected_pnl, Money::from_yuan(170));
+        // 持有满 91 天时目标价 10.5056 才高于现价
+        assert_eq!(target.days_until_target_passes, Some(81));
+
+        let hold = params.evaluate(ten, 1000, 10, Some(money("10")), ten, Money::FEN).signal;
+        assert_eq!(hold.target().map(|t| t.distance), Some(money("0.17")));
+        assert_eq!(hold.target().and_then(|t| t.days_until_target_passes), None);
+
+        let json = serde_json::to_value(hold).unwrap();
+        assert_eq!(json["kind"], "none");
+        assert_eq!(json["targetPrice"], 10.17);
+        assert_eq!(json["projectedPnl"], 170.0);
+    }
+
+    #[test]
+    fn rejects_out_of_range_overrides() {
+        let fields = |item: StrategyOverride| match AppError::from(validate_override(&item).unwrap_err()) {
+            AppError::InvalidFields(errors) => errors.into_iter().map(|e| e.field).collect::<Vec<_>>(),
+            other => panic!("unexpected error: {:?}", other),
+        };
+        assert_eq!(fields(item(OverrideScope::Stock, "600000", None, Some(1.5), None)), ["buyStepPercentage"]);
+        assert_eq!(fields(item(OverrideScope::Trade, "abc", Some(-0.1), None, None)), ["scopeKey", "annualReturnRate"]);
+        assert!(validate_override(&item(OverrideScope::Account, "default", Some(0.1), Some(0.05), Some(0))).is_ok());
+    }
+}
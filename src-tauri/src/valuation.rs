@@ -0,0 +1,537 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, Timelike, Utc, Weekday};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::analytics::{self, Valuation};
+use crate::database::{get_database, is_database_initialized};
+use crate::fx::{self, Currency, FxRates};
+use crate::models::{
+    BenchmarkComparison, BenchmarkPeriod, BenchmarkPoint, DailyBar, EquityCurve, EquityPoint, SnapshotReport, Trade,
+    TruncatedHistory, ValuationSnapshot,
+};
+use crate::money::Money;
+use crate::portfolio::build_positions;
+use crate::stock_api::{StockApi, BENCHMARK_INDICES, MAX_DAILY_BARS};
+
+/// 收盘后开始记录当日快照的北京时间，留出行情接口更新日线的时间
+const SNAPSHOT_HOUR: u32 = 15;
+const SNAPSHOT_MINUTE: u32 = 30;
+
+/// 停牌时向前查找最近收盘价的天数
+const PRICE_LOOKBACK_DAYS: i64 = 30;
+
+/// 后台任务：启动时补齐历史快照，之后每个工作日收盘后记录一次
+pub async fn run_daily_snapshots() {
+    // 节假日没有新的日线，同一个目标日期只尝试一次
+    let mut attempted_for: Option<NaiveDate> = None;
+
+    loop {
+        if is_database_initialized() {
+            let target = last_expected_trading_date(beijing_now());
+            if attempted_for != Some(target) {
+                match snapshot_if_due(target).await {
+                    Ok(()) => attempted_for = Some(target),
+                    Err(e) => println!("记录估值快照失败: {}", e),
+                }
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(1800)).await;
+    }
+}
+
+async fn snapshot_if_due(target: NaiveDate) -> Result<()> {
+    let latest = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        db_lock.latest_valuation_date().await?
+    };
+
+    if latest.map(|date| date < target).unwrap_or(true) {
+        record_snapshots(false).await?;
+    }
+
+    Ok(())
+}
+
+/// 拉取缺失的日线并重建快照
+///
+/// full 为 false 时从最近一次快照的日期开始增量更新，否则从第一笔交易开始全部重建。
+/// 行情接口最多返回最近 1023 个交易日，取到的日线达到上限且数据库中也没有更早的日线时，
+/// 在结果中报告该股票实际能取到的最早日期。
+pub async fn record_snapshots(full: bool) -> Result<SnapshotReport> {
+    let (trades, latest) = {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        (db_lock.get_all_trades().await?, db_lock.latest_valuation_date().await?)
+    };
+
+    let Some(first_trade_date) = trades.iter().map(|t| local_date(t.buy_time)).min() else {
+        return Ok(SnapshotReport { trading_days: 0, truncated: Vec::new() });
+    };
+
+    let today = beijing_now().date_naive();
+    let start = match latest {
+        Some(date) if !full => date.max(first_trade_date),
+        _ => first_trade_date,
+    };
+
+    let codes: BTreeSet<&str> = trades.iter().map(|t| t.stock_code.as_str()).collect();
+    let days = (today - start).num_days() as usize + 1;
+
+    // 逐只股票获取日线，失败时使用数据库中已有的历史数据
+    let mut capped: BTreeSet<&str> = BTreeSet::new();
+    for code in &codes {
+        match update_daily_bars(code, days).await {
+            Ok(count) if count >= MAX_DAILY_BARS => {
+                capped.insert(code);
+            }
+            Ok(_) => {}
+            Err(e) => println!("获取 {} 日线失败: {}", code, e),
+        }
+    }
+
+    // 顺带更新基准指数的历史行情，供业绩对比使用
+    for (symbol, _) in BENCHMARK_INDICES {
+        if let Err(e) = update_daily_bars(symbol, days).await {
+            println!("获取指数 {} 日线失败: {}", symbol, e);
+        }
+    }
+
+    let mut bars: HashMap<String, Vec<DailyBar>> = HashMap::new();
+    {
+        let db = get_database()?;
+        let db_lock = db.lock().await;
+        for code in &codes {
+            let history = db_lock
+                .get_daily_bars(code, start - Duration::days(PRICE_LOOKBACK_DAYS), today)
+                .await?;
+            bars.insert(code.to_string(), history);
+        }
+    }
+
+    let truncated = truncated_histories(&trades, start, &capped, &bars);
+
+    let fx_rates = fx::load_fx_rates(trades.iter().any(|t| t.currency() != Currency::Cny)).await?;
+    let missing_fx_rates: BTreeSet<Currency> = trades
+        .iter()
+        .map(|t| t.currency())
+        .filter(|currency| fx_rates.cny_rate(*currency, today).is_none())
+        .collect();
+    if !missing_fx_rates.is_empty() {
+        println!("缺少 {:?} 汇率，相应持仓不记录估值快照", missing_fx_rates);
+    }
+
+    let dates: BTreeSet<NaiveDate> = bars
+        .values()
+        .flatten()
+        .map(|bar| bar.date)
+        .filter(|date| *date >= start && *date <= today)
+        .collect();
+    if dates.is_empty() {
+        return Ok(SnapshotReport { trading_days: 0, truncated });
+    }
+
+    let snapshots: Vec<ValuationSnapshot> = dates
+        .iter()
+        .flat_map(|date| build_snapshots(&trades, *date, &bars, &fx_rates))
+        .collect();
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock.replace_valuation_snapshots(start, &snapshots).await?;
+
+    Ok(SnapshotReport {
+        trading_days: dates.len(),
+        truncated,
+    })
+}
+
+/// 获取最近 days 个交易日的日线并写入数据库，返回取到的日线数量
+pub async fn update_daily_bars(code: &str, days: usize) -> Result<usize> {
+    let bars = StockApi::get_daily_bars(code, days).await?;
+
+    let db = get_database()?;
+    let db_lock = db.lock().await;
+    db_lock.upsert_daily_bars(code, &bars).await?;
+    Ok(bars.len())
+}
+
+/// 取到的日线达到接口上限、且已保存的最早日线晚于需要的第一天的股票
+///
+/// 需要的第一天为该股票第一次买入与本次重建起始日中较晚的一天。
+fn truncated_histories(
+    trades: &[Trade],
+    start: NaiveDate,
+    capped: &BTreeSet<&str>,
+    bars: &HashMap<String, Vec<DailyBar>>,
+) -> Vec<TruncatedHistory> {
+    capped
+        .iter()
+        .filter_map(|code| {
+            let first_buy = trades
+                .iter()
+                .filter(|t| t.stock_code == *code)
+                .map(|t| local_date(t.buy_time))
+                .min()?;
+            let required_from = first_buy.max(start);
+            let available_from = bars.get(*code)?.first()?.date;
+
+            (available_from > required_from).then(|| TruncatedHistory {
+                stock_code: code.to_string(),
+                required_from,
+                available_from,
+            })
+        })
+        .collect()
+}
+
+/// 按某日收盘价估值当日已持有的仓位，停牌股票沿用最近的收盘价，没有行情时按成本计
+///
+/// 收盘价为交易货币，市值和成本折算为人民币，以便与沪深指数对比：成本用买入日汇率，市值用当日汇率。
+/// 缺少汇率的持仓不记录快照。
+pub fn build_snapshots(
+    trades: &[Trade],
+    date: NaiveDate,
+    bars: &HashMap<String, Vec<DailyBar>>,
+    fx: &FxRates,
+) -> Vec<ValuationSnapshot> {
+    let held: Vec<Trade> = trades
+        .iter()
+        .filter(|t| local_date(t.buy_time) <= date)
+        .cloned()
+        .collect();
+
+    let mut costs: HashMap<(String, String), (Currency, Option<Money>)> = HashMap::new();
+    for trade in &held {
+        let cost = fx
+            .cny_rate(trade.currency(), local_date(trade.buy_time))
+            .map(|rate| (trade.buy_price * trade.quantity as i64).mul_f64(rate));
+        costs
+            .entry((trade.account.clone(), trade.stock_code.clone()))
+            .and_modify(|(_, total)| *total = total.zip(cost).map(|(a, b)| a + b))
+            .or_insert((trade.currency(), cost));
+    }
+
+    build_positions(&held)
+        .into_iter()
+        .filter_map(|p| {
+            let close_price = bars
+                .get(&p.stock_code)
+                .and_then(|history| history.iter().rev().find(|bar| bar.date <= date))
+                .map(|bar| bar.close)
+                .unwrap_or(p.average_cost);
+            let (currency, cost) = costs.remove(&(p.account.clone(), p.stock_code.clone()))?;
+            let market_value = (close_price * p.quantity).mul_f64(fx.cny_rate(currency, date)?);
+
+            Some(ValuationSnapshot {
+                snapshot_date: date,
+                market_value,
+                cost: cost?,
+                close_price,
+                quantity: p.quantity,
+                account: p.account,
+                stock_code: p.stock_code,
+                stock_name: p.stock_name,
+            })
+        })
+        .collect()
+}
+
+/// 按日汇总快照得到资金曲线，成本的变化视为当日的外部资金流入
+///
+/// 市值和成本按金额精确汇总后再换算为浮点数计算收益率。
+pub fn build_equity_curve(snapshots: &[ValuationSnapshot]) -> EquityCurve {
+    let mut totals: BTreeMap<NaiveDate, (Money, Money)> = BTreeMap::new();
+    for snapshot in snapshots {
+        let entry = totals.entry(snapshot.snapshot_date).or_default();
+        entry.0 += snapshot.market_value;
+        entry.1 += snapshot.cost;
+    }
+
+    let mut previous_cost = None;
+    let valuations: Vec<Valuation> = totals
+        .iter()
+        .map(|(date, (market_value, cost))| {
+            let flow = previous_cost.map(|prev| *cost - prev).unwrap_or(*cost);
+            previous_cost = Some(*cost);
+            Valuation {
+                date: *date,
+                value: market_value.to_f64(),
+                flow: flow.to_f64(),
+            }
+        })
+        .collect();
+
+    let returns = analytics::daily_returns(&valuations);
+    let mut nav = Vec::with_capacity(valuations.len());
+    let mut points = Vec::with_capacity(valuations.len());
+
+    for (i, (date, (market_value, cost))) in totals.iter().enumerate() {
+        let daily_return = if i == 0 { None } else { Some(returns[i - 1]) };
+        let value = nav.last().copied().unwrap_or(1.0) * (1.0 + daily_return.unwrap_or(0.0));
+        nav.push(value);
+
+        points.push(EquityPoint {
+            date: *date,
+            market_value: *market_value,
+            cost: *cost,
+            pnl: *market_value - *cost,
+            daily_return,
+            nav: value,
+        });
+    }
+
+    let (max_drawdown, range) = analytics::max_drawdown(&nav);
+    let time_weighted_return = analytics::time_weighted_return(&valuations);
+    let annualized_return = match (points.first(), points.last(), time_weighted_return) {
+        (Some(first), Some(last), Some(twr)) => analytics::annualize(twr, (last.date - first.date).num_days()),
+        _ => None,
+    };
+
+    EquityCurve {
+        max_drawdown_start: range.map(|(peak, _)| points[peak].date),
+        max_drawdown_end: range.map(|(_, trough)| points[trough].date),
+        volatility: analytics::annualized_volatility(&returns),
+        points,
+        time_weighted_return,
+        annualized_return,
+        max_drawdown,
+    }
+}
+
+/// 区间的起始日期，All 表示不限制
+pub fn period_start(period: BenchmarkPeriod, today: NaiveDate) -> Option<NaiveDate> {
+    match period {
+        BenchmarkPeriod::OneMonth => today.checked_sub_months(Months::new(1)),
+        BenchmarkPeriod::ThreeMonths => today.checked_sub_months(Months::new(3)),
+        BenchmarkPeriod::SixMonths => today.checked_sub_months(Months::new(6)),
+        BenchmarkPeriod::YearToDate => NaiveDate::from_ymd_opt(today.year(), 1, 1),
+        BenchmarkPeriod::OneYear => today.checked_sub_months(Months::new(12)),
+        BenchmarkPeriod::All => None,
+    }
+}
+
+/// 在组合和指数都有数据的交易日上对齐两条净值曲线，计算超额收益、贝塔和跟踪误差
+pub fn build_benchmark_comparison(
+    benchmark: &str,
+    period: BenchmarkPeriod,
+    curve: &EquityCurve,
+    benchmark_bars: &[DailyBar],
+) -> BenchmarkComparison {
+    let closes: HashMap<NaiveDate, f64> = benchmark_bars
+        .iter()
+        .filter(|bar| bar.close.is_positive())
+        .map(|bar| (bar.date, bar.close.to_f64()))
+        .collect();
+    let aligned: Vec<(NaiveDate, f64, f64)> = curve
+        .points
+        .iter()
+        .filter_map(|p| closes.get(&p.date).map(|close| (p.date, p.nav, *close)))
+        .collect();
+
+    let mut portfolio_returns = Vec::new();
+    let mut benchmark_returns = Vec::new();
+    for pair in aligned.windows(2) {
+        portfolio_returns.push(pair[1].1 / pair[0].1 - 1.0);
+        benchmark_returns.push(pair[1].2 / pair[0].2 - 1.0);
+    }
+
+    let points: Vec<BenchmarkPoint> = match aligned.first() {
+        Some((_, first_nav, first_close)) => aligned
+            .iter()
+            .map(|(date, nav, close)| BenchmarkPoint {
+                date: *date,
+                portfolio_nav: nav / first_nav,
+                benchmark_nav: close / first_close,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let portfolio_return = points.last().map(|p| p.portfolio_nav - 1.0).unwrap_or(0.0);
+    let benchmark_return = points.last().map(|p| p.benchmark_nav - 1.0).unwrap_or(0.0);
+
+    BenchmarkComparison {
+        benchmark: benchmark.to_string(),
+        benchmark_name: StockApi::benchmark_name(benchmark).unwrap_or(benchmark).to_string(),
+        period,
+        start_date: points.first().map(|p| p.date),
+        end_date: points.last().map(|p| p.date),
+        portfolio_return,
+        benchmark_return,
+        excess_return: portfolio_return - benchmark_return,
+        beta: analytics::beta(&portfolio_returns, &benchmark_returns),
+        tracking_error: analytics::tracking_error(&portfolio_returns, &benchmark_returns),
+        points,
+    }
+}
+
+/// 最近一个应当已有收盘数据的工作日
+fn last_expected_trading_date(now: DateTime<FixedOffset>) -> NaiveDate {
+    let closed = (now.hour(), now.minute()) >= (SNAPSHOT_HOUR, SNAPSHOT_MINUTE);
+    let mut date = if closed {
+        now.date_naive()
+    } else {
+        now.date_naive() - Duration::days(1)
+    };
+
+    while matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+        date -= Duration::days(1);
+    }
+    date
+}
+
+fn beijing_now() -> DateTime<FixedOffset> {
+    Utc::now().with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap())
+}
+
+fn local_date(time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()).date_naive()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn money(value: &str) -> Money {
+        value.parse().unwrap()
+    }
+
+    fn trade(code: &str, price: &str, day: u32, quantity: i32) -> Trade {
+        Trade {
+            id: None,
+            stock_code: code.to_string(),
+            stock_name: code.to_string(),
+            buy_price: money(price),
+            // 北京时间上午十点买入
+            buy_time: Utc.with_ymd_and_hms(2024, 1, day, 2, 0, 0).unwrap(),
+            quantity,
+            notes: None,
+            account: "default".to_string(),
+            security: None,
+            currency: None,
+            created_at: None,
+        }
+    }
+
+    fn bar(day: u32, close: f64) -> DailyBar {
+        let close = Money::from_f64(close);
+        DailyBar {
+            date: date(day),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 0,
+        }
+    }
+
+    fn snapshot(day: u32, market_value: &str, cost: &str) -> ValuationSnapshot {
+        ValuationSnapshot {
+            snapshot_date: date(day),
+            account: "default".to_string(),
+            stock_code: "600000".to_string(),
+            stock_name: "600000".to_string(),
+            quantity: 1000,
+            close_price: Money::ZERO,
+            market_value: money(market_value),
+            cost: money(cost),
+        }
+    }
+
+    #[test]
+    fn snapshots_use_latest_close_and_fall_back_to_cost() {
+        let trades = [
+            trade("600000", "10", 2, 1000),
+            trade("000001", "9", 3, 500),
+            trade("600000", "11", 4, 1000),
+        ];
+        // 600000 在 4 日停牌，000001 没有日线
+        let bars = HashMap::from([("600000".to_string(), vec![bar(2, 10.2), bar(3, 10.4)])]);
+
+        let first = build_snapshots(&trades, date(2), &bars, &FxRates::default());
+        assert_eq!(first.len(), 1);
+        assert_eq!((first[0].quantity, first[0].close_price), (1000, money("10.2")));
+        assert_eq!((first[0].market_value, first[0].cost), (money("10200"), money("10000")));
+
+        let later = build_snapshots(&trades, date(4), &bars, &FxRates::default());
+        let spdb = later.iter().find(|s| s.stock_code == "600000").unwrap();
+        assert_eq!((spdb.quantity, spdb.close_price), (2000, money("10.4")));
+        assert_eq!((spdb.market_value, spdb.cost), (money("20800"), money("21000")));
+
+        let unpriced = later.iter().find(|s| s.stock_code == "000001").unwrap();
+        assert_eq!(unpriced.close_price, money("9"));
+        assert_eq!(unpriced.market_value, unpriced.cost);
+    }
+
+    #[test]
+    fn snapshots_convert_foreign_positions_to_cny() {
+        use crate::models::FxRate;
+
+        let rate = |day: u32, rate: f64| FxRate { currency: Currency::Hkd, rate_date: date(day), rate };
+        let fx = FxRates::new(&[rate(2, 0.9), rate(4, 0.92)]);
+        let trades = [trade("hk00700", "300", 2, 100), trade("gb_aapl", "180", 2, 10)];
+        let bars = HashMap::from([("hk00700".to_string(), vec![bar(4, 320.0)])]);
+
+        // 成本按买入日汇率、市值按当日汇率折算，没有美元汇率的持仓不记录
+        let snapshots = build_snapshots(&trades, date(4), &bars, &fx);
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].close_price, money("320"));
+        assert_eq!((snapshots[0].market_value, snapshots[0].cost), (money("29440"), money("27000")));
+    }
+
+    #[test]
+    fn equity_curve_treats_cost_changes_as_flows() {
+        let snapshots = [
+            snapshot(2, "10000", "10000"),
+            snapshot(3, "11000", "10000"),
+            // 加仓 10000，当日收益 = 20900 / (11000 + 10000) - 1
+            snapshot(4, "20900", "20000"),
+            snapshot(5, "23100", "20000"),
+        ];
+
+        let curve = build_equity_curve(&snapshots);
+        assert_eq!(curve.points.len(), 4);
+        assert_eq!(curve.points[0].daily_return, None);
+        assert_eq!(curve.points[0].nav, 1.0);
+        assert_eq!(curve.points[2].pnl, money("900"));
+
+        let returns = [0.1, 20900.0 / 21000.0 - 1.0, 23100.0 / 20900.0 - 1.0];
+        let mut nav = 1.0;
+        for (point, expected) in curve.points[1..].iter().zip(returns) {
+            nav *= 1.0 + expected;
+            assert!((point.daily_return.unwrap() - expected).abs() < 1e-12);
+            assert!((point.nav - nav).abs() < 1e-12);
+        }
+
+        assert!((curve.time_weighted_return.unwrap() - (nav - 1.0)).abs() < 1e-12);
+        assert!((curve.max_drawdown - (1.0 - 20900.0 / 21000.0)).abs() < 1e-12);
+        assert_eq!((curve.max_drawdown_start, curve.max_drawdown_end), (Some(date(3)), Some(date(4))));
+    }
+
+    #[test]
+    fn reports_histories_cut_by_the_provider_limit() {
+        let trades = [trade("600000", "10", 2, 1000), trade("000001", "9", 2, 1000), trade("000002", "8", 2, 1000)];
+        let bars = HashMap::from([
+            ("600000".to_string(), vec![bar(8, 10.0)]),
+            // 之前导入过更早的日线
+            ("000001".to_string(), vec![bar(2, 9.0), bar(8, 9.0)]),
+            ("000002".to_string(), vec![bar(8, 8.0)]),
+        ]);
+        // 000002 取到的日线没有达到上限，说明更早的日期本来就没有行情
+        let capped = BTreeSet::from(["600000", "000001"]);
+
+        let truncated = truncated_histories(&trades, date(1), &capped, &bars);
+        assert_eq!(truncated.len(), 1);
+        assert_eq!(truncated[0].stock_code, "600000");
+        assert_eq!((truncated[0].required_from, truncated[0].available_from), (date(2), date(8)));
+
+        // 增量更新只需要起始日之后的日线
+        assert!(truncated_histories(&trades, date(9), &capped, &bars).is_empty());
+    }
+}
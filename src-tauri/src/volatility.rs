@@ -0,0 +1,332 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use crate::database::Database;
+use crate::error;
+use crate::models::DailyBar;
+use crate::stock_api::StockApi;
+
+/// 平均真实波幅的周期
+pub const ATR_PERIOD: usize = 14;
+
+/// 历史波动率的收益率窗口
+pub const HV_WINDOW: usize = 20;
+
+const DEFAULT_MULTIPLIER: f64 = 2.0;
+const DEFAULT_FLOOR: f64 = 0.02;
+const DEFAULT_CEILING: f64 = 0.15;
+
+/// 买入台阶的确定方式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StepMode {
+    /// 使用设置的固定台阶
+    #[default]
+    Fixed,
+    /// 倍数 × ATR(14) ÷ 收盘价
+    Atr,
+    /// 倍数 × 20 日对数收益率标准差
+    HistoricalVolatility,
+}
+
+impl FromStr for StepMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fixed" => Ok(StepMode::Fixed),
+            "atr" => Ok(StepMode::Atr),
+            "historical_volatility" | "hv" => Ok(StepMode::HistoricalVolatility),
+            _ => Err(anyhow::anyhow!("未知的买入台阶方式: {}", s)),
+        }
+    }
+}
+
+/// 按波动率计算买入台阶的设置
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct StepSettings {
+    pub mode: StepMode,
+    pub multiplier: f64,
+    /// 台阶下限
+    pub floor: f64,
+    /// 台阶上限
+    pub ceiling: f64,
+}
+
+impl Default for StepSettings {
+    fn default() -> Self {
+        StepSettings {
+            mode: StepMode::Fixed,
+            multiplier: DEFAULT_MULTIPLIER,
+            floor: DEFAULT_FLOOR,
+            ceiling: DEFAULT_CEILING,
+        }
+    }
+}
+
+/// 实际使用的买入台阶及其来源
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BuyStep {
+    /// 实际采用的方式，日线不足时回退为固定台阶
+    pub mode: StepMode,
+    pub step: f64,
+    /// 相对波动率（日）
+    pub volatility: Option<f64>,
+    pub reason: String,
+}
+
+impl BuyStep {
+    pub fn fixed(step: f64, reason: impl Into<String>) -> Self {
+        BuyStep {
+            mode: StepMode::Fixed,
+            step,
+            volatility: None,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl StepSettings {
+    /// 从配置项读取，缺失或无法识别的值使用默认值
+    pub fn from_settings(
+        mode: Option<&str>,
+        multiplier: Option<&str>,
+        floor: Option<&str>,
+        ceiling: Option<&str>,
+    ) -> Self {
+        let number = |value: Option<&str>| value.and_then(|v| v.trim().parse::<f64>().ok()).filter(|v| *v > 0.0);
+        let defaults = StepSettings::default();
+        let floor = number(floor).unwrap_or(defaults.floor);
+
+        StepSettings {
+            mode: mode.and_then(|s| s.trim().parse().ok()).unwrap_or_default(),
+            multiplier: number(multiplier).unwrap_or(defaults.multiplier),
+            floor,
+            ceiling: number(ceiling).unwrap_or(defaults.ceiling).max(floor),
+        }
+    }
+
+    /// 计算波动率需要的日线数量
+    pub fn bars_needed(&self) -> usize {
+        match self.mode {
+            StepMode::Fixed => 0,
+            StepMode::Atr => ATR_PERIOD + 1,
+            StepMode::HistoricalVolatility => HV_WINDOW + 1,
+        }
+    }
+
+    /// 按设置的方式计算相对波动率，日线不足时返回 None
+    pub fn measure(&self, bars: &[DailyBar]) -> Option<f64> {
+        match self.mode {
+            StepMode::Fixed => None,
+            StepMode::Atr => average_true_range(bars, ATR_PERIOD)
+                .zip(bars.last())
+                .filter(|(_, last)| last.close.is_positive())
+                .map(|(atr, last)| atr / last.close.to_f64()),
+            StepMode::HistoricalVolatility => historical_volatility(bars, HV_WINDOW),
+        }
+    }
+
+    /// 由相对波动率得到买入台阶，限制在上下限之间；没有波动率时使用固定台阶
+    pub fn buy_step(&self, fixed_step: f64, volatility: Option<f64>) -> BuyStep {
+        let name = match self.mode {
+            StepMode::Fixed => return BuyStep::fixed(fixed_step, format!("固定台阶 {:.2}%", fixed_step * 100.0)),
+            StepMode::Atr => format!("ATR({})", ATR_PERIOD),
+            StepMode::HistoricalVolatility => format!("{}日历史波动率", HV_WINDOW),
+        };
+
+        let Some(volatility) = volatility else {
+            return BuyStep::fixed(
+                fixed_step,
+                format!(
+                    "日线不足 {} 根，无法计算{}，使用固定台阶 {:.2}%",
+                    self.bars_needed(),
+                    name,
+                    fixed_step * 100.0
+                ),
+            );
+        };
+
+        let raw = self.multiplier * volatility;
+        let step = raw.clamp(self.floor, self.ceiling);
+        let mut reason = format!(
+            "{} {:.2}% × {} = {:.2}%",
+            name,
+            volatility * 100.0,
+            self.multiplier,
+            raw * 100.0
+        );
+        if raw < self.floor {
+            reason.push_str(&format!("，低于下限，使用 {:.2}%", step * 100.0));
+        } else if raw > self.ceiling {
+            reason.push_str(&format!("，高于上限，使用 {:.2}%", step * 100.0));
+        }
+
+        BuyStep {
+            mode: self.mode,
+            step,
+            volatility: Some(volatility),
+            reason,
+        }
+    }
+}
+
+/// Wilder 平均真实波幅，首个值为前 period 个真实波幅的均值，之后按 1/period 平滑
+pub fn average_true_range(bars: &[DailyBar], period: usize) -> Option<f64> {
+    if period == 0 || bars.len() < period + 1 {
+        return None;
+    }
+
+    let true_ranges: Vec<f64> = bars
+        .windows(2)
+        .map(|pair| {
+            let (prev, bar) = (&pair[0], &pair[1]);
+            (bar.high - bar.low)
+                .max((bar.high - prev.close).abs())
+                .max((bar.low - prev.close).abs())
+                .to_f64()
+        })
+        .collect();
+
+    let initial = true_ranges[..period].iter().sum::<f64>() / period as f64;
+    Some(
+        true_ranges[period..]
+            .iter()
+            .fold(initial, |atr, tr| (atr * (period - 1) as f64 + tr) / period as f64),
+    )
+}
+
+/// 最近 window 个交易日对数收益率的样本标准差（日波动率，未年化）
+pub fn historical_volatility(bars: &[DailyBar], window: usize) -> Option<f64> {
+    if window < 2 || bars.len() < window + 1 {
+        return None;
+    }
+
+    let returns: Vec<f64> = bars[bars.len() - window - 1..]
+        .windows(2)
+        .filter(|pair| pair[0].close.is_positive() && pair[1].close.is_positive())
+        .map(|pair| pair[1].close.ratio(pair[0].close).ln())
+        .collect();
+    if returns.len() < window {
+        return None;
+    }
+
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+/// 联网获取这些股票最近 count 根日线，失败的股票不返回；不访问数据库，调用时不要持有数据库锁
+pub async fn fetch_bars(stock_codes: &[&str], count: usize) -> HashMap<String, Vec<DailyBar>> {
+    let mut fetched = HashMap::new();
+    for code in stock_codes {
+        match StockApi::get_daily_bars(code, count).await {
+            Ok(bars) => {
+                fetched.insert(code.to_string(), bars);
+            }
+            Err(e) => println!("获取 {} 日线失败，使用已保存的日线: {}", code, e),
+        }
+    }
+    fetched
+}
+
+/// 读取最近保存的 count 根日线，不限日期，从 CSV 导入的历史日线同样使用
+pub async fn recent_bars(db: &Database, stock_code: &str, count: usize) -> error::Result<Vec<DailyBar>> {
+    db.get_latest_daily_bars(stock_code, count).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, NaiveDate};
+    use crate::money::Money;
+
+    fn bars(closes: &[f64], range: f64) -> Vec<DailyBar> {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| DailyBar {
+                date: start + Duration::days(i as i64),
+                open: Money::from_f64(close),
+                high: Money::from_f64(close + range / 2.0),
+                low: Money::from_f64(close - range / 2.0),
+                close: Money::from_f64(close),
+                volume: 0,
+            })
+            .collect()
+    }
+
+    fn settings(mode: StepMode) -> StepSettings {
+        StepSettings {
+            mode,
+            ..StepSettings::default()
+        }
+    }
+
+    #[test]
+    fn atr_of_constant_range_equals_range() {
+        let history = bars(&[10.0; 30], 0.4);
+        let atr = average_true_range(&history, ATR_PERIOD).unwrap();
+        assert!((atr - 0.4).abs() < 1e-9);
+
+        // 跳空时真实波幅包含与前收盘价的差
+        let gap = bars(&[10.0, 11.0], 0.2);
+        assert!((average_true_range(&gap, 1).unwrap() - 1.1).abs() < 1e-9);
+
+        assert!(average_true_range(&history[..ATR_PERIOD], ATR_PERIOD).is_none());
+    }
+
+    #[test]
+    fn historical_volatility_of_alternating_returns() {
+        let flat = bars(&[10.0; 21], 0.0);
+        assert_eq!(historical_volatility(&flat, HV_WINDOW), Some(0.0));
+
+        let closes: Vec<f64> = (0..21).map(|i| if i % 2 == 0 { 10.0 } else { 10.5 }).collect();
+        let hv = historical_volatility(&bars(&closes, 0.0), HV_WINDOW).unwrap();
+        let r = (10.5f64 / 10.0).ln();
+        // 收益率在 ±r 之间交替，均值为 0，样本标准差为 r × √(20/19)
+        assert!((hv - r * (20.0f64 / 19.0).sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn step_is_clamped_and_explained() {
+        let atr = settings(StepMode::Atr);
+        let step = atr.buy_step(0.05, Some(0.03));
+        assert!((step.step - 0.06).abs() < 1e-9);
+        assert_eq!(step.mode, StepMode::Atr);
+
+        let low = atr.buy_step(0.05, Some(0.005));
+        assert_eq!(low.step, atr.floor);
+        assert!(low.reason.contains("下限"));
+
+        let high = atr.buy_step(0.05, Some(0.2));
+        assert_eq!(high.step, atr.ceiling);
+        assert!(high.reason.contains("上限"));
+    }
+
+    #[test]
+    fn falls_back_to_fixed_step_without_enough_bars() {
+        let hv = settings(StepMode::HistoricalVolatility);
+        let history = bars(&[10.0; 10], 0.2);
+
+        let step = hv.buy_step(0.05, hv.measure(&history));
+        assert_eq!(step.mode, StepMode::Fixed);
+        assert_eq!(step.step, 0.05);
+        assert!(step.reason.contains("日线不足"));
+    }
+
+    #[test]
+    fn parses_settings_with_defaults() {
+        let parsed = StepSettings::from_settings(Some("atr"), Some("1.5"), Some("0.03"), Some("0.01"));
+        assert_eq!(parsed.mode, StepMode::Atr);
+        assert_eq!(parsed.multiplier, 1.5);
+        // 上限不低于下限
+        assert_eq!(parsed.ceiling, 0.03);
+
+        assert_eq!(StepSettings::from_settings(None, Some("abc"), None, None), StepSettings::default());
+    }
+}
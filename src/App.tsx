@@ -8,6 +8,8 @@ import { PriceAlerts } from "./components/PriceAlerts";
 import { ErrorBoundary } from "./components/ErrorBoundary";
 import { StockSelectTest } from "./components/StockSelectTest";
 import { useTauri } from "./hooks/useTauri";
+import { PriceCalculator } from "./utils/priceCalculator";
+import { describeError } from "./utils/appError";
 import "./App.css";
 
 function App() {
@@ -51,10 +53,14 @@ function App() {
 
   const loadSettings = async () => {
     try {
-      // 这里将来会调用 Tauri 命令加载设置
-      // const buyStep = await invoke<string>("get_setting", { key: "buy_step_percentage" });
-      // const annualReturn = await invoke<string>("get_setting", { key: "annual_return_rate" });
-      // 暂时使用默认设置
+      // 策略参数保存在后端，价格计算和提醒都以后端设置为准
+      const buyStep = parseFloat((await tauri.getSetting("buy_step_percentage")) ?? "");
+      const annualReturn = parseFloat((await tauri.getSetting("annual_return_rate")) ?? "");
+      setSettings((current) => ({
+        ...current,
+        buyStepPercentage: Number.isFinite(buyStep) ? buyStep : current.buyStepPercentage,
+        annualReturnRate: Number.isFinite(annualReturn) ? annualReturn : current.annualReturnRate,
+      }));
     } catch (error) {
       console.error("加载设置失败:", error);
     }
@@ -84,6 +90,7 @@ function App() {
       setShowTradeForm(false);
     } catch (error) {
       console.error("添加交易记录失败:", error);
+      alert(`添加交易记录失败：\n${describeError(error)}`);
     }
   };
 
@@ -112,6 +119,7 @@ function App() {
       setShowTradeForm(false);
     } catch (error) {
       console.error("更新交易记录失败:", error);
+      alert(`更新交易记录失败：\n${describeError(error)}`);
     }
   };
 
@@ -162,8 +170,7 @@ function App() {
         buyTargetPrice: buyTarget,
         daysSincePurchase: daysHeld,
         currentPrice,
-        priceReached: currentPrice >= sellTarget ? 'sell' :
-                     currentPrice <= buyTarget ? 'buy' : 'none',
+        signal: PriceCalculator.checkPriceSignal(currentPrice, sellTarget, buyTarget, trade, settings.annualReturnRate),
       };
 
       setPriceCalculations(prev => ({
@@ -177,9 +184,8 @@ function App() {
 
   const handleSaveSettings = async (newSettings: SettingsType) => {
     try {
-      // 这里将来会调用 Tauri 命令保存设置
-      // await invoke("set_setting", { key: "buy_step_percentage", value: newSettings.buyStepPercentage.toString() });
-      // await invoke("set_setting", { key: "annual_return_rate", value: newSettings.annualReturnRate.toString() });
+      await tauri.setSetting("buy_step_percentage", newSettings.buyStepPercentage.toString());
+      await tauri.setSetting("annual_return_rate", newSettings.annualReturnRate.toString());
 
       setSettings(newSettings);
       setShowSettings(false);
@@ -1,6 +1,5 @@
 import React, { useState, useEffect } from 'react';
-import { Trade, Settings } from '../types';
-import { PriceCalculationService } from '../services/priceCalculationService';
+import { Trade, Settings, PortfolioSummary, Currency } from '../types';
 import { useTauri } from '../hooks/useTauri';
 
 interface PortfolioAnalysisProps {
@@ -14,45 +13,20 @@ export const PortfolioAnalysis: React.FC<PortfolioAnalysisProps> = ({
   settings,
   onRefresh,
 }) => {
-  const [analysis, setAnalysis] = useState<any>(null);
+  const [analysis, setAnalysis] = useState<PortfolioSummary | null>(null);
   const [isLoading, setIsLoading] = useState(false);
   
   const tauri = useTauri();
-  const calculationService = new PriceCalculationService(settings);
 
-  // 获取所有股票的当前价格
+  // 由后端批量获取行情并计算组合分析
   const fetchCurrentPrices = async () => {
     setIsLoading(true);
-    const prices: Record<string, number> = {};
     
     try {
-      // 获取所有唯一的股票代码
-      const uniqueStockCodes = [...new Set(trades.map(trade => trade.stockCode))];
-      
-      // 并发获取所有股价
-      const pricePromises = uniqueStockCodes.map(async (code) => {
-        try {
-          const stockInfo = await tauri.getStockInfo(code);
-          return { code, price: stockInfo.currentPrice };
-        } catch (error) {
-          console.error(`获取股价失败: ${code}`, error);
-          return { code, price: 0 };
-        }
-      });
-      
-      const results = await Promise.all(pricePromises);
-      results.forEach(({ code, price }) => {
-        prices[code] = price;
-      });
-      
-
-      
-      // 计算分析结果
-      const portfolioAnalysis = calculationService.analyzePortfolio(trades, prices);
-      setAnalysis(portfolioAnalysis);
-      
+      const summary = await tauri.getPortfolioSummary();
+      setAnalysis(summary);
     } catch (error) {
-      console.error('获取股价失败:', error);
+      console.error('投资组合分析失败:', error);
     } finally {
       setIsLoading(false);
     }
@@ -64,8 +38,10 @@ export const PortfolioAnalysis: React.FC<PortfolioAnalysisProps> = ({
     }
   }, [trades, settings]);
 
-  const formatCurrency = (amount: number) => {
-    return `¥${amount.toLocaleString('zh-CN', { minimumFractionDigits: 2, maximumFractionDigits: 2 })}`;
+  const CURRENCY_SYMBOLS: Record<Currency, string> = { CNY: '¥', HKD: 'HK$', USD: 'US$' };
+
+  const formatCurrency = (amount: number, currency: Currency = 'CNY') => {
+    return `${CURRENCY_SYMBOLS[currency]}${amount.toLocaleString('zh-CN', { minimumFractionDigits: 2, maximumFractionDigits: 2 })}`;
   };
 
   const formatPercent = (percent: number) => {
@@ -117,7 +93,9 @@ export const PortfolioAnalysis: React.FC<PortfolioAnalysisProps> = ({
     );
   }
 
-  const { overview, stockSummary } = analysis;
+  const overview = analysis;
+  const base = overview.baseCurrency;
+  const hasForeign = analysis.positions.some((p) => p.currency !== base);
 
   return (
     <div className="portfolio-analysis">
@@ -145,25 +123,57 @@ export const PortfolioAnalysis: React.FC<PortfolioAnalysisProps> = ({
         <div className="overview-grid">
           <div className="overview-item">
             <span className="label">总投资金额</span>
-            <span className="value">{formatCurrency(overview.totalInvestment)}</span>
+            <span className="value">{formatCurrency(overview.totalCost, base)}</span>
           </div>
           <div className="overview-item">
             <span className="label">当前市值</span>
-            <span className="value">{formatCurrency(overview.totalCurrentValue)}</span>
+            <span className="value">{formatCurrency(overview.marketValue, base)}</span>
           </div>
           <div className="overview-item">
             <span className="label">浮动盈亏</span>
-            <span className={`value ${getPercentColorClass(overview.totalUnrealizedGain)}`}>
-              {formatCurrency(overview.totalUnrealizedGain)}
+            <span className={`value ${getPercentColorClass(overview.unrealizedPnl)}`}>
+              {formatCurrency(overview.unrealizedPnl, base)}
             </span>
           </div>
           <div className="overview-item">
             <span className="label">收益率</span>
-            <span className={`value ${getPercentColorClass(overview.totalUnrealizedGainPercent)}`}>
-              {formatPercent(overview.totalUnrealizedGainPercent)}
+            <span className={`value ${getPercentColorClass(overview.unrealizedPnlPercent)}`}>
+              {formatPercent(overview.unrealizedPnlPercent)}
+            </span>
+          </div>
+          <div className="overview-item">
+            <span className="label">当日盈亏</span>
+            <span className={`value ${getPercentColorClass(overview.dayChange)}`}>
+              {formatCurrency(overview.dayChange, base)} ({formatPercent(overview.dayChangePercent)})
             </span>
           </div>
+          {hasForeign && (
+            <>
+              <div className="overview-item">
+                <span className="label">价格盈亏</span>
+                <span className={`value ${getPercentColorClass(overview.pricePnl)}`}>
+                  {formatCurrency(overview.pricePnl, base)}
+                </span>
+              </div>
+              <div className="overview-item">
+                <span className="label">汇兑盈亏</span>
+                <span className={`value ${getPercentColorClass(overview.fxPnl)}`}>
+                  {formatCurrency(overview.fxPnl, base)}
+                </span>
+              </div>
+            </>
+          )}
         </div>
+        {overview.missingFxRates.length > 0 && (
+          <p className="fx-missing">
+            缺少 {overview.missingFxRates.join('、')} 汇率，相关持仓未计入合计
+          </p>
+        )}
+        {overview.missingQuotes.length > 0 && (
+          <p className="fx-missing">
+            {overview.missingQuotes.join('、')} 暂时取不到行情，市值按成本计算
+          </p>
+        )}
         
         <div className="signals-summary">
           <div className="signal-item sell">
@@ -174,6 +184,10 @@ export const PortfolioAnalysis: React.FC<PortfolioAnalysisProps> = ({
             <span className="signal-count">{overview.buySignals}</span>
             <span className="signal-label">买入信号</span>
           </div>
+          <div className="signal-item stop">
+            <span className="signal-count">{overview.stopSignals}</span>
+            <span className="signal-label">止损信号</span>
+          </div>
           <div className="signal-item total">
             <span className="signal-count">{overview.totalTrades}</span>
             <span className="signal-label">总交易数</span>
@@ -197,41 +211,47 @@ export const PortfolioAnalysis: React.FC<PortfolioAnalysisProps> = ({
                 <th>平均成本</th>
                 <th>当前价格</th>
                 <th>市值</th>
+                <th>占比</th>
+                <th>当日涨跌</th>
                 <th>盈亏</th>
                 <th>收益率</th>
                 <th>信号</th>
               </tr>
             </thead>
             <tbody>
-              {stockSummary.map((stock: any) => (
-                <tr key={stock.code}>
+              {analysis.positions.map((stock) => (
+                <tr key={`${stock.account}-${stock.stockCode}`}>
                   <td>
                     <div className="stock-info">
-                      <span className="stock-code">{stock.code}</span>
-                      <span className="stock-name">{stock.name}</span>
+                      <span className="stock-code">{stock.stockCode}</span>
+                      <span className="stock-name">{stock.stockName}</span>
                     </div>
                   </td>
-                  <td>{stock.totalQuantity.toLocaleString()}</td>
-                  <td>{formatCurrency(stock.averagePrice)}</td>
+                  <td>{stock.quantity.toLocaleString()}</td>
+                  <td>{formatCurrency(stock.averageCost, stock.currency)}</td>
                   <td>
-                    {stock.currentPrice ? formatCurrency(stock.currentPrice) : '-'}
+                    {stock.currentPrice ? formatCurrency(stock.currentPrice, stock.currency) : '-'}
+                  </td>
+                  <td>{formatCurrency(stock.marketValue, stock.currency)}</td>
+                  <td>{stock.weight.toFixed(2)}%</td>
+                  <td className={getPercentColorClass(stock.dayChange)}>
+                    {formatCurrency(stock.dayChange, stock.currency)}
                   </td>
-                  <td>{formatCurrency(stock.totalValue)}</td>
-                  <td className={getPercentColorClass(stock.unrealizedGain)}>
-                    {formatCurrency(stock.unrealizedGain)}
+                  <td className={getPercentColorClass(stock.unrealizedPnl)}>
+                    {formatCurrency(stock.unrealizedPnl, stock.currency)}
                   </td>
-                  <td className={getPercentColorClass(stock.unrealizedGainPercent)}>
-                    {formatPercent(stock.unrealizedGainPercent)}
+                  <td className={getPercentColorClass(stock.unrealizedPnlPercent)}>
+                    {formatPercent(stock.unrealizedPnlPercent)}
                   </td>
                   <td>
                     <div className="signals">
-                      {stock.signals.map((signal: any, index: number) => (
+                      {stock.signals.map((signal, index) => (
                         <span 
                           key={index}
-                          className={`signal-badge ${signal.type}`}
-                          title={`目标价格: ${formatCurrency(signal.targetPrice)}`}
+                          className={`signal-badge ${signal.kind}`}
+                          title={`目标价格: ${formatCurrency(signal.targetPrice)}，按目标价盈亏: ${formatCurrency(signal.projectedPnl)}`}
                         >
-                          {signal.type === 'sell' ? '卖' : '买'}
+                          {signal.kind === 'sell' ? '卖' : signal.kind === 'stop' ? '止' : '买'}
                         </span>
                       ))}
                     </div>
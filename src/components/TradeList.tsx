@@ -1,5 +1,5 @@
 import React from 'react';
-import { Trade, PriceCalculation } from '../types';
+import { Trade, PriceCalculation, PriceSignal } from '../types';
 import { format } from 'date-fns';
 import { zhCN } from 'date-fns/locale';
 
@@ -29,25 +29,31 @@ export const TradeList: React.FC<TradeListProps> = ({
     return format(new Date(date), 'yyyy-MM-dd HH:mm', { locale: zhCN });
   };
 
-  const getPriceReachedColor = (status: string) => {
-    switch (status) {
+  const getPriceReachedColor = (signal: PriceSignal) => {
+    switch (signal.kind) {
       case 'sell':
         return 'text-green-600';
       case 'buy':
         return 'text-blue-600';
+      case 'stop':
+        return 'text-red-600';
       default:
         return 'text-gray-600';
     }
   };
 
-  const getPriceReachedText = (status: string) => {
-    switch (status) {
+  const getPriceReachedText = (signal: PriceSignal) => {
+    switch (signal.kind) {
       case 'sell':
         return '达到卖出价';
       case 'buy':
         return '达到买入价';
+      case 'stop':
+        return '触发止损';
+      case 'no_quote':
+        return '暂无行情';
       default:
-        return '未达到目标';
+        return `距卖出价 ${signal.distancePercent.toFixed(2)}%`;
     }
   };
 
@@ -109,8 +115,13 @@ export const TradeList: React.FC<TradeListProps> = ({
                   </td>
                   <td>
                     {calculation ? (
-                      <span className={getPriceReachedColor(calculation.priceReached)}>
-                        {getPriceReachedText(calculation.priceReached)}
+                      <span
+                        className={getPriceReachedColor(calculation.signal)}
+                        title={'projectedPnl' in calculation.signal
+                          ? `按目标价盈亏: ${formatCurrency(calculation.signal.projectedPnl)}`
+                          : undefined}
+                      >
+                        {getPriceReachedText(calculation.signal)}
                       </span>
                     ) : (
                       <button 
@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/tauri';
-import { Trade, PriceCalculation, StockPriceResponse, StockSearchResult, StockInfo } from '../types';
+import { Trade, PriceCalculation, StockPriceResponse, StockSearchResult, StockInfo, PortfolioSummary, PositionSummary, FxRate } from '../types';
+import { PriceCalculator } from '../utils/priceCalculator';
 
 // 检查是否在 Tauri 环境中
 const isTauri = () => {
@@ -136,15 +137,15 @@ export const useTauri = () => {
   };
 
   // 价格计算命令
-  const calculatePriceTargets = async (
-    tradeId: number,
-    buyStepPercentage: number,
-    annualReturnRate: number
-  ): Promise<PriceCalculation> => {
+  // 策略参数由后端按 交易 → 股票 → 账户 → 全局设置 解析
+  const calculatePriceTargets = async (tradeId: number): Promise<PriceCalculation> => {
     if (!isTauri()) {
-      // 模拟计算
+      // 模拟计算，使用默认参数
+      const buyStepPercentage = 0.05;
+      const annualReturnRate = 0.20;
       const mockTrade = {
         buyPrice: 10.0,
+        quantity: 100,
         buyTime: new Date("2024-01-15T10:30:00"),
       };
       
@@ -159,15 +160,42 @@ export const useTauri = () => {
         buyTargetPrice: buyTarget,
         daysSincePurchase: daysHeld,
         currentPrice,
-        priceReached: currentPrice >= sellTarget ? 'sell' : 
-                     currentPrice <= buyTarget ? 'buy' : 'none',
+        signal: PriceCalculator.checkPriceSignal(currentPrice, sellTarget, buyTarget, mockTrade, annualReturnRate),
       });
     }
-    return invoke<PriceCalculation>('calculate_price_targets', {
-      tradeId,
-      buyStepPercentage,
-      annualReturnRate,
-    });
+    return invoke<PriceCalculation>('calculate_price_targets', { tradeId });
+  };
+
+  // 投资组合分析
+  const getPortfolioSummary = async (account?: string): Promise<PortfolioSummary> => {
+    if (!isTauri()) {
+      // 网页模式下没有数据库，用示例交易和模拟行情在前端汇总
+      const trades = (await getAllTrades()).filter(t => !account || (t.account ?? 'default') === account);
+      return summarizeMockPortfolio(trades);
+    }
+    return invoke<PortfolioSummary>('get_portfolio_summary', { account });
+  };
+
+  // 汇率
+  const getFxRates = async (): Promise<FxRate[]> => {
+    if (!isTauri()) {
+      return Promise.resolve([]);
+    }
+    return invoke<FxRate[]>('get_fx_rates');
+  };
+
+  const setFxRate = async (rate: FxRate): Promise<void> => {
+    if (!isTauri()) {
+      return Promise.resolve();
+    }
+    return invoke('set_fx_rate', { rate });
+  };
+
+  const updateFxRates = async (): Promise<number> => {
+    if (!isTauri()) {
+      return Promise.resolve(0);
+    }
+    return invoke<number>('update_fx_rates');
   };
 
   // 设置相关命令
@@ -211,18 +239,12 @@ export const useTauri = () => {
     return invoke('send_notification', { title, body, icon });
   };
 
-  const checkPriceAlertsAndNotify = async (
-    buyStepPercentage: number,
-    annualReturnRate: number
-  ): Promise<string[]> => {
+  const checkPriceAlertsAndNotify = async (): Promise<string[]> => {
     if (!isTauri()) {
       // 在网页模式下模拟价格检查和通知
       return Promise.resolve([]);
     }
-    return invoke<string[]>('check_price_alerts_and_notify', {
-      buyStepPercentage,
-      annualReturnRate,
-    });
+    return invoke<string[]>('check_price_alerts_and_notify');
   };
 
   // 问候命令（测试用）
@@ -248,6 +270,10 @@ export const useTauri = () => {
 
     // 价格计算
     calculatePriceTargets,
+    getPortfolioSummary,
+    getFxRates,
+    setFxRate,
+    updateFxRates,
 
     // 设置
     getSetting,
@@ -366,15 +392,30 @@ const fetchRealStockInfo = async (stockCode: string): Promise<StockInfo> => {
   };
 };
 
-// 格式化股票代码为新浪财经API格式
+// 各交易所的代码前缀，与后端 security.rs 的规则表保持一致（不含只能带前缀查询的指数）
+const EXCHANGE_CODE_PREFIXES: Record<string, string[]> = {
+  sh: ['600', '601', '603', '605', '900', '688', '689', '50', '51', '52', '56', '58', '010', '019', '110', '111', '113', '118'],
+  sz: ['000', '001', '002', '003', '004', '200', '300', '301', '302', '15', '16', '18', '10', '123', '127', '128'],
+  bj: ['43', '83', '87', '88', '920'],
+};
+
+// 格式化股票代码为新浪财经API格式，按最长前缀确定交易所，已带前缀的代码（含港股 hk00700、美股 gb_aapl）保持不变
 const formatStockCodeForSina = (stockCode: string): string => {
-  if (stockCode.startsWith('6')) {
-    return `sh${stockCode}`; // 上海交易所
-  } else if (stockCode.startsWith('0') || stockCode.startsWith('3')) {
-    return `sz${stockCode}`; // 深圳交易所
-  } else {
-    return `sh${stockCode}`; // 默认上海交易所
+  if (/^((sh|sz|bj)\d{6}|hk\d{5}|gb_[a-z][a-z0-9.]*)$/i.test(stockCode)) {
+    return stockCode.toLowerCase();
   }
+
+  let exchange = 'sh';
+  let matched = 0;
+  for (const [prefix, codePrefixes] of Object.entries(EXCHANGE_CODE_PREFIXES)) {
+    for (const codePrefix of codePrefixes) {
+      if (stockCode.startsWith(codePrefix) && codePrefix.length > matched) {
+        exchange = prefix;
+        matched = codePrefix.length;
+      }
+    }
+  }
+  return `${exchange}${stockCode}`;
 };
 
 // 模拟股票信息数据
@@ -412,6 +453,75 @@ const getMockStockInfo = (stockCode: string): StockInfo => {
   };
 };
 
+// 网页模式下的组合汇总，与后端一样按账户和股票合并持仓；示例数据都是人民币，不计算信号
+const summarizeMockPortfolio = (trades: Trade[]): PortfolioSummary => {
+  const groups = new Map<string, Trade[]>();
+  trades.forEach(trade => {
+    const key = `${trade.account ?? 'default'}|${trade.stockCode}`;
+    groups.set(key, [...(groups.get(key) ?? []), trade]);
+  });
+
+  const positions: PositionSummary[] = Array.from(groups.values()).map(lots => {
+    const quote = getMockStockInfo(lots[0].stockCode);
+    const quantity = lots.reduce((sum, t) => sum + t.quantity, 0);
+    const totalCost = lots.reduce((sum, t) => sum + t.buyPrice * t.quantity, 0);
+    const marketValue = quote.currentPrice * quantity;
+    const unrealizedPnl = marketValue - totalCost;
+    const dayChange = quote.change * quantity;
+    const previousValue = marketValue - dayChange;
+
+    return {
+      account: lots[0].account ?? 'default',
+      stockCode: lots[0].stockCode,
+      stockName: lots[0].stockName,
+      currency: 'CNY',
+      quantity,
+      averageCost: quantity > 0 ? totalCost / quantity : 0,
+      totalCost,
+      currentPrice: quote.currentPrice,
+      marketValue,
+      unrealizedPnl,
+      unrealizedPnlPercent: totalCost > 0 ? (unrealizedPnl / totalCost) * 100 : 0,
+      dayChange,
+      dayChangePercent: previousValue > 0 ? (dayChange / previousValue) * 100 : 0,
+      totalCostBase: totalCost,
+      marketValueBase: marketValue,
+      pricePnl: unrealizedPnl,
+      fxPnl: 0,
+      weight: 0,
+      signals: [],
+    };
+  });
+
+  const totalCost = positions.reduce((sum, p) => sum + p.totalCost, 0);
+  const marketValue = positions.reduce((sum, p) => sum + p.marketValue, 0);
+  const dayChange = positions.reduce((sum, p) => sum + p.dayChange, 0);
+  positions.forEach(p => {
+    p.weight = marketValue > 0 ? (p.marketValue / marketValue) * 100 : 0;
+  });
+
+  return {
+    baseCurrency: 'CNY',
+    totalCost,
+    marketValue,
+    unrealizedPnl: marketValue - totalCost,
+    unrealizedPnlPercent: totalCost > 0 ? ((marketValue - totalCost) / totalCost) * 100 : 0,
+    dayChange,
+    dayChangePercent: marketValue - dayChange > 0 ? (dayChange / (marketValue - dayChange)) * 100 : 0,
+    pricePnl: marketValue - totalCost,
+    fxPnl: 0,
+    missingFxRates: [],
+    missingQuotes: [],
+    sellSignals: 0,
+    buySignals: 0,
+    stopSignals: 0,
+    totalTrades: trades.length,
+    totalStocks: new Set(trades.map(t => t.stockCode)).size,
+    positions,
+    quotedAt: new Date(),
+  };
+};
+
 // 模拟股票搜索数据
 const getMockStockSearch = (query: string): StockSearchResult[] => {
   const mockStocks = [
@@ -69,10 +69,7 @@ export class NotificationService {
     try {
       if (this.tauri.isTauri) {
         // 在Tauri环境中使用后端检查
-        const alerts = await this.tauri.checkPriceAlertsAndNotify(
-          this.settings.buyStepPercentage,
-          this.settings.annualReturnRate
-        );
+        const alerts = await this.tauri.checkPriceAlertsAndNotify();
         
         if (alerts.length > 0) {
           console.log('价格提醒:', alerts);
@@ -105,7 +102,7 @@ export class NotificationService {
         );
 
         // 检查是否达到目标价格
-        if (calculation.priceReached === 'sell') {
+        if (calculation.signal.kind === 'sell') {
           const message = `${trade.stockName}(${trade.stockCode}) 已达到卖出目标价格 ¥${calculation.sellTargetPrice.toFixed(2)}，当前价格 ¥${stockInfo.currentPrice.toFixed(2)}`;
           
           await this.sendNotification('🔔 卖出提醒', message);
@@ -114,11 +111,20 @@ export class NotificationService {
           if (this.settings.soundEnabled) {
             this.playNotificationSound();
           }
-        } else if (calculation.priceReached === 'buy') {
+        } else if (calculation.signal.kind === 'buy') {
           const message = `${trade.stockName}(${trade.stockCode}) 已达到买入目标价格 ¥${calculation.buyTargetPrice.toFixed(2)}，当前价格 ¥${stockInfo.currentPrice.toFixed(2)}`;
           
           await this.sendNotification('🔔 买入提醒', message);
           
+          // 播放提示音
+          if (this.settings.soundEnabled) {
+            this.playNotificationSound();
+          }
+        } else if (calculation.signal.kind === 'stop') {
+          const message = `${trade.stockName}(${trade.stockCode}) 已触发止损 ¥${calculation.signal.targetPrice.toFixed(2)}，当前价格 ¥${stockInfo.currentPrice.toFixed(2)}`;
+          
+          await this.sendNotification('🔔 止损提醒', message);
+          
           // 播放提示音
           if (this.settings.soundEnabled) {
             this.playNotificationSound();
@@ -50,101 +50,6 @@ export class PriceCalculationService {
     return results;
   }
 
-  /**
-   * 分析投资组合
-   */
-  analyzePortfolio(trades: Trade[], currentPrices: Record<string, number> = {}) {
-    const calculations = this.calculateMultipleTradeTargets(trades, currentPrices);
-    
-    let totalInvestment = 0;
-    let totalCurrentValue = 0;
-    let totalUnrealizedGain = 0;
-    let sellSignals = 0;
-    let buySignals = 0;
-    
-    const stockSummary: Record<string, {
-      code: string;
-      name: string;
-      totalQuantity: number;
-      averagePrice: number;
-      currentPrice?: number;
-      totalValue: number;
-      unrealizedGain: number;
-      unrealizedGainPercent: number;
-      signals: Array<{ type: 'sell' | 'buy'; tradeId: number; targetPrice: number }>;
-    }> = {};
-
-    trades.forEach(trade => {
-      if (!trade.id) return;
-      
-      const calculation = calculations[trade.id];
-      const currentPrice = currentPrices[trade.stockCode];
-      const investment = trade.buyPrice * trade.quantity;
-      const currentValue = currentPrice ? currentPrice * trade.quantity : investment;
-      
-      totalInvestment += investment;
-      totalCurrentValue += currentValue;
-      totalUnrealizedGain += (currentValue - investment);
-      
-      // 统计信号
-      if (calculation.priceReached === 'sell') sellSignals++;
-      if (calculation.priceReached === 'buy') buySignals++;
-      
-      // 股票汇总
-      if (!stockSummary[trade.stockCode]) {
-        stockSummary[trade.stockCode] = {
-          code: trade.stockCode,
-          name: trade.stockName,
-          totalQuantity: 0,
-          averagePrice: 0,
-          currentPrice: currentPrice,
-          totalValue: 0,
-          unrealizedGain: 0,
-          unrealizedGainPercent: 0,
-          signals: [],
-        };
-      }
-      
-      const stock = stockSummary[trade.stockCode];
-      const prevTotalValue = stock.averagePrice * stock.totalQuantity;
-      stock.totalQuantity += trade.quantity;
-      stock.averagePrice = (prevTotalValue + investment) / stock.totalQuantity;
-      stock.totalValue += currentValue;
-      stock.unrealizedGain += (currentValue - investment);
-      stock.unrealizedGainPercent = ((stock.totalValue - (stock.averagePrice * stock.totalQuantity)) / (stock.averagePrice * stock.totalQuantity)) * 100;
-      
-      // 添加信号
-      if (calculation.priceReached === 'sell') {
-        stock.signals.push({
-          type: 'sell',
-          tradeId: trade.id,
-          targetPrice: calculation.sellTargetPrice,
-        });
-      } else if (calculation.priceReached === 'buy') {
-        stock.signals.push({
-          type: 'buy',
-          tradeId: trade.id,
-          targetPrice: calculation.buyTargetPrice,
-        });
-      }
-    });
-
-    return {
-      overview: {
-        totalInvestment,
-        totalCurrentValue,
-        totalUnrealizedGain,
-        totalUnrealizedGainPercent: totalInvestment > 0 ? (totalUnrealizedGain / totalInvestment) * 100 : 0,
-        sellSignals,
-        buySignals,
-        totalTrades: trades.length,
-        totalStocks: Object.keys(stockSummary).length,
-      },
-      stockSummary: Object.values(stockSummary),
-      calculations,
-    };
-  }
-
   /**
    * 获取需要关注的交易（达到目标价格）
    */
@@ -163,14 +68,14 @@ export class PriceCalculationService {
       
       const calculation = calculations[trade.id];
       
-      if (calculation.priceReached === 'sell') {
+      if (calculation.signal.kind === 'sell') {
         alerts.push({
           trade,
           calculation,
           alertType: 'sell',
           message: `${trade.stockName}(${trade.stockCode}) 已达到卖出目标价格 ¥${calculation.sellTargetPrice.toFixed(2)}`,
         });
-      } else if (calculation.priceReached === 'buy') {
+      } else if (calculation.signal.kind === 'buy') {
         alerts.push({
           trade,
           calculation,
@@ -245,12 +150,12 @@ export class PriceCalculationService {
     let confidence: 'high' | 'medium' | 'low' = 'medium';
 
     // 基于价格信号的建议
-    if (calculation.priceReached === 'sell') {
+    if (calculation.signal.kind === 'sell') {
       action = 'sell';
       confidence = 'high';
       reasons.push('当前价格已达到卖出目标');
       suggestions.push('考虑分批卖出以降低风险');
-    } else if (calculation.priceReached === 'buy') {
+    } else if (calculation.signal.kind === 'buy') {
       action = 'buy_more';
       confidence = 'medium';
       reasons.push('当前价格已达到买入目标');
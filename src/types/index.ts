@@ -7,6 +7,9 @@ export interface Trade {
   buyTime: Date;
   quantity: number;
   notes?: string;
+  account?: string; // 所属账户，默认 "default"
+  security?: SecurityId; // 保存时按代码规则确定的交易所、板块和品种
+  currency?: Currency; // 买入价的计价货币，为空时按证券所在市场确定
   createdAt?: Date;
 }
 
@@ -39,7 +42,149 @@ export interface PriceCalculation {
   buyTargetPrice: number;
   daysSincePurchase: number;
   currentPrice?: number;
-  priceReached: 'sell' | 'buy' | 'none';
+  signal: PriceSignal; // 现价触发的信号
+  stopLossPrice?: number;
+  trailingStopPrice?: number;
+  highWaterPrice?: number; // 买入以来的最高价
+  security?: SecurityId; // 代码无法识别时为空
+  limitUpPrice?: number;   // 当日涨停价
+  limitDownPrice?: number; // 当日跌停价
+  sellTargetBeyondLimit?: boolean; // 卖出目标高于涨停价，当日无法达到
+  buyTargetBeyondLimit?: boolean;  // 买入目标低于跌停价，当日无法达到
+  buyStepPercentage?: number;
+  buyStepMode?: StepMode; // 实际采用的台阶方式
+  volatility?: number;    // 计算台阶使用的相对波动率（日）
+  buyStepReason?: string;
+  growthModel?: GrowthModel;
+  dayCountBasis?: DayCountBasis;
+}
+
+// 交易板块，与品种类型一起决定报价单位、涨跌幅限制和买入数量规则
+export type Board = 'main' | 'chi_next' | 'star' | 'beijing';
+
+// 交易所，hk 为港股、us 为美股
+export type Exchange = 'sh' | 'sz' | 'bj' | 'hk' | 'us';
+
+// 计价货币
+export type Currency = 'CNY' | 'HKD' | 'USD';
+
+// 某日 1 单位外币折合的人民币
+export interface FxRate {
+  currency: Currency;
+  rateDate: string;
+  rate: number;
+}
+
+// 品种类型，指数只有行情不能交易
+export type InstrumentType = 'stock' | 'etf' | 'lof' | 'bond' | 'index';
+
+// 证券标识，板块和品种类型由后端的代码规则表确定
+export interface SecurityId {
+  exchange: Exchange;
+  code: string;
+  board: Board;
+  instrumentType: InstrumentType;
+}
+
+// 买入台阶的确定方式
+export type StepMode = 'fixed' | 'atr' | 'historical_volatility';
+
+// 止损触发的原因
+export type StopKind = 'stop_loss' | 'trailing_stop' | 'time_stop';
+
+// 信号对应的目标价、与现价的差距和按目标价成交的盈亏
+export interface SignalTarget {
+  targetPrice: number;
+  distance: number;        // 目标价 - 现价，正数表示还需上涨
+  distancePercent: number; // 差距占现价的百分比
+  projectedPnl: number;    // 全部数量按目标价成交的盈亏，不含费用
+  daysUntilTargetPasses?: number; // 卖出目标价涨过现价还需的天数，只用于卖出信号
+}
+
+// 现价触发的价格信号，none 表示未触发，目标为卖出目标
+export type PriceSignal =
+  | ({ kind: 'sell' | 'buy' | 'none' } & SignalTarget)
+  | ({ kind: 'stop'; reason: StopKind } & SignalTarget)
+  | { kind: 'no_quote' };
+
+// 卖出目标价的增长模型
+export type GrowthModel = 'simple' | 'daily_compound' | 'annual_compound' | 'continuous';
+
+// 计息天数基准
+export type DayCountBasis = 'days360' | 'days365';
+
+// 达到目标价格的交易信号
+export type TradeSignal = { tradeId: number } & Exclude<PriceSignal, { kind: 'none' | 'no_quote' }>;
+
+// 单个持仓的分析结果
+export interface PositionSummary {
+  account: string;
+  stockCode: string;
+  stockName: string;
+  currency: Currency; // 成本、市值和盈亏的计价货币
+  quantity: number;
+  averageCost: number;
+  totalCost: number;
+  currentPrice?: number;
+  marketValue: number;
+  unrealizedPnl: number;
+  unrealizedPnlPercent: number;
+  dayChange: number;
+  dayChangePercent: number;
+  totalCostBase?: number; // 按买入日汇率折算的基准货币成本
+  marketValueBase?: number; // 按最新汇率折算的基准货币市值
+  pricePnl?: number; // 价格变动带来的基准货币盈亏
+  fxPnl?: number; // 汇率变动带来的基准货币盈亏
+  weight: number; // 市值占比（%）
+  signals: TradeSignal[];
+}
+
+// 投资组合分析结果（由后端 get_portfolio_summary 计算）
+export interface PortfolioSummary {
+  baseCurrency: Currency; // 合计金额使用的基准货币
+  totalCost: number;
+  marketValue: number;
+  unrealizedPnl: number;
+  unrealizedPnlPercent: number;
+  dayChange: number;
+  dayChangePercent: number;
+  pricePnl: number;
+  fxPnl: number;
+  missingFxRates: Currency[]; // 缺少汇率、未计入合计的货币
+  missingQuotes: string[]; // 取不到行情的股票代码，这些持仓的市值按成本计算
+  sellSignals: number;
+  buySignals: number;
+  stopSignals: number;
+  totalTrades: number;
+  totalStocks: number;
+  positions: PositionSummary[];
+  quotedAt: Date;
+}
+
+// 后端命令失败时返回的错误，按 code 区分处理，details 只用于排查问题
+export type AppErrorCode =
+  | 'not_found'
+  | 'validation'
+  | 'network'
+  | 'provider_parse'
+  | 'database'
+  | 'not_initialized'
+  | 'database_locked'
+  | 'invalid_passphrase'
+  | 'io'
+  | 'internal';
+
+export interface AppError {
+  code: AppErrorCode;
+  message: string;
+  details?: string;
+  fields?: FieldError[]; // 字段校验失败时逐个字段的错误
+}
+
+// 单个字段的校验错误，field 与交易记录的字段名一致
+export interface FieldError {
+  field: string;
+  message: string;
 }
 
 // API 响应类型
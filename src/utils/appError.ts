@@ -0,0 +1,10 @@
+import { AppError } from '../types';
+
+// 将后端命令返回的错误转换为可展示的文字，字段校验错误逐条列出
+export const describeError = (error: unknown): string => {
+  const appError = error as Partial<AppError> | undefined;
+  if (appError?.fields?.length) {
+    return appError.fields.map(field => field.message).join('\n');
+  }
+  return appError?.message ?? String(error);
+};
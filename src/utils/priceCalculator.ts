@@ -1,4 +1,4 @@
-import { Trade, PriceCalculation } from '../types';
+import { Trade, PriceCalculation, PriceSignal, SignalTarget } from '../types';
 
 /**
  * 价格计算工具类
@@ -52,25 +52,49 @@ export class PriceCalculator {
   }
 
   /**
-   * 判断当前价格是否达到目标
+   * 判断当前价格触发的信号，与后端的优先顺序一致（前端不计算止损）
    * 
-   * @param currentPrice 当前价格
+   * @param currentPrice 当前价格，没有行情时为 no_quote
    * @param sellTargetPrice 卖出目标价格
    * @param buyTargetPrice 买入目标价格
-   * @returns 价格状态: 'sell' | 'buy' | 'none'
+   * @param trade 交易记录，用于计算按目标价成交的盈亏
+   * @param annualReturnRate 年化收益率，用于推算卖出目标价涨过现价的天数
+   * @returns 价格信号
    */
-  static checkPriceTarget(
-    currentPrice: number,
+  static checkPriceSignal(
+    currentPrice: number | undefined,
     sellTargetPrice: number,
-    buyTargetPrice: number
-  ): 'sell' | 'buy' | 'none' {
+    buyTargetPrice: number,
+    trade: Pick<Trade, 'buyPrice' | 'quantity' | 'buyTime'>,
+    annualReturnRate: number
+  ): PriceSignal {
+    if (!currentPrice) {
+      return { kind: 'no_quote' };
+    }
+
+    const target = (targetPrice: number): SignalTarget => ({
+      targetPrice,
+      distance: targetPrice - currentPrice,
+      distancePercent: ((targetPrice - currentPrice) / currentPrice) * 100,
+      projectedPnl: (targetPrice - trade.buyPrice) * trade.quantity,
+    });
+
     if (currentPrice >= sellTargetPrice) {
-      return 'sell';
-    } else if (currentPrice <= buyTargetPrice) {
-      return 'buy';
-    } else {
-      return 'none';
+      // 单利下 买入价 × (1 + 年化收益率 ÷ 360 × 天数) 超过现价的最少持有天数
+      const daysHeld = this.calculateDaysHeld(trade.buyTime);
+      const daysToPass = annualReturnRate > 0
+        ? Math.floor(((currentPrice / trade.buyPrice - 1) * 360) / annualReturnRate) + 1
+        : undefined;
+      return {
+        kind: 'sell',
+        ...target(sellTargetPrice),
+        daysUntilTargetPasses: daysToPass === undefined ? undefined : Math.max(daysToPass - daysHeld, 1),
+      };
+    }
+    if (currentPrice <= buyTargetPrice) {
+      return { kind: 'buy', ...target(buyTargetPrice) };
     }
+    return { kind: 'none', ...target(sellTargetPrice) };
   }
 
   /**
@@ -101,16 +125,14 @@ export class PriceCalculator {
       buyStepPercentage
     );
     
-    const priceReached = currentPrice 
-      ? this.checkPriceTarget(currentPrice, sellTargetPrice, buyTargetPrice)
-      : 'none';
+    const signal = this.checkPriceSignal(currentPrice, sellTargetPrice, buyTargetPrice, trade, annualReturnRate);
     
     return {
       sellTargetPrice,
       buyTargetPrice,
       daysSincePurchase: daysHeld,
       currentPrice,
-      priceReached,
+      signal,
     };
   }
 